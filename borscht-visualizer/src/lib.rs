@@ -1,37 +1,172 @@
 /*!
- * Visualizer for BIRCH CFTrees.
+ * Visualizer for BIRCH CFTrees, of any kind of cluster feature and dimensionality: each entry of
+ * a node is drawn with a width proportional to its size.
+ *
+ * Trees can be drawn to raster images (e.g. PNG) or to SVG, which stays crisp at any scale (e.g.
+ * in papers and web pages); see [OutputFormat]. They can also be drawn without touching the
+ * filesystem, into a drawing area of any plotters backend (see [draw_to_area], e.g. for GUI apps)
+ * or into a buffer of RGB pixels (see [render_to_rgb_buffer], e.g. for servers).
+ *
+ * Alongside the tree, [draw_scatter] plots the data itself on two chosen dimensions, colored by
+ * leaf cluster, with the center and radius of each cluster overlaid.
  */
 
-use std::ops::{Range, RangeBounds};
+use std::{collections::HashMap, path::Path};
 
 use palettes::{Palette, Triple};
 use plotters::{
     coord::Shift,
-    prelude::{BitMapBackend, DrawingArea, IntoDrawingArea, RGBColor, TextStyle, WHITE},
+    prelude::{
+        BitMapBackend, ChartBuilder, Circle, Color, Cross, DrawingArea, DrawingBackend,
+        IntoDrawingArea, PathElement, RGBColor, SVGBackend, TextStyle, BLACK, WHITE,
+    },
 };
 use plotters_backend::BackendTextStyle;
 use thiserror::Error;
 
 use borscht::{
-    cfeature::{birch::CFeature as BirchFeature, CFeature},
-    cftree::Node,
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{Node, NodeEntry},
+    point::{Float, Point},
 };
-use plotters_bitmap::bitmap_pixel::RGBPixel;
 
 pub mod palettes;
 
-const IMG_WIDTH: u32 = 512;
+const DEFAULT_WIDTH: u32 = 512;
 
-const TITLE_TEXT: &str = "Sample Visualization";
-const TITLE_FONT_FAMILY: &str = "sans-serif";
-const TITLE_HEIGHT: u32 = 30;
-const TITLE_STYLE: (&str, u32) = (TITLE_FONT_FAMILY, TITLE_HEIGHT);
+const DEFAULT_TITLE: &str = "Sample Visualization";
+const DEFAULT_FONT_FAMILY: &str = "sans-serif";
+const DEFAULT_FONT_SIZE: u32 = 30;
 
-const DRAW_AREA_LR_MARGIN: u32 = 12;
-const DRAW_AREA_WIDTH: u32 = IMG_WIDTH - DRAW_AREA_LR_MARGIN * 2;
-const DRAW_AREA_TB_MARGIN: u32 = 4;
+const DEFAULT_LR_MARGIN: u32 = 12;
+const DEFAULT_TB_MARGIN: u32 = 4;
 
-const NODE_HEIGHT: u32 = 40;
+const DEFAULT_NODE_HEIGHT: u32 = 40;
+const DEFAULT_SCATTER_HEIGHT: u32 = 512;
+const DEFAULT_PALETTE: usize = 308;
+
+/// Layout and styling of tree visualizations. All settings have defaults, which can be overridden
+/// with the setters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisualizerOptions {
+    title: String,
+    font_family: String,
+    font_size: u32,
+    width: u32,
+    node_height: u32,
+    level_spacing: u32,
+    scatter_height: u32,
+    lr_margin: u32,
+    tb_margin: u32,
+    palette: usize,
+}
+
+impl Default for VisualizerOptions {
+    fn default() -> VisualizerOptions {
+        VisualizerOptions {
+            title: DEFAULT_TITLE.to_string(),
+            font_family: DEFAULT_FONT_FAMILY.to_string(),
+            font_size: DEFAULT_FONT_SIZE,
+            width: DEFAULT_WIDTH,
+            node_height: DEFAULT_NODE_HEIGHT,
+            level_spacing: 0,
+            scatter_height: DEFAULT_SCATTER_HEIGHT,
+            lr_margin: DEFAULT_LR_MARGIN,
+            tb_margin: DEFAULT_TB_MARGIN,
+            palette: DEFAULT_PALETTE,
+        }
+    }
+}
+
+impl VisualizerOptions {
+    pub fn new() -> VisualizerOptions {
+        VisualizerOptions::default()
+    }
+
+    /// Sets the title drawn above the tree. An empty title isn't drawn, and takes no room.
+    pub fn title<S: Into<String>>(mut self, title: S) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Sets the font family and size (in pixels) of the title.
+    pub fn font<S: Into<String>>(mut self, family: S, size: u32) -> Self {
+        self.font_family = family.into();
+        self.font_size = size;
+        self
+    }
+
+    /// Sets the width of the image, in pixels.
+    pub fn width(mut self, width: u32) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Sets the height of the band drawn for each level of the tree, in pixels.
+    pub fn node_height(mut self, node_height: u32) -> Self {
+        self.node_height = node_height;
+        self
+    }
+
+    /// Sets the vertical space left between consecutive levels of the tree, in pixels. Defaults
+    /// to none.
+    pub fn level_spacing(mut self, level_spacing: u32) -> Self {
+        self.level_spacing = level_spacing;
+        self
+    }
+
+    /// Sets the height of scatter plots (see [draw_scatter]), in pixels. Trees are drawn as high
+    /// as their levels require instead.
+    pub fn scatter_height(mut self, scatter_height: u32) -> Self {
+        self.scatter_height = scatter_height;
+        self
+    }
+
+    /// Sets the left and right (`horizontal`) and top and bottom (`vertical`) margins around the
+    /// tree, in pixels.
+    pub fn margins(mut self, horizontal: u32, vertical: u32) -> Self {
+        self.lr_margin = horizontal;
+        self.tb_margin = vertical;
+        self
+    }
+
+    /// Sets the index of the palette entries are colored from (see [palettes::PALETTES] and
+    /// [palettes::PALETTE_NAMES]).
+    pub fn palette(mut self, palette: usize) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    fn title_style(&self) -> TextStyle<'_> {
+        (self.font_family.as_str(), self.font_size).into()
+    }
+
+    fn title_height(&self) -> Result<u32> {
+        match self.title.is_empty() {
+            true => Ok(0),
+            false => estimate_title_height(&self.title, &self.title_style()),
+        }
+    }
+
+    /// Height of the drawing of a tree of height `height`, without the title and margins.
+    fn tree_height(&self, height: usize) -> u32 {
+        let height = height as u32;
+        self.node_height * height + self.level_spacing * height.saturating_sub(1)
+    }
+
+    /// Size of the image of a tree of height `height`, in pixels.
+    pub fn image_size(&self, height: usize) -> Result<(u32, u32)> {
+        let image_height = self.tree_height(height) + self.tb_margin * 2 + self.title_height()?;
+        Ok((self.width, image_height))
+    }
+
+    fn color_iter(&self) -> Result<ColorIter<'static>> {
+        palettes::PALETTES
+            .get(self.palette)
+            .map(ColorIter::new)
+            .ok_or(VisualizerError::InvalidPalette(self.palette))
+    }
+}
 
 #[derive(Error, Debug)]
 pub enum VisualizerError {
@@ -39,11 +174,37 @@ pub enum VisualizerError {
     FontLayout(Box<dyn std::error::Error>),
     #[error("drawing error")]
     Drawing(Box<dyn std::error::Error>),
+    #[error("cannot draw a node without entries")]
+    EmptyNode,
+    #[error("no palette with index {0}")]
+    InvalidPalette(usize),
+    #[error("no dimension with index {0}")]
+    InvalidDimension(usize),
+}
+
+/// Format of the image files trees are drawn to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Raster image, encoded according to the extension of the file (e.g. PNG).
+    #[default]
+    Bitmap,
+    /// Scalable vector graphics.
+    Svg,
+}
+
+impl OutputFormat {
+    /// Format of the file `filename` according to its extension: SVG for `.svg` files (in any
+    /// case), and bitmap otherwise.
+    pub fn from_filename(filename: &str) -> OutputFormat {
+        match Path::new(filename).extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("svg") => OutputFormat::Svg,
+            _ => OutputFormat::Bitmap,
+        }
+    }
 }
 
-type TreeNode = Node<BirchFeature<3>, 3>;
 type Result<T> = std::result::Result<T, VisualizerError>;
-type DrawArea<'a> = DrawingArea<BitMapBackend<'a, RGBPixel>, Shift>;
+type DrawArea<DB> = DrawingArea<DB, Shift>;
 
 fn estimate_title_height(text: &str, style: &TextStyle) -> Result<u32> {
     let layout = style
@@ -53,7 +214,10 @@ fn estimate_title_height(text: &str, style: &TextStyle) -> Result<u32> {
     Ok(layout_h + (layout_h / 2).min(5) * 2)
 }
 
-fn split_into_subareas(area: DrawArea, mut xs: Vec<f64>) -> Vec<DrawArea> {
+fn split_into_subareas<DB: DrawingBackend>(
+    area: DrawArea<DB>,
+    mut xs: Vec<f64>,
+) -> Vec<DrawArea<DB>> {
     if let Some(x) = xs.pop() {
         let (l, r) = area.split_horizontally(x as u32);
 
@@ -84,93 +248,504 @@ impl<'a> ColorIter<'a> {
     }
 }
 
-trait TransposeRange {
-    type Type;
-    fn transpose_range(&self) -> Range<(Self::Type, Self::Type)>;
-}
-impl<T> TransposeRange for (Range<T>, Range<T>)
+/// Draws the subtree rooted at `node` into `area`: each entry of the node as a band of the top of
+/// the area, with a width proportional to its size, and its child (if any) below it.
+pub fn draw_node_to_area<DB, CF, const DIMS: usize>(
+    area: &DrawArea<DB>,
+    node: &Node<CF, DIMS>,
+    color_iter: &mut ColorIter,
+    options: &VisualizerOptions,
+) -> Result<()>
 where
-    T: Copy,
+    DB: DrawingBackend,
+    CF: CFeature<DIMS>,
+    DB::ErrorType: 'static,
 {
-    type Type = T;
-    fn transpose_range(&self) -> Range<(Self::Type, Self::Type)> {
-        return (self.0.start_bound(), self.1.start_bound())
-            ..(self.0.end_bound(), self.1.end_bound());
+    if node.entries.is_empty() {
+        return Err(VisualizerError::EmptyNode);
     }
-}
-
-pub fn draw_node_to_area(
-    area: &DrawArea,
-    node: &TreeNode,
-    color_iter: &mut ColorIter,
-) -> Result<()> {
-    let height = node.height();
+    let width = area.dim_in_pixel().0 as f64;
     let (sum, xs) = node
         .entries
         .iter()
         .fold((0.0, vec![]), |(sum, mut v), entry| {
-            let new_sum = sum + entry.feature.size();
+            let new_sum = sum + entry.feature.size().to_scalar();
             v.push(new_sum);
             (new_sum, v)
         });
     let xs = xs
         .iter()
         .take(xs.len() - 1)
-        .map(|x| x / sum * DRAW_AREA_WIDTH as f64)
+        .map(|x| x / sum * width)
         .rev()
         .collect::<Vec<_>>();
-    println!("start area: {:?}", area.get_pixel_range());
     let hsplits = split_into_subareas(area.clone(), xs);
-    println!(
-        "hsplits: {:?}",
-        hsplits
-            .iter()
-            .map(|hs| hs.get_pixel_range())
-            .collect::<Vec<_>>()
-    );
-    for (i, hsplit) in hsplits.iter().enumerate() {
-        let vsplits = hsplit.split_evenly((height, 1));
-        println!(
-            "vsplits: {:?}",
-            vsplits
-                .iter()
-                .map(|vs| vs.get_pixel_range())
-                .collect::<Vec<_>>()
-        );
-        for ((j, vsplit), entry) in vsplits.iter().enumerate().zip(node.entries.iter()) {
-            println!("split ({}, {}): {:?}", i, j, vsplit.get_pixel_range());
-            let (r, g, b) = color_iter.next();
-            vsplit
-                .fill(&RGBColor(r, g, b))
-                .map_err(|e| VisualizerError::Drawing(Box::new(e)))?;
-            if let Some(child) = entry.child.as_ref() {
-                draw_node_to_area(vsplit, child, color_iter)?;
-            }
+    for (hsplit, entry) in hsplits.iter().zip(node.entries.iter()) {
+        let (band, below) = hsplit.split_vertically(options.node_height);
+        let (r, g, b) = color_iter.next();
+        band.fill(&RGBColor(r, g, b))
+            .map_err(|e| VisualizerError::Drawing(Box::new(e)))?;
+        if let Some(child) = entry.child.as_ref() {
+            let (_, child_area) = below.split_vertically(options.level_spacing);
+            draw_node_to_area(&child_area, child, color_iter, options)?;
         }
     }
     Ok(())
 }
 
-pub fn draw_to_file(filename: &str, tree: &TreeNode) -> Result<()> {
-    let draw_area_height = NODE_HEIGHT * tree.height() as u32;
-    let title_style: TextStyle = TITLE_STYLE.into();
-    let estimated_title_height = estimate_title_height(TITLE_TEXT, &title_style)?;
-    let img_height = draw_area_height + DRAW_AREA_TB_MARGIN * 2 + estimated_title_height;
-    let root = BitMapBackend::new(filename, (IMG_WIDTH, img_height)).into_drawing_area();
-    root.fill(&WHITE)
+/// Draws `tree` to the file `filename`, in the format given by its extension (see
+/// [OutputFormat::from_filename]).
+pub fn draw_to_file<CF: CFeature<DIMS>, const DIMS: usize>(
+    filename: &str,
+    tree: &Node<CF, DIMS>,
+    options: &VisualizerOptions,
+) -> Result<()> {
+    draw_to_file_as(
+        filename,
+        tree,
+        options,
+        OutputFormat::from_filename(filename),
+    )
+}
+
+/// Draws `tree` to the file `filename`, in the format `format` regardless of its extension.
+pub fn draw_to_file_as<CF: CFeature<DIMS>, const DIMS: usize>(
+    filename: &str,
+    tree: &Node<CF, DIMS>,
+    options: &VisualizerOptions,
+    format: OutputFormat,
+) -> Result<()> {
+    if tree.entries.is_empty() {
+        return Err(VisualizerError::EmptyNode);
+    }
+    let size = options.image_size(tree.height())?;
+    match format {
+        OutputFormat::Bitmap => draw_tree(
+            BitMapBackend::new(filename, size).into_drawing_area(),
+            tree,
+            options,
+        ),
+        OutputFormat::Svg => draw_tree(
+            SVGBackend::new(filename, size).into_drawing_area(),
+            tree,
+            options,
+        ),
+    }
+}
+
+/// Draws `tree` into `area`, which is filled with white first: the title (if any) at the top, and
+/// the tree below it, within the margins of `options`. The tree spans the width of `area`, rather
+/// than the width of `options`. The area isn't presented, so that callers can draw over it.
+pub fn draw_to_area<DB, CF, const DIMS: usize>(
+    area: &DrawArea<DB>,
+    tree: &Node<CF, DIMS>,
+    options: &VisualizerOptions,
+) -> Result<()>
+where
+    DB: DrawingBackend,
+    CF: CFeature<DIMS>,
+    DB::ErrorType: 'static,
+{
+    let mut color_iter = options.color_iter()?;
+    area.fill(&WHITE)
+        .map_err(|e| VisualizerError::Drawing(Box::new(e)))?;
+    let titled = match options.title.is_empty() {
+        true => area.clone(),
+        false => area
+            .titled(&options.title, options.title_style())
+            .map_err(|e| VisualizerError::Drawing(Box::new(e)))?,
+    };
+    let width = area.dim_in_pixel().0;
+    let tree_area = titled.shrink(
+        (options.lr_margin, options.tb_margin),
+        (
+            width.saturating_sub(options.lr_margin * 2),
+            options.tree_height(tree.height()),
+        ),
+    );
+    draw_node_to_area(&tree_area, tree, &mut color_iter, options)
+}
+
+/// Renders `tree` into a buffer of RGB pixels (three bytes per pixel, row by row), of the size
+/// given by [VisualizerOptions::image_size].
+pub fn render_to_rgb_buffer<CF: CFeature<DIMS>, const DIMS: usize>(
+    tree: &Node<CF, DIMS>,
+    options: &VisualizerOptions,
+) -> Result<Vec<u8>> {
+    if tree.entries.is_empty() {
+        return Err(VisualizerError::EmptyNode);
+    }
+    let (width, height) = options.image_size(tree.height())?;
+    let mut buffer = vec![0; width as usize * height as usize * 3];
+    draw_tree(
+        BitMapBackend::with_buffer(&mut buffer, (width, height)).into_drawing_area(),
+        tree,
+        options,
+    )?;
+    Ok(buffer)
+}
+
+fn draw_tree<DB, CF, const DIMS: usize>(
+    root: DrawArea<DB>,
+    tree: &Node<CF, DIMS>,
+    options: &VisualizerOptions,
+) -> Result<()>
+where
+    DB: DrawingBackend,
+    CF: CFeature<DIMS>,
+    DB::ErrorType: 'static,
+{
+    draw_to_area(&root, tree, options)?;
+    root.present()
         .map_err(|e| VisualizerError::Drawing(Box::new(e)))?;
-    let root = root
-        .titled(TITLE_TEXT, TITLE_STYLE)
-        .map_err(|e| VisualizerError::Drawing(Box::new(e)))?
-        .shrink(
-            (DRAW_AREA_LR_MARGIN, 0),
-            (DRAW_AREA_WIDTH, draw_area_height),
+
+    Ok(())
+}
+
+/// Stride between the palette colors of consecutive clusters of scatter plots; coprime with the
+/// length of the palettes, so that up to that many clusters get distinct colors.
+const SCATTER_COLOR_GAP: usize = 97;
+/// Pixel radius of the data points of scatter plots.
+const SCATTER_POINT_SIZE: u32 = 2;
+/// Pixel size of the crosses marking cluster centers in scatter plots.
+const SCATTER_CENTER_SIZE: u32 = 5;
+/// Number of segments of the circles drawn around cluster centers in scatter plots.
+const SCATTER_CIRCLE_SEGMENTS: usize = 64;
+
+/// Plots the data clustered by `tree` to the file `filename` (in the format given by its
+/// extension), on dimensions `x` and `y`; see [draw_scatter_to_area].
+pub fn draw_scatter<CF, T, const DIMS: usize>(
+    filename: &str,
+    tree: &Node<CF, DIMS>,
+    points: Option<&[Point<DIMS, T>]>,
+    (x, y): (usize, usize),
+    options: &VisualizerOptions,
+) -> Result<()>
+where
+    CF: CFeature<DIMS>,
+    T: Float,
+{
+    draw_scatter_as(
+        filename,
+        tree,
+        points,
+        (x, y),
+        options,
+        OutputFormat::from_filename(filename),
+    )
+}
+
+/// Plots the data clustered by `tree` to the file `filename` like [draw_scatter], in the format
+/// `format` regardless of its extension.
+pub fn draw_scatter_as<CF, T, const DIMS: usize>(
+    filename: &str,
+    tree: &Node<CF, DIMS>,
+    points: Option<&[Point<DIMS, T>]>,
+    (x, y): (usize, usize),
+    options: &VisualizerOptions,
+    format: OutputFormat,
+) -> Result<()>
+where
+    CF: CFeature<DIMS>,
+    T: Float,
+{
+    let size = (options.width, options.scatter_height);
+    match format {
+        OutputFormat::Bitmap => draw_scatter_plot(
+            BitMapBackend::new(filename, size).into_drawing_area(),
+            tree,
+            points,
+            (x, y),
+            options,
+        ),
+        OutputFormat::Svg => draw_scatter_plot(
+            SVGBackend::new(filename, size).into_drawing_area(),
+            tree,
+            points,
+            (x, y),
+            options,
+        ),
+    }
+}
+
+/// Plots the data clustered by `tree` into `area`, on dimensions `x` and `y`: `points` (or, if
+/// `None`, the samples kept by the leaf entries of the tree, see
+/// [TreeConfig::reservoir_size](borscht::cftree::TreeConfig::reservoir_size)), each colored like
+/// the leaf cluster it is assigned to: the one reached by descending the tree through the entries
+/// closest to it (see [Node::closest_entry]), as during insertion. The center of each leaf cluster
+/// is marked with a cross, circled by its radius (which spans all dimensions, so it only
+/// approximates the spread of the cluster on the plotted ones). Points with a missing (NaN)
+/// coordinate on either dimension aren't plotted.
+pub fn draw_scatter_to_area<DB, CF, T, const DIMS: usize>(
+    area: &DrawArea<DB>,
+    tree: &Node<CF, DIMS>,
+    points: Option<&[Point<DIMS, T>]>,
+    (x, y): (usize, usize),
+    options: &VisualizerOptions,
+) -> Result<()>
+where
+    DB: DrawingBackend,
+    CF: CFeature<DIMS>,
+    T: Float,
+    DB::ErrorType: 'static,
+{
+    if let Some(&dim) = [x, y].iter().find(|&&dim| dim >= DIMS) {
+        return Err(VisualizerError::InvalidDimension(dim));
+    }
+    let palette = palettes::PALETTES
+        .get(options.palette)
+        .ok_or(VisualizerError::InvalidPalette(options.palette))?;
+    let mut entries = vec![];
+    collect_leaf_entries(tree, &mut entries);
+    let clusters = tree
+        .clusters()
+        .map(|cluster| {
+            let center = Point::<DIMS>::from_fn(|d| cluster.center[d].to_scalar());
+            (center, cluster.radius.to_scalar())
+        })
+        .collect::<Vec<_>>();
+    if clusters.is_empty() {
+        return Err(VisualizerError::EmptyNode);
+    }
+    let points = match points {
+        Some(points) => points
+            .iter()
+            .map(|p| Point::<DIMS>::from_fn(|d| p[d].to_scalar()))
+            .collect::<Vec<_>>(),
+        None => entries
+            .iter()
+            .flat_map(|entry| entry.samples.samples().iter().cloned())
+            .collect(),
+    };
+    let ids = entries
+        .iter()
+        .enumerate()
+        .map(|(id, &entry)| (entry as *const NodeEntry<CF, DIMS>, id))
+        .collect::<HashMap<_, _>>();
+    let labeled = points
+        .iter()
+        .filter(|p| !p[x].is_nan() && !p[y].is_nan())
+        .map(|p| {
+            let feature_point =
+                FeaturePoint::<CF, DIMS>::from_fn(|d| CF::Scalar::from_scalar(p[d]));
+            let id = ids[&(assigned_entry(tree, &feature_point) as *const _)];
+            ((p[x], p[y]), id)
+        })
+        .collect::<Vec<_>>();
+
+    // bounds of the points and of the circles around the centers
+    let ((x_min, x_max), (y_min, y_max)) = labeled
+        .iter()
+        .map(|&(xy, _)| (xy, 0.0))
+        .chain(clusters.iter().map(|(c, radius)| ((c[x], c[y]), *radius)))
+        .fold(
+            (
+                (f64::INFINITY, f64::NEG_INFINITY),
+                (f64::INFINITY, f64::NEG_INFINITY),
+            ),
+            |((x_min, x_max), (y_min, y_max)), ((px, py), r)| {
+                (
+                    (x_min.min(px - r), x_max.max(px + r)),
+                    (y_min.min(py - r), y_max.max(py + r)),
+                )
+            },
         );
-    let palette = &palettes::PALETTES[308];
-    let mut palette_iter = ColorIter::new(palette);
-    draw_node_to_area(&root, tree, &mut palette_iter)?;
+    let (x_range, y_range) = (padded(x_min, x_max), padded(y_min, y_max));
+
+    area.fill(&WHITE)
+        .map_err(|e| VisualizerError::Drawing(Box::new(e)))?;
+    let mut builder = ChartBuilder::on(area);
+    builder
+        .margin_left(options.lr_margin)
+        .margin_right(options.lr_margin)
+        .margin_top(options.tb_margin)
+        .margin_bottom(options.tb_margin)
+        .x_label_area_size(options.font_size)
+        .y_label_area_size(options.font_size * 2);
+    if !options.title.is_empty() {
+        builder.caption(&options.title, options.title_style());
+    }
+    let mut chart = builder
+        .build_cartesian_2d(x_range, y_range)
+        .map_err(|e| VisualizerError::Drawing(Box::new(e)))?;
+    chart
+        .configure_mesh()
+        .x_desc(format!("dimension {}", x))
+        .y_desc(format!("dimension {}", y))
+        .draw()
+        .map_err(|e| VisualizerError::Drawing(Box::new(e)))?;
+
+    let color = |id: usize| {
+        let (r, g, b) = palette[id * SCATTER_COLOR_GAP % palette.len()];
+        RGBColor(r, g, b)
+    };
+    chart
+        .draw_series(
+            labeled
+                .iter()
+                .map(|&(xy, id)| Circle::new(xy, SCATTER_POINT_SIZE, color(id).filled())),
+        )
+        .map_err(|e| VisualizerError::Drawing(Box::new(e)))?;
+    chart
+        .draw_series(clusters.iter().enumerate().map(|(id, (center, radius))| {
+            let circle = (0..=SCATTER_CIRCLE_SEGMENTS)
+                .map(|i| {
+                    let angle = i as f64 / SCATTER_CIRCLE_SEGMENTS as f64 * std::f64::consts::TAU;
+                    (
+                        center[x] + radius * angle.cos(),
+                        center[y] + radius * angle.sin(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            PathElement::new(circle, color(id))
+        }))
+        .map_err(|e| VisualizerError::Drawing(Box::new(e)))?;
+    chart
+        .draw_series(
+            clusters
+                .iter()
+                .map(|(center, _)| Cross::new((center[x], center[y]), SCATTER_CENTER_SIZE, BLACK)),
+        )
+        .map_err(|e| VisualizerError::Drawing(Box::new(e)))?;
+
+    Ok(())
+}
+
+fn draw_scatter_plot<DB, CF, T, const DIMS: usize>(
+    root: DrawArea<DB>,
+    tree: &Node<CF, DIMS>,
+    points: Option<&[Point<DIMS, T>]>,
+    (x, y): (usize, usize),
+    options: &VisualizerOptions,
+) -> Result<()>
+where
+    DB: DrawingBackend,
+    CF: CFeature<DIMS>,
+    T: Float,
+    DB::ErrorType: 'static,
+{
+    draw_scatter_to_area(&root, tree, points, (x, y), options)?;
     root.present()
         .map_err(|e| VisualizerError::Drawing(Box::new(e)))?;
 
     Ok(())
 }
+
+/// The leaf entry of the tree rooted at `node` (which has entries) which `p` is assigned to,
+/// reached by descending through the entries closest to it.
+fn assigned_entry<'a, CF: CFeature<DIMS>, const DIMS: usize>(
+    node: &'a Node<CF, DIMS>,
+    p: &FeaturePoint<CF, DIMS>,
+) -> &'a NodeEntry<CF, DIMS> {
+    let (idx, _) = node.closest_entry(p).expect("non-empty node");
+    let entry = &node.entries[idx];
+    match entry.child {
+        Some(ref child) => assigned_entry(child, p),
+        None => entry,
+    }
+}
+
+/// Range from `min` to `max` padded by a twentieth on either side (or by one, if empty).
+fn padded(min: f64, max: f64) -> std::ops::Range<f64> {
+    let pad = match max > min {
+        true => (max - min) / 20.0,
+        false => 1.0,
+    };
+    (min - pad)..(max + pad)
+}
+
+/// Collects the leaf entries of the tree rooted at `node`, in order of cluster id.
+fn collect_leaf_entries<'a, CF, const DIMS: usize>(
+    node: &'a Node<CF, DIMS>,
+    entries: &mut Vec<&'a NodeEntry<CF, DIMS>>,
+) {
+    for entry in &node.entries {
+        match entry.child {
+            Some(ref child) => collect_leaf_entries(child, entries),
+            None => entries.push(entry),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use borscht::{
+        cfeature::birch::CFeature as BirchFeature,
+        cftree::{BasicConfig, BirchCFTree, BirchTree},
+    };
+
+    fn config() -> BasicConfig {
+        BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .build()
+            .unwrap()
+    }
+
+    fn points() -> Vec<Point<2>> {
+        (0..40)
+            .map(|i| Point::from_arr([(i % 8) as f64, (i / 8) as f64 * 3.0]))
+            .collect()
+    }
+
+    #[test]
+    fn rgb_buffer() {
+        let tree = BirchCFTree::from_iter(points(), config());
+        assert!(tree.root().height() > 1);
+        let options = VisualizerOptions::new().width(64).node_height(8);
+        let buffer = render_to_rgb_buffer(tree.root(), &options).unwrap();
+        let (width, height) = options.image_size(tree.root().height()).unwrap();
+        assert_eq!(buffer.len(), width as usize * height as usize * 3);
+        // the background is filled, and the entries drawn over it
+        assert!(buffer.contains(&255));
+        assert!(buffer.iter().any(|&byte| byte != 255 && byte != 0));
+
+        assert!(matches!(
+            render_to_rgb_buffer(&BirchTree::<2>::new(&config()), &options),
+            Err(VisualizerError::EmptyNode)
+        ));
+    }
+
+    #[test]
+    fn scatter() {
+        let tree = BirchCFTree::from_iter(points(), config());
+        let options = VisualizerOptions::new()
+            .width(128)
+            .scatter_height(96)
+            .title("");
+        let (width, height) = (options.width, options.scatter_height);
+        let mut buffer = vec![0; width as usize * height as usize * 3];
+        let area = BitMapBackend::with_buffer(&mut buffer, (width, height)).into_drawing_area();
+        assert!(matches!(
+            draw_scatter_plot(area.clone(), tree.root(), Some(&points()), (0, 2), &options),
+            Err(VisualizerError::InvalidDimension(2))
+        ));
+        draw_scatter_plot(area, tree.root(), Some(&points()), (0, 1), &options).unwrap();
+        assert!(buffer.contains(&255));
+        assert!(buffer.iter().any(|&byte| byte != 255 && byte != 0));
+
+        // points are colored by the leaf entry they descend to, rather than the closest one
+        let leaf = |x: f64| NodeEntry {
+            feature: BirchFeature::from(Point::from_arr([x, 0.0])),
+            ..NodeEntry::default()
+        };
+        let parent = |leaves: Vec<NodeEntry<BirchFeature<2>, 2>>| NodeEntry {
+            feature: leaves
+                .iter()
+                .map(|leaf| leaf.feature.clone())
+                .reduce(|sum, feature| sum + feature)
+                .unwrap(),
+            child: Some(Arc::new(Node::with_entries(leaves))),
+            ..NodeEntry::default()
+        };
+        let tree = Node::with_entries(vec![
+            parent(vec![leaf(0.0), leaf(10.0)]),
+            parent(vec![leaf(6.0)]),
+        ]);
+        let assigned = assigned_entry(&tree, &Point::from_arr([9.0, 0.0]));
+        assert_eq!(assigned.feature.center()[0], 6.0);
+    }
+}
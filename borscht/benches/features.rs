@@ -0,0 +1,53 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use borscht::{
+    bench::compare_features,
+    cftree::{BasicConfig, BetulaCFTree, BirchCFTree},
+    point::Point,
+};
+
+/// Points spread over a 100-unit cube offset by `offset` from the origin.
+fn points(n: u64, offset: f64) -> Vec<Point<3>> {
+    (0..n)
+        .map(|i| {
+            let h = i.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            Point::from_arr([
+                offset + (h % 1000) as f64 / 10.0,
+                offset + ((h >> 20) % 1000) as f64 / 10.0,
+                offset + ((h >> 40) % 1000) as f64 / 10.0,
+            ])
+        })
+        .collect()
+}
+
+fn config() -> BasicConfig {
+    BasicConfig::builder()
+        .capacity(2, 8)
+        .threshold(0.5)
+        .build()
+        .unwrap()
+}
+
+fn features(c: &mut Criterion) {
+    let mut group = c.benchmark_group("features");
+    group.sample_size(10);
+    for &offset in &[0.0, 1e6] {
+        let points = points(100_000, offset);
+        // the numbers the timings can't show
+        println!(
+            "offset {}:\n{}",
+            offset,
+            compare_features(&points, &config())
+        );
+        group.bench_with_input(BenchmarkId::new("birch", offset), &points, |b, points| {
+            b.iter(|| BirchCFTree::from_iter(black_box(points.clone()), config()))
+        });
+        group.bench_with_input(BenchmarkId::new("betula", offset), &points, |b, points| {
+            b.iter(|| BetulaCFTree::from_iter(black_box(points.clone()), config()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, features);
+criterion_main!(benches);
@@ -0,0 +1,101 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use borscht::{
+    arena::ArenaTree,
+    cfeature::birch::CFeature as BirchFeature,
+    cftree::{BasicConfig, BirchCFTree},
+    point::Point,
+    split::{FarthestPair, LinearSeeds, Split, SplitEntry, SplitPolicy},
+};
+
+fn points(n: u64) -> Vec<Point<3>> {
+    (0..n)
+        .map(|i| {
+            let h = i.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            Point::from_arr([
+                (h % 1000) as f64 / 10.0,
+                ((h >> 20) % 1000) as f64 / 10.0,
+                ((h >> 40) % 1000) as f64 / 10.0,
+            ])
+        })
+        .collect()
+}
+
+fn config() -> BasicConfig {
+    BasicConfig::builder()
+        .capacity(2, 8)
+        .threshold(0.5)
+        .build()
+        .unwrap()
+}
+
+fn insertion(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insertion");
+    group.sample_size(10);
+    for &n in &[10_000, 100_000] {
+        let points = points(n);
+        group.bench_with_input(BenchmarkId::new("cftree", n), &points, |b, points| {
+            b.iter(|| BirchCFTree::from_iter(black_box(points.clone()), config()))
+        });
+        group.bench_with_input(BenchmarkId::new("arena", n), &points, |b, points| {
+            b.iter(|| {
+                ArenaTree::<BirchFeature<3>, 3>::from_iter(black_box(points.clone()), config())
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("bulk_load", n), &points, |b, points| {
+            b.iter(|| BirchCFTree::bulk_load(black_box(points.clone()), config()))
+        });
+        group.bench_with_input(BenchmarkId::new("insert_batch", n), &points, |b, points| {
+            b.iter(|| {
+                let mut tree = BirchCFTree::new(config());
+                for batch in black_box(points).chunks(1000) {
+                    tree.insert_batch(batch);
+                }
+                tree
+            })
+        });
+    }
+    group.finish();
+}
+
+fn split(c: &mut Criterion) {
+    let mut group = c.benchmark_group("split");
+    for &n in &[16, 64, 256] {
+        let entries = points(n)
+            .into_iter()
+            .map(|p| SplitEntry {
+                center: p.as_slice().to_vec(),
+                size: 1.0,
+            })
+            .collect::<Vec<_>>();
+        group.bench_with_input(
+            BenchmarkId::new("farthest_pair", n),
+            &entries,
+            |b, entries| b.iter(|| FarthestPair.partition(black_box(entries))),
+        );
+        group.bench_with_input(BenchmarkId::new("linear", n), &entries, |b, entries| {
+            b.iter(|| LinearSeeds.partition(black_box(entries)))
+        });
+    }
+    // whole builds with large nodes, where splits are most expensive
+    group.sample_size(10);
+    let points = points(100_000);
+    let wide = |split| {
+        BasicConfig::builder()
+            .capacity(8, 64)
+            .threshold(0.5)
+            .split(split)
+            .build()
+            .unwrap()
+    };
+    group.bench_function("cftree_farthest_pair", |b| {
+        b.iter(|| BirchCFTree::from_iter(black_box(points.clone()), wide(Split::FarthestPair)))
+    });
+    group.bench_function("cftree_linear", |b| {
+        b.iter(|| BirchCFTree::from_iter(black_box(points.clone()), wide(Split::Linear)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, insertion, split);
+criterion_main!(benches);
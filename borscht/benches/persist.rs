@@ -0,0 +1,87 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use borscht::{
+    cftree::{BasicConfig, BirchCFTree},
+    persist::{Compression, SaveOptions},
+    point::Point,
+};
+
+/// Points of 16 dimensions scattered around a few dozen centers.
+fn points(n: u64) -> Vec<Point<16>> {
+    (0..n)
+        .map(|i| {
+            let center = (i.wrapping_mul(0x9e37_79b9_7f4a_7c15) % 32) as f64 * 10.0;
+            let mut coords = [0.0; 16];
+            for (d, coord) in coords.iter_mut().enumerate() {
+                let h = (i * 16 + d as u64 + 1)
+                    .wrapping_mul(0xd6e8_feb8_6659_fd93)
+                    .rotate_left(29);
+                *coord = center + (h >> 11) as f64 / (1u64 << 52) as f64;
+            }
+            Point::from_arr(coords)
+        })
+        .collect()
+}
+
+fn options() -> Vec<(String, SaveOptions)> {
+    let mut options = vec![("plain".to_string(), SaveOptions::default())];
+    for (name, compression) in [("zstd", Compression::Zstd(3)), ("lz4", Compression::Lz4)] {
+        for (filters, delta, shuffle) in [
+            ("", false, false),
+            ("_delta", true, false),
+            ("_shuffle", false, true),
+            ("_delta_shuffle", true, true),
+        ] {
+            let save_options = SaveOptions::compressed(compression)
+                .with_delta(delta)
+                .with_shuffle(shuffle);
+            options.push((format!("{}{}", name, filters), save_options));
+        }
+    }
+    options
+}
+
+fn persist(c: &mut Criterion) {
+    let config = BasicConfig::builder()
+        .capacity(4, 16)
+        .threshold(0.5)
+        .build()
+        .unwrap();
+    let tree = BirchCFTree::from_iter(points(50_000), config);
+
+    let mut group = c.benchmark_group("persist");
+    group.sample_size(10);
+    for (name, options) in options() {
+        let mut buffer = vec![];
+        tree.write_with(&mut buffer, options).unwrap();
+        if options.delta {
+            // compressed size relative to the same options without delta encoding
+            let mut without = vec![];
+            tree.write_with(&mut without, options.with_delta(false))
+                .unwrap();
+            println!(
+                "persist/{}: {} bytes ({:.1}% of {} bytes without delta)",
+                name,
+                buffer.len(),
+                100.0 * buffer.len() as f64 / without.len() as f64,
+                without.len()
+            );
+        } else {
+            println!("persist/{}: {} bytes", name, buffer.len());
+        }
+        group.bench_with_input(BenchmarkId::new("write", &name), &options, |b, &options| {
+            b.iter(|| {
+                let mut buffer = vec![];
+                tree.write_with(&mut buffer, black_box(options)).unwrap();
+                buffer
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("load", &name), &buffer, |b, buffer| {
+            b.iter(|| BirchCFTree::<16>::read_from(black_box(buffer.as_slice())).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, persist);
+criterion_main!(benches);
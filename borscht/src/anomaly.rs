@@ -0,0 +1,86 @@
+/*!
+ * Anomaly scores for points, relative to the leaf clusters of a tree. Lets a tree built over a
+ * stream double as a streaming anomaly detector.
+ */
+
+use num_traits::Float as _;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{CFTree, Metric, Node, TreeConfig},
+    point::Float,
+};
+
+impl<CF: CFeature<DIMS>, const DIMS: usize> Node<CF, DIMS> {
+    /// Returns the leaf cluster feature nearest to `p` along the path an insertion of `p` (into
+    /// a tree with configuration `config`) would take, or `None` if this node is empty.
+    pub fn nearest_leaf<TC: TreeConfig>(
+        &self,
+        p: &FeaturePoint<CF, DIMS>,
+        config: &TC,
+    ) -> Option<&CF> {
+        let mut node = self;
+        loop {
+            let (idx, _) = node.closest_entry_with(p, config)?;
+            let entry = &node.entries[idx];
+            match entry.child {
+                Some(ref child) => node = child,
+                None => return Some(&entry.feature),
+            }
+        }
+    }
+}
+
+impl<CF: CFeature<DIMS>, TC: TreeConfig, const DIMS: usize> CFTree<CF, DIMS, TC> {
+    /// Anomaly score of `p`: its distance from the center of its nearest leaf cluster,
+    /// standardized by the per-dimension variance of that cluster. A point one radius away from
+    /// the center of a roughly spherical cluster scores about 1, and larger scores are more
+    /// anomalous. Returns `None` if the tree is empty.
+    ///
+    /// The variance of every dimension is padded by the spread allowed by the tree's threshold,
+    /// so points near small (or single-point) clusters don't get arbitrarily large scores. Missing
+    /// coordinates of `p` are handled as configured (see [TreeConfig::missing_values]).
+    pub fn anomaly_score(&self, p: &FeaturePoint<CF, DIMS>) -> Option<CF::Scalar> {
+        let feature = self.root().nearest_leaf(p, self.config())?;
+        let score2 = Metric::Mahalanobis.dist2(feature, p, self.config())
+            / CF::Scalar::from_scalar(DIMS as f64);
+        Some(score2.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cftree::{BasicConfig, BetulaCFTree, BirchCFTree},
+        point::Point,
+    };
+
+    #[test]
+    fn anomaly_score() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(8.0)
+            .build()
+            .unwrap();
+        let points = (0..100)
+            .map(|i| {
+                let angle = i as f64 * 0.7;
+                Point::from_arr([angle.cos(), angle.sin()])
+            })
+            .collect::<Vec<_>>();
+
+        let tree = BirchCFTree::from_iter(points.clone(), config.clone());
+        assert_eq!(tree.clusters().count(), 1);
+        let typical = tree.anomaly_score(&Point::from_arr([0.0, 1.0])).unwrap();
+        let outlier = tree.anomaly_score(&Point::from_arr([0.0, 10.0])).unwrap();
+        assert!(typical < 1.0);
+        assert!(outlier > 3.0);
+
+        let tree = BetulaCFTree::from_iter(points, config.clone());
+        assert!(tree.anomaly_score(&Point::from_arr([0.0, 1.0])).unwrap() < 1.0);
+        assert!(tree.anomaly_score(&Point::from_arr([0.0, 10.0])).unwrap() > 3.0);
+
+        let empty = BirchCFTree::<2>::new(config);
+        assert_eq!(empty.anomaly_score(&Point::from_arr([0.0, 0.0])), None);
+    }
+}
@@ -0,0 +1,126 @@
+/*!
+ * End-to-end applications built on the tree.
+ *
+ * [color_quantize] reduces the colors of an image to a palette of `k` colors: its pixels (as
+ * points of their RGB or RGBA channels) are summarized by a tree in a single pass, and the leaf
+ * clusters of the tree are then grouped into `k` colors with [kmeans](crate::kmeans::kmeans), so
+ * the cost of the global phase doesn't grow with the size of the image.
+ */
+
+use alloc::vec::Vec;
+
+use crate::{
+    birch::Birch,
+    cfeature::birch::CFeature as BirchFeature,
+    cftree::BasicConfig,
+    kmeans::kmeans,
+    point::{Point, Scalar},
+};
+
+/// Maximum number of leaf clusters summarizing the pixels of an image in [color_quantize]; the
+/// tree is rebuilt with a larger threshold whenever it has more.
+pub const MAX_SUBCLUSTERS: usize = 1024;
+
+/// Seed of the k-means initialization in [color_quantize], so that results are reproducible.
+const SEED: u64 = 0x636f_6c6f_7273;
+
+/// An image reduced to a palette of colors by [color_quantize].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuantizedImage<const CHANNELS: usize> {
+    /// Colors of the palette.
+    pub palette: Vec<[u8; CHANNELS]>,
+    /// Index into the palette of the color of each pixel.
+    pub indices: Vec<usize>,
+}
+
+impl<const CHANNELS: usize> QuantizedImage<CHANNELS> {
+    /// Pixels of the quantized image, each with its palette color.
+    pub fn pixels(&self) -> Vec<[u8; CHANNELS]> {
+        self.indices.iter().map(|&idx| self.palette[idx]).collect()
+    }
+}
+
+/// Reduces the colors of the image with pixels `pixels` (e.g. `[u8; 3]` for RGB, or `[u8; 4]`
+/// for RGBA) to a palette of at most `k` colors, and maps each pixel to the palette color closest
+/// to it. Returns fewer colors if the image has fewer distinct colors than `k`.
+pub fn color_quantize<const CHANNELS: usize>(
+    pixels: &[[u8; CHANNELS]],
+    k: usize,
+) -> QuantizedImage<CHANNELS> {
+    let point = |pixel: &[u8; CHANNELS]| Point::from_fn(|i| pixel[i] as Scalar);
+    let config = BasicConfig::builder()
+        .capacity(4, 16)
+        .threshold(1.0)
+        .build()
+        .expect("valid configuration");
+    let mut birch =
+        Birch::<BirchFeature<CHANNELS>, CHANNELS>::new(config).max_leaves(MAX_SUBCLUSTERS);
+    birch.fit(pixels.iter().map(point));
+
+    let centers = kmeans(birch.tree(), k, SEED).centers;
+    let palette = centers
+        .iter()
+        .map(|center| {
+            let mut color = [0; CHANNELS];
+            for (channel, &x) in color.iter_mut().zip(center.as_slice()) {
+                *channel = num_traits::Float::round(x).clamp(0.0, 255.0) as u8;
+            }
+            color
+        })
+        .collect();
+    let indices = pixels
+        .iter()
+        .map(|pixel| {
+            let p = point(pixel);
+            centers
+                .iter()
+                .map(|center| (center - &p).norm2())
+                .enumerate()
+                .fold((0, Scalar::INFINITY), |closest, (idx, d2)| {
+                    match d2 < closest.1 {
+                        true => (idx, d2),
+                        false => closest,
+                    }
+                })
+                .0
+        })
+        .collect();
+    QuantizedImage { palette, indices }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SplitMix64;
+
+    #[test]
+    fn color_quantize() {
+        // a noisy image of three colors
+        let colors = [[200, 30, 30], [30, 200, 30], [30, 30, 200]];
+        let mut rng = SplitMix64(3);
+        let pixels = (0..3000)
+            .map(|i| {
+                let mut pixel: [u8; 3] = colors[i % 3];
+                for channel in pixel.iter_mut() {
+                    *channel = (*channel as i64 + rng.below(11) as i64 - 5) as u8;
+                }
+                pixel
+            })
+            .collect::<Vec<_>>();
+
+        let quantized = super::color_quantize(&pixels, 3);
+        assert_eq!(quantized.palette.len(), 3);
+        assert_eq!(quantized.indices.len(), pixels.len());
+        for (i, (&idx, pixel)) in quantized.indices.iter().zip(quantized.pixels()).enumerate() {
+            assert_eq!(idx, quantized.indices[i % 3]);
+            for (&x, &expected) in pixel.iter().zip(&colors[i % 3]) {
+                assert!((x as i64 - expected as i64).abs() <= 2);
+            }
+        }
+
+        // RGBA pixels, with fewer distinct colors than requested
+        let quantized = super::color_quantize(&[[10, 20, 30, 255], [10, 20, 30, 255]], 4);
+        assert_eq!(quantized.palette, vec![[10, 20, 30, 255]]);
+        assert_eq!(quantized.indices, vec![0, 0]);
+    }
+}
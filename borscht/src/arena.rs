@@ -0,0 +1,736 @@
+/*!
+ * Arena-backed cluster feature tree.
+ *
+ * [ArenaTree] stores all of its nodes in a single contiguous vector and refers to child nodes by
+ * index, rather than having each [NodeEntry](crate::cftree::NodeEntry) own its child node. This
+ * keeps the nodes of a tree close together in memory and avoids an allocation per node, which
+ * speeds up insertion into large trees. Insertion follows exactly the same algorithm as
+ * [CFTree](crate::cftree::CFTree) (including its [metric](TreeConfig::metric) and
+ * [handling of missing values](TreeConfig::missing_values)), so both produce the same cluster
+ * features for the same points and configuration. Arena entries only hold cluster features,
+ * though: the point ids, samples, quantile sketches and stable ids a
+ * [NodeEntry](crate::cftree::NodeEntry) can keep aren't tracked.
+ *
+ * The arena itself is a [NodeStore]: a `Vec` of nodes in memory by default, or pages of a file
+ * for trees too large for memory (see the [paged](crate::paged) module, which requires the `fs`
+ * feature).
+ */
+
+use alloc::{borrow::Cow, sync::Arc, vec, vec::Vec};
+use core::{fmt::Debug, marker::PhantomData};
+
+use itertools::{Either, Itertools};
+use num_traits::Float as _;
+use serde::{Deserialize, Serialize};
+
+#[cfg(doc)]
+use crate::error::BorschtError;
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{
+        absorbs, closest_pair, fill_missing, partition_features, within_leaf_threshold,
+        BasicConfig, Capacity, InsertOutcome, MissingValues, Node, NodeEntry, TreeConfig,
+    },
+    error::Result,
+    point::Float,
+    quantiles::QuantileSketch,
+    reservoir::Reservoir,
+    summary::ClusterSummary,
+};
+
+/// Index of a node within an [ArenaTree].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    /// Id of the node at position `index` of a [NodeStore].
+    pub fn new(index: usize) -> NodeId {
+        NodeId(index)
+    }
+
+    /// Position of this node in its [NodeStore].
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArenaEntry<CF> {
+    pub feature: CF,
+    pub child: Option<NodeId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArenaNode<CF> {
+    pub entries: Vec<ArenaEntry<CF>>,
+}
+
+impl<CF> ArenaNode<CF> {
+    /// Whether this node is a leaf node (i.e. none of its entries have children).
+    pub fn is_leaf(&self) -> bool {
+        self.entries.iter().all(|entry| entry.child.is_none())
+    }
+}
+
+/// Storage backend for the nodes of an [ArenaTree], addressed by [NodeId].
+///
+/// `Vec<ArenaNode<CF>>` (the default) keeps all nodes in memory, and never fails. With the `fs`
+/// feature, [PagedStore](crate::paged::PagedStore) keeps them in the pages of a file instead, for
+/// trees too large for memory, and reports failures to read or write pages as
+/// [BorschtError::Io].
+pub trait NodeStore<CF: Clone> {
+    /// Number of nodes in this store, including nodes no longer part of the tree.
+    fn len(&self) -> usize;
+
+    /// Whether this store holds no nodes.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The node with id `id`, borrowed from this store or read into memory.
+    fn get(&self, id: NodeId) -> Result<Cow<'_, ArenaNode<CF>>>;
+
+    /// The node with id `id`, for modification. Changes are kept by this store.
+    fn get_mut(&mut self, id: NodeId) -> Result<&mut ArenaNode<CF>>;
+
+    /// Replaces the node with id `id` by `node`.
+    fn set(&mut self, id: NodeId, node: ArenaNode<CF>) -> Result<()>;
+
+    /// Adds `node` to this store, returning its id.
+    fn push(&mut self, node: ArenaNode<CF>) -> Result<NodeId>;
+}
+
+impl<CF: Clone> NodeStore<CF> for Vec<ArenaNode<CF>> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn get(&self, id: NodeId) -> Result<Cow<'_, ArenaNode<CF>>> {
+        Ok(Cow::Borrowed(&self[id.0]))
+    }
+
+    fn get_mut(&mut self, id: NodeId) -> Result<&mut ArenaNode<CF>> {
+        Ok(&mut self[id.0])
+    }
+
+    fn set(&mut self, id: NodeId, node: ArenaNode<CF>) -> Result<()> {
+        self[id.0] = node;
+        Ok(())
+    }
+
+    fn push(&mut self, node: ArenaNode<CF>) -> Result<NodeId> {
+        Vec::push(self, node);
+        Ok(NodeId(Vec::len(self) - 1))
+    }
+}
+
+/// A cluster feature tree whose nodes are stored in an arena (by default in memory; see
+/// [NodeStore]).
+///
+/// Operations which visit nodes fail if the store fails to provide them (see [NodeStore]); trees
+/// kept in memory also implement [Extend], since their store can't fail.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArenaTree<CF, const DIMS: usize, TC = BasicConfig, S = Vec<ArenaNode<CF>>> {
+    nodes: S,
+    /// Ids of nodes which are no longer part of the tree, available for reuse.
+    free: Vec<NodeId>,
+    root: NodeId,
+    config: TC,
+    #[serde(skip)]
+    features: PhantomData<CF>,
+}
+
+impl<CF, TC, S, const DIMS: usize> ArenaTree<CF, DIMS, TC, S>
+where
+    CF: Clone,
+    S: NodeStore<CF>,
+{
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    pub fn node(&self, id: NodeId) -> Result<Cow<'_, ArenaNode<CF>>> {
+        self.nodes.get(id)
+    }
+
+    pub fn config(&self) -> &TC {
+        &self.config
+    }
+
+    /// The store holding the nodes of this tree.
+    pub fn store(&self) -> &S {
+        &self.nodes
+    }
+
+    /// Mutable access to the store holding the nodes of this tree (e.g. to flush it).
+    pub fn store_mut(&mut self) -> &mut S {
+        &mut self.nodes
+    }
+
+    /// Number of levels in this tree. An empty tree has a single (empty) level: its root.
+    pub fn height(&self) -> Result<usize> {
+        let mut height = 0;
+        let mut level = vec![self.root];
+        while !level.is_empty() {
+            height += 1;
+            let mut next = vec![];
+            for id in level {
+                let node = self.nodes.get(id)?;
+                next.extend(node.entries.iter().filter_map(|entry| entry.child));
+            }
+            level = next;
+        }
+        Ok(height)
+    }
+
+    /// Number of leaf clusters in this tree.
+    pub fn leaf_count(&self) -> Result<usize> {
+        self.subtree_leaf_count(self.root)
+    }
+
+    fn subtree_leaf_count(&self, id: NodeId) -> Result<usize> {
+        self.nodes
+            .get(id)?
+            .entries
+            .iter()
+            .map(|entry| {
+                entry
+                    .child
+                    .map_or(Ok(1), |child| self.subtree_leaf_count(child))
+            })
+            .sum()
+    }
+}
+
+impl<CF, TC, const DIMS: usize> ArenaTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    TC: TreeConfig,
+{
+    pub fn new(config: TC) -> ArenaTree<CF, DIMS, TC> {
+        ArenaTree {
+            nodes: vec![ArenaNode { entries: vec![] }],
+            free: vec![],
+            root: NodeId(0),
+            config,
+            features: PhantomData,
+        }
+    }
+
+    pub fn from_iter<T: IntoIterator<Item = FeaturePoint<CF, DIMS>>>(
+        iter: T,
+        config: TC,
+    ) -> ArenaTree<CF, DIMS, TC> {
+        let mut tree = ArenaTree::new(config);
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<CF, TC, S, const DIMS: usize> ArenaTree<CF, DIMS, TC, S>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    TC: TreeConfig,
+    S: NodeStore<CF>,
+{
+    /// Creates an empty tree whose nodes are kept in `nodes`, which should be empty.
+    pub fn with_store(config: TC, mut nodes: S) -> Result<ArenaTree<CF, DIMS, TC, S>> {
+        let root = nodes.push(ArenaNode { entries: vec![] })?;
+        Ok(ArenaTree {
+            nodes,
+            free: vec![],
+            root,
+            config,
+            features: PhantomData,
+        })
+    }
+
+    fn alloc(&mut self, node: ArenaNode<CF>) -> Result<NodeId> {
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes.set(id, node)?;
+                Ok(id)
+            }
+            None => self.nodes.push(node),
+        }
+    }
+
+    fn capacity(&self, id: NodeId) -> Result<&Capacity> {
+        Ok(match self.nodes.get(id)?.is_leaf() {
+            true => self.config.leaf_capacity(),
+            false => self.config.node_capacity(),
+        })
+    }
+
+    /// Number of levels node `id` lies above the leaves. The tree is balanced, so following the
+    /// first child of each node reaches a leaf.
+    fn level(&self, mut id: NodeId) -> Result<usize> {
+        let mut level = 0;
+        while let Some(child) = self
+            .nodes
+            .get(id)?
+            .entries
+            .first()
+            .and_then(|entry| entry.child)
+        {
+            id = child;
+            level += 1;
+        }
+        Ok(level)
+    }
+
+    fn compute_feature(&self, id: NodeId) -> Result<CF> {
+        Ok(self
+            .nodes
+            .get(id)?
+            .entries
+            .iter()
+            .map(|entry| &entry.feature)
+            .fold(CF::zero(), |acc, feature| acc + feature))
+    }
+
+    /// Returns the index of the entry of node `id` closest to `p`, along with its squared
+    /// distance to `p` under the metric of this tree. See [Node::closest_entry_with].
+    pub fn closest_entry(
+        &self,
+        id: NodeId,
+        p: &FeaturePoint<CF, DIMS>,
+    ) -> Result<Option<(usize, CF::Scalar)>> {
+        let metric = self.config.metric();
+        Ok(self
+            .nodes
+            .get(id)?
+            .entries
+            .iter()
+            .map(|entry| metric.dist2(&entry.feature, p, &self.config))
+            .enumerate()
+            .fold(None, |closest, (idx, d2)| match closest {
+                Some((_, closest_d2)) if closest_d2 <= d2 => closest,
+                _ => Some((idx, d2)),
+            }))
+    }
+
+    /// Fills in the missing coordinates of `p` from the leaf cluster nearest to it, as
+    /// [CFTree](crate::cftree::CFTree) does (see [MissingValues]).
+    fn complete(&self, p: FeaturePoint<CF, DIMS>) -> Result<FeaturePoint<CF, DIMS>> {
+        if self.config.missing_values() == MissingValues::Propagate
+            || !p.as_slice().iter().any(|x| x.is_nan())
+        {
+            return Ok(p);
+        }
+        let mut id = self.root;
+        let fill = loop {
+            let Some((idx, _)) = self.closest_entry(id, &p)? else {
+                break Default::default();
+            };
+            let node = self.nodes.get(id)?;
+            match node.entries[idx].child {
+                Some(child) => id = child,
+                None => break node.entries[idx].feature.center(),
+            }
+        };
+        Ok(fill_missing(p, &fill))
+    }
+
+    /// Splits node `id` if it has reached capacity, keeping the first group of entries in place
+    /// and returning the id of the new node holding the second group.
+    fn check_split(&mut self, id: NodeId) -> Result<Option<NodeId>> {
+        let capacity = self.capacity(id)?;
+        let partition = {
+            let node = self.nodes.get(id)?;
+            if node.entries.len() < capacity.max {
+                return Ok(None);
+            }
+            partition_features(
+                node.entries.iter().map(|entry| &entry.feature),
+                capacity,
+                &self.config,
+            )
+        };
+        let entries = core::mem::take(&mut self.nodes.get_mut(id)?.entries);
+        let (left, right) = entries
+            .into_iter()
+            .zip(partition)
+            .partition_map(|(entry, left)| match left {
+                true => Either::Left(entry),
+                false => Either::Right(entry),
+            });
+        self.nodes.get_mut(id)?.entries = left;
+        Ok(Some(self.alloc(ArenaNode { entries: right })?))
+    }
+
+    /// Merges the two closest non-leaf entries of node `id`, unless they are the entries at
+    /// indices `split`. See [TreeConfig::merge_refinement].
+    fn merge_closest(&mut self, id: NodeId, split: (usize, usize)) -> Result<()> {
+        let closest = closest_pair(
+            self.nodes
+                .get(id)?
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| entry.child.is_some())
+                .map(|(idx, entry)| (idx, &entry.feature)),
+        );
+        let (lidx, ridx) = match closest {
+            Some((lidx, ridx)) if (lidx, ridx) != split && (ridx, lidx) != split => (lidx, ridx),
+            _ => return Ok(()),
+        };
+        // lidx < ridx, so remove the right entry first
+        let node = self.nodes.get_mut(id)?;
+        let right = node.entries.remove(ridx).child.expect("non-leaf entry");
+        let left = node.entries.remove(lidx).child.expect("non-leaf entry");
+        let right_entries = core::mem::take(&mut self.nodes.get_mut(right)?.entries);
+        self.free.push(right);
+        self.nodes.get_mut(left)?.entries.extend(right_entries);
+        // keep the merged entries where the left entry was
+        let ids = match self.check_split(left)? {
+            None => vec![left],
+            Some(right) => vec![left, right],
+        };
+        let entries = ids
+            .into_iter()
+            .map(|child| {
+                Ok(ArenaEntry {
+                    feature: self.compute_feature(child)?,
+                    child: Some(child),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.nodes.get_mut(id)?.entries.splice(lidx..lidx, entries);
+        Ok(())
+    }
+
+    /// Inserts a single point into this tree.
+    pub fn insert(&mut self, p: FeaturePoint<CF, DIMS>) -> Result<InsertOutcome> {
+        let p = self.complete(p)?;
+        // added to the features of the ancestors of the node the point ends up in, unless that
+        // node splits
+        let delta = CF::from(p.clone());
+        // descend to the node where the point is inserted
+        let mut path = vec![];
+        let mut id = self.root;
+        // levels of the root above the leaves, and the largest threshold of the entries above the
+        // leaves which absorbed the point, if any (see [TreeConfig::threshold_at])
+        let mut level = self.level(self.root)?;
+        let mut absorbing = None;
+        let (mut split, mut outcome) = loop {
+            match self.closest_entry(id, &p)?.map(|(idx, _)| idx) {
+                Some(idx) => match self.nodes.get(id)?.entries[idx].child {
+                    Some(child) => {
+                        let threshold = self.config.threshold_at(level);
+                        let feature = &self.nodes.get(id)?.entries[idx].feature;
+                        if absorbs(feature, &delta, threshold, absorbing) {
+                            absorbing = Some(threshold);
+                        }
+                        path.push((id, idx));
+                        id = child;
+                        level -= 1;
+                    }
+                    None => {
+                        let entry = &mut self.nodes.get_mut(id)?.entries[idx];
+                        let feature_with_point = entry.feature.clone() + &p;
+                        if within_leaf_threshold(&feature_with_point, &self.config)
+                            || absorbing.is_some_and(|threshold| {
+                                feature_with_point.diam2() <= CF::Scalar::from_scalar(threshold)
+                            })
+                        {
+                            entry.feature = feature_with_point;
+                            break (None, InsertOutcome::Absorbed);
+                        }
+                        self.nodes.get_mut(id)?.entries.push(ArenaEntry {
+                            feature: CF::from(p),
+                            child: None,
+                        });
+                        break (self.check_split(id)?, InsertOutcome::NewEntry);
+                    }
+                },
+                None => {
+                    self.nodes.get_mut(id)?.entries.push(ArenaEntry {
+                        feature: CF::from(p),
+                        child: None,
+                    });
+                    break (None, InsertOutcome::NewEntry);
+                }
+            }
+        };
+        // update features on the way back up, propagating splits
+        while let Some((parent, idx)) = path.pop() {
+            let child = self.nodes.get(parent)?.entries[idx]
+                .child
+                .expect("non-leaf entry");
+            let feature = match split {
+                None => {
+                    let feature = &mut self.nodes.get_mut(parent)?.entries[idx].feature;
+                    core::mem::replace(feature, CF::zero()) + &delta
+                }
+                Some(_) => self.compute_feature(child)?,
+            };
+            self.nodes.get_mut(parent)?.entries[idx].feature = feature;
+            split = match split {
+                None => None,
+                Some(right) => {
+                    outcome = InsertOutcome::Split;
+                    let feature = self.compute_feature(right)?;
+                    self.nodes.get_mut(parent)?.entries.push(ArenaEntry {
+                        feature,
+                        child: Some(right),
+                    });
+                    match self.check_split(parent)? {
+                        None if self.config.merge_refinement() => {
+                            // split propagation stops here
+                            let split = (idx, self.nodes.get(parent)?.entries.len() - 1);
+                            self.merge_closest(parent, split)?;
+                            None
+                        }
+                        split => split,
+                    }
+                }
+            };
+        }
+        // grow a new root if the root split
+        if let Some(right) = split {
+            outcome = InsertOutcome::Split;
+            let entries = [self.root, right]
+                .iter()
+                .map(|&child| {
+                    Ok(ArenaEntry {
+                        feature: self.compute_feature(child)?,
+                        child: Some(child),
+                    })
+                })
+                .collect::<Result<_>>()?;
+            self.root = self.alloc(ArenaNode { entries })?;
+        }
+        Ok(outcome)
+    }
+
+    /// Inserts the points of `iter` into this tree, stopping at the first failure of the store.
+    pub fn try_extend<T: IntoIterator<Item = FeaturePoint<CF, DIMS>>>(
+        &mut self,
+        iter: T,
+    ) -> Result<()> {
+        for p in iter {
+            self.insert(p)?;
+        }
+        Ok(())
+    }
+
+    /// Converts this tree into the equivalent tree of owned [Node]s.
+    pub fn to_node(&self) -> Result<Node<CF, DIMS>> {
+        self.to_node_at(self.root)
+    }
+
+    fn to_node_at(&self, id: NodeId) -> Result<Node<CF, DIMS>> {
+        let entries = self
+            .nodes
+            .get(id)?
+            .entries
+            .iter()
+            .map(|entry| {
+                Ok(NodeEntry {
+                    feature: entry.feature.clone(),
+                    child: match entry.child {
+                        Some(child) => Some(Arc::new(self.to_node_at(child)?)),
+                        None => None,
+                    },
+                    ids: vec![],
+                    samples: Reservoir::default(),
+                    quantiles: QuantileSketch::default(),
+                    identity: None,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Node::with_entries(entries))
+    }
+}
+
+impl<CF, TC, const DIMS: usize> Extend<FeaturePoint<CF, DIMS>> for ArenaTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    TC: TreeConfig,
+{
+    fn extend<T: IntoIterator<Item = FeaturePoint<CF, DIMS>>>(&mut self, iter: T) {
+        self.try_extend(iter)
+            .expect("in-memory node stores don't fail");
+    }
+}
+
+/// Iterator over the leaf clusters of an [ArenaTree]. Created by [ArenaTree::clusters].
+///
+/// Yields an error (and skips the subtree) for each node the store fails to provide.
+pub struct ArenaClusters<'a, CF: Clone, S, const DIMS: usize> {
+    nodes: &'a S,
+    /// Node to visit before the nodes of the stack (initially the root).
+    pending: Option<(NodeId, usize)>,
+    /// Nodes being visited, with the index of their next entry and their depth.
+    stack: Vec<(Cow<'a, ArenaNode<CF>>, usize, usize)>,
+    next_id: usize,
+}
+
+impl<'a, CF, S, const DIMS: usize> Iterator for ArenaClusters<'a, CF, S, DIMS>
+where
+    CF: CFeature<DIMS> + Clone,
+    S: NodeStore<CF>,
+{
+    type Item = Result<ClusterSummary<DIMS, CF::Scalar>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((id, depth)) = self.pending.take() {
+                match self.nodes.get(id) {
+                    Ok(node) => self.stack.push((node, 0, depth)),
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+            let (node, next, depth) = self.stack.last_mut()?;
+            let depth = *depth;
+            let entry = match node.entries.get(*next) {
+                Some(entry) => entry,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            *next += 1;
+            match entry.child {
+                Some(child) => self.pending = Some((child, depth + 1)),
+                None => {
+                    let id = self.next_id;
+                    self.next_id += 1;
+                    return Some(Ok(ClusterSummary {
+                        id,
+                        center: entry.feature.center(),
+                        radius: entry.feature.radius(),
+                        diameter: entry.feature.diam(),
+                        size: entry.feature.size(),
+                        depth,
+                    }));
+                }
+            }
+        }
+    }
+}
+
+impl<CF, TC, S, const DIMS: usize> ArenaTree<CF, DIMS, TC, S>
+where
+    CF: CFeature<DIMS> + Clone,
+    S: NodeStore<CF>,
+{
+    /// Returns an iterator over summaries of the leaf clusters of this tree, in the same order
+    /// as [Node::clusters].
+    pub fn clusters(&self) -> ArenaClusters<'_, CF, S, DIMS> {
+        ArenaClusters {
+            nodes: &self.nodes,
+            pending: Some((self.root, 0)),
+            stack: vec![],
+            next_id: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cfeature::{betula::CFeature as BetulaFeature, birch::CFeature as BirchFeature},
+        cftree::{BetulaCFTree, BirchCFTree, Metric},
+        point::{Point, Scalar},
+    };
+
+    fn points() -> Vec<Point<2>> {
+        (0..2000u64)
+            .map(|i| {
+                let h = i.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                Point::from_arr([(h % 1000) as f64 / 10.0, ((h >> 20) % 1000) as f64 / 10.0])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_cftree() {
+        for merge_refinement in [false, true] {
+            let config = BasicConfig::builder()
+                .capacity(2, 5)
+                .leaf_capacity(1, 4)
+                .threshold(2.0)
+                .merge_refinement(merge_refinement)
+                .build()
+                .unwrap();
+
+            let tree = BirchCFTree::from_iter(points(), config.clone());
+            let arena = ArenaTree::<BirchFeature<2>, 2>::from_iter(points(), config.clone());
+            assert_eq!(
+                format!("{:?}", tree.root()),
+                format!("{:?}", arena.to_node().unwrap())
+            );
+            assert_eq!(
+                tree.clusters().collect::<Vec<_>>(),
+                arena.clusters().collect::<Result<Vec<_>>>().unwrap()
+            );
+            assert_eq!(tree.root().height(), arena.height().unwrap());
+            assert_eq!(tree.root().leaf_count(), arena.leaf_count().unwrap());
+
+            let tree = BetulaCFTree::from_iter(points(), config.clone());
+            let arena = ArenaTree::<BetulaFeature<2>, 2>::from_iter(points(), config);
+            assert_eq!(
+                format!("{:?}", tree.root()),
+                format!("{:?}", arena.to_node().unwrap())
+            );
+        }
+
+        // including absorption above the leaves
+        let config = BasicConfig::builder()
+            .capacity(2, 5)
+            .leaf_capacity(1, 4)
+            .threshold(0.5)
+            .upper_thresholds(vec![20.0])
+            .build()
+            .unwrap();
+        let tree = BirchCFTree::from_iter(points(), config.clone());
+        let arena = ArenaTree::<BirchFeature<2>, 2>::from_iter(points(), config);
+        assert_eq!(
+            format!("{:?}", tree.root()),
+            format!("{:?}", arena.to_node().unwrap())
+        );
+        assert!(arena
+            .clusters()
+            .all(|c| c.unwrap().diameter.powi(2) <= 20.0));
+    }
+
+    #[test]
+    fn matches_cftree_with_metric_and_missing_values() {
+        // every seventh point is missing a coordinate
+        let partial = points()
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| match i % 7 {
+                3 => Point::from_arr([Scalar::NAN, p[1]]),
+                _ => p,
+            })
+            .collect::<Vec<_>>();
+        for (metric, missing_values) in [
+            (Metric::Mahalanobis, MissingValues::Propagate),
+            (Metric::Euclidean, MissingValues::Impute),
+            (Metric::Mahalanobis, MissingValues::Skip),
+        ] {
+            let config = BasicConfig::builder()
+                .capacity(2, 5)
+                .leaf_capacity(1, 4)
+                .threshold(2.0)
+                .metric(metric)
+                .missing_values(missing_values)
+                .build()
+                .unwrap();
+            let points = match missing_values {
+                MissingValues::Propagate => points(),
+                _ => partial.clone(),
+            };
+            let tree = BetulaCFTree::from_iter(points.clone(), config.clone());
+            let arena = ArenaTree::<BetulaFeature<2>, 2>::from_iter(points, config);
+            assert_eq!(
+                format!("{:?}", tree.root()),
+                format!("{:?}", arena.to_node().unwrap())
+            );
+        }
+    }
+}
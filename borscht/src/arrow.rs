@@ -0,0 +1,204 @@
+/*!
+ * Reading points from [Apache Arrow](https://arrow.apache.org/) record batches and (with the
+ * `parquet` feature) Parquet files. Requires the `arrow` feature.
+ *
+ * Coordinates are read straight from the value buffers of `Float64` columns: converting a batch
+ * shares its buffers rather than copying the columns, and points are only materialized as they
+ * are iterated over.
+ */
+
+use arrow_array::{cast::AsArray, types::Float64Type, Array, RecordBatch};
+use arrow_schema::DataType;
+use thiserror::Error;
+
+use crate::point::{Point, Scalar};
+
+#[derive(Error, Debug)]
+pub enum ArrowError {
+    #[error("arrow error")]
+    Arrow(#[from] arrow_schema::ArrowError),
+    #[cfg(feature = "parquet")]
+    #[error("parquet error")]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[error("unknown column '{0}'")]
+    UnknownColumn(String),
+    #[error("expected {expected} columns, found {found}")]
+    ColumnCount { expected: usize, found: usize },
+    #[error("column '{column}' has type {data_type}, expected Float64")]
+    ColumnType { column: String, data_type: DataType },
+    #[error("column '{0}' contains nulls")]
+    Nulls(String),
+}
+
+type Result<T> = std::result::Result<T, ArrowError>;
+
+/// Converts the rows of `batch` into points, reading coordinates from the `Float64` columns named
+/// `columns` (or the first `DIMS` columns, if `columns` is empty). Columns may not contain nulls.
+pub fn points_from_batch<const DIMS: usize>(
+    batch: &RecordBatch,
+    columns: &[&str],
+) -> Result<impl Iterator<Item = Point<DIMS>>> {
+    let schema = batch.schema();
+    let indices = match columns.len() {
+        0 if batch.num_columns() >= DIMS => (0..DIMS).collect::<Vec<_>>(),
+        0 => {
+            return Err(ArrowError::ColumnCount {
+                expected: DIMS,
+                found: batch.num_columns(),
+            })
+        }
+        len if len != DIMS => {
+            return Err(ArrowError::ColumnCount {
+                expected: DIMS,
+                found: len,
+            })
+        }
+        _ => columns
+            .iter()
+            .map(|&name| {
+                schema
+                    .index_of(name)
+                    .map_err(|_| ArrowError::UnknownColumn(name.to_string()))
+            })
+            .collect::<Result<Vec<_>>>()?,
+    };
+
+    let values = indices
+        .into_iter()
+        .map(|idx| {
+            let name = schema.field(idx).name();
+            let column = batch.column(idx);
+            if column.data_type() != &DataType::Float64 {
+                return Err(ArrowError::ColumnType {
+                    column: name.clone(),
+                    data_type: column.data_type().clone(),
+                });
+            }
+            if column.null_count() > 0 {
+                return Err(ArrowError::Nulls(name.clone()));
+            }
+            // shares the underlying buffer
+            Ok(column.as_primitive::<Float64Type>().values().clone())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((0..batch.num_rows()).map(move |row| {
+        let mut coords: [Scalar; DIMS] = [0.0; DIMS];
+        for (coord, column) in coords.iter_mut().zip(&values) {
+            *coord = column[row];
+        }
+        Point::from_arr(coords)
+    }))
+}
+
+/// Converts a stream of record batches into points, one batch at a time. See
+/// [points_from_batch].
+pub fn points_from_batches<'a, const DIMS: usize, I, E>(
+    batches: I,
+    columns: &'a [&'a str],
+) -> impl Iterator<Item = Result<Point<DIMS>>> + 'a
+where
+    I: IntoIterator<Item = std::result::Result<RecordBatch, E>> + 'a,
+    ArrowError: From<E>,
+{
+    batches.into_iter().flat_map(move |batch| {
+        let points = batch
+            .map_err(ArrowError::from)
+            .and_then(|batch| points_from_batch::<DIMS>(&batch, columns));
+        let (points, err) = match points {
+            Ok(points) => (Some(points.map(Ok)), None),
+            Err(err) => (None, Some(Err(err))),
+        };
+        points.into_iter().flatten().chain(err)
+    })
+}
+
+/// Reads points from the Parquet file `file`, decoding `batch_size` rows at a time. See
+/// [points_from_batch] for how columns are selected. Requires the `parquet` feature.
+#[cfg(feature = "parquet")]
+pub fn points_from_parquet<'a, const DIMS: usize>(
+    file: std::fs::File,
+    columns: &'a [&'a str],
+    batch_size: usize,
+) -> Result<impl Iterator<Item = Result<Point<DIMS>>> + 'a> {
+    let reader = parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(file)?
+        .with_batch_size(batch_size.max(1))
+        .build()?;
+    Ok(points_from_batches(reader, columns))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Float32Array, Float64Array};
+
+    use super::*;
+
+    fn batch() -> RecordBatch {
+        RecordBatch::try_from_iter(vec![
+            (
+                "x",
+                Arc::new(Float64Array::from(vec![1.0, 2.0, 3.0])) as Arc<dyn Array>,
+            ),
+            ("y", Arc::new(Float64Array::from(vec![4.0, 5.0, 6.0]))),
+            ("z", Arc::new(Float32Array::from(vec![7.0, 8.0, 9.0]))),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn from_batch() {
+        let points = points_from_batch::<2>(&batch(), &["y", "x"])
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            points,
+            vec![
+                Point::from_arr([4.0, 1.0]),
+                Point::from_arr([5.0, 2.0]),
+                Point::from_arr([6.0, 3.0])
+            ]
+        );
+        assert_eq!(points_from_batch::<2>(&batch(), &[]).unwrap().count(), 3);
+
+        assert!(matches!(
+            points_from_batch::<2>(&batch(), &["x", "z"]),
+            Err(ArrowError::ColumnType { .. })
+        ));
+        assert!(matches!(
+            points_from_batch::<2>(&batch(), &["x", "w"]),
+            Err(ArrowError::UnknownColumn(_))
+        ));
+
+        let batches = vec![Ok::<_, arrow_schema::ArrowError>(batch()), Ok(batch())];
+        let points = points_from_batches::<2, _, _>(batches, &["x", "y"])
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(points.len(), 6);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn from_parquet() {
+        let path =
+            std::env::temp_dir().join(format!("borscht-arrow-{}.parquet", std::process::id()));
+        let batch = batch();
+        let mut writer = parquet::arrow::ArrowWriter::try_new(
+            std::fs::File::create(&path).unwrap(),
+            batch.schema(),
+            None,
+        )
+        .unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let points = points_from_parquet::<2>(std::fs::File::open(&path).unwrap(), &["x", "y"], 2)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[2], Point::from_arr([3.0, 6.0]));
+    }
+}
@@ -0,0 +1,464 @@
+/*!
+ * Batch insertion of points into an existing tree.
+ *
+ * [CFTree::insert_batch] routes a whole batch of points down the tree at once: at each node, the
+ * batch is sorted by the entry closest to each point (under the metric of the tree, as for
+ * sequential insertion), and each part descends into its entry's child once. Leaf nodes are
+ * split as soon as they fill up, with the rest of their points routed to the closer half, and the
+ * features of ancestors are recomputed once per batch rather than once per point.
+ *
+ * Routing uses the features of the tree as they were before the batch, so the resulting tree can
+ * differ slightly from inserting the points one at a time (which routes each point using the
+ * features updated by all earlier points). Otherwise, points are absorbed as they would be one at
+ * a time, including above the leaves (see [TreeConfig::threshold_at]) and under per-dimension
+ * thresholds (see [TreeConfig::dimension_thresholds]). The tree's metrics and recorder (see
+ * [trace](crate::trace)) account for the points in batch order, with the splits of the whole
+ * batch after its points. Merge refinement is not applied to batches, and constraints (see
+ * [constraints](crate::constraints)) aren't consulted.
+ */
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::fmt::Debug;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{
+        absorbs, CFTree, EntryInsertion, InsertOutcome, Node, NodeEntry, NodeInsertion, TreeConfig,
+    },
+    identity::ClusterIdentity,
+    point::{Float, Scalar},
+    quantiles::QuantileSketch,
+    reservoir::Reservoir,
+    trace::{TraceEvent, TracedEntry},
+};
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    TC: TreeConfig,
+{
+    /// Inserts a batch of points into this tree. Faster than inserting the points one at a time,
+    /// especially for large batches (see the [module documentation](self) for the differences).
+    pub fn insert_batch(&mut self, points: &[FeaturePoint<CF, DIMS>]) {
+        if points.is_empty() {
+            return;
+        }
+        debug_event!(points = points.len(), "inserting batch");
+        let first_id = self.metrics().inserted;
+        let batch = points
+            .iter()
+            .zip(first_id..)
+            .enumerate()
+            .map(|(position, (p, id))| {
+                let p = self.root().complete(p.clone(), self.config());
+                Pending {
+                    position,
+                    absorbing: None,
+                    entry: NodeEntry::with_point(p, id, self.config()),
+                }
+            })
+            .collect::<Vec<_>>();
+        // only summarize the points and collect splits if they're recorded
+        let traced = self.recorder().map(|_| {
+            batch
+                .iter()
+                .map(|pending| TracedEntry::of(&pending.entry.feature))
+                .collect::<Vec<_>>()
+        });
+        let mut report = BatchReport {
+            outcomes: vec![InsertOutcome::NewEntry; batch.len()],
+            split_depths: vec![],
+            splits: traced.as_ref().map(|_| vec![]),
+            next_cluster_id: self.new_cluster_id(),
+        };
+        let mut root = core::mem::replace(self.root_mut(), Node::with_entries(vec![]));
+        let mut split = root.insert_batch(batch, self.config(), 0, &mut report);
+        // grow new roots until the top level fits into a single node
+        while !split.is_empty() {
+            let mut entries = vec![entry_for(root)];
+            entries.extend(split.into_iter().map(entry_for));
+            root = Node::with_entries(entries);
+            split = root.split_all(self.config(), 0, &mut report);
+        }
+        *self.root_mut() = root;
+        let new_entries = report
+            .outcomes
+            .iter()
+            .filter(|&&outcome| outcome != InsertOutcome::Absorbed)
+            .count();
+        self.skip_cluster_ids(new_entries as u64);
+
+        if let (Some(recorder), Some(traced), Some(splits)) =
+            (self.recorder_mut().as_mut(), traced, report.splits)
+        {
+            for ((entry, &outcome), index) in
+                traced.into_iter().zip(&report.outcomes).zip(first_id..)
+            {
+                recorder.push(match outcome {
+                    InsertOutcome::Absorbed => TraceEvent::Absorbed { index, entry },
+                    InsertOutcome::NewEntry | InsertOutcome::Split => {
+                        TraceEvent::NewEntry { index, entry }
+                    }
+                });
+            }
+            for split in splits {
+                recorder.push(split);
+            }
+        }
+        let mut split_depths = report.split_depths.as_slice();
+        for outcome in report.outcomes {
+            self.metrics_mut().record(outcome, split_depths);
+            split_depths = &[];
+        }
+    }
+}
+
+/// A point of a batch on its way down the tree.
+struct Pending<CF, const DIMS: usize> {
+    /// Position of the point in the batch
+    position: usize,
+    /// Largest threshold of the entries above the leaves which absorbed the point, if any (see
+    /// [TreeConfig::threshold_at])
+    absorbing: Option<Scalar>,
+    entry: NodeEntry<CF, DIMS>,
+}
+
+/// What happened during a batch insertion.
+#[derive(Debug)]
+struct BatchReport<const DIMS: usize> {
+    /// Outcome of the insertion of each point, by position in the batch
+    outcomes: Vec<InsertOutcome>,
+    /// Depth of each split node (roots, including new roots grown during the batch, have depth 0)
+    split_depths: Vec<usize>,
+    /// The splits themselves, if they're recorded
+    splits: Option<Vec<TraceEvent<DIMS>>>,
+    /// Stable id of the next new leaf entry, if the tree assigns them
+    next_cluster_id: Option<u64>,
+}
+
+impl<const DIMS: usize> BatchReport<DIMS> {
+    fn split<CF: CFeature<DIMS>>(
+        &mut self,
+        depth: usize,
+        left: &Node<CF, DIMS>,
+        right: &Node<CF, DIMS>,
+    ) {
+        self.split_depths.push(depth);
+        if let Some(splits) = self.splits.as_mut() {
+            splits.push(TraceEvent::split(depth, left, right));
+        }
+    }
+}
+
+impl<CF, const DIMS: usize> Node<CF, DIMS>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+{
+    /// Inserts the points of `batch` into the subtree rooted at this node (at depth `depth`). If
+    /// the node had to be split, returns the nodes split off from it, which belong next to it in
+    /// its parent.
+    fn insert_batch<TC: TreeConfig>(
+        &mut self,
+        mut batch: Vec<Pending<CF, DIMS>>,
+        config: &TC,
+        depth: usize,
+        report: &mut BatchReport<DIMS>,
+    ) -> Vec<Self> {
+        let Some(routes) = self.route(&mut batch, config) else {
+            return self.fill_leaf(batch, config, depth, report);
+        };
+        // sort the batch by the entry each of its points descends into, so each entry is visited
+        // once
+        let mut routed = routes.into_iter().zip(batch).collect::<Vec<_>>();
+        routed.sort_by_key(|&(idx, _)| idx);
+        let mut routed = routed.into_iter().peekable();
+        let mut split = vec![];
+        let mut group = vec![];
+        for (idx, entry) in self.entries.iter_mut().enumerate() {
+            // points are only routed to entries with children
+            let Some(child) = entry.child.as_mut() else {
+                continue;
+            };
+            while let Some((_, pending)) = routed.next_if(|&(routed_idx, _)| routed_idx == idx) {
+                group.push(pending);
+            }
+            if group.is_empty() {
+                continue;
+            }
+            let child = Arc::make_mut(child);
+            split.extend(child.insert_batch(
+                core::mem::take(&mut group),
+                config,
+                depth + 1,
+                report,
+            ));
+            entry.feature = child.compute_feature();
+        }
+        self.entries.extend(split.into_iter().map(entry_for));
+        self.split_all(config, depth, report)
+    }
+
+    /// Index of the entry of this node each point of `batch` descends into: the closest one under
+    /// the metric of `config`, as [Node::closest_to_feature] would find. Raises the absorbing
+    /// threshold of the points the entries absorb (see [TreeConfig::threshold_at]). Returns
+    /// `None` if the points are to be inserted into this node instead, i.e. if it's a leaf node.
+    fn route<TC: TreeConfig>(
+        &self,
+        batch: &mut [Pending<CF, DIMS>],
+        config: &TC,
+    ) -> Option<Vec<usize>> {
+        let threshold = config.threshold_at(self.height() - 1);
+        batch
+            .iter_mut()
+            .map(|pending| {
+                let idx = self.closest_to_feature(&pending.entry.feature, config)?;
+                let entry = &self.entries[idx];
+                entry.child.as_ref()?;
+                if absorbs(
+                    &entry.feature,
+                    &pending.entry.feature,
+                    threshold,
+                    pending.absorbing,
+                ) {
+                    pending.absorbing = Some(threshold);
+                }
+                Some(idx)
+            })
+            .collect()
+    }
+
+    /// Absorbs (or adds) the points of `batch` into this leaf node. Whenever a leaf fills up, it is
+    /// split, and the rest of the batch is routed to the closer half, so that leaves stay small
+    /// while the batch is inserted. Returns the leaves split off from this one.
+    fn fill_leaf<TC: TreeConfig>(
+        &mut self,
+        batch: Vec<Pending<CF, DIMS>>,
+        config: &TC,
+        depth: usize,
+        report: &mut BatchReport<DIMS>,
+    ) -> Vec<Self> {
+        let mut split = vec![];
+        let mut pending = vec![];
+        let mut leaf = core::mem::replace(self, Node::with_entries(vec![]));
+        let mut batch = batch.into_iter();
+        loop {
+            for Pending {
+                position,
+                absorbing,
+                entry: new,
+            } in batch.by_ref()
+            {
+                let mut new = match leaf.closest_to_feature(&new.feature, config) {
+                    Some(idx) => {
+                        let entry = &mut leaf.entries[idx];
+                        let insertion = match absorbing.is_some_and(|threshold| {
+                            (entry.feature.clone() + &new.feature).diam2()
+                                <= CF::Scalar::from_scalar(threshold)
+                        }) {
+                            true => {
+                                entry.absorb(new, config);
+                                EntryInsertion::Success
+                            }
+                            false => entry.insert(new, config),
+                        };
+                        match insertion {
+                            EntryInsertion::Success => {
+                                report.outcomes[position] = InsertOutcome::Absorbed;
+                                continue;
+                            }
+                            EntryInsertion::Failure(new) => new,
+                        }
+                    }
+                    None => new,
+                };
+                if let Some(id) = report.next_cluster_id.as_mut() {
+                    new.identity = Some(ClusterIdentity::new(*id));
+                    *id += 1;
+                }
+                leaf.entries.push(new);
+                if leaf.entries.len() >= config.leaf_capacity().max {
+                    break;
+                }
+            }
+            match leaf.check_split(config) {
+                NodeInsertion::Single(done) => split.push(done),
+                NodeInsertion::Split(left, right) => {
+                    report.split(depth, &left, &right);
+                    let halves = Node::with_entries(vec![
+                        NodeEntry::with_feature(left.compute_feature()),
+                        NodeEntry::with_feature(right.compute_feature()),
+                    ]);
+                    let (left_batch, right_batch): (Vec<_>, Vec<_>) = batch.partition(|new| {
+                        halves.closest_to_feature(&new.entry.feature, config) == Some(0)
+                    });
+                    pending.push((right, right_batch));
+                    pending.push((left, left_batch));
+                }
+            }
+            match pending.pop() {
+                Some((next, next_batch)) => {
+                    leaf = next;
+                    batch = next_batch.into_iter();
+                }
+                None => break,
+            }
+        }
+        *self = split.swap_remove(0);
+        split
+    }
+
+    /// Splits this node (at depth `depth`), and the nodes resulting from the split, until none
+    /// are over capacity, returning the nodes split off from this one.
+    fn split_all<TC: TreeConfig>(
+        &mut self,
+        config: &TC,
+        depth: usize,
+        report: &mut BatchReport<DIMS>,
+    ) -> Vec<Self> {
+        let mut pending = vec![core::mem::replace(self, Node::with_entries(vec![]))];
+        let mut split = vec![];
+        while let Some(node) = pending.pop() {
+            match node.check_split(config) {
+                NodeInsertion::Single(node) => split.push(node),
+                NodeInsertion::Split(left, right) => {
+                    report.split(depth, &left, &right);
+                    pending.push(right);
+                    pending.push(left);
+                }
+            }
+        }
+        *self = split.swap_remove(0);
+        split
+    }
+}
+
+/// Entry summarizing (and pointing to) `node`.
+fn entry_for<CF, const DIMS: usize>(node: Node<CF, DIMS>) -> NodeEntry<CF, DIMS>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+{
+    NodeEntry {
+        feature: node.compute_feature(),
+        child: Some(Arc::new(node)),
+        ids: vec![],
+        samples: Reservoir::default(),
+        quantiles: QuantileSketch::default(),
+        identity: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cftree::{BasicConfig, BirchCFTree},
+        point::Point,
+        trace::TraceRecorder,
+    };
+
+    #[test]
+    fn insert_batch() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        // 40 well-separated groups
+        let points = (0..2000)
+            .map(|i| {
+                let group = ((i * 17) % 40) as f64;
+                Point::from_arr([group * 10.0, ((i / 40) % 5) as f64 * 0.05])
+            })
+            .collect::<Vec<_>>();
+
+        let mut tree = BirchCFTree::<2>::new(config);
+        for batch in points.chunks(500) {
+            tree.insert_batch(batch);
+        }
+
+        let metrics = tree.metrics();
+        assert_eq!(metrics.inserted, 2000);
+        assert_eq!(metrics.new_entries as usize, tree.clusters().count());
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.iter().map(|c| c.size).sum::<f64>(), 2000.0);
+        // routing by the features from before each batch can spread a group over more than one
+        // cluster (as can sequential insertion), but never mixes groups
+        assert!((40..=45).contains(&clusters.len()));
+        assert!(clusters.iter().all(|c| c.diameter <= 0.5));
+        let mut sizes = [0.0; 40];
+        for cluster in &clusters {
+            sizes[(cluster.center[0] / 10.0).round() as usize] += cluster.size;
+        }
+        assert!(sizes.iter().all(|&size| size == 50.0));
+        // the tree is balanced, and no node is over capacity
+        fn check<CF, const DIMS: usize>(
+            node: &Node<CF, DIMS>,
+            depth: usize,
+            leaves: &mut Vec<usize>,
+        ) {
+            assert!(node.entries.len() < 4);
+            for entry in &node.entries {
+                match entry.child {
+                    Some(ref child) => check(child, depth + 1, leaves),
+                    None => leaves.push(depth),
+                }
+            }
+        }
+        let mut leaves = vec![];
+        check(tree.root(), 0, &mut leaves);
+        assert!(leaves.iter().all(|&depth| depth == leaves[0]));
+        // growing the tree by a level takes at least one split at depth 0
+        assert!(metrics.splits.len() <= leaves[0] + 1);
+        assert!(metrics.splits[0] as usize >= leaves[0]);
+    }
+
+    #[test]
+    fn batch_order_and_thresholds() {
+        let builder = BasicConfig::builder().capacity(2, 4).threshold(0.5);
+
+        // outcomes are accounted for in batch order
+        let points = [[0.0, 0.0], [10.0, 0.0], [0.1, 0.0], [20.0, 0.0]].map(Point::from_arr);
+        let mut tree = BirchCFTree::<2>::new(builder.clone().build().unwrap())
+            .with_recorder(TraceRecorder::new());
+        tree.insert_batch(&points);
+        let outcomes = tree
+            .take_recorder()
+            .unwrap()
+            .into_events()
+            .into_iter()
+            .map(|event| match event {
+                TraceEvent::Absorbed { index, .. } => (index, InsertOutcome::Absorbed),
+                TraceEvent::NewEntry { index, .. } => (index, InsertOutcome::NewEntry),
+                _ => unreachable!("no splits"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outcomes,
+            vec![
+                (0, InsertOutcome::NewEntry),
+                (1, InsertOutcome::NewEntry),
+                (2, InsertOutcome::Absorbed),
+                (3, InsertOutcome::NewEntry),
+            ]
+        );
+
+        // entries above the leaves absorb points, within their threshold
+        let points = (0..2000u64)
+            .map(|i| {
+                let h = i.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                Point::from_arr([(h % 1000) as f64 / 10.0, ((h >> 20) % 1000) as f64 / 10.0])
+            })
+            .collect::<Vec<_>>();
+        let build = |config: BasicConfig| {
+            let mut tree = BirchCFTree::<2>::from_iter(points[..1000].to_vec(), config);
+            tree.insert_batch(&points[1000..]);
+            tree
+        };
+        let tight = build(builder.clone().build().unwrap());
+        let loose = build(builder.upper_thresholds(vec![20.0]).build().unwrap());
+        assert!(loose.clusters().count() < tight.clusters().count());
+        assert!(loose.clusters().any(|c| c.diameter.powi(2) > 0.5));
+        assert!(loose.clusters().all(|c| c.diameter.powi(2) <= 20.0));
+        assert_eq!(loose.root().weight(), 2000.0);
+    }
+}
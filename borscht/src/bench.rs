@@ -0,0 +1,235 @@
+/*!
+ * Side-by-side comparison of cluster feature types on the same input.
+ *
+ * [compare_features] builds a tree of BIRCH features and a tree of BETULA features from the same
+ * points and configuration, and reports for each (as a [FeatureReport]):
+ * - the time taken to build the tree, and the size of the tree;
+ * - numerical-stability indicators: how many leaf clusters of several points have no variance
+ *   left (which cancellation produces), and the relative error of the variance of all the points as summarized by
+ *   the tree, against its exact (two-pass) value;
+ * - clustering quality: the sum of squared distances of the points from the centers of their leaf
+ *   clusters, both measured directly and as predicted by the features of the leaf clusters.
+ *
+ * BIRCH features store sums of squares, which lose precision to cancellation when points lie far
+ * from the origin relative to their spread; BETULA features don't, at some extra cost. The
+ * `features` benchmark of the crate measures the build times more precisely, with criterion.
+ */
+
+use std::{
+    fmt::{self, Display},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cfeature::{
+        betula::CFeature as BetulaFeature, birch::CFeature as BirchFeature, CFeature, FeaturePoint,
+    },
+    cftree::{CFTree, TreeConfig},
+    point::{Float, Point, Scalar},
+    summary::collect_leaves,
+};
+
+/// Build time, size, numerical stability and clustering quality of a tree of one feature type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureReport {
+    /// Kind of cluster feature (see [CFeature::KIND]).
+    pub kind: &'static str,
+    pub build_time: Duration,
+    pub height: usize,
+    pub leaves: usize,
+    /// Estimated memory used by the tree (see [CFTree::estimated_bytes]).
+    pub bytes: usize,
+    /// Number of leaf clusters of more than one point whose squared radius isn't positive. Points
+    /// may coincide, but far more often, the variance was lost to cancellation (and came out
+    /// negative, clamped to zero).
+    pub degenerate_variances: usize,
+    /// Relative error of the total variance of the points, as summarized by the tree.
+    pub variance_error: Scalar,
+    /// Sum of squared distances of the points from the centers of their leaf clusters, i.e. the
+    /// leaf clusters their insertion would reach (see
+    /// [Node::nearest_leaf](crate::cftree::Node::nearest_leaf)).
+    pub inertia: Scalar,
+    /// Sum of squared distances of the points from the centers of their leaf clusters, as
+    /// predicted by the features of the leaf clusters (size times squared radius). Differs from
+    /// the [inertia](FeatureReport::inertia) where points no longer reach the leaf clusters which
+    /// absorbed them, or where features lose precision.
+    pub predicted_inertia: Scalar,
+}
+
+/// Builds a tree with features of type `CF` from `points` with configuration `config`, and
+/// reports on it.
+pub fn feature_report<CF, TC, const DIMS: usize>(
+    points: &[FeaturePoint<CF, DIMS>],
+    config: TC,
+) -> FeatureReport
+where
+    CF: CFeature<DIMS> + core::fmt::Debug + Clone,
+    TC: TreeConfig,
+{
+    let start = Instant::now();
+    let tree = CFTree::<CF, DIMS, TC>::from_iter(points.iter().cloned(), config);
+    let build_time = start.elapsed();
+
+    let mut leaves = vec![];
+    collect_leaves(tree.root(), &mut leaves);
+    let root = tree
+        .root()
+        .entries
+        .iter()
+        .fold(CF::zero(), |root, entry| root + &entry.feature);
+    let exact = total_variance(points);
+    let variance_error = match exact > 0.0 {
+        true => (root.radius2().to_scalar() - exact).abs() / exact,
+        false => root.radius2().to_scalar().abs(),
+    };
+    let inertia = points
+        .iter()
+        .filter_map(|p| {
+            let leaf = tree.root().nearest_leaf(p, tree.config())?;
+            Some((leaf.center() - p).norm2().to_scalar())
+        })
+        .sum();
+
+    FeatureReport {
+        kind: CF::KIND,
+        build_time,
+        height: tree.root().height(),
+        leaves: leaves.len(),
+        bytes: tree.estimated_bytes(),
+        degenerate_variances: leaves
+            .iter()
+            .filter(|leaf| leaf.size().to_scalar() > 1.0 && leaf.radius2().to_scalar() <= 0.0)
+            .count(),
+        variance_error,
+        inertia,
+        predicted_inertia: leaves
+            .iter()
+            .map(|leaf| (leaf.size() * leaf.radius2()).to_scalar())
+            .sum(),
+    }
+}
+
+/// Mean squared distance of `points` from their mean, computed in two passes.
+fn total_variance<T: Float, const DIMS: usize>(points: &[Point<DIMS, T>]) -> Scalar {
+    if points.is_empty() {
+        return 0.0;
+    }
+    let n = points.len() as Scalar;
+    let mean = points
+        .iter()
+        .fold(Point::<DIMS>::default(), |sum, p| sum + p.cast())
+        / n;
+    points
+        .iter()
+        .map(|p| (p.cast() - &mean).norm2())
+        .sum::<Scalar>()
+        / n
+}
+
+/// Reports on trees of BIRCH and BETULA features built from the same points. Displays as a
+/// side-by-side table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureComparison {
+    pub birch: FeatureReport,
+    pub betula: FeatureReport,
+}
+
+/// Builds trees of BIRCH and of BETULA features from `points` with configuration `config`, and
+/// reports on both.
+pub fn compare_features<TC, const DIMS: usize>(
+    points: &[Point<DIMS>],
+    config: &TC,
+) -> FeatureComparison
+where
+    TC: TreeConfig + Clone,
+{
+    FeatureComparison {
+        birch: feature_report::<BirchFeature<DIMS>, _, DIMS>(points, config.clone()),
+        betula: feature_report::<BetulaFeature<DIMS>, _, DIMS>(points, config.clone()),
+    }
+}
+
+impl Display for FeatureComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (birch, betula) = (&self.birch, &self.betula);
+        writeln!(f, "{:<20} {:>14} {:>14}", "", birch.kind, betula.kind)?;
+        let rows: [(&str, String, String); 8] = [
+            (
+                "build time",
+                format!("{:.2?}", birch.build_time),
+                format!("{:.2?}", betula.build_time),
+            ),
+            (
+                "height",
+                birch.height.to_string(),
+                betula.height.to_string(),
+            ),
+            (
+                "leaves",
+                birch.leaves.to_string(),
+                betula.leaves.to_string(),
+            ),
+            ("bytes", birch.bytes.to_string(), betula.bytes.to_string()),
+            (
+                "degenerate variances",
+                birch.degenerate_variances.to_string(),
+                betula.degenerate_variances.to_string(),
+            ),
+            (
+                "variance error",
+                format!("{:.3e}", birch.variance_error),
+                format!("{:.3e}", betula.variance_error),
+            ),
+            (
+                "inertia",
+                format!("{:.6e}", birch.inertia),
+                format!("{:.6e}", betula.inertia),
+            ),
+            (
+                "predicted inertia",
+                format!("{:.6e}", birch.predicted_inertia),
+                format!("{:.6e}", betula.predicted_inertia),
+            ),
+        ];
+        for (name, birch, betula) in rows {
+            writeln!(f, "{:<20} {:>14} {:>14}", name, birch, betula)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cftree::BasicConfig;
+
+    #[test]
+    fn compare_features() {
+        // points far from the origin relative to their spread
+        let points = (0..2000u64)
+            .map(|i| {
+                let h = i.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                Point::from_arr([
+                    1e8 + (h % 1000) as f64 / 100.0,
+                    -1e8 + ((h >> 20) % 1000) as f64 / 100.0,
+                ])
+            })
+            .collect::<Vec<_>>();
+        let config = BasicConfig::builder()
+            .capacity(2, 8)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+
+        let comparison = super::compare_features(&points, &config);
+        let (birch, betula) = (&comparison.birch, &comparison.betula);
+        assert_eq!((birch.kind, betula.kind), ("birch", "betula"));
+        assert!(betula.variance_error < 1e-6);
+        assert!(birch.variance_error > 100.0 * betula.variance_error);
+        assert!(birch.degenerate_variances > 0);
+        assert_eq!(betula.degenerate_variances, 0);
+        let table = comparison.to_string();
+        assert_eq!(table.lines().count(), 9);
+        assert!(table.contains("variance error"));
+    }
+}
@@ -0,0 +1,372 @@
+/*!
+ * A [Birch] estimator, bundling the phases of the BIRCH algorithm behind a single
+ * [scikit-learn](https://scikit-learn.org/stable/modules/generated/sklearn.cluster.Birch.html)-like
+ * lifecycle.
+ *
+ * A [Birch] estimator owns a tree, which it [fits](Birch::fit) (or
+ * [incrementally fits](Birch::partial_fit)) to points, and groups the leaf clusters (*subclusters*)
+ * of the tree into final clusters with a [GlobalClustering] after every fit. Like the original
+ * algorithm, it can bound the size of the tree: whenever the tree has more leaf clusters than
+ * allowed, it is rebuilt with a larger threshold by reinserting its leaf clusters. Light leaf
+ * clusters can be set aside in an outlier buffer during rebuilds (see [Birch::outlier_buffer]),
+ * and are reabsorbed by the tree once its threshold has grown enough for them to fit in.
+ */
+
+use alloc::{vec, vec::Vec};
+use core::fmt::Debug;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{BasicConfig, CFTree, InsertOutcome, TreeMetrics},
+    point::{Float, Scalar},
+    summary::collect_leaf_entries,
+    trace::TraceRecorder,
+};
+
+/// How the leaf clusters of the tree are grouped into the final clusters of a [Birch] estimator.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum GlobalClustering<T = Scalar> {
+    /// Every leaf cluster is a final cluster of its own.
+    #[default]
+    None,
+    /// Leaf clusters are grouped into density-based clusters (see [crate::offline]); leaf clusters
+    /// in no group are noise.
+    Density { eps: T, min_weight: T },
+}
+
+/// A BIRCH clustering estimator (see the [module documentation](self)).
+#[derive(Debug)]
+pub struct Birch<CF: CFeature<DIMS>, const DIMS: usize> {
+    tree: CFTree<CF, DIMS, BasicConfig>,
+    /// Threshold the tree started with, before any rebuilds
+    initial_threshold: Scalar,
+    max_leaves: Option<usize>,
+    global: GlobalClustering<CF::Scalar>,
+    /// Final cluster of each leaf cluster
+    labels: Vec<Option<usize>>,
+    /// Leaf clusters counted since the tree was last checked against `max_leaves`
+    leaves: usize,
+    /// Weight under which leaf clusters are set aside as outliers during rebuilds, if any
+    outlier_weight: Option<CF::Scalar>,
+    /// Leaf clusters set aside as outliers, and not reabsorbed since
+    outliers: Vec<CF>,
+    /// Number of outliers reabsorbed since the estimator was last fitted from scratch
+    reclaimed: usize,
+}
+
+impl<CF, const DIMS: usize> Birch<CF, DIMS>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+{
+    /// Creates a new unfitted estimator, whose tree has configuration `config`. The tree is
+    /// unbounded, and every leaf cluster is a final cluster, unless configured otherwise with
+    /// [Birch::max_leaves] and [Birch::global_clustering].
+    pub fn new(config: BasicConfig) -> Birch<CF, DIMS> {
+        Birch {
+            initial_threshold: config.threshold,
+            tree: CFTree::new(config),
+            max_leaves: None,
+            global: GlobalClustering::None,
+            labels: vec![],
+            leaves: 0,
+            outlier_weight: None,
+            outliers: vec![],
+            reclaimed: 0,
+        }
+    }
+
+    /// Bounds the number of leaf clusters of the tree to `max_leaves` (at least 1), by rebuilding
+    /// the tree with a larger threshold whenever it has more.
+    pub fn max_leaves(mut self, max_leaves: usize) -> Self {
+        self.max_leaves = Some(max_leaves.max(1));
+        self
+    }
+
+    /// Sets leaf clusters lighter than `min_weight` aside in an outlier buffer whenever the tree is
+    /// rebuilt, as in the original BIRCH algorithm, so that sparse noise doesn't take up room in
+    /// the bounded tree. After every rebuild, buffered outliers which fit in a leaf cluster of
+    /// the tree under its larger threshold are reabsorbed into it (see [Birch::outliers]).
+    pub fn outlier_buffer(mut self, min_weight: CF::Scalar) -> Self {
+        self.outlier_weight = Some(min_weight);
+        self
+    }
+
+    /// Sets how leaf clusters are grouped into final clusters.
+    pub fn global_clustering(mut self, global: GlobalClustering<CF::Scalar>) -> Self {
+        self.global = global;
+        self
+    }
+
+    /// Attaches `recorder` to the tree, to log its structural changes, including rebuilds (see
+    /// [trace](crate::trace)). The recorder is kept when the estimator is fitted from scratch.
+    pub fn with_recorder(mut self, recorder: TraceRecorder<DIMS>) -> Self {
+        *self.tree.recorder_mut() = Some(recorder);
+        self
+    }
+
+    /// Detaches the recorder of the tree, if any.
+    pub fn take_recorder(&mut self) -> Option<TraceRecorder<DIMS>> {
+        self.tree.take_recorder()
+    }
+
+    /// The underlying tree.
+    pub fn tree(&self) -> &CFTree<CF, DIMS, BasicConfig> {
+        &self.tree
+    }
+
+    /// Current threshold of the tree, which grows whenever the tree is rebuilt.
+    pub fn threshold(&self) -> Scalar {
+        self.tree.config().threshold
+    }
+
+    /// Leaf clusters currently set aside in the outlier buffer (see [Birch::outlier_buffer]),
+    /// which aren't part of the tree.
+    pub fn outliers(&self) -> &[CF] {
+        &self.outliers
+    }
+
+    /// Number of buffered outliers reabsorbed into the tree after rebuilds since the estimator
+    /// was last fitted from scratch.
+    pub fn reclaimed_outliers(&self) -> usize {
+        self.reclaimed
+    }
+
+    /// Fits this estimator to `points` from scratch, discarding anything fitted before.
+    pub fn fit<I: IntoIterator<Item = FeaturePoint<CF, DIMS>>>(&mut self, points: I) {
+        let mut config = self.tree.config().clone();
+        config.threshold = self.initial_threshold;
+        let recorder = self.tree.take_recorder();
+        self.tree = CFTree::new(config);
+        *self.tree.recorder_mut() = recorder;
+        self.leaves = 0;
+        self.outliers.clear();
+        self.reclaimed = 0;
+        self.partial_fit(points);
+    }
+
+    /// Fits this estimator to `points` on top of the points it was fitted to before, then
+    /// regroups the leaf clusters into final clusters.
+    pub fn partial_fit<I: IntoIterator<Item = FeaturePoint<CF, DIMS>>>(&mut self, points: I) {
+        for p in points {
+            if self.tree.insert(p) == InsertOutcome::NewEntry {
+                self.leaves += 1;
+            }
+            if let Some(max_leaves) = self.max_leaves {
+                // recount only once the (over-)estimate exceeds the bound, since leaf clusters can
+                // also be merged away
+                if self.leaves > max_leaves {
+                    self.leaves = self.tree.root().leaf_count();
+                    while self.leaves > max_leaves && self.rebuild() {
+                        self.leaves = self.tree.root().leaf_count();
+                    }
+                }
+            }
+        }
+        self.labels = match self.global {
+            GlobalClustering::None => (0..self.tree.clusters().count()).map(Some).collect(),
+            GlobalClustering::Density { eps, min_weight } => {
+                self.tree.offline_cluster(eps, min_weight)
+            }
+        };
+    }
+
+    /// Rebuilds the tree with a larger threshold: twice the current threshold, or, if larger, the
+    /// smallest threshold at which the two closest leaf clusters could merge (if they were single
+    /// points). Returns false if the threshold couldn't grow. Light leaf clusters are then set
+    /// aside as outliers, and buffered outliers reabsorbed, if the estimator has an outlier
+    /// buffer.
+    fn rebuild(&mut self) -> bool {
+        let mut entries = vec![];
+        collect_leaf_entries(self.tree.root(), &mut entries);
+        let centers = entries
+            .iter()
+            .map(|entry| entry.feature.center())
+            .collect::<Vec<_>>();
+        let closest = (0..centers.len())
+            .flat_map(|i| (i + 1..centers.len()).map(move |j| (i, j)))
+            .map(|(i, j)| (&centers[i] - &centers[j]).norm2().to_scalar())
+            .fold(Scalar::INFINITY, Scalar::min);
+        let mut config = self.tree.config().clone();
+        let threshold = config.threshold;
+        config.threshold = (threshold * 2.0).max(closest);
+        if !(config.threshold > threshold && config.threshold.is_finite()) {
+            return false;
+        }
+        debug_event!(
+            leaves = entries.len(),
+            threshold = config.threshold,
+            "too many leaf clusters, rebuilding tree"
+        );
+
+        let mut tree = CFTree::new(config);
+        for entry in entries {
+            tree.insert_entry(entry.clone());
+        }
+        // the reinsertions aren't counted as insertions
+        *tree.metrics_mut() = TreeMetrics {
+            rebuilds: self.tree.metrics().rebuilds + 1,
+            ..self.tree.metrics().clone()
+        };
+        tree.trace_rebuild(&mut self.tree);
+        self.tree = tree;
+        if let Some(min_weight) = self.outlier_weight {
+            let pruned = self.tree.prune_min_size(min_weight);
+            self.outliers.extend(pruned);
+            self.reabsorb_outliers();
+        }
+        true
+    }
+
+    /// Reabsorbs the buffered outliers which fit in a leaf cluster of the tree, keeping the others
+    /// buffered.
+    fn reabsorb_outliers(&mut self) {
+        let metrics = self.tree.metrics().clone();
+        let outliers = core::mem::take(&mut self.outliers);
+        let before = outliers.len();
+        for outlier in outliers {
+            if let Err(outlier) = self.tree.try_absorb_feature(outlier) {
+                self.outliers.push(outlier);
+            }
+        }
+        // the reabsorptions aren't counted as insertions
+        *self.tree.metrics_mut() = metrics;
+        let reclaimed = before - self.outliers.len();
+        debug_event!(
+            reclaimed,
+            outliers = self.outliers.len(),
+            "reabsorbed buffered outliers"
+        );
+        self.reclaimed += reclaimed;
+    }
+
+    /// Centers of the leaf clusters of the tree, indexed by
+    /// [ClusterSummary::id](crate::summary::ClusterSummary::id).
+    pub fn subcluster_centers(&self) -> Vec<FeaturePoint<CF, DIMS>> {
+        self.tree.clusters().map(|cluster| cluster.center).collect()
+    }
+
+    /// Final cluster of each leaf cluster of the tree (indexed by
+    /// [ClusterSummary::id](crate::summary::ClusterSummary::id)), or `None` for leaf clusters
+    /// considered noise by the global clustering. Empty until the estimator is fitted. Use
+    /// [Birch::predict] for the final clusters of points.
+    pub fn labels(&self) -> &[Option<usize>] {
+        &self.labels
+    }
+
+    /// Final cluster of each point: that of the leaf cluster closest to it (see
+    /// [CFTree::labels]), or `None` if that leaf cluster is noise or the estimator is unfitted.
+    pub fn predict<'a, I>(&self, points: I) -> Vec<Option<usize>>
+    where
+        I: IntoIterator<Item = &'a FeaturePoint<CF, DIMS>>,
+        FeaturePoint<CF, DIMS>: 'a,
+    {
+        self.tree
+            .labels(points)
+            .into_iter()
+            .map(|leaf| leaf.and_then(|leaf| self.labels.get(leaf).copied().flatten()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cfeature::birch::CFeature as BirchCF, point::Point};
+
+    #[test]
+    fn birch() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.0)
+            .build()
+            .unwrap();
+        // two groups of 10 tight blobs each
+        let points = (0..400)
+            .map(|i| {
+                let (blob, group) = ((i % 10) as f64, (i / 10 % 2) as f64);
+                let jitter = (i / 20) as f64 * 0.001;
+                Point::from_arr([blob + jitter, group * 100.0])
+            })
+            .collect::<Vec<_>>();
+
+        let mut birch = Birch::<BirchCF<2>, 2>::new(config.clone())
+            .max_leaves(30)
+            .global_clustering(GlobalClustering::Density {
+                eps: 2.0,
+                min_weight: 10.0,
+            });
+        assert!(birch.predict(&points).iter().all(Option::is_none));
+        birch.fit(points[..200].iter().cloned());
+        birch.partial_fit(points[200..].iter().cloned());
+
+        let centers = birch.subcluster_centers();
+        assert!(centers.len() <= 30);
+        assert!(birch.threshold() > 0.0);
+        assert!(birch.tree().metrics().rebuilds > 0);
+        assert_eq!(birch.tree().metrics().inserted, 400);
+        assert_eq!(birch.labels().len(), centers.len());
+        let labels = birch.predict(&points);
+        for (p, label) in points.iter().zip(&labels) {
+            let same_group = points
+                .iter()
+                .zip(&labels)
+                .filter(|(q, _)| q[1] == p[1])
+                .all(|(_, other)| other == label);
+            assert!(label.is_some() && same_group);
+        }
+        assert_ne!(labels[0], labels[10]);
+
+        // refitting starts over from the initial threshold
+        birch.fit(points[..20].iter().cloned());
+        assert_eq!(birch.tree().metrics().inserted, 20);
+        assert_eq!(birch.subcluster_centers().len(), 20);
+        assert_eq!(birch.threshold(), 0.0);
+
+        let mut unbounded = Birch::<BirchCF<2>, 2>::new(config);
+        unbounded.fit(points.iter().cloned());
+        assert_eq!(unbounded.subcluster_centers().len(), 400);
+        let label = unbounded.predict([&points[3]])[0].unwrap();
+        assert_eq!(unbounded.subcluster_centers()[label], points[3]);
+    }
+
+    #[test]
+    fn outlier_buffer() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.0)
+            .build()
+            .unwrap();
+        // four tight blobs, with a straggler next to the first and a point far from everything
+        let mut points = (0..200)
+            .map(|i| {
+                let blob = (i % 4) as f64 * 10.0;
+                Point::from_arr([blob + (i / 4 % 5) as f64 * 0.01, 0.0])
+            })
+            .collect::<Vec<_>>();
+        points.insert(20, Point::from_arr([0.5, 0.0]));
+        points.insert(40, Point::from_arr([100.0, 100.0]));
+
+        let mut birch = Birch::<BirchCF<2>, 2>::new(config)
+            .max_leaves(4)
+            .outlier_buffer(2.0);
+        birch.fit(points.iter().cloned());
+        assert!(birch.tree().metrics().rebuilds > 0);
+        // the straggler is reabsorbed by its blob once the threshold has grown, while the far
+        // point stays buffered
+        assert!(birch.reclaimed_outliers() > 0);
+        assert_eq!(birch.outliers().len(), 1);
+        assert_eq!(
+            birch.outliers()[0].center(),
+            Point::from_arr([100.0, 100.0])
+        );
+        assert!(birch.subcluster_centers().len() <= 4);
+        // every point is either in the tree or buffered
+        assert_eq!(birch.tree().root().weight(), 201.0);
+        assert_eq!(birch.tree().metrics().inserted, 202);
+
+        // refitting empties the buffer
+        birch.fit(points[..3].iter().cloned());
+        assert!(birch.outliers().is_empty());
+        assert_eq!(birch.reclaimed_outliers(), 0);
+    }
+}
@@ -0,0 +1,245 @@
+/*!
+ * Bulk loading of trees from offline datasets.
+ *
+ * Rather than inserting points one at a time, [CFTree::bulk_load] sorts the points along a
+ * space-filling curve (Z-order), absorbs runs of nearby points into leaf entries, and then builds
+ * the tree bottom-up, packing each level into nodes as full as the configuration allows. The
+ * resulting tree is balanced and compact, and is built in `O(n log n)` time.
+ */
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::fmt::Debug;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{within_leaf_threshold, CFTree, Capacity, Node, NodeEntry, TreeConfig},
+    point::Float,
+    quantiles::QuantileSketch,
+    reservoir::Reservoir,
+};
+
+/// Computes the Z-order (Morton) code of each point, after scaling each dimension to the bounding
+/// box of all points.
+fn morton_codes<T: Float, const DIMS: usize>(points: &[crate::point::Point<DIMS, T>]) -> Vec<u64> {
+    if DIMS == 0 {
+        return vec![0; points.len()];
+    }
+    let bits = (64 / DIMS).min(32) as u32;
+    let levels = ((1u64 << bits) - 1) as f64;
+    let (mins, maxs) = points.iter().fold(
+        ([f64::INFINITY; DIMS], [f64::NEG_INFINITY; DIMS]),
+        |(mut mins, mut maxs), point| {
+            for d in 0..DIMS {
+                mins[d] = mins[d].min(point[d].to_scalar());
+                maxs[d] = maxs[d].max(point[d].to_scalar());
+            }
+            (mins, maxs)
+        },
+    );
+    points
+        .iter()
+        .map(|point| {
+            let cells = (0..DIMS)
+                .map(|d| {
+                    let extent = maxs[d] - mins[d];
+                    match extent > 0.0 {
+                        true => ((point[d].to_scalar() - mins[d]) / extent * levels) as u64,
+                        false => 0,
+                    }
+                })
+                .collect::<Vec<_>>();
+            // interleave bits, most significant first
+            (0..bits).rev().fold(0u64, |code, bit| {
+                cells
+                    .iter()
+                    .fold(code, |code, cell| (code << 1) | ((cell >> bit) & 1))
+            })
+        })
+        .collect()
+}
+
+/// Splits `len` items into consecutive groups which are as even as possible, each holding fewer
+/// than `capacity.max` items.
+fn group_sizes(len: usize, capacity: &Capacity) -> Vec<usize> {
+    let fill = capacity.max.saturating_sub(1).max(1);
+    let ngroups = len.div_ceil(fill).max(1);
+    (0..ngroups)
+        .map(|group| len / ngroups + usize::from(group < len % ngroups))
+        .collect()
+}
+
+/// Packs `entries` into nodes of the given capacity.
+fn pack<CF: CFeature<DIMS>, const DIMS: usize>(
+    entries: Vec<NodeEntry<CF, DIMS>>,
+    capacity: &Capacity,
+) -> Vec<Node<CF, DIMS>> {
+    let mut entries = entries.into_iter();
+    group_sizes(entries.len(), capacity)
+        .into_iter()
+        .map(|size| Node::with_entries(entries.by_ref().take(size).collect()))
+        .collect()
+}
+
+impl<CF, const DIMS: usize> Node<CF, DIMS>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+{
+    /// Builds a balanced tree from `points`. See [CFTree::bulk_load].
+    pub fn bulk_load<TC: TreeConfig>(
+        points: Vec<FeaturePoint<CF, DIMS>>,
+        config: &TC,
+    ) -> Node<CF, DIMS> {
+        Node::bulk_load_features(points.into_iter().map(CF::from).collect(), config)
+    }
+
+    /// Builds a balanced tree from a collection of cluster features, which are absorbed into
+    /// one another where the threshold allows. See [CFTree::bulk_load_features].
+    pub fn bulk_load_features<TC: TreeConfig>(features: Vec<CF>, config: &TC) -> Node<CF, DIMS> {
+        // order features along the space-filling curve
+        let centers = features
+            .iter()
+            .map(|feature| feature.center())
+            .collect::<Vec<_>>();
+        let codes = morton_codes(&centers);
+        let mut features = features.into_iter().zip(codes).collect::<Vec<_>>();
+        features.sort_by_key(|&(_, code)| code);
+
+        // absorb consecutive features into leaf entries
+        let mut entries: Vec<NodeEntry<CF, DIMS>> = vec![];
+        for (feature, _) in features {
+            if let Some(entry) = entries.last_mut() {
+                let absorbed = entry.feature.clone() + &feature;
+                if within_leaf_threshold(&absorbed, config) {
+                    entry.feature = absorbed;
+                    continue;
+                }
+            }
+            entries.push(NodeEntry::with_feature(feature));
+        }
+
+        // build the tree bottom-up
+        let mut nodes = pack(entries, config.leaf_capacity());
+        while nodes.len() > 1 {
+            let entries = nodes
+                .into_iter()
+                .map(|node| NodeEntry {
+                    feature: node.compute_feature(),
+                    child: Some(Arc::new(node)),
+                    ids: vec![],
+                    samples: Reservoir::default(),
+                    quantiles: QuantileSketch::default(),
+                    identity: None,
+                })
+                .collect();
+            nodes = pack(entries, config.node_capacity());
+        }
+        nodes.pop().unwrap_or_else(|| Node::with_entries(vec![]))
+    }
+}
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    TC: TreeConfig,
+{
+    /// Builds a tree from an offline dataset of `points`.
+    ///
+    /// Considerably faster than [CFTree::from_iter] for large datasets, and produces a balanced
+    /// tree with fully-packed nodes. Points can still be inserted into the resulting tree as
+    /// usual.
+    pub fn bulk_load(points: Vec<FeaturePoint<CF, DIMS>>, config: TC) -> CFTree<CF, DIMS, TC> {
+        let root = Node::bulk_load(points, &config);
+        CFTree::from_root(root, config)
+    }
+
+    /// Builds a tree from a collection of cluster features (e.g. the leaf clusters of other
+    /// trees), in the same manner as [CFTree::bulk_load].
+    pub fn bulk_load_features(features: Vec<CF>, config: TC) -> CFTree<CF, DIMS, TC> {
+        let root = Node::bulk_load_features(features, &config);
+        CFTree::from_root(root, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cftree::{BasicConfig, BetulaCFTree, BirchCFTree},
+        point::Point,
+    };
+
+    fn points() -> Vec<Point<2>> {
+        (0..5000u64)
+            .map(|i| {
+                let h = i.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                Point::from_arr([(h % 1000) as f64 / 10.0, ((h >> 20) % 1000) as f64 / 10.0])
+            })
+            .collect()
+    }
+
+    fn check_capacity<CF: CFeature<DIMS>, const DIMS: usize>(
+        node: &Node<CF, DIMS>,
+        config: &BasicConfig,
+    ) {
+        let capacity = match node.is_leaf() {
+            true => config.leaf_capacity(),
+            false => config.node_capacity(),
+        };
+        assert!(node.entries.len() >= capacity.min && node.entries.len() < capacity.max);
+        for child in node
+            .entries
+            .iter()
+            .filter_map(|entry| entry.child.as_deref())
+        {
+            check_capacity(child, config);
+        }
+    }
+
+    #[test]
+    fn bulk_load() {
+        let config = BasicConfig::builder()
+            .capacity(2, 6)
+            .leaf_capacity(3, 8)
+            .threshold(2.0)
+            .build()
+            .unwrap();
+        let mut tree = BirchCFTree::bulk_load(points(), config.clone());
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.iter().map(|c| c.size).sum::<f64>(), 5000.0);
+        let height = tree.root().height();
+        assert!(clusters.iter().all(|c| c.depth == height - 1));
+        for child in tree
+            .root()
+            .entries
+            .iter()
+            .filter_map(|entry| entry.child.as_deref())
+        {
+            check_capacity(child, &config);
+        }
+        let inserted = BirchCFTree::from_iter(points(), config.clone());
+        assert!(tree.root().node_count() <= inserted.root().node_count());
+
+        // insertion continues to work
+        tree.extend(vec![Point::from_arr([1000.0, 1000.0])]);
+        assert_eq!(tree.clusters().map(|c| c.size).sum::<f64>(), 5001.0);
+
+        let tree = BetulaCFTree::<2>::bulk_load(vec![], config.clone());
+        assert!(tree.root().entries.is_empty());
+        let tree = BetulaCFTree::bulk_load(vec![Point::from_arr([1.0, 1.0])], config);
+        assert_eq!(tree.root().entries.len(), 1);
+    }
+
+    #[test]
+    fn morton_order() {
+        let points = vec![
+            Point::from_arr([1.0, 1.0]),
+            Point::from_arr([0.0, 0.0]),
+            Point::from_arr([1.0, 0.0]),
+            Point::from_arr([0.0, 1.0]),
+        ];
+        let codes = morton_codes(&points);
+        let mut order = (0..4).collect::<Vec<_>>();
+        order.sort_by_key(|&idx| codes[idx]);
+        assert_eq!(order, vec![1, 3, 2, 0]);
+    }
+}
@@ -2,43 +2,315 @@
  * Provies the primary cluster feature ([CFeature]) trait.
  */
 
-use std::ops::Add;
+use core::ops::Add;
 
-use num_traits::Zero;
+use num_traits::{Float as _, One, Zero};
 
-use crate::point::{Point, Scalar};
+use crate::point::{Float, Point, Scalar};
 
 pub mod betula;
 pub mod birch;
+pub mod covariance;
+pub mod decay;
+pub mod quantized;
 
-pub trait Dist<R> {
-    fn dist2(&self, r: &R) -> Scalar;
-    fn dist(&self, r: &R) -> Scalar {
+pub trait Dist<R, T: Float = Scalar> {
+    fn dist2(&self, r: &R) -> T;
+    fn dist(&self, r: &R) -> T {
         self.dist2(r).sqrt()
     }
 }
 
-pub enum Absorption<const DIMS: usize> {
+pub enum Absorption<const DIMS: usize, T = Scalar> {
     Absorbed,
-    Failed(Point<DIMS>),
+    Failed(Point<DIMS, T>),
 }
 
+/// Shorthand for the point type accepted by a cluster feature.
+pub type FeaturePoint<CF, const DIMS: usize> = Point<DIMS, <CF as CFeature<DIMS>>::Scalar>;
+
 pub trait CFeature<const DIMS: usize>:
     Add<Self, Output = Self>
     + for<'a> Add<&'a Self, Output = Self>
-    + Add<Point<DIMS>, Output = Self>
-    + for<'a> Add<&'a Point<DIMS>, Output = Self>
+    + Add<FeaturePoint<Self, DIMS>, Output = Self>
+    + for<'a> Add<&'a FeaturePoint<Self, DIMS>, Output = Self>
     + Clone
     + Sized
     + Zero
-    + Dist<Self>
-    + Dist<Point<DIMS>>
-    + From<Point<DIMS>>
+    + Dist<Self, <Self as CFeature<DIMS>>::Scalar>
+    + Dist<FeaturePoint<Self, DIMS>, <Self as CFeature<DIMS>>::Scalar>
+    + From<FeaturePoint<Self, DIMS>>
 {
-    fn diam2(&self) -> Scalar;
-    fn diam(&self) -> Scalar {
+    /// Scalar type of the points summarized by this cluster feature.
+    type Scalar: Float;
+    /// Name of this kind of cluster feature, recorded when trees are saved so they can only be
+    /// loaded as trees of the same kind.
+    const KIND: &'static str;
+
+    fn diam2(&self) -> Self::Scalar;
+    fn diam(&self) -> Self::Scalar {
         self.diam2().sqrt()
     }
-    fn center(&self) -> Point<DIMS>;
-    fn size(&self) -> Scalar;
+    /// Per-dimension components of the squared diameter: the average squared pairwise distance
+    /// of the summarized points along each dimension. By default, this is derived from the
+    /// per-dimension variance.
+    fn diam2_by_dimension(&self) -> FeaturePoint<Self, DIMS> {
+        let n = self.size();
+        if n <= Self::Scalar::one() {
+            return Point::zero();
+        }
+        let two = Self::Scalar::from_scalar(2.0);
+        let variance = self.variance();
+        Point::from_fn(|d| two * n / (n - Self::Scalar::one()) * variance[d])
+    }
+    /// Squared radius: the average squared distance of the summarized points from the center.
+    fn radius2(&self) -> Self::Scalar;
+    fn radius(&self) -> Self::Scalar {
+        self.radius2().sqrt()
+    }
+    fn center(&self) -> FeaturePoint<Self, DIMS>;
+    fn size(&self) -> Self::Scalar;
+    /// Linear sum of the summarized points.
+    fn sum(&self) -> FeaturePoint<Self, DIMS>;
+    /// Per-dimension (population) variance of the summarized points.
+    fn variance(&self) -> FeaturePoint<Self, DIMS>;
+    /// Bytes this feature has allocated on the heap, on top of its own size. Zero by default.
+    fn heap_bytes(&self) -> usize {
+        0
+    }
+    /// Squared Mahalanobis distance of `p` from the center of this feature. By default, this
+    /// uses the per-dimension variances of the summarized points (i.e. ignores correlations
+    /// between dimensions).
+    fn mahalanobis2(&self, p: &FeaturePoint<Self, DIMS>) -> Self::Scalar {
+        self.padded_mahalanobis2(p, Self::Scalar::zero())
+    }
+    /// As [CFeature::mahalanobis2], but with `padding` added to the variance of every dimension,
+    /// so that distances to small (or single-point) clusters don't grow arbitrarily large.
+    fn padded_mahalanobis2(
+        &self,
+        p: &FeaturePoint<Self, DIMS>,
+        padding: Self::Scalar,
+    ) -> Self::Scalar {
+        let center = self.center();
+        let variance = self.variance();
+        (0..DIMS).fold(Self::Scalar::zero(), |acc, d| {
+            let diff = p[d] - center[d];
+            acc + diff * diff / (variance[d] + padding).max(Self::Scalar::min_positive_value())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocess::Rescale;
+
+    fn points() -> Vec<Point<2>> {
+        vec![
+            Point::from_arr([1.0, 2.0]),
+            Point::from_arr([3.0, 2.0]),
+            Point::from_arr([2.0, 5.0]),
+            Point::from_arr([2.0, 3.0]),
+        ]
+    }
+
+    fn assert_close(left: &Point<2>, right: &Point<2>) {
+        assert!((left - right).norm2() < 1e-12, "{:?} != {:?}", left, right);
+    }
+
+    fn assert_rescaled<CF: CFeature<2, Scalar = f64> + Rescale<2>>(
+        feature: &CF,
+        scale: &Point<2>,
+        shift: &Point<2>,
+        expected: &birch::CFeature<2>,
+    ) {
+        let rescaled = feature.rescale(scale, shift);
+        assert_close(&rescaled.center(), &expected.center());
+        assert_close(&rescaled.variance(), &expected.variance());
+    }
+
+    #[test]
+    fn geometry() {
+        let birch = points()
+            .into_iter()
+            .fold(birch::CFeature::<2>::zero(), |acc, p| acc + p);
+        let betula = points()
+            .into_iter()
+            .fold(betula::CFeature::<2>::zero(), |acc, p| acc + p);
+
+        let expected_variance = Point::from_arr([0.5, 1.5]);
+        assert_close(&birch.variance(), &expected_variance);
+        assert_close(&betula.variance(), &expected_variance);
+        assert_close(&birch.sum(), &Point::from_arr([8.0, 12.0]));
+        assert_close(&betula.sum(), &Point::from_arr([8.0, 12.0]));
+        assert!((birch.radius2() - 2.0).abs() < 1e-12);
+        assert!((betula.radius2() - 2.0).abs() < 1e-12);
+        // twice the sample variance, summing up to the squared diameter
+        let expected_diam2 = Point::from_arr([4.0 / 3.0, 4.0]);
+        assert_close(&birch.diam2_by_dimension(), &expected_diam2);
+        assert_close(&betula.diam2_by_dimension(), &expected_diam2);
+        assert!(
+            (birch.diam2_by_dimension().as_slice().iter().sum::<f64>() - birch.diam2()).abs()
+                < 1e-12
+        );
+
+        assert_eq!(birch.n(), 4);
+        assert_eq!(birch.ls(), &Point::from_arr([8.0, 12.0]));
+        assert_eq!(birch.ss(), &Point::from_arr([18.0, 42.0]));
+        assert_eq!(betula.n(), 4.0);
+        assert_eq!(betula.mu(), &Point::from_arr([2.0, 3.0]));
+
+        let decay = points()
+            .iter()
+            .fold(decay::CFeature::<2>::zero(), |acc, p| {
+                acc + decay::CFeature::weighted(p, 0.5)
+            });
+        assert_close(&decay.variance(), &expected_variance);
+        assert_close(&decay.center(), &Point::from_arr([2.0, 3.0]));
+        assert!((decay.radius2() - 2.0).abs() < 1e-12);
+        assert_eq!(decay.size(), 2.0);
+
+        let covariance = points()
+            .into_iter()
+            .fold(covariance::CFeature::<2>::zero(), |acc, p| acc + p);
+        assert_close(&covariance.variance(), &expected_variance);
+        assert_close(&covariance.sum(), &Point::from_arr([8.0, 12.0]));
+        assert!((covariance.radius2() - 2.0).abs() < 1e-12);
+        assert!((covariance.diam2() - birch.diam2()).abs() < 1e-12);
+
+        // without correlations, all features agree on mahalanobis distances
+        let p = Point::from_arr([3.0, 0.0]);
+        let expected = 1.0 / 0.5 + 9.0 / 1.5;
+        assert!((birch.mahalanobis2(&p) - expected).abs() < 1e-9);
+        assert!((betula.mahalanobis2(&p) - expected).abs() < 1e-9);
+        assert!((covariance.mahalanobis2(&p) - expected).abs() < 1e-6);
+        assert!((betula.padded_mahalanobis2(&p, 0.5) - (1.0 + 9.0 / 2.0)).abs() < 1e-9);
+
+        // rescaled features summarize the rescaled points
+        let (scale, shift) = (Point::from_arr([2.0, -1.0]), Point::from_arr([1.0, 3.0]));
+        let moved = points()
+            .iter()
+            .map(|p| p * &scale + &shift)
+            .fold(birch::CFeature::<2>::zero(), |acc, p| acc + p);
+        assert_rescaled(&birch, &scale, &shift, &moved);
+        assert_rescaled(&betula, &scale, &shift, &moved);
+        assert_rescaled(&decay, &scale, &shift, &moved);
+        assert_rescaled(&covariance, &scale, &shift, &moved);
+        assert!((birch.rescale(&scale, &shift).diam2() - moved.diam2()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn covariance() {
+        // points scattered along the diagonal
+        let points = [
+            [0.0, 0.1],
+            [1.0, 0.9],
+            [2.0, 2.1],
+            [3.0, 2.9],
+            [4.0, 4.0],
+            [5.0, 5.1],
+        ]
+        .map(Point::from_arr);
+        let whole = points
+            .iter()
+            .fold(covariance::CFeature::<2>::zero(), |acc, p| acc + p);
+        let merged = points[..2]
+            .iter()
+            .fold(covariance::CFeature::zero(), |acc, p| acc + p)
+            + points[2..]
+                .iter()
+                .fold(covariance::CFeature::zero(), |acc, p| acc + p);
+        for (left, right) in whole.covariance().iter().zip(&merged.covariance()) {
+            assert_close(left, right);
+        }
+        let cov = whole.covariance();
+        assert!((cov[0][1] - cov[1][0]).abs() < 1e-12);
+        assert!(cov[0][1] > 2.5);
+
+        let removed = whole.clone() - &points[5];
+        let partial = points[..5]
+            .iter()
+            .fold(covariance::CFeature::<2>::zero(), |acc, p| acc + p);
+        for (left, right) in removed.covariance().iter().zip(&partial.covariance()) {
+            assert_close(left, right);
+        }
+
+        // equally far (in euclidean terms) from the center, but only one lies along the cluster
+        let center = whole.center();
+        let along = &center + Point::from_arr([1.0, 1.0]);
+        let across = &center + Point::from_arr([1.0, -1.0]);
+        assert!(whole.mahalanobis2(&along) < 1.0);
+        assert!(whole.mahalanobis2(&across) > 10.0 * whole.mahalanobis2(&along));
+        assert_eq!(whole.mahalanobis2(&center), 0.0);
+    }
+
+    #[test]
+    fn quantized() {
+        use crate::cftree::{BasicConfig, BirchCFTree, QuantizedCFTree};
+        use quantized::{Fixed16, Quantize};
+
+        let birch = points()
+            .into_iter()
+            .fold(birch::CFeature::<2>::zero(), |acc, p| acc + p);
+        let single = points()
+            .into_iter()
+            .fold(quantized::CFeature::<2>::zero(), |acc, p| acc + p);
+        let fixed = points()
+            .into_iter()
+            .fold(quantized::CFeature::<2, Fixed16<8>>::zero(), |acc, p| {
+                acc + p
+            });
+        assert_eq!(single.n(), 4);
+        assert!((&single.center() - &birch.center()).norm2() < 1e-12);
+        assert!((&single.variance() - &birch.variance()).norm2() < 1e-12);
+        assert!((single.diam2() - birch.diam2()).abs() < 1e-6);
+        assert!((&fixed.center() - &birch.center()).norm2() < 1e-4);
+        assert!((&fixed.variance() - &birch.variance()).norm2() < 1e-4);
+
+        // merging and removing points agree with summarizing them directly
+        let points = points();
+        let merged = points[..1]
+            .iter()
+            .fold(quantized::CFeature::<2>::zero(), |acc, p| acc + p)
+            + points[1..]
+                .iter()
+                .fold(quantized::CFeature::<2>::zero(), |acc, p| acc + p);
+        assert!((&merged.variance() - &single.variance()).norm2() < 1e-10);
+        let removed = single.clone() - &points[3];
+        let partial = birch::CFeature::<2>::zero() + &points[0] + &points[1] + &points[2];
+        assert!((&removed.center() - &partial.center()).norm2() < 1e-10);
+        assert!((&removed.variance() - &partial.variance()).norm2() < 1e-10);
+
+        // half the size of (f64) birch features in high dimensions, a quarter with 16 bits
+        let birch_size = core::mem::size_of::<birch::CFeature<256>>();
+        assert!(core::mem::size_of::<quantized::CFeature<256>>() * 2 <= birch_size + 8);
+        assert!(
+            core::mem::size_of::<quantized::CFeature<256, Fixed16<12>>>() * 4 <= birch_size + 24
+        );
+        assert_eq!(
+            Fixed16::<12>::quantize(100.0).dequantize(),
+            8.0 - 1.0 / 4096.0
+        );
+
+        // trees of quantized features find the same clusters
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let grid = (0..200)
+            .map(|i| Point::from_arr([(i % 4) as f64 * 10.0 + (i % 7) as f64 * 0.01, 0.5]))
+            .collect::<Vec<_>>();
+        let exact = BirchCFTree::from_iter(grid.clone(), config.clone());
+        let quantized = QuantizedCFTree::<2>::from_iter(grid, config);
+        let (exact, quantized) = (
+            exact.clusters().collect::<Vec<_>>(),
+            quantized.clusters().collect::<Vec<_>>(),
+        );
+        assert_eq!(exact.len(), quantized.len());
+        for (exact, quantized) in exact.iter().zip(&quantized) {
+            assert_eq!(exact.size, quantized.size);
+            assert!((&exact.center - &quantized.center).norm2() < 1e-8);
+        }
+    }
 }
@@ -2,28 +2,32 @@
  * Betula cluster feature implementation.
  */
 
-use std::ops::Add;
+use core::ops::{Add, Sub};
 
 use num_traits::Zero;
 use serde::{Deserialize, Serialize};
 
-use crate::point::{Point, Scalar};
+use crate::{
+    point::{Float, Point, Scalar},
+    preprocess::Rescale,
+};
 
 use super::Dist;
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CFeature<const DIMS: usize> {
+#[serde(bound = "T: Float")]
+pub struct CFeature<const DIMS: usize, T = Scalar> {
     /// Sum of weights
-    n: Scalar,
+    n: T,
     /// Weighted mean
-    mu: Point<DIMS>,
+    mu: Point<DIMS, T>,
     /// Weighted sum of squared deviations from mean
-    s: Point<DIMS>,
+    s: Point<DIMS, T>,
 }
 
-impl<const DIMS: usize> Zero for CFeature<DIMS> {
-    fn zero() -> CFeature<DIMS> {
+impl<T: Float, const DIMS: usize> Zero for CFeature<DIMS, T> {
+    fn zero() -> CFeature<DIMS, T> {
         CFeature {
-            n: Scalar::zero(),
+            n: T::zero(),
             mu: Point::zero(),
             s: Point::zero(),
         }
@@ -34,73 +38,141 @@ impl<const DIMS: usize> Zero for CFeature<DIMS> {
     }
 }
 
-impl<const DIMS: usize> Add<Self> for CFeature<DIMS> {
-    type Output = CFeature<DIMS>;
+impl<T: Float, const DIMS: usize> Add<Self> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
 
     fn add(self, rhs: Self) -> Self::Output {
         self.add(&rhs)
     }
 }
 
-impl<const DIMS: usize> Add<&Self> for CFeature<DIMS> {
-    type Output = CFeature<DIMS>;
+impl<T: Float, const DIMS: usize> Add<&Self> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
 
     fn add(self, rhs: &Self) -> Self::Output {
         let n = self.n + rhs.n;
-        let mu = &self.mu + rhs.n / n * (&rhs.mu - &self.mu);
+        let mu = &self.mu + (&rhs.mu - &self.mu) * (rhs.n / n);
         CFeature {
             n,
             mu: mu.clone(),
-            s: self.s + &rhs.s + rhs.n * (&self.mu - &rhs.mu) * (mu - &rhs.mu),
+            s: self.s + &rhs.s + (&self.mu - &rhs.mu) * (mu - &rhs.mu) * rhs.n,
         }
     }
 }
 
-impl<const DIMS: usize> Add<&Point<DIMS>> for CFeature<DIMS> {
-    type Output = CFeature<DIMS>;
+impl<T: Float, const DIMS: usize> Add<&Point<DIMS, T>> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
 
-    fn add(self, rhs: &Point<DIMS>) -> Self::Output {
+    fn add(self, rhs: &Point<DIMS, T>) -> Self::Output {
         self + CFeature {
-            n: 1.0,
+            n: T::one(),
             mu: rhs.clone(),
-            s: Point::<DIMS>::zero(),
+            s: Point::<DIMS, T>::zero(),
         }
     }
 }
-impl<const DIMS: usize> Add<Point<DIMS>> for CFeature<DIMS> {
-    type Output = CFeature<DIMS>;
+impl<T: Float, const DIMS: usize> Add<Point<DIMS, T>> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
 
-    fn add(self, rhs: Point<DIMS>) -> Self::Output {
+    fn add(self, rhs: Point<DIMS, T>) -> Self::Output {
         self.add(&rhs)
     }
 }
 
-impl<const DIMS: usize> Dist<Point<DIMS>> for CFeature<DIMS> {
-    fn dist2(&self, r: &Point<DIMS>) -> Scalar {
+impl<T: Float, const DIMS: usize> Sub<&Point<DIMS, T>> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
+
+    /// Removes a previously summarized point (of unit weight) from this feature, reversing the
+    /// incremental update of [Add].
+    fn sub(self, rhs: &Point<DIMS, T>) -> Self::Output {
+        let n = self.n - T::one();
+        if n <= T::zero() {
+            return Self::zero();
+        }
+        let mu = (&self.mu * self.n - rhs) / n;
+        let mut s = self.s - (rhs - &self.mu) * (rhs - &mu);
+        for v in s.as_mut_slice() {
+            *v = v.max(T::zero());
+        }
+        CFeature { n, mu, s }
+    }
+}
+
+impl<T: Float, const DIMS: usize> CFeature<DIMS, T> {
+    /// Sum of weights of the summarized points.
+    pub fn n(&self) -> T {
+        self.n
+    }
+    /// Weighted mean of the summarized points.
+    pub fn mu(&self) -> &Point<DIMS, T> {
+        &self.mu
+    }
+    /// Per-dimension weighted sum of squared deviations from the mean.
+    pub fn s(&self) -> &Point<DIMS, T> {
+        &self.s
+    }
+}
+
+impl<T: Float, const DIMS: usize> Dist<Point<DIMS, T>, T> for CFeature<DIMS, T> {
+    fn dist2(&self, r: &Point<DIMS, T>) -> T {
         (&self.mu - r).norm2()
     }
 }
 
-impl<const DIMS: usize> Dist<Self> for CFeature<DIMS> {
-    fn dist2(&self, r: &Self) -> Scalar {
+impl<T: Float, const DIMS: usize> Dist<Self, T> for CFeature<DIMS, T> {
+    fn dist2(&self, r: &Self) -> T {
         (&self.mu - &r.mu).norm2()
     }
 }
 
-impl<const DIMS: usize> From<Point<DIMS>> for CFeature<DIMS> {
-    fn from(orig: Point<DIMS>) -> CFeature<DIMS> {
+impl<T: Float, const DIMS: usize> From<Point<DIMS, T>> for CFeature<DIMS, T> {
+    fn from(orig: Point<DIMS, T>) -> CFeature<DIMS, T> {
         Self::zero() + orig
     }
 }
 
-impl<const DIMS: usize> crate::cfeature::CFeature<DIMS> for CFeature<DIMS> {
-    fn diam2(&self) -> Scalar {
-        2.0 / self.n * self.s.norm2()
+impl<T: Float, const DIMS: usize> crate::cfeature::CFeature<DIMS> for CFeature<DIMS, T> {
+    type Scalar = T;
+    const KIND: &'static str = "betula";
+
+    fn diam2(&self) -> T {
+        T::from_scalar(2.0) / self.n * self.s.norm2()
     }
-    fn size(&self) -> Scalar {
+    fn diam2_by_dimension(&self) -> Point<DIMS, T> {
+        match self.n <= T::one() {
+            true => Point::zero(),
+            false => &self.s * (T::from_scalar(2.0) / (self.n - T::one())),
+        }
+    }
+    fn radius2(&self) -> T {
+        match self.n.is_zero() {
+            true => T::zero(),
+            false => self.s.sum() / self.n,
+        }
+    }
+    fn size(&self) -> T {
         self.n
     }
-    fn center(&self) -> Point<DIMS> {
+    fn center(&self) -> Point<DIMS, T> {
         self.mu.clone()
     }
+    fn sum(&self) -> Point<DIMS, T> {
+        &self.mu * self.n
+    }
+    fn variance(&self) -> Point<DIMS, T> {
+        match self.n.is_zero() {
+            true => Point::zero(),
+            false => &self.s / self.n,
+        }
+    }
+}
+
+impl<T: Float, const DIMS: usize> Rescale<DIMS> for CFeature<DIMS, T> {
+    fn rescale(&self, scale: &Point<DIMS, T>, shift: &Point<DIMS, T>) -> CFeature<DIMS, T> {
+        CFeature {
+            n: self.n,
+            mu: &self.mu * scale + shift,
+            s: &self.s * scale * scale,
+        }
+    }
 }
@@ -2,31 +2,35 @@
  * Standard cluster feature implementation.
  */
 
-use std::ops::Add;
+use core::ops::{Add, Sub};
 
 use serde::{Deserialize, Serialize};
 
 use num_traits::Zero;
 
-use crate::point::{Point, Scalar};
+use crate::{
+    point::{Float, Point, Scalar},
+    preprocess::Rescale,
+};
 
 use super::Dist;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CFeature<const DIMS: usize> {
+#[serde(bound = "T: Float")]
+pub struct CFeature<const DIMS: usize, T = Scalar> {
     /// Linear Sum
-    ls: Point<DIMS>,
-    /// Sum of Squares
-    ss: Scalar,
+    ls: Point<DIMS, T>,
+    /// Per-dimension Sum of Squares
+    ss: Point<DIMS, T>,
     /// Size
     n: usize,
 }
 
-impl<const DIMS: usize> Zero for CFeature<DIMS> {
-    fn zero() -> CFeature<DIMS> {
+impl<T: Float, const DIMS: usize> Zero for CFeature<DIMS, T> {
+    fn zero() -> CFeature<DIMS, T> {
         CFeature {
             ls: Point::zero(),
-            ss: Scalar::zero(),
+            ss: Point::zero(),
             n: usize::zero(),
         }
     }
@@ -36,16 +40,16 @@ impl<const DIMS: usize> Zero for CFeature<DIMS> {
     }
 }
 
-impl<const DIMS: usize> Add<Self> for CFeature<DIMS> {
-    type Output = CFeature<DIMS>;
+impl<T: Float, const DIMS: usize> Add<Self> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
 
     fn add(self, rhs: Self) -> Self::Output {
         self.add(&rhs)
     }
 }
 
-impl<const DIMS: usize> Add<&Self> for CFeature<DIMS> {
-    type Output = CFeature<DIMS>;
+impl<T: Float, const DIMS: usize> Add<&Self> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
 
     fn add(self, rhs: &Self) -> Self::Output {
         CFeature {
@@ -56,57 +60,137 @@ impl<const DIMS: usize> Add<&Self> for CFeature<DIMS> {
     }
 }
 
-impl<const DIMS: usize> Add<&Point<DIMS>> for CFeature<DIMS> {
-    type Output = CFeature<DIMS>;
+impl<T: Float, const DIMS: usize> Add<&Point<DIMS, T>> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
 
-    fn add(self, rhs: &Point<DIMS>) -> Self::Output {
+    fn add(self, rhs: &Point<DIMS, T>) -> Self::Output {
         CFeature {
             ls: self.ls + rhs,
-            ss: self.ss + rhs.norm2(),
+            ss: self.ss + rhs * rhs,
             n: self.n + 1,
         }
     }
 }
 
-impl<const DIMS: usize> Add<Point<DIMS>> for CFeature<DIMS> {
-    type Output = CFeature<DIMS>;
+impl<T: Float, const DIMS: usize> Add<Point<DIMS, T>> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
 
-    fn add(self, rhs: Point<DIMS>) -> Self::Output {
+    fn add(self, rhs: Point<DIMS, T>) -> Self::Output {
         self.add(&rhs)
     }
 }
 
-impl<const DIMS: usize> Dist<Point<DIMS>> for CFeature<DIMS> {
-    fn dist2(&self, r: &Point<DIMS>) -> Scalar {
-        (&self.ls - r).norm2()
+impl<T: Float, const DIMS: usize> Sub<&Point<DIMS, T>> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
+
+    /// Removes a previously summarized point from this feature.
+    fn sub(self, rhs: &Point<DIMS, T>) -> Self::Output {
+        if self.n <= 1 {
+            return Self::zero();
+        }
+        CFeature {
+            ls: self.ls - rhs,
+            ss: self.ss - rhs * rhs,
+            n: self.n - 1,
+        }
     }
 }
 
-impl<const DIMS: usize> Dist<Self> for CFeature<DIMS> {
-    fn dist2(&self, r: &Self) -> Scalar {
-        (&self.ls - &r.ls).norm2()
+impl<T: Float, const DIMS: usize> CFeature<DIMS, T> {
+    /// Linear sum of the summarized points.
+    pub fn ls(&self) -> &Point<DIMS, T> {
+        &self.ls
+    }
+    /// Per-dimension sum of squares of the summarized points.
+    pub fn ss(&self) -> &Point<DIMS, T> {
+        &self.ss
+    }
+    /// Number of summarized points.
+    pub fn n(&self) -> usize {
+        self.n
     }
 }
 
-impl<const DIMS: usize> From<Point<DIMS>> for CFeature<DIMS> {
-    fn from(orig: Point<DIMS>) -> CFeature<DIMS> {
+impl<T: Float, const DIMS: usize> Dist<Point<DIMS, T>, T> for CFeature<DIMS, T> {
+    fn dist2(&self, r: &Point<DIMS, T>) -> T {
+        (&crate::cfeature::CFeature::center(self) - r).norm2()
+    }
+}
+
+impl<T: Float, const DIMS: usize> Dist<Self, T> for CFeature<DIMS, T> {
+    fn dist2(&self, r: &Self) -> T {
+        use crate::cfeature::CFeature as _;
+        (&self.center() - &r.center()).norm2()
+    }
+}
+
+impl<T: Float, const DIMS: usize> From<Point<DIMS, T>> for CFeature<DIMS, T> {
+    fn from(orig: Point<DIMS, T>) -> CFeature<DIMS, T> {
         Self::zero() + orig
     }
 }
 
-impl<const DIMS: usize> crate::cfeature::CFeature<DIMS> for CFeature<DIMS> {
-    fn diam2(&self) -> Scalar {
-        (2.0 * self.n as Scalar * self.ss - 2.0 * self.ls.norm2())
+impl<T: Float, const DIMS: usize> crate::cfeature::CFeature<DIMS> for CFeature<DIMS, T> {
+    type Scalar = T;
+    const KIND: &'static str = "birch";
+
+    fn diam2(&self) -> T {
+        let two = T::from_scalar(2.0);
+        (two * self.size() * self.ss.sum() - two * self.ls.norm2())
             / if self.n < 2 {
-                1 as Scalar
+                T::one()
             } else {
-                (self.n * (self.n - 1)) as Scalar
+                T::from_scalar((self.n * (self.n - 1)) as Scalar)
             }
     }
-    fn size(&self) -> Scalar {
-        self.n as Scalar
+    fn diam2_by_dimension(&self) -> Point<DIMS, T> {
+        let two = T::from_scalar(2.0);
+        let pairs = match self.n {
+            0 | 1 => T::one(),
+            n => T::from_scalar((n * (n - 1)) as Scalar),
+        };
+        Point::from_fn(|d| (two * self.size() * self.ss[d] - two * self.ls[d] * self.ls[d]) / pairs)
     }
-    fn center(&self) -> Point<DIMS> {
-        self.ls.clone() / (self.n as Scalar)
+    fn radius2(&self) -> T {
+        match self.n {
+            0 => T::zero(),
+            _ => {
+                let n = self.size();
+                ((n * self.ss.sum() - self.ls.norm2()) / (n * n)).max(T::zero())
+            }
+        }
+    }
+    fn size(&self) -> T {
+        T::from_scalar(self.n as Scalar)
+    }
+    fn center(&self) -> Point<DIMS, T> {
+        self.ls.clone() / self.size()
+    }
+    fn sum(&self) -> Point<DIMS, T> {
+        self.ls.clone()
+    }
+    fn variance(&self) -> Point<DIMS, T> {
+        if self.n == 0 {
+            return Point::zero();
+        }
+        let center = self.center();
+        let mut variance = &self.ss / self.size() - &center * &center;
+        for v in variance.as_mut_slice() {
+            *v = v.max(T::zero());
+        }
+        variance
+    }
+}
+
+impl<T: Float, const DIMS: usize> Rescale<DIMS> for CFeature<DIMS, T> {
+    fn rescale(&self, scale: &Point<DIMS, T>, shift: &Point<DIMS, T>) -> CFeature<DIMS, T> {
+        let n = T::from_scalar(self.n as Scalar);
+        CFeature {
+            ls: &self.ls * scale + shift * n,
+            ss: &self.ss * scale * scale
+                + &self.ls * scale * shift * T::from_scalar(2.0)
+                + shift * shift * n,
+            n: self.n,
+        }
     }
 }
@@ -0,0 +1,268 @@
+/*!
+ * Covariance-tracking cluster feature implementation.
+ *
+ * Like the [Betula](super::betula) feature, this maintains the size and mean of the summarized
+ * points incrementally, but also tracks the full matrix of co-moments (sums of products of
+ * deviations from the mean) instead of only its diagonal. This allows summarizing elongated or
+ * rotated (elliptical) clusters and measuring Mahalanobis distances to them which account for
+ * correlations between dimensions, at the cost of O(DIMS²) memory per entry.
+ */
+
+use alloc::{vec, vec::Vec};
+use core::ops::{Add, Sub};
+
+use num_traits::Zero;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    point::{Float, Point, Scalar},
+    preprocess::Rescale,
+};
+
+use super::Dist;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct CFeature<const DIMS: usize, T = Scalar> {
+    /// Size
+    n: T,
+    /// Mean
+    mu: Point<DIMS, T>,
+    /// Co-moment matrix (sum of outer products of deviations from mean), one point per row
+    c: Vec<Point<DIMS, T>>,
+}
+
+impl<T: Float, const DIMS: usize> Zero for CFeature<DIMS, T> {
+    fn zero() -> CFeature<DIMS, T> {
+        CFeature {
+            n: T::zero(),
+            mu: Point::zero(),
+            c: vec![Point::zero(); DIMS],
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.n.is_zero() && self.mu.is_zero() && self.c.iter().all(Point::is_zero)
+    }
+}
+
+impl<T: Float, const DIMS: usize> Add<Self> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl<T: Float, const DIMS: usize> Add<&Self> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
+
+    fn add(mut self, rhs: &Self) -> Self::Output {
+        let n = self.n + rhs.n;
+        if n.is_zero() {
+            return self;
+        }
+        let delta = &rhs.mu - &self.mu;
+        let weight = self.n * rhs.n / n;
+        for (i, (row, rhs_row)) in self.c.iter_mut().zip(&rhs.c).enumerate() {
+            *row += rhs_row + &delta * (delta[i] * weight);
+        }
+        CFeature {
+            n,
+            mu: &self.mu + delta * (rhs.n / n),
+            c: self.c,
+        }
+    }
+}
+
+impl<T: Float, const DIMS: usize> Add<&Point<DIMS, T>> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
+
+    fn add(self, rhs: &Point<DIMS, T>) -> Self::Output {
+        self + CFeature {
+            n: T::one(),
+            mu: rhs.clone(),
+            c: vec![Point::zero(); DIMS],
+        }
+    }
+}
+
+impl<T: Float, const DIMS: usize> Add<Point<DIMS, T>> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
+
+    fn add(self, rhs: Point<DIMS, T>) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl<T: Float, const DIMS: usize> Sub<&Point<DIMS, T>> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
+
+    /// Removes a previously summarized point from this feature, reversing the incremental update
+    /// of [Add].
+    fn sub(mut self, rhs: &Point<DIMS, T>) -> Self::Output {
+        let n = self.n - T::one();
+        if n <= T::zero() {
+            return Self::zero();
+        }
+        let mu = (&self.mu * self.n - rhs) / n;
+        let before = rhs - &self.mu;
+        let after = rhs - &mu;
+        for (i, row) in self.c.iter_mut().enumerate() {
+            *row -= &after * before[i];
+        }
+        for i in 0..DIMS {
+            self.c[i][i] = self.c[i][i].max(T::zero());
+        }
+        CFeature { n, mu, c: self.c }
+    }
+}
+
+impl<T: Float, const DIMS: usize> CFeature<DIMS, T> {
+    /// Number of summarized points.
+    pub fn n(&self) -> T {
+        self.n
+    }
+    /// Mean of the summarized points.
+    pub fn mu(&self) -> &Point<DIMS, T> {
+        &self.mu
+    }
+    /// Co-moment matrix of the summarized points (the sum of outer products of their deviations
+    /// from the mean), one row per dimension.
+    pub fn comoments(&self) -> &[Point<DIMS, T>] {
+        &self.c
+    }
+    /// (Population) covariance matrix of the summarized points, one row per dimension.
+    pub fn covariance(&self) -> Vec<Point<DIMS, T>> {
+        match self.n.is_zero() {
+            true => vec![Point::zero(); DIMS],
+            false => self.c.iter().map(|row| row / self.n).collect(),
+        }
+    }
+}
+
+/// In-place Cholesky decomposition of the symmetric matrix `a`, leaving the lower-triangular
+/// factor `L` (with `a = L Lᵀ`) in its lower triangle. Returns `None` if `a` is not positive
+/// definite.
+fn cholesky<T: Float, const DIMS: usize>(a: &mut [Point<DIMS, T>]) -> Option<()> {
+    for j in 0..DIMS {
+        let diag = a[j][j] - (0..j).fold(T::zero(), |acc, k| acc + a[j][k] * a[j][k]);
+        if diag <= T::zero() {
+            return None;
+        }
+        let diag = diag.sqrt();
+        a[j][j] = diag;
+        for i in j + 1..DIMS {
+            let dot = (0..j).fold(T::zero(), |acc, k| acc + a[i][k] * a[j][k]);
+            a[i][j] = (a[i][j] - dot) / diag;
+        }
+    }
+    Some(())
+}
+
+/// Solves `L y = b` for the lower-triangular factor `L` produced by [cholesky].
+fn forward_substitute<T: Float, const DIMS: usize>(
+    l: &[Point<DIMS, T>],
+    mut b: Point<DIMS, T>,
+) -> Point<DIMS, T> {
+    for i in 0..DIMS {
+        let dot = (0..i).fold(T::zero(), |acc, k| acc + l[i][k] * b[k]);
+        b[i] = (b[i] - dot) / l[i][i];
+    }
+    b
+}
+
+impl<T: Float, const DIMS: usize> Dist<Point<DIMS, T>, T> for CFeature<DIMS, T> {
+    fn dist2(&self, r: &Point<DIMS, T>) -> T {
+        (&self.mu - r).norm2()
+    }
+}
+
+impl<T: Float, const DIMS: usize> Dist<Self, T> for CFeature<DIMS, T> {
+    fn dist2(&self, r: &Self) -> T {
+        (&self.mu - &r.mu).norm2()
+    }
+}
+
+impl<T: Float, const DIMS: usize> From<Point<DIMS, T>> for CFeature<DIMS, T> {
+    fn from(orig: Point<DIMS, T>) -> CFeature<DIMS, T> {
+        Self::zero() + orig
+    }
+}
+
+impl<T: Float, const DIMS: usize> crate::cfeature::CFeature<DIMS> for CFeature<DIMS, T> {
+    type Scalar = T;
+    const KIND: &'static str = "covariance";
+
+    /// Squared diameter: the average squared distance between pairs of summarized points, as
+    /// for the BIRCH feature.
+    fn diam2(&self) -> T {
+        let trace = (0..DIMS).fold(T::zero(), |acc, i| acc + self.c[i][i]);
+        T::from_scalar(2.0) * trace / (self.n - T::one()).max(T::one())
+    }
+    fn radius2(&self) -> T {
+        match self.n.is_zero() {
+            true => T::zero(),
+            false => (0..DIMS).fold(T::zero(), |acc, i| acc + self.c[i][i]) / self.n,
+        }
+    }
+    /// Squared Mahalanobis distance of `p` from the center of this feature, under the full
+    /// covariance of the summarized points (padded by `padding` along the diagonal).
+    ///
+    /// The covariance is further regularized by a small multiple of the identity (relative to its
+    /// trace), so that distances remain defined for degenerate (e.g. flat or single-point)
+    /// clusters; without padding, such clusters are very far from any point off their span.
+    fn padded_mahalanobis2(&self, p: &Point<DIMS, T>, padding: T) -> T {
+        let mut cov = self.covariance();
+        let trace = (0..DIMS).fold(T::zero(), |acc, i| acc + cov[i][i]);
+        let ridge =
+            trace / T::from_scalar(DIMS as Scalar) * T::from_scalar(1e-9) + T::min_positive_value();
+        for (i, row) in cov.iter_mut().enumerate() {
+            row[i] += padding + ridge;
+        }
+        let diff = p - &self.mu;
+        match cholesky(&mut cov) {
+            Some(()) => forward_substitute(&cov, diff).norm2(),
+            // not positive definite (only due to rounding); fall back to the diagonal
+            None => (0..DIMS).fold(T::zero(), |acc, i| {
+                acc + diff[i] * diff[i] / (self.c[i][i] / self.n.max(T::one()) + padding + ridge)
+            }),
+        }
+    }
+    fn size(&self) -> T {
+        self.n
+    }
+    fn center(&self) -> Point<DIMS, T> {
+        self.mu.clone()
+    }
+    fn sum(&self) -> Point<DIMS, T> {
+        &self.mu * self.n
+    }
+    fn variance(&self) -> Point<DIMS, T> {
+        let mut variance = Point::zero();
+        if !self.n.is_zero() {
+            for i in 0..DIMS {
+                variance[i] = self.c[i][i] / self.n;
+            }
+        }
+        variance
+    }
+    fn heap_bytes(&self) -> usize {
+        self.c.capacity() * core::mem::size_of::<Point<DIMS, T>>()
+    }
+}
+
+impl<T: Float, const DIMS: usize> Rescale<DIMS> for CFeature<DIMS, T> {
+    fn rescale(&self, scale: &Point<DIMS, T>, shift: &Point<DIMS, T>) -> CFeature<DIMS, T> {
+        CFeature {
+            n: self.n,
+            mu: &self.mu * scale + shift,
+            c: self
+                .c
+                .iter()
+                .enumerate()
+                .map(|(i, row)| row * scale * scale[i])
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+/*!
+ * Weighted cluster feature, for summarizing points whose influence fades over time.
+ *
+ * Each summarized point carries a (non-negative) weight, and the linear sum, sum of squares and
+ * size are all weighted sums. Since all three are linear in the weights, an entire feature can be
+ * faded by [scaling](CFeature::scale) it by the decay factor, as in CluStream / DenStream. See
+ * [FadingCFTree](crate::fading::FadingCFTree) for a tree which applies this decay.
+ */
+
+use core::ops::Add;
+
+use serde::{Deserialize, Serialize};
+
+use num_traits::Zero;
+
+use crate::{
+    point::{Float, Point, Scalar},
+    preprocess::Rescale,
+};
+
+use super::Dist;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct CFeature<const DIMS: usize, T = Scalar> {
+    /// Weighted Linear Sum
+    ls: Point<DIMS, T>,
+    /// Weighted per-dimension Sum of Squares
+    ss: Point<DIMS, T>,
+    /// Total weight
+    w: T,
+}
+
+impl<T: Float, const DIMS: usize> Zero for CFeature<DIMS, T> {
+    fn zero() -> CFeature<DIMS, T> {
+        CFeature {
+            ls: Point::zero(),
+            ss: Point::zero(),
+            w: T::zero(),
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.ls.is_zero() && self.ss.is_zero() && self.w.is_zero()
+    }
+}
+
+impl<T: Float, const DIMS: usize> Add<Self> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl<T: Float, const DIMS: usize> Add<&Self> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
+
+    fn add(self, rhs: &Self) -> Self::Output {
+        CFeature {
+            ls: self.ls + &rhs.ls,
+            ss: self.ss + &rhs.ss,
+            w: self.w + rhs.w,
+        }
+    }
+}
+
+impl<T: Float, const DIMS: usize> Add<&Point<DIMS, T>> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
+
+    fn add(self, rhs: &Point<DIMS, T>) -> Self::Output {
+        CFeature {
+            ls: self.ls + rhs,
+            ss: self.ss + rhs * rhs,
+            w: self.w + T::one(),
+        }
+    }
+}
+
+impl<T: Float, const DIMS: usize> Add<Point<DIMS, T>> for CFeature<DIMS, T> {
+    type Output = CFeature<DIMS, T>;
+
+    fn add(self, rhs: Point<DIMS, T>) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl<T: Float, const DIMS: usize> CFeature<DIMS, T> {
+    /// Creates a feature summarizing the single point `p` with weight `w`.
+    pub fn weighted(p: &Point<DIMS, T>, w: T) -> CFeature<DIMS, T> {
+        CFeature {
+            ls: p * w,
+            ss: p * p * w,
+            w,
+        }
+    }
+    /// Scales the weight of every summarized point by `factor`. The center, radius and diameter
+    /// of the feature are unchanged.
+    pub fn scale(&mut self, factor: T) {
+        self.ls = &self.ls * factor;
+        self.ss = &self.ss * factor;
+        self.w *= factor;
+    }
+    /// Weighted linear sum of the summarized points.
+    pub fn ls(&self) -> &Point<DIMS, T> {
+        &self.ls
+    }
+    /// Weighted per-dimension sum of squares of the summarized points.
+    pub fn ss(&self) -> &Point<DIMS, T> {
+        &self.ss
+    }
+    /// Total weight of the summarized points.
+    pub fn weight(&self) -> T {
+        self.w
+    }
+}
+
+impl<T: Float, const DIMS: usize> Dist<Point<DIMS, T>, T> for CFeature<DIMS, T> {
+    fn dist2(&self, r: &Point<DIMS, T>) -> T {
+        (&crate::cfeature::CFeature::center(self) - r).norm2()
+    }
+}
+
+impl<T: Float, const DIMS: usize> Dist<Self, T> for CFeature<DIMS, T> {
+    fn dist2(&self, r: &Self) -> T {
+        use crate::cfeature::CFeature as _;
+        (&self.center() - &r.center()).norm2()
+    }
+}
+
+impl<T: Float, const DIMS: usize> From<Point<DIMS, T>> for CFeature<DIMS, T> {
+    fn from(orig: Point<DIMS, T>) -> CFeature<DIMS, T> {
+        Self::zero() + orig
+    }
+}
+
+impl<T: Float, const DIMS: usize> crate::cfeature::CFeature<DIMS> for CFeature<DIMS, T> {
+    type Scalar = T;
+    const KIND: &'static str = "decay";
+
+    /// Squared diameter, taken as twice the squared radius. Unlike the BIRCH diameter, this
+    /// doesn't depend on the number of summarized points, so it is unaffected by decay.
+    fn diam2(&self) -> T {
+        T::from_scalar(2.0) * self.radius2()
+    }
+    fn radius2(&self) -> T {
+        if self.w <= T::zero() {
+            return T::zero();
+        }
+        ((self.w * self.ss.sum() - self.ls.norm2()) / (self.w * self.w)).max(T::zero())
+    }
+    fn size(&self) -> T {
+        self.w
+    }
+    fn center(&self) -> Point<DIMS, T> {
+        self.ls.clone() / self.w
+    }
+    fn sum(&self) -> Point<DIMS, T> {
+        self.ls.clone()
+    }
+    fn variance(&self) -> Point<DIMS, T> {
+        if self.w <= T::zero() {
+            return Point::zero();
+        }
+        let center = self.center();
+        let mut variance = &self.ss / self.w - &center * &center;
+        for v in variance.as_mut_slice() {
+            *v = v.max(T::zero());
+        }
+        variance
+    }
+}
+
+impl<T: Float, const DIMS: usize> Rescale<DIMS> for CFeature<DIMS, T> {
+    fn rescale(&self, scale: &Point<DIMS, T>, shift: &Point<DIMS, T>) -> CFeature<DIMS, T> {
+        CFeature {
+            ls: &self.ls * scale + shift * self.w,
+            ss: &self.ss * scale * scale
+                + &self.ls * scale * shift * T::from_scalar(2.0)
+                + shift * shift * self.w,
+            w: self.w,
+        }
+    }
+}
@@ -0,0 +1,233 @@
+/*!
+ * Cluster feature with quantized storage, for trees of high-dimensional points.
+ *
+ * Instead of the linear sum and sum of squares, this feature stores the per-dimension mean and
+ * variance of the summarized points (which, unlike the sums, don't grow with the number of points)
+ * in a compact [Quantize] type: `f32` halves the memory used by the features of a tree compared to
+ * [birch](super::birch) features, and 16-bit [Fixed16] quarters it. All arithmetic still happens
+ * in [Scalar] precision; only the stored values are rounded.
+ *
+ * The storage type is chosen per tree through its feature type (see
+ * [QuantizedCFTree](crate::cftree::QuantizedCFTree)).
+ */
+
+use core::{
+    fmt::Debug,
+    ops::{Add, Sub},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use num_traits::Zero;
+
+use crate::{
+    point::{Point, Scalar},
+    preprocess::Rescale,
+};
+
+use super::Dist;
+
+/// Compact storage type for the means and variances of a quantized [CFeature].
+pub trait Quantize: Copy + Default + Debug + Serialize + DeserializeOwned + 'static {
+    /// Name of the storage type, used as the [kind](super::CFeature::KIND) of its features.
+    const KIND: &'static str;
+
+    /// Rounds `value` to this type.
+    fn quantize(value: Scalar) -> Self;
+    /// Converts back to [Scalar].
+    fn dequantize(self) -> Scalar;
+}
+
+impl Quantize for f32 {
+    const KIND: &'static str = "quantized_f32";
+
+    fn quantize(value: Scalar) -> f32 {
+        value as f32
+    }
+    fn dequantize(self) -> Scalar {
+        self as Scalar
+    }
+}
+
+/// 16-bit fixed-point number with `FRAC_BITS` fractional bits, covering
+/// `[-2^(15 - FRAC_BITS), 2^(15 - FRAC_BITS))` with a resolution of `2^-FRAC_BITS`. Values outside
+/// that range saturate, so points should be scaled to a known range first (see
+/// [preprocess](crate::preprocess)); e.g. 12 fractional bits suit standardized data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fixed16<const FRAC_BITS: u32>(i16);
+
+impl<const FRAC_BITS: u32> Quantize for Fixed16<FRAC_BITS> {
+    const KIND: &'static str = "quantized_fixed16";
+
+    fn quantize(value: Scalar) -> Fixed16<FRAC_BITS> {
+        let scaled = (value * (1u32 << FRAC_BITS) as Scalar).round();
+        // `as` saturates at the bounds of i16 (and maps NaN to 0)
+        Fixed16(scaled as i16)
+    }
+    fn dequantize(self) -> Scalar {
+        self.0 as Scalar / (1u32 << FRAC_BITS) as Scalar
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "Q: Quantize")]
+pub struct CFeature<const DIMS: usize, Q = f32> {
+    /// Per-dimension mean
+    mean: Point<DIMS, Q>,
+    /// Per-dimension (population) variance
+    variance: Point<DIMS, Q>,
+    /// Size
+    n: usize,
+}
+
+impl<Q: Quantize, const DIMS: usize> CFeature<DIMS, Q> {
+    /// Feature of `n` points with the given (unquantized) mean and variance.
+    fn with_moments(n: usize, mean: &Point<DIMS>, variance: &Point<DIMS>) -> CFeature<DIMS, Q> {
+        CFeature {
+            mean: Point::from_fn(|d| Q::quantize(mean[d])),
+            variance: Point::from_fn(|d| Q::quantize(variance[d].max(0.0))),
+            n,
+        }
+    }
+
+    /// Number of summarized points.
+    pub fn n(&self) -> usize {
+        self.n
+    }
+}
+
+impl<Q: Quantize, const DIMS: usize> Zero for CFeature<DIMS, Q> {
+    fn zero() -> CFeature<DIMS, Q> {
+        CFeature {
+            mean: Point::from_fn(|_| Q::quantize(0.0)),
+            variance: Point::from_fn(|_| Q::quantize(0.0)),
+            n: 0,
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.n == 0
+    }
+}
+
+impl<Q: Quantize, const DIMS: usize> Add<Self> for CFeature<DIMS, Q> {
+    type Output = CFeature<DIMS, Q>;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl<Q: Quantize, const DIMS: usize> Add<&Self> for CFeature<DIMS, Q> {
+    type Output = CFeature<DIMS, Q>;
+
+    fn add(self, rhs: &Self) -> Self::Output {
+        use crate::cfeature::CFeature as _;
+        if rhs.n == 0 {
+            return self;
+        }
+        if self.n == 0 {
+            return rhs.clone();
+        }
+        // pooled mean and variance of both groups of points
+        let n = self.n + rhs.n;
+        let (wl, wr) = (self.size() / n as Scalar, rhs.size() / n as Scalar);
+        let (ml, mr) = (self.center(), rhs.center());
+        let diff = &mr - &ml;
+        let mean = &ml * wl + &mr * wr;
+        let variance = self.variance() * wl + rhs.variance() * wr + &diff * &diff * (wl * wr);
+        CFeature::with_moments(n, &mean, &variance)
+    }
+}
+
+impl<Q: Quantize, const DIMS: usize> Add<&Point<DIMS>> for CFeature<DIMS, Q> {
+    type Output = CFeature<DIMS, Q>;
+
+    fn add(self, rhs: &Point<DIMS>) -> Self::Output {
+        self + &CFeature::with_moments(1, rhs, &Point::zero())
+    }
+}
+
+impl<Q: Quantize, const DIMS: usize> Add<Point<DIMS>> for CFeature<DIMS, Q> {
+    type Output = CFeature<DIMS, Q>;
+
+    fn add(self, rhs: Point<DIMS>) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl<Q: Quantize, const DIMS: usize> Sub<&Point<DIMS>> for CFeature<DIMS, Q> {
+    type Output = CFeature<DIMS, Q>;
+
+    /// Removes a previously summarized point from this feature.
+    fn sub(self, rhs: &Point<DIMS>) -> Self::Output {
+        use crate::cfeature::CFeature as _;
+        if self.n <= 1 {
+            return Self::zero();
+        }
+        // reverses the pooling of `Add`
+        let n = self.n - 1;
+        let center = self.center();
+        let mean = (&center * self.size() - rhs) / n as Scalar;
+        let diff = rhs - &mean;
+        let variance = (self.variance() * self.size()
+            - &diff * &diff * (n as Scalar / self.size()))
+            / n as Scalar;
+        CFeature::with_moments(n, &mean, &variance)
+    }
+}
+
+impl<Q: Quantize, const DIMS: usize> Dist<Point<DIMS>> for CFeature<DIMS, Q> {
+    fn dist2(&self, r: &Point<DIMS>) -> Scalar {
+        (&crate::cfeature::CFeature::center(self) - r).norm2()
+    }
+}
+
+impl<Q: Quantize, const DIMS: usize> Dist<Self> for CFeature<DIMS, Q> {
+    fn dist2(&self, r: &Self) -> Scalar {
+        use crate::cfeature::CFeature as _;
+        (&self.center() - &r.center()).norm2()
+    }
+}
+
+impl<Q: Quantize, const DIMS: usize> From<Point<DIMS>> for CFeature<DIMS, Q> {
+    fn from(orig: Point<DIMS>) -> CFeature<DIMS, Q> {
+        Self::zero() + orig
+    }
+}
+
+impl<Q: Quantize, const DIMS: usize> crate::cfeature::CFeature<DIMS> for CFeature<DIMS, Q> {
+    type Scalar = Scalar;
+    const KIND: &'static str = Q::KIND;
+
+    fn diam2(&self) -> Scalar {
+        match self.n {
+            0 | 1 => 0.0,
+            n => 2.0 * n as Scalar / (n - 1) as Scalar * self.radius2(),
+        }
+    }
+    fn radius2(&self) -> Scalar {
+        self.variance().sum()
+    }
+    fn size(&self) -> Scalar {
+        self.n as Scalar
+    }
+    fn center(&self) -> Point<DIMS> {
+        Point::from_fn(|d| self.mean[d].dequantize())
+    }
+    fn sum(&self) -> Point<DIMS> {
+        self.center() * self.size()
+    }
+    fn variance(&self) -> Point<DIMS> {
+        Point::from_fn(|d| self.variance[d].dequantize())
+    }
+}
+
+impl<Q: Quantize, const DIMS: usize> Rescale<DIMS> for CFeature<DIMS, Q> {
+    fn rescale(&self, scale: &Point<DIMS>, shift: &Point<DIMS>) -> CFeature<DIMS, Q> {
+        use crate::cfeature::CFeature as _;
+        let mean = self.center() * scale + shift;
+        let variance = self.variance() * scale * scale;
+        CFeature::with_moments(self.n, &mean, &variance)
+    }
+}
@@ -1,20 +1,45 @@
 /*!
  * Cluster Feature tree struct and implementation.
+ *
+ * Tree construction is deterministic: inserting the same sequence of points (or features) with
+ * the same configuration always yields a bit-identical tree. Whenever several entries are equally
+ * close to a point (or several pairs of entries are equally far apart), the first one in entry
+ * order is chosen, and split policies break ties the same way (see [crate::split]).
  */
 
-use std::{collections::HashSet, fmt::Debug};
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::{
+    fmt::Debug,
+    mem::size_of,
+    ops::ControlFlow,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use thiserror::Error;
 
 use itertools::{Either, Itertools};
-use num_traits::Float;
+use num_traits::{Float as _, Zero};
 
 use crate::{
-    cfeature::{betula::CFeature as BetulaFeature, birch::CFeature as BirchFeature, CFeature},
-    point::{Point, Scalar},
+    cfeature::{
+        betula::CFeature as BetulaFeature, birch::CFeature as BirchFeature,
+        covariance::CFeature as CovarianceFeature, quantized::CFeature as QuantizedFeature,
+        CFeature, FeaturePoint,
+    },
+    constraints::ConstraintSet,
+    error::BorschtError,
+    identity::{merge_identity, next_cluster_id, ClusterIdentity, NewId},
+    point::{Float, Point, Scalar},
+    preprocess::Transform,
+    quantiles::QuantileSketch,
+    reservoir::Reservoir,
+    split::{rebalance, FarthestPair, Split, SplitEntry, SplitPolicy},
+    trace::{TraceEvent, TraceRecorder, TracedEntry},
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Capacity {
     pub min: usize,
     pub max: usize,
@@ -26,52 +51,673 @@ pub trait TreeConfig {
         self.node_capacity()
     }
     fn threshold(&self) -> Scalar;
+    /// Per-dimension absorption thresholds of leaf entries, indexed by dimension. If any are set,
+    /// they replace [TreeConfig::threshold] at the leaves: a leaf entry only absorbs a point if
+    /// its squared diameter along each dimension (see
+    /// [CFeature::diam2_by_dimension](crate::cfeature::CFeature::diam2_by_dimension)) stays
+    /// within the threshold of that dimension, so dimensions with a naturally wider spread don't
+    /// force a loose threshold on all the others. Dimensions past the end of the thresholds are
+    /// bounded by [TreeConfig::threshold]. Defaults to none.
+    fn dimension_thresholds(&self) -> &[Scalar] {
+        &[]
+    }
+    /// Absorption threshold of the entries `level` levels above the leaves. At level 0, this bounds
+    /// the diameter of leaf entries, as [TreeConfig::threshold]. Above the leaves, an inserted
+    /// point which would leave the diameter of the entry it descends into within the threshold of
+    /// its level is absorbed by that entry's subtree: it joins the closest leaf entry below past
+    /// the leaf threshold, as long as that entry's diameter stays within the (largest) threshold
+    /// of the levels which absorbed it, and only starts a new leaf entry otherwise. Leaf
+    /// diameters are thus bounded by the largest threshold of the levels above them rather than
+    /// by the leaf threshold. Tight leaf thresholds keep fine summaries of sparse regions, while
+    /// looser upper-level thresholds stop dense regions from growing the tree. A threshold of
+    /// zero disables absorption at its level. Bulk loads (see [CFTree::bulk_load]) absorb at the
+    /// leaves only.
+    ///
+    /// Defaults to [TreeConfig::threshold] at the leaves and zero above them.
+    fn threshold_at(&self, level: usize) -> Scalar {
+        match level {
+            0 => self.threshold(),
+            _ => 0.0,
+        }
+    }
+    /// Policy used to partition the entries of a node which exceeds its capacity. Defaults to
+    /// [FarthestPair].
+    fn split_policy(&self) -> &dyn SplitPolicy {
+        &FarthestPair
+    }
+    /// Whether to apply the BIRCH merging refinement after a split: at the node where split
+    /// propagation stops, the two closest entries are merged (and resplit, if necessary), which
+    /// counteracts splits caused by skewed insertion orders. Defaults to `false`.
+    fn merge_refinement(&self) -> bool {
+        false
+    }
+    /// Distance used to find the entry closest to a new point during insertion (and prediction).
+    /// Defaults to [Metric::Euclidean].
+    fn metric(&self) -> Metric {
+        Metric::Euclidean
+    }
+    /// How points with missing (NaN) coordinates are handled. Defaults to
+    /// [MissingValues::Propagate].
+    fn missing_values(&self) -> MissingValues {
+        MissingValues::Propagate
+    }
+    /// Whether leaf entries keep the ids of the points they absorbed (see [NodeEntry::ids]), e.g.
+    /// to recover which input records ended up in which cluster. Defaults to `false`.
+    fn track_ids(&self) -> bool {
+        false
+    }
+    /// Number of the points absorbed by each leaf entry to keep as a uniform sample (see
+    /// [NodeEntry::samples]), e.g. for visualization. Defaults to 0, which keeps no samples.
+    fn reservoir_size(&self) -> usize {
+        0
+    }
+    /// Whether leaf entries get stable ids when they're created (see [NodeEntry::identity]),
+    /// to follow clusters across insertions and rebuilds. Defaults to `false`.
+    fn stable_cluster_ids(&self) -> bool {
+        false
+    }
+    /// Compression of the quantile sketches leaf entries keep of the points they absorbed (see
+    /// [NodeEntry::quantiles]): larger compressions keep more centroids per dimension, for more
+    /// accurate quantiles. Defaults to 0, which keeps no sketches.
+    fn quantile_compression(&self) -> usize {
+        0
+    }
 }
 
-#[derive(Debug, Clone)]
+/// Handling of missing (NaN) coordinates in inserted and queried points.
+///
+/// Cluster features can't summarize partially-observed points, so unless missing values are
+/// propagated, the missing coordinates of an inserted point are filled in with the center of the
+/// leaf cluster nearest to it (as measured over its observed coordinates), or with zero if the
+/// tree is empty. Filled-in coordinates don't move the center of the cluster which absorbs them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MissingValues {
+    /// Missing values are used as-is, and spread NaNs through every feature they reach.
+    #[default]
+    Propagate,
+    /// Missing coordinates are skipped when measuring distances, which are computed over the
+    /// observed coordinates and scaled up by the fraction of coordinates observed.
+    Skip,
+    /// Missing coordinates are imputed with the corresponding coordinate of the center of each
+    /// cluster measured against (and so contribute nothing to distances).
+    Impute,
+}
+
+/// Distance between a point (or the center of a cluster feature) and a cluster feature.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Metric {
+    /// Euclidean distance from the center of the feature.
+    #[default]
+    Euclidean,
+    /// Mahalanobis distance under the variance of the feature (see [CFeature::mahalanobis2]),
+    /// which accounts for the spread of each cluster, and therefore for dimensions with very
+    /// different scales. The variance of every dimension is padded by the spread allowed by the
+    /// threshold, so that single-point clusters remain reachable.
+    Mahalanobis,
+}
+
+impl Metric {
+    /// Squared distance of `p` from `feature` under this metric, for a tree with configuration
+    /// `config`.
+    pub fn dist2<CF, TC, const DIMS: usize>(
+        &self,
+        feature: &CF,
+        p: &FeaturePoint<CF, DIMS>,
+        config: &TC,
+    ) -> CF::Scalar
+    where
+        CF: CFeature<DIMS>,
+        TC: TreeConfig + ?Sized,
+    {
+        let dist2 = |p: &FeaturePoint<CF, DIMS>| match self {
+            Metric::Euclidean => feature.dist2(p),
+            Metric::Mahalanobis => {
+                feature.padded_mahalanobis2(p, variance_padding::<_, _, DIMS>(config))
+            }
+        };
+        let observed = p.as_slice().iter().filter(|x| !x.is_nan()).count();
+        match (config.missing_values(), observed) {
+            (MissingValues::Propagate, _) => dist2(p),
+            (_, observed) if observed == DIMS => dist2(p),
+            (MissingValues::Skip, 0) => CF::Scalar::zero(),
+            (MissingValues::Skip, _) => {
+                dist2(&fill_missing(p.clone(), &feature.center()))
+                    * CF::Scalar::from_scalar(DIMS as Scalar / observed as Scalar)
+            }
+            (MissingValues::Impute, _) => dist2(&fill_missing(p.clone(), &feature.center())),
+        }
+    }
+}
+
+/// Replaces the missing (NaN) coordinates of `p` with those of `fill`.
+pub(crate) fn fill_missing<T: Float, const DIMS: usize>(
+    mut p: Point<DIMS, T>,
+    fill: &Point<DIMS, T>,
+) -> Point<DIMS, T> {
+    for (x, &value) in p.as_mut_slice().iter_mut().zip(fill.as_slice()) {
+        if x.is_nan() {
+            *x = value;
+        }
+    }
+    p
+}
+
+/// Per-dimension variance corresponding to the spread allowed by the threshold of `config`.
+pub(crate) fn variance_padding<T: Float, TC: TreeConfig + ?Sized, const DIMS: usize>(
+    config: &TC,
+) -> T {
+    // the threshold bounds the squared diameter, i.e. twice the squared radius
+    T::from_scalar(config.threshold() / (2 * DIMS) as Scalar)
+}
+
+/// Whether the leaf feature `feature` is within the leaf threshold of `config`: along each
+/// dimension if it sets per-dimension thresholds (see [TreeConfig::dimension_thresholds]), or as
+/// a whole otherwise.
+pub(crate) fn within_leaf_threshold<CF, TC, const DIMS: usize>(feature: &CF, config: &TC) -> bool
+where
+    CF: CFeature<DIMS>,
+    TC: TreeConfig + ?Sized,
+{
+    let thresholds = config.dimension_thresholds();
+    if thresholds.is_empty() {
+        return feature.diam2() <= CF::Scalar::from_scalar(config.threshold_at(0));
+    }
+    let diam2 = feature.diam2_by_dimension();
+    (0..DIMS).all(|d| {
+        let threshold = thresholds.get(d).copied().unwrap_or(config.threshold());
+        diam2[d] <= CF::Scalar::from_scalar(threshold)
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BasicConfig {
     pub capacity: Capacity,
+    /// Capacity of leaf nodes; uses `capacity` if `None`.
+    pub leaf_capacity: Option<Capacity>,
     pub threshold: Scalar,
+    /// Absorption thresholds of the levels above the leaves, starting at level 1 (see
+    /// [TreeConfig::threshold_at]); higher levels don't absorb.
+    pub upper_thresholds: Vec<Scalar>,
+    /// Per-dimension absorption thresholds of leaf entries (see
+    /// [TreeConfig::dimension_thresholds]).
+    pub dimension_thresholds: Vec<Scalar>,
+    /// Policy used to split overflowing nodes (see [TreeConfig::split_policy]).
+    pub split: Split,
+    pub merge_refinement: bool,
+    pub metric: Metric,
+    pub missing_values: MissingValues,
+    pub track_ids: bool,
+    pub reservoir_size: usize,
+    pub stable_cluster_ids: bool,
+    pub quantile_compression: usize,
+}
+impl BasicConfig {
+    pub fn builder() -> BasicConfigBuilder {
+        BasicConfigBuilder::default()
+    }
+
+    /// Builder with node capacities chosen so that each full node fits in a page of `bytes`
+    /// bytes, as in the original BIRCH design, for trees of `CF` cluster features. Leaf entries
+    /// hold a cluster feature; non-leaf entries also hold a pointer to their child, so leaves
+    /// have the larger capacity. Minimum capacities are half the maximum.
+    ///
+    /// The page must fit at least one non-leaf entry, or building the configuration fails with
+    /// [ConfigError::InvalidCapacity].
+    pub fn from_page_size<CF: CFeature<DIMS>, const DIMS: usize>(
+        bytes: usize,
+    ) -> BasicConfigBuilder {
+        let leaf_entry = size_of::<CF>();
+        let entry = leaf_entry + size_of::<Arc<Node<CF, DIMS>>>();
+        // nodes split once they hold `max` entries, so a full node holds `max - 1`
+        let (max, leaf_max) = (bytes / entry + 1, bytes / leaf_entry + 1);
+        BasicConfig::builder()
+            .capacity(max / 2, max)
+            .leaf_capacity(leaf_max / 2, leaf_max)
+    }
 }
 impl TreeConfig for BasicConfig {
     fn node_capacity(&self) -> &Capacity {
         &self.capacity
     }
+    fn leaf_capacity(&self) -> &Capacity {
+        self.leaf_capacity.as_ref().unwrap_or(&self.capacity)
+    }
     fn threshold(&self) -> Scalar {
         self.threshold
     }
+    fn threshold_at(&self, level: usize) -> Scalar {
+        match level {
+            0 => self.threshold,
+            _ => self.upper_thresholds.get(level - 1).copied().unwrap_or(0.0),
+        }
+    }
+    fn dimension_thresholds(&self) -> &[Scalar] {
+        &self.dimension_thresholds
+    }
+    fn split_policy(&self) -> &dyn SplitPolicy {
+        &self.split
+    }
+    fn merge_refinement(&self) -> bool {
+        self.merge_refinement
+    }
+    fn metric(&self) -> Metric {
+        self.metric
+    }
+    fn missing_values(&self) -> MissingValues {
+        self.missing_values
+    }
+    fn track_ids(&self) -> bool {
+        self.track_ids
+    }
+    fn reservoir_size(&self) -> usize {
+        self.reservoir_size
+    }
+    fn stable_cluster_ids(&self) -> bool {
+        self.stable_cluster_ids
+    }
+    fn quantile_compression(&self) -> usize {
+        self.quantile_compression
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Error, Debug, PartialEq)]
+pub enum ConfigError {
+    #[error("missing required configuration value '{0}'")]
+    Missing(&'static str),
+    #[error(
+        "invalid capacity: min {min} must be at most half of max {max}, and max must be at least 2"
+    )]
+    InvalidCapacity { min: usize, max: usize },
+    #[error("invalid threshold {0}: must be non-negative")]
+    InvalidThreshold(Scalar),
+}
+
+/// Builder for [BasicConfig] which validates the configuration before building it.
+#[derive(Debug, Clone, Default)]
+pub struct BasicConfigBuilder {
+    capacity: Option<Capacity>,
+    leaf_capacity: Option<Capacity>,
+    threshold: Option<Scalar>,
+    upper_thresholds: Vec<Scalar>,
+    dimension_thresholds: Vec<Scalar>,
+    split: Split,
+    merge_refinement: bool,
+    metric: Metric,
+    missing_values: MissingValues,
+    track_ids: bool,
+    reservoir_size: usize,
+    stable_cluster_ids: bool,
+    quantile_compression: usize,
+}
+
+impl BasicConfigBuilder {
+    /// Sets the capacity of nodes (and of leaf nodes, unless overridden by
+    /// [BasicConfigBuilder::leaf_capacity]). Required.
+    pub fn capacity(mut self, min: usize, max: usize) -> Self {
+        self.capacity = Some(Capacity { min, max });
+        self
+    }
+
+    /// Sets the capacity of leaf nodes.
+    pub fn leaf_capacity(mut self, min: usize, max: usize) -> Self {
+        self.leaf_capacity = Some(Capacity { min, max });
+        self
+    }
+
+    /// Sets the absorption threshold of leaf entries. Required, unless per-dimension thresholds
+    /// are set (see [BasicConfigBuilder::dimension_thresholds]).
+    pub fn threshold(mut self, threshold: Scalar) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// Sets the absorption thresholds of the levels above the leaves, starting at level 1 (see
+    /// [TreeConfig::threshold_at]).
+    pub fn upper_thresholds(mut self, upper_thresholds: Vec<Scalar>) -> Self {
+        self.upper_thresholds = upper_thresholds;
+        self
+    }
+
+    /// Sets per-dimension absorption thresholds of leaf entries (see
+    /// [TreeConfig::dimension_thresholds]). Unless set, [BasicConfigBuilder::threshold] defaults
+    /// to their sum, the equivalent bound on the whole diameter.
+    pub fn dimension_thresholds(mut self, dimension_thresholds: Vec<Scalar>) -> Self {
+        self.dimension_thresholds = dimension_thresholds;
+        self
+    }
+
+    /// Sets the policy used to split overflowing nodes (see [TreeConfig::split_policy]).
+    pub fn split(mut self, split: Split) -> Self {
+        self.split = split;
+        self
+    }
+
+    /// Enables or disables the post-split merge refinement (see [TreeConfig::merge_refinement]).
+    pub fn merge_refinement(mut self, merge_refinement: bool) -> Self {
+        self.merge_refinement = merge_refinement;
+        self
+    }
+
+    /// Sets the distance used to find the closest entry to new points (see [TreeConfig::metric]).
+    pub fn metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Sets how points with missing coordinates are handled (see [TreeConfig::missing_values]).
+    pub fn missing_values(mut self, missing_values: MissingValues) -> Self {
+        self.missing_values = missing_values;
+        self
+    }
+
+    /// Enables or disables tracking the ids of the points absorbed by each leaf entry (see
+    /// [TreeConfig::track_ids]).
+    pub fn track_ids(mut self, track_ids: bool) -> Self {
+        self.track_ids = track_ids;
+        self
+    }
+
+    /// Sets the number of absorbed points each leaf entry keeps as a sample (see
+    /// [TreeConfig::reservoir_size]).
+    pub fn reservoir_size(mut self, reservoir_size: usize) -> Self {
+        self.reservoir_size = reservoir_size;
+        self
+    }
+
+    /// Enables or disables assigning stable ids to leaf entries (see
+    /// [TreeConfig::stable_cluster_ids]).
+    pub fn stable_cluster_ids(mut self, stable_cluster_ids: bool) -> Self {
+        self.stable_cluster_ids = stable_cluster_ids;
+        self
+    }
+
+    /// Sets the compression of the quantile sketches each leaf entry keeps (see
+    /// [TreeConfig::quantile_compression]).
+    pub fn quantile_compression(mut self, quantile_compression: usize) -> Self {
+        self.quantile_compression = quantile_compression;
+        self
+    }
+
+    pub fn build(self) -> Result<BasicConfig, BorschtError> {
+        fn validate(capacity: &Capacity) -> Result<(), ConfigError> {
+            // a node splits once it holds `max` entries, so both halves of a split can only
+            // satisfy `min` if `min` is at most half of `max`
+            match 2 * capacity.min <= capacity.max && capacity.max >= 2 {
+                true => Ok(()),
+                false => Err(ConfigError::InvalidCapacity {
+                    min: capacity.min,
+                    max: capacity.max,
+                }),
+            }
+        }
+
+        let capacity = self.capacity.ok_or(ConfigError::Missing("capacity"))?;
+        validate(&capacity)?;
+        if let Some(leaf_capacity) = &self.leaf_capacity {
+            validate(leaf_capacity)?;
+        }
+        let threshold = match (self.threshold, self.dimension_thresholds.is_empty()) {
+            (Some(threshold), _) => threshold,
+            (None, false) => self.dimension_thresholds.iter().sum(),
+            (None, true) => return Err(ConfigError::Missing("threshold").into()),
+        };
+        if threshold.is_nan() || threshold < 0.0 {
+            return Err(ConfigError::InvalidThreshold(threshold).into());
+        }
+        if let Some(&threshold) = self
+            .upper_thresholds
+            .iter()
+            .chain(&self.dimension_thresholds)
+            .find(|threshold| threshold.is_nan() || **threshold < 0.0)
+        {
+            return Err(ConfigError::InvalidThreshold(threshold).into());
+        }
+        Ok(BasicConfig {
+            capacity,
+            leaf_capacity: self.leaf_capacity,
+            threshold,
+            upper_thresholds: self.upper_thresholds,
+            dimension_thresholds: self.dimension_thresholds,
+            split: self.split,
+            merge_refinement: self.merge_refinement,
+            metric: self.metric,
+            missing_values: self.missing_values,
+            track_ids: self.track_ids,
+            reservoir_size: self.reservoir_size,
+            stable_cluster_ids: self.stable_cluster_ids,
+            quantile_compression: self.quantile_compression,
+        })
+    }
+}
+
+/// Number of entries a node stores inline, without a heap allocation of their own. Nodes with
+/// more entries (e.g. under configurations with a larger maximum capacity) spill onto the heap.
+pub const INLINE_ENTRIES: usize = 8;
+
+/// Entries of a [Node]. Serialized as a sequence, like a `Vec`.
+pub type Entries<CF, const DIMS: usize> = SmallVec<[NodeEntry<CF, DIMS>; INLINE_ENTRIES]>;
+
+/// A node of a cluster feature tree.
+///
+/// Each node caches its [height](Node::height) and [weight](Node::weight), which are kept up to
+/// date by the tree operations of this crate. Code which modifies `entries` directly must call
+/// [Node::refresh] afterwards (after refreshing any modified child nodes).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    from = "SerializedNode<CF, DIMS>",
+    bound(deserialize = "CF: CFeature<DIMS> + Deserialize<'de>")
+)]
 pub struct Node<CF, const DIMS: usize> {
-    pub entries: Vec<NodeEntry<CF, DIMS>>,
+    pub entries: Entries<CF, DIMS>,
+    #[serde(skip)]
+    height: usize,
+    #[serde(skip)]
+    weight: Scalar,
+    #[serde(skip)]
+    min_leaf_weight: Scalar,
+}
+
+/// Serialized form of a [Node], without its cached values (which are recomputed when it is
+/// deserialized).
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "CF: CFeature<DIMS> + Deserialize<'de>"))]
+struct SerializedNode<CF, const DIMS: usize> {
+    entries: Entries<CF, DIMS>,
+}
+
+impl<CF: CFeature<DIMS>, const DIMS: usize> From<SerializedNode<CF, DIMS>> for Node<CF, DIMS> {
+    fn from(node: SerializedNode<CF, DIMS>) -> Node<CF, DIMS> {
+        let mut node = Node {
+            entries: node.entries,
+            height: 1,
+            weight: 0.0,
+            min_leaf_weight: Scalar::INFINITY,
+        };
+        node.refresh();
+        node
+    }
 }
 
 impl<CF: CFeature<DIMS>, const DIMS: usize> Node<CF, DIMS> {
     pub fn new<'a, TC: TreeConfig>(config: &'a TC) -> Node<CF, DIMS> {
+        // room for an overflowing entry before a split
+        let capacity = config.node_capacity().max.max(config.leaf_capacity().max) + 1;
         Node {
-            entries: Vec::with_capacity(config.node_capacity().max + 1),
+            entries: Entries::with_capacity(capacity),
+            height: 1,
+            weight: 0.0,
+            min_leaf_weight: Scalar::INFINITY,
+        }
+    }
+
+    /// Whether this node is a leaf node (i.e. none of its entries have children).
+    pub fn is_leaf(&self) -> bool {
+        self.entries.iter().all(|entry| entry.child.is_none())
+    }
+
+    /// The capacity which applies to this node under `config`.
+    pub(crate) fn capacity<'a, TC: TreeConfig>(&self, config: &'a TC) -> &'a Capacity {
+        match self.is_leaf() {
+            true => config.leaf_capacity(),
+            false => config.node_capacity(),
         }
     }
 
     pub fn with_entries(entries: Vec<NodeEntry<CF, DIMS>>) -> Node<CF, DIMS> {
-        Node { entries }
+        // moved into inline storage if they fit
+        let mut node = Node {
+            entries: entries.into_iter().collect(),
+            height: 1,
+            weight: 0.0,
+            min_leaf_weight: Scalar::INFINITY,
+        };
+        node.refresh();
+        node
+    }
+
+    /// Recomputes the cached height, weight and minimum leaf weight of this node from its entries
+    /// (and the cached values of their child nodes).
+    pub fn refresh(&mut self) {
+        self.height = 1 + self
+            .entries
+            .iter()
+            .map(|entry| entry.height())
+            .max()
+            .unwrap_or(0);
+        self.weight = self
+            .entries
+            .iter()
+            .map(|entry| entry.feature.size().to_scalar())
+            .fold(0.0, |total, size| total + size);
+        self.refresh_min_leaf_weight();
+    }
+
+    /// Recomputes the cached minimum leaf weight of this node from its entries (and the cached
+    /// values of their child nodes), e.g. after one of them absorbed a point.
+    fn refresh_min_leaf_weight(&mut self) {
+        self.min_leaf_weight = self
+            .entries
+            .iter()
+            .map(|entry| match entry.child {
+                Some(ref child) => child.min_leaf_weight,
+                None => entry.feature.size().to_scalar(),
+            })
+            .fold(Scalar::INFINITY, Scalar::min);
+    }
+
+    /// Returns the index of the entry of this node closest to `p`, along with its squared
+    /// distance to `p`, or `None` if this node has no entries. Ties are resolved in favor of the
+    /// first entry.
+    pub fn closest_entry(&self, p: &FeaturePoint<CF, DIMS>) -> Option<(usize, CF::Scalar)> {
+        self.entries
+            .iter()
+            .map(|entry| entry.feature.dist2(p))
+            .enumerate()
+            .fold(None, |closest, (idx, d2)| match closest {
+                Some((_, closest_d2)) if closest_d2 <= d2 => closest,
+                _ => Some((idx, d2)),
+            })
+    }
+
+    /// As [Node::closest_entry], but measuring distances with the metric of `config` (see
+    /// [TreeConfig::metric]).
+    pub fn closest_entry_with<TC: TreeConfig>(
+        &self,
+        p: &FeaturePoint<CF, DIMS>,
+        config: &TC,
+    ) -> Option<(usize, CF::Scalar)> {
+        let metric = config.metric();
+        self.entries
+            .iter()
+            .map(|entry| metric.dist2(&entry.feature, p, config))
+            .enumerate()
+            .fold(None, |closest, (idx, d2)| match closest {
+                Some((_, closest_d2)) if closest_d2 <= d2 => closest,
+                _ => Some((idx, d2)),
+            })
     }
 
+    /// Number of levels in the tree rooted at this node. A node without entries (such as the
+    /// root of an empty tree) is a single level. Cached; see [Node::compute_height].
     pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Computes the height of the tree rooted at this node by walking all of it, rather than
+    /// using the cached [Node::height] (e.g. to validate it).
+    pub fn compute_height(&self) -> usize {
         1 + self
             .entries
             .iter()
-            .map(|entry| entry.height())
+            .filter_map(|entry| entry.child.as_ref())
+            .map(|child| child.compute_height())
             .max()
             .unwrap_or(0)
     }
+
+    /// Total size (weight) of the points summarized by the tree rooted at this node. Cached; it
+    /// always equals the total size of the features of its entries.
+    pub fn weight(&self) -> Scalar {
+        self.weight
+    }
+
+    /// Smallest size (weight) of the leaf clusters of the tree rooted at this node (infinite if
+    /// it has none). Cached, like [Node::weight].
+    pub fn min_leaf_weight(&self) -> Scalar {
+        self.min_leaf_weight
+    }
+
+    /// Whether the tree rooted at this node is empty (has no entries).
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Estimate of the memory used by the subtree rooted at this node, in bytes: the node itself,
+    /// its allocated entries (including any heap memory of their features) and its descendants.
+    /// Child nodes shared with snapshots of the tree are counted in full.
+    pub fn estimated_bytes(&self) -> usize {
+        // inline entries are part of the node itself
+        let spilled = match self.entries.spilled() {
+            true => self.entries.capacity() * size_of::<NodeEntry<CF, DIMS>>(),
+            false => 0,
+        };
+        size_of::<Self>()
+            + spilled
+            + self
+                .entries
+                .iter()
+                .map(|entry| {
+                    entry.feature.heap_bytes()
+                        + entry.ids.capacity() * size_of::<u64>()
+                        + entry.samples.heap_bytes()
+                        + entry.quantiles.heap_bytes()
+                        + entry.child.as_ref().map_or(0, |child| {
+                            // strong and weak reference counts of the shared allocation
+                            2 * size_of::<usize>() + child.estimated_bytes()
+                        })
+                })
+                .sum::<usize>()
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "CF: CFeature<DIMS> + Deserialize<'de>"))]
 pub struct NodeEntry<CF, const DIMS: usize> {
     pub feature: CF,
-    pub child: Option<Node<CF, DIMS>>,
+    /// Child node of this entry, shared (copy-on-write) with any snapshots of the tree.
+    pub child: Option<Arc<Node<CF, DIMS>>>,
+    /// Ids of the points absorbed by this leaf entry, in order of insertion, if the tree tracks
+    /// them (see [TreeConfig::track_ids]). Empty for non-leaf entries.
+    pub ids: Vec<u64>,
+    /// Uniform sample of the points absorbed by this leaf entry, if the tree keeps samples (see
+    /// [TreeConfig::reservoir_size]). Empty for non-leaf entries.
+    pub samples: Reservoir<DIMS>,
+    /// Sketch of the distribution of the points absorbed by this leaf entry, if the tree keeps
+    /// sketches (see [TreeConfig::quantile_compression]). Empty for non-leaf entries.
+    pub quantiles: QuantileSketch<DIMS>,
+    /// Stable identity of this leaf entry, if the tree assigns them (see
+    /// [TreeConfig::stable_cluster_ids]). `None` for non-leaf entries.
+    pub identity: Option<ClusterIdentity>,
 }
 
 impl<CF: CFeature<DIMS>, const DIMS: usize> Default for NodeEntry<CF, DIMS> {
@@ -79,15 +725,47 @@ impl<CF: CFeature<DIMS>, const DIMS: usize> Default for NodeEntry<CF, DIMS> {
         NodeEntry {
             feature: CF::zero(),
             child: None,
+            ids: vec![],
+            samples: Reservoir::default(),
+            quantiles: QuantileSketch::default(),
+            identity: None,
         }
     }
 }
 
 impl<'a, CF: CFeature<DIMS>, const DIMS: usize> NodeEntry<CF, DIMS> {
-    fn with_point(orig: Point<DIMS>) -> NodeEntry<CF, DIMS> {
+    pub(crate) fn with_feature(feature: CF) -> NodeEntry<CF, DIMS> {
+        NodeEntry {
+            feature,
+            child: None,
+            ids: vec![],
+            samples: Reservoir::default(),
+            quantiles: QuantileSketch::default(),
+            identity: None,
+        }
+    }
+    /// Leaf entry for the point `p`, which keeps its id if `config` tracks ids.
+    pub(crate) fn with_point<TC: TreeConfig>(
+        p: FeaturePoint<CF, DIMS>,
+        id: u64,
+        config: &TC,
+    ) -> NodeEntry<CF, DIMS> {
         NodeEntry {
-            feature: CF::from(orig),
+            ids: match config.track_ids() {
+                true => vec![id],
+                false => vec![],
+            },
+            samples: match config.reservoir_size() {
+                0 => Reservoir::default(),
+                _ => Reservoir::with_sample(&p, id),
+            },
+            quantiles: match config.quantile_compression() {
+                0 => QuantileSketch::default(),
+                _ => QuantileSketch::with_point(&p),
+            },
+            feature: CF::from(p),
             child: None,
+            identity: None,
         }
     }
     fn height(&self) -> usize {
@@ -102,21 +780,50 @@ pub enum EntryInsertion<Point> {
 }
 
 impl<CF: CFeature<DIMS>, const DIMS: usize> NodeEntry<CF, DIMS> {
-    fn insert<'a, TC: TreeConfig>(
+    /// Absorbs the (leaf) entry `entry` into this leaf entry if the threshold allows, along with
+    /// its ids.
+    pub(crate) fn insert<TC: TreeConfig>(
         &mut self,
-        p: Point<DIMS>,
-        config: &'a TC,
-    ) -> EntryInsertion<Point<DIMS>> {
-        // check if feature can absorb point
-        let feature_with_point = self.feature.clone() + &p;
-        match feature_with_point.diam2() <= config.threshold() {
+        mut entry: NodeEntry<CF, DIMS>,
+        config: &TC,
+    ) -> EntryInsertion<NodeEntry<CF, DIMS>> {
+        // check if this entry's feature can absorb the new feature
+        let absorbed = self.feature.clone() + &entry.feature;
+        match within_leaf_threshold(&absorbed, config) {
             true => {
-                self.feature = feature_with_point;
+                self.feature = absorbed;
+                self.ids.append(&mut entry.ids);
+                self.samples.merge(entry.samples, config.reservoir_size());
+                self.quantiles
+                    .merge(entry.quantiles, config.quantile_compression());
+                merge_identity(&mut self.identity, entry.identity);
                 EntryInsertion::Success
             }
-            false => EntryInsertion::Failure(p),
+            false => EntryInsertion::Failure(entry),
         }
     }
+
+    /// Absorbs the (leaf) entry `entry` into this leaf entry regardless of the threshold, along
+    /// with its ids.
+    pub(crate) fn absorb<TC: TreeConfig>(&mut self, mut entry: NodeEntry<CF, DIMS>, config: &TC) {
+        self.feature = core::mem::replace(&mut self.feature, CF::zero()) + &entry.feature;
+        self.ids.append(&mut entry.ids);
+        self.samples.merge(entry.samples, config.reservoir_size());
+        self.quantiles
+            .merge(entry.quantiles, config.quantile_compression());
+        merge_identity(&mut self.identity, entry.identity);
+    }
+}
+
+/// Outcome of inserting a single point (or cluster feature) into a tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InsertOutcome {
+    /// The point was absorbed into an existing leaf entry.
+    Absorbed,
+    /// The point started a new leaf entry.
+    NewEntry,
+    /// The point started a new leaf entry, which caused one or more nodes to split.
+    Split,
 }
 
 #[derive(Debug, Clone)]
@@ -129,207 +836,1862 @@ impl<CF, const DIMS: usize> Node<CF, DIMS>
 where
     CF: CFeature<DIMS> + Debug + Clone,
 {
-    fn compute_feature(&self) -> CF {
+    pub(crate) fn compute_feature(&self) -> CF {
         self.entries
             .iter()
             .map(|entry| &entry.feature)
             .fold(CF::zero(), |acc, feature| acc + feature)
     }
-    fn check_split<'a, TC: TreeConfig>(mut self, config: &'a TC) -> NodeInsertion<Self> {
-        match self.entries.len() >= config.node_capacity().max {
+
+    /// Removes the leaf entries of the subtree rooted at this node whose size is less than
+    /// `min_weight`, along with any nodes left without entries, and recomputes the features of
+    /// the remaining ancestors. The removed features are appended to `pruned`.
+    pub fn prune_min_size(&mut self, min_weight: CF::Scalar, pruned: &mut Vec<CF>) {
+        self.entries.retain_mut(|entry| match entry.child {
+            Some(ref mut child) => {
+                let child = Arc::make_mut(child);
+                child.prune_min_size(min_weight, pruned);
+                if child.entries.is_empty() {
+                    return false;
+                }
+                entry.feature = child.compute_feature();
+                true
+            }
+            None if entry.feature.size() < min_weight => {
+                pruned.push(entry.feature.clone());
+                false
+            }
+            None => true,
+        });
+        self.refresh();
+    }
+    pub(crate) fn check_split<TC: TreeConfig>(mut self, config: &TC) -> NodeInsertion<Self> {
+        let capacity = self.capacity(config);
+        match self.entries.len() >= capacity.max {
             true => {
                 // time to split!
-                // find farthest
-                let Farthest {
-                    lidx,
-                    ridx,
-                    mut rest,
-                    ..
-                } = self.farthest();
-                // assign entries to respective closest
-                let lset = rest
-                    .drain()
-                    .filter(|&idx| {
-                        self.entries[lidx].feature.dist2(&self.entries[idx].feature)
-                            < self.entries[ridx].feature.dist2(&self.entries[idx].feature)
-                    })
-                    .collect::<HashSet<_>>();
+                let partition = partition_features(
+                    self.entries.iter().map(|entry| &entry.feature),
+                    capacity,
+                    config,
+                );
                 // return split
-                let (left, right) =
-                    self.entries
-                        .drain(..)
-                        .enumerate()
-                        .partition_map(|(idx, entry)| match lset.contains(&idx) {
+                let (left, right): (Vec<_>, Vec<_>) =
+                    self.entries.drain(..).zip(partition).partition_map(
+                        |(entry, left)| match left {
                             true => Either::Left(entry),
                             false => Either::Right(entry),
-                        });
+                        },
+                    );
+                debug_event!(left = left.len(), right = right.len(), "node split");
 
                 NodeInsertion::Split(Node::with_entries(left), Node::with_entries(right))
             }
-            _ => NodeInsertion::Single(self),
+            _ => {
+                self.refresh();
+                NodeInsertion::Single(self)
+            }
         }
     }
 
-    fn insert<'a, TC: TreeConfig>(mut self, p: Point<DIMS>, config: &'a TC) -> NodeInsertion<Self> {
-        // find closest cluster
-        match self
-            .entries
-            .iter_mut()
-            .fold((None, Scalar::max_value()), |(_, closest_dist2), entry| {
-                let d2 = entry.feature.dist2(&p);
-                match d2 < closest_dist2 {
-                    true => (Some(entry), d2),
-                    false => (None, closest_dist2),
+    /// Merges the two closest non-leaf entries of this node (resplitting the merged node if it
+    /// exceeds capacity), unless they are the two entries at indices `split` which were just
+    /// produced by a split.
+    fn merge_closest<TC: TreeConfig>(&mut self, split: (usize, usize), config: &TC) {
+        let closest = closest_pair(
+            self.entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| entry.child.is_some())
+                .map(|(idx, entry)| (idx, &entry.feature)),
+        );
+        let (lidx, ridx) = match closest {
+            Some((lidx, ridx)) if (lidx, ridx) != split && (ridx, lidx) != split => (lidx, ridx),
+            _ => return,
+        };
+        debug_event!(left = lidx, right = ridx, "merging closest entries");
+        // lidx < ridx, so remove the right entry first
+        let right = unshare(self.entries.remove(ridx).child.expect("non-leaf entry"));
+        let mut merged = unshare(self.entries.remove(lidx).child.expect("non-leaf entry"));
+        merged.entries.extend(right.entries);
+        // keep the merged entries where the left entry was
+        let nodes = match merged.check_split(config) {
+            NodeInsertion::Single(node) => vec![node],
+            NodeInsertion::Split(left, right) => vec![left, right],
+        };
+        self.entries.insert_many(
+            lidx,
+            nodes.into_iter().map(|node| NodeEntry {
+                feature: node.compute_feature(),
+                child: Some(Arc::new(node)),
+                ids: vec![],
+                samples: Reservoir::default(),
+                quantiles: QuantileSketch::default(),
+                identity: None,
+            }),
+        );
+        self.refresh();
+    }
+
+    /// Squared distances of the entries of this node from `feature`, as measured to route the
+    /// feature during insertion.
+    pub(crate) fn distances2_to_feature<'a, TC: TreeConfig>(
+        &'a self,
+        feature: &'a CF,
+        config: &'a TC,
+    ) -> impl Iterator<Item = CF::Scalar> + 'a {
+        let metric = config.metric();
+        let center = feature.center();
+        self.entries.iter().map(move |entry| match metric {
+            Metric::Euclidean => entry.feature.dist2(feature),
+            Metric::Mahalanobis => metric.dist2(&entry.feature, &center, config),
+        })
+    }
+
+    /// Index of the entry of this node closest to `feature` (under the metric of `config`); see
+    /// [Node::closest_entry].
+    pub(crate) fn closest_to_feature<TC: TreeConfig>(
+        &self,
+        feature: &CF,
+        config: &TC,
+    ) -> Option<usize> {
+        self.distances2_to_feature(feature, config)
+            .enumerate()
+            .fold(
+                None,
+                |closest: Option<(usize, CF::Scalar)>, (idx, d2)| match closest {
+                    Some((_, closest_d2)) if closest_d2 <= d2 => closest,
+                    _ => Some((idx, d2)),
+                },
+            )
+            .map(|(idx, _)| idx)
+    }
+
+    fn insert<TC: TreeConfig, I: NewId>(
+        self,
+        entry: NodeEntry<CF, DIMS>,
+        config: &TC,
+        split_depths: &mut Vec<usize>,
+        mut splits: Option<&mut Vec<TraceEvent<DIMS>>>,
+        new_id: I,
+        constraints: Option<&ConstraintSet>,
+    ) -> (NodeInsertion<Self>, InsertOutcome, I) {
+        // the inserted feature, added to the features of the ancestors of the node it ends up in
+        // unless that node splits
+        let delta = entry.feature.clone();
+        let delta_size = delta.size().to_scalar();
+        // descend to the node where the entry is inserted, detaching each child node from its
+        // parent along the way
+        let mut path = vec![];
+        let mut node = self;
+        // the path to the leaf entry holding points must-linked to the inserted ones, if any,
+        // which absorbs the inserted feature regardless of the thresholds (see
+        // [crate::constraints])
+        let route = constraints.and_then(|constraints| constraints.route(&node, &entry.ids));
+        // largest threshold of the entries above the leaves which absorbed the inserted feature,
+        // if any (see [TreeConfig::threshold_at])
+        let mut absorbing = None;
+        let mut outcome = InsertOutcome::NewEntry;
+        // stable id of the leaf entry holding the inserted feature (see [NewId])
+        let holder;
+        let mut insertion = loop {
+            let closest = match &route {
+                Some(route) => Some(route[path.len()]),
+                None => node.closest_to_feature(&entry.feature, config),
+            };
+            match closest {
+                Some(idx) if node.entries[idx].child.is_some() => {
+                    trace_event!(depth = path.len(), entry = idx, "descending");
+                    let threshold = config.threshold_at(node.height - 1);
+                    if absorbs(
+                        &node.entries[idx].feature,
+                        &entry.feature,
+                        threshold,
+                        absorbing,
+                    ) {
+                        absorbing = Some(threshold);
+                    }
+                    let child = unshare(node.entries[idx].child.take().expect("non-leaf entry"));
+                    path.push((node, idx));
+                    node = child;
                 }
-            })
-            .0
-        {
-            Some(entry) if entry.child.is_some() => {
-                let child_node = entry.child.as_mut().unwrap();
-                let mut temp_node = Node::new(config);
-                // make empty node the temporary child of this entry
-                std::mem::swap(child_node, &mut temp_node);
-                // insert into previous child node
-                match temp_node.insert(p, config) {
-                    NodeInsertion::Split(mut left, right) => {
-                        // put the 'left' into the previous spot where child was
-                        std::mem::swap(child_node, &mut left);
-                        // update computed features of left
-                        entry.feature = child_node.compute_feature();
-                        // add new entry with 'right'
-                        self.entries.push(NodeEntry {
-                            feature: right.compute_feature(),
-                            child: Some(right),
-                        });
-                        self.check_split(config)
+                Some(idx)
+                    if constraints.is_some_and(|constraints| {
+                        constraints.conflict(&entry.ids, &node.entries[idx].ids)
+                    }) =>
+                {
+                    trace_event!(
+                        depth = path.len(),
+                        entry = idx,
+                        "new leaf entry, cannot link"
+                    );
+                    let mut entry = entry;
+                    holder = new_id.identify(&mut entry);
+                    node.entries.push(entry);
+                    break node.check_split(config);
+                }
+                Some(idx)
+                    if route.is_some()
+                        || absorbing.is_some_and(|threshold| {
+                            (node.entries[idx].feature.clone() + &entry.feature).diam2()
+                                <= CF::Scalar::from_scalar(threshold)
+                        }) =>
+                {
+                    trace_event!(depth = path.len(), entry = idx, "absorbed above the leaves");
+                    node.entries[idx].absorb(entry, config);
+                    holder = new_id.identify(&mut node.entries[idx]);
+                    outcome = InsertOutcome::Absorbed;
+                    node.weight += delta_size;
+                    node.refresh_min_leaf_weight();
+                    break NodeInsertion::Single(node);
+                }
+                Some(idx) => match node.entries[idx].insert(entry, config) {
+                    EntryInsertion::Success => {
+                        trace_event!(depth = path.len(), entry = idx, "absorbed into leaf entry");
+                        holder = new_id.identify(&mut node.entries[idx]);
+                        outcome = InsertOutcome::Absorbed;
+                        node.weight += delta_size;
+                        node.refresh_min_leaf_weight();
+                        break NodeInsertion::Single(node);
                     }
-                    NodeInsertion::Single(mut node) => {
-                        std::mem::swap(child_node, &mut node);
-                        // update computed features of node
-                        entry.feature = child_node.compute_feature();
-                        NodeInsertion::Single(self)
+                    EntryInsertion::Failure(mut entry) => {
+                        trace_event!(depth = path.len(), "new leaf entry");
+                        holder = new_id.identify(&mut entry);
+                        node.entries.push(entry);
+                        break node.check_split(config);
                     }
+                },
+                None => {
+                    trace_event!(depth = path.len(), "new leaf entry in empty node");
+                    let mut entry = entry;
+                    holder = new_id.identify(&mut entry);
+                    node.entries.push(entry);
+                    node.refresh();
+                    break NodeInsertion::Single(node);
                 }
             }
-            Some(entry) => match entry.insert(p, config) {
-                EntryInsertion::Success => NodeInsertion::Single(self),
-                EntryInsertion::Failure(p) => {
-                    self.entries.push(NodeEntry::with_point(p));
-                    self.check_split(config)
-                }
-            },
-            None => {
-                self.entries.push(NodeEntry::with_point(p));
-                NodeInsertion::Single(self)
+        };
+        if let NodeInsertion::Split(left, right) = &insertion {
+            split_depths.push(path.len());
+            if let Some(splits) = splits.as_deref_mut() {
+                splits.push(TraceEvent::split(path.len(), left, right));
             }
         }
+        // reattach child nodes on the way back up, propagating splits
+        while let Some((mut parent, idx)) = path.pop() {
+            insertion = match insertion {
+                NodeInsertion::Single(child) => {
+                    // the child holds the same entries as before plus the inserted one (merges
+                    // below only regroup them), so its feature just grows by the inserted feature
+                    let parent_entry = &mut parent.entries[idx];
+                    let feature = core::mem::replace(&mut parent_entry.feature, CF::zero());
+                    parent_entry.feature = feature + &delta;
+                    parent.height = parent.height.max(child.height + 1);
+                    parent.weight += delta_size;
+                    parent.entries[idx].child = Some(Arc::new(child));
+                    parent.refresh_min_leaf_weight();
+                    NodeInsertion::Single(parent)
+                }
+                NodeInsertion::Split(left, right) => {
+                    outcome = InsertOutcome::Split;
+                    // put the 'left' into the previous spot where the child was, and add a new
+                    // entry with 'right'
+                    parent.entries[idx] = NodeEntry {
+                        feature: left.compute_feature(),
+                        child: Some(Arc::new(left)),
+                        ids: vec![],
+                        samples: Reservoir::default(),
+                        quantiles: QuantileSketch::default(),
+                        identity: None,
+                    };
+                    parent.entries.push(NodeEntry {
+                        feature: right.compute_feature(),
+                        child: Some(Arc::new(right)),
+                        ids: vec![],
+                        samples: Reservoir::default(),
+                        quantiles: QuantileSketch::default(),
+                        identity: None,
+                    });
+                    match parent.check_split(config) {
+                        NodeInsertion::Single(mut node) if config.merge_refinement() => {
+                            // split propagation stops here
+                            let split = (idx, node.entries.len() - 1);
+                            node.merge_closest(split, config);
+                            NodeInsertion::Single(node)
+                        }
+                        NodeInsertion::Single(node) => NodeInsertion::Single(node),
+                        NodeInsertion::Split(left, right) => {
+                            split_depths.push(path.len());
+                            if let Some(splits) = splits.as_deref_mut() {
+                                splits.push(TraceEvent::split(path.len(), &left, &right));
+                            }
+                            NodeInsertion::Split(left, right)
+                        }
+                    }
+                }
+            };
+        }
+        (insertion, outcome, holder)
     }
 
-    pub fn from_iter<'a, T: IntoIterator<Item = Point<DIMS>>, TC: TreeConfig>(
+    pub fn from_iter<'a, T: IntoIterator<Item = FeaturePoint<CF, DIMS>>, TC: TreeConfig>(
         iter: T,
         config: &'a TC,
     ) -> Self {
         let mut root = Node::new(config);
-        for (_i, p) in iter.into_iter().enumerate() {
-            match root.insert(p, config) {
-                NodeInsertion::Single(node) => {
-                    root = node;
-                }
-                NodeInsertion::Split(left, right) => {
-                    root = Node {
-                        entries: vec![
-                            NodeEntry {
-                                feature: left.compute_feature(),
-                                child: Some(left),
-                            },
-                            NodeEntry {
-                                feature: right.compute_feature(),
-                                child: Some(right),
-                            },
-                        ],
-                    };
-                }
+        let mut new_entries = 0;
+        for (id, p) in iter.into_iter().enumerate() {
+            let p = root.complete(p, config);
+            let entry = NodeEntry::with_point(p, id as u64, config);
+            let new_id = match config.stable_cluster_ids() {
+                true => Some(new_entries),
+                false => None,
+            };
+            let (node, outcome, _) =
+                root.insert_root(entry, config, &mut vec![], None, new_id, None);
+            if outcome != InsertOutcome::Absorbed {
+                new_entries += 1;
             }
-            // root.display_tree();
+            root = node;
         }
         root
     }
+
+    /// Fills in the missing coordinates of `p` for insertion into the tree rooted at this node,
+    /// unless the configuration propagates missing values (see [MissingValues]).
+    pub(crate) fn complete<TC: TreeConfig>(
+        &self,
+        p: FeaturePoint<CF, DIMS>,
+        config: &TC,
+    ) -> FeaturePoint<CF, DIMS> {
+        if config.missing_values() == MissingValues::Propagate
+            || !p.as_slice().iter().any(|x| x.is_nan())
+        {
+            return p;
+        }
+        let fill = self
+            .nearest_leaf(&p, config)
+            .map(|feature| feature.center())
+            .unwrap_or_default();
+        fill_missing(p, &fill)
+    }
+
+    /// Inserts a leaf entry into the tree rooted at this node, growing a new root if the insertion
+    /// splits this one. The depths of the nodes split by the insertion are appended to
+    /// `split_depths`, and the splits themselves to `splits` if given. If the leaf entry which ends
+    /// up holding the inserted one has no identity, it gets the stable id `new_id`, if any (see
+    /// [NewId]). The entry is routed according to `constraints`, if given (see
+    /// [crate::constraints]). Also returns the stable id of the leaf entry holding the inserted one.
+    fn insert_root<TC: TreeConfig, I: NewId>(
+        self,
+        entry: NodeEntry<CF, DIMS>,
+        config: &TC,
+        split_depths: &mut Vec<usize>,
+        splits: Option<&mut Vec<TraceEvent<DIMS>>>,
+        new_id: I,
+        constraints: Option<&ConstraintSet>,
+    ) -> (Self, InsertOutcome, I) {
+        enter_trace_span!("insert");
+        let (insertion, outcome, holder) =
+            self.insert(entry, config, split_depths, splits, new_id, constraints);
+        match insertion {
+            NodeInsertion::Single(node) => (node, outcome, holder),
+            NodeInsertion::Split(left, right) => {
+                debug_event!("root split, growing a new root");
+                (
+                    Node::with_entries(vec![
+                        NodeEntry {
+                            feature: left.compute_feature(),
+                            child: Some(Arc::new(left)),
+                            ids: vec![],
+                            samples: Reservoir::default(),
+                            quantiles: QuantileSketch::default(),
+                            identity: None,
+                        },
+                        NodeEntry {
+                            feature: right.compute_feature(),
+                            child: Some(Arc::new(right)),
+                            ids: vec![],
+                            samples: Reservoir::default(),
+                            quantiles: QuantileSketch::default(),
+                            identity: None,
+                        },
+                    ]),
+                    InsertOutcome::Split,
+                    holder,
+                )
+            }
+        }
+    }
 }
 
-struct Farthest {
-    farthest_dist2: Scalar,
-    lidx: usize,
-    ridx: usize,
-    rest: HashSet<usize>,
+/// Whether an entry above the leaves with feature `feature` and threshold `threshold` (see
+/// [TreeConfig::threshold_at]) absorbs `inserted` under a looser bound than `absorbing`, the
+/// largest threshold of the entries which absorbed it higher up, if any.
+pub(crate) fn absorbs<CF: CFeature<DIMS>, const DIMS: usize>(
+    feature: &CF,
+    inserted: &CF,
+    threshold: Scalar,
+    absorbing: Option<Scalar>,
+) -> bool {
+    threshold > absorbing.unwrap_or(0.0)
+        && (feature.clone() + inserted).diam2() <= CF::Scalar::from_scalar(threshold)
 }
 
-impl<CF, const DIMS: usize> Node<CF, DIMS>
+/// Takes ownership of a child node, cloning it if it is shared with a snapshot.
+pub(crate) fn unshare<CF: Clone, const DIMS: usize>(node: Arc<Node<CF, DIMS>>) -> Node<CF, DIMS> {
+    Arc::try_unwrap(node).unwrap_or_else(|node| (*node).clone())
+}
+
+/// Partitions the features of the entries of a node which has reached `capacity` into two groups
+/// using the split policy of `config`, returning for each entry whether it belongs to the first
+/// group. Both groups honor the minimum capacity.
+pub(crate) fn partition_features<'a, CF, TC, const DIMS: usize>(
+    features: impl Iterator<Item = &'a CF>,
+    capacity: &Capacity,
+    config: &TC,
+) -> Vec<bool>
 where
-    CF: CFeature<DIMS> + Debug + Clone,
+    CF: CFeature<DIMS> + 'a,
+    TC: TreeConfig,
 {
-    fn farthest<'b>(&'b self) -> Farthest {
-        let pairwise_iter = self.entries.iter().enumerate().tuple_combinations();
+    let split_entries = features
+        .map(|feature| {
+            let center = feature.center();
+            SplitEntry {
+                center: (0..DIMS).map(|d| center[d].to_scalar()).collect(),
+                size: feature.size().to_scalar(),
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut partition = config.split_policy().partition(&split_entries);
+    debug_assert!(partition.iter().any(|&left| left));
+    debug_assert!(partition.iter().any(|&left| !left));
+    // each side must hold at least `min` entries, and (since the entries of two nodes can be
+    // resplit after a merge) must not itself be over capacity
+    let min = capacity
+        .min
+        .max((split_entries.len() + 1).saturating_sub(capacity.max));
+    rebalance(&split_entries, &mut partition, min);
+    partition
+}
 
-        let tracker = pairwise_iter
-            .fold(
-                None,
-                |tracker: Option<Farthest>, ((lidx, lnode), (ridx, rnode))| {
-                    let dist2 = lnode.feature.dist2(&rnode.feature);
-                    Some(match tracker {
-                        Some(mut t) if dist2 > t.farthest_dist2 => {
-                            // switched farthest nodes
-                            t.rest.insert(t.lidx);
-                            t.rest.insert(t.ridx);
-                            Farthest {
-                                farthest_dist2: dist2,
-                                lidx,
-                                ridx,
-                                rest: t.rest,
-                            }
-                        }
-                        Some(mut t) => {
-                            // no change to farthest nodes
-                            t.rest.insert(lidx);
-                            t.rest.insert(ridx);
-                            t
-                        }
-                        // initial
-                        None => Farthest {
-                            farthest_dist2: dist2,
-                            lidx,
-                            ridx,
-                            rest: HashSet::new(),
-                        },
-                    })
-                },
-            )
-            .expect("unexpected empty node");
-        tracker
+/// Returns the indices of the closest pair among the indexed `features`, if there are at least
+/// two.
+pub(crate) fn closest_pair<'a, CF, const DIMS: usize>(
+    features: impl Iterator<Item = (usize, &'a CF)> + Clone,
+) -> Option<(usize, usize)>
+where
+    CF: CFeature<DIMS> + 'a,
+{
+    features
+        .tuple_combinations()
+        .map(|((lidx, lfeature), (ridx, rfeature))| (lidx, ridx, lfeature.dist2(rfeature)))
+        .fold(
+            None,
+            |closest: Option<(usize, usize, CF::Scalar)>, pair| match closest {
+                Some(closest) if closest.2 <= pair.2 => Some(closest),
+                _ => Some(pair),
+            },
+        )
+        .map(|(lidx, ridx, _)| (lidx, ridx))
+}
+
+/// Operational counters of a [CFTree], e.g. for tuning its threshold and capacities.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeMetrics {
+    /// Number of points and features inserted.
+    pub inserted: u64,
+    /// Number of insertions absorbed into an existing leaf entry.
+    pub absorbed: u64,
+    /// Number of insertions which created a new leaf entry (whether or not they caused a split).
+    pub new_entries: u64,
+    /// Number of node splits by the depth of the split node at the time (the root has depth 0;
+    /// splitting the root grows the tree by a level).
+    pub splits: Vec<u64>,
+    /// Number of times the tree was rebuilt from its leaf entries (e.g. by a
+    /// [StandardizedCFTree](crate::standardized::StandardizedCFTree)).
+    pub rebuilds: u64,
+    /// Number of leaf entries removed as outliers by [CFTree::prune_min_size].
+    pub pruned: u64,
+}
+
+impl TreeMetrics {
+    /// Total number of node splits, at any depth.
+    pub fn total_splits(&self) -> u64 {
+        self.splits.iter().sum()
+    }
+
+    pub(crate) fn record(&mut self, outcome: InsertOutcome, split_depths: &[usize]) {
+        self.inserted += 1;
+        match outcome {
+            InsertOutcome::Absorbed => self.absorbed += 1,
+            InsertOutcome::NewEntry | InsertOutcome::Split => self.new_entries += 1,
+        }
+        for &depth in split_depths {
+            if self.splits.len() <= depth {
+                self.splits.resize(depth + 1, 0);
+            }
+            self.splits[depth] += 1;
+        }
     }
 }
 
-pub type BirchTree<const DIMS: usize> = Node<BirchFeature<DIMS>, DIMS>;
-pub type BetulaTree<const DIMS: usize> = Node<BetulaFeature<DIMS>, DIMS>;
+/// Source of cancellation requests for long-running builds (see
+/// [CFTree::from_iter_cancellable]). Implemented for [AtomicBool]s (e.g. shared with another
+/// thread in an [Arc]) and for closures returning whether to cancel.
+pub trait CancellationToken {
+    fn is_cancelled(&self) -> bool;
+}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl CancellationToken for AtomicBool {
+    fn is_cancelled(&self) -> bool {
+        self.load(Ordering::Relaxed)
+    }
+}
 
-    #[test]
-    fn from_arr() {
-        let mut points = vec![
-            Point::from_arr([1.0, 2.0, 3.0]),
-            Point::from_arr([2.0, 2.0, 3.0]),
-            Point::from_arr([1.0, 3.0, 3.0]),
-            Point::from_arr([1.0, 2.0, 4.0]),
-        ];
-        let root = BirchTree::from_iter(
-            points.drain(..),
-            &BasicConfig {
-                capacity: Capacity { min: 1, max: 3 },
-                threshold: 0.5,
-            },
+impl<F: Fn() -> bool> CancellationToken for F {
+    fn is_cancelled(&self) -> bool {
+        self()
+    }
+}
+
+/// A cluster feature tree: a root [Node] together with the configuration used to build it.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "CF: CFeature<DIMS> + Deserialize<'de>, TC: Deserialize<'de>"))]
+pub struct CFTree<CF, const DIMS: usize, TC = BasicConfig> {
+    root: Node<CF, DIMS>,
+    config: TC,
+    metrics: TreeMetrics,
+    #[serde(skip)]
+    recorder: Option<TraceRecorder<DIMS>>,
+    #[serde(skip)]
+    constraints: Option<ConstraintSet>,
+    /// Stable id of the next new leaf entry (see [TreeConfig::stable_cluster_ids]).
+    next_cluster_id: u64,
+}
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    TC: TreeConfig,
+{
+    pub fn new(config: TC) -> CFTree<CF, DIMS, TC> {
+        CFTree {
+            root: Node::new(&config),
+            config,
+            metrics: TreeMetrics::default(),
+            recorder: None,
+            constraints: None,
+            next_cluster_id: 0,
+        }
+    }
+
+    pub fn from_iter<T: IntoIterator<Item = FeaturePoint<CF, DIMS>>>(
+        iter: T,
+        config: TC,
+    ) -> CFTree<CF, DIMS, TC> {
+        let mut tree = CFTree::new(config);
+        tree.extend(iter);
+        tree
+    }
+
+    /// Creates a tree from `iter`, calling `callback` with the number of points inserted so far
+    /// and the tree's [metrics](TreeMetrics) after every `every` points (and once more at the end
+    /// if the number of points isn't a multiple of `every`). The callback can stop the build early
+    /// by returning [ControlFlow::Break], in which case the tree of the points inserted so far is
+    /// returned.
+    pub fn from_iter_with_progress<T, F>(
+        iter: T,
+        config: TC,
+        every: usize,
+        mut callback: F,
+    ) -> CFTree<CF, DIMS, TC>
+    where
+        T: IntoIterator<Item = FeaturePoint<CF, DIMS>>,
+        F: FnMut(usize, &TreeMetrics) -> ControlFlow<()>,
+    {
+        let every = every.max(1);
+        let mut tree = CFTree::new(config);
+        let mut processed = 0;
+        for p in iter {
+            tree.insert(p);
+            processed += 1;
+            if processed % every == 0 && callback(processed, &tree.metrics).is_break() {
+                return tree;
+            }
+        }
+        if processed % every != 0 {
+            let _ = callback(processed, &tree.metrics);
+        }
+        tree
+    }
+
+    /// Creates a tree from `iter`, checking `cancel` before each insertion. Returns the tree,
+    /// along with the rest of the iterator if the build was cancelled (which may be empty, if
+    /// cancellation came after the last point), so the build can be resumed later with
+    /// [Extend::extend].
+    pub fn from_iter_cancellable<T, C>(
+        iter: T,
+        config: TC,
+        cancel: &C,
+    ) -> (CFTree<CF, DIMS, TC>, Option<T::IntoIter>)
+    where
+        T: IntoIterator<Item = FeaturePoint<CF, DIMS>>,
+        C: CancellationToken + ?Sized,
+    {
+        let mut tree = CFTree::new(config);
+        let mut iter = iter.into_iter();
+        loop {
+            if cancel.is_cancelled() {
+                return (tree, Some(iter));
+            }
+            match iter.next() {
+                Some(p) => {
+                    tree.insert(p);
+                }
+                None => return (tree, None),
+            }
+        }
+    }
+
+    /// Creates a tree from points which are first mapped through `transform`, e.g. a fitted
+    /// [scaler](crate::preprocess) or a [projection](crate::projection) from a higher-dimensional
+    /// space. Cluster features of the tree summarize the transformed points.
+    pub fn from_iter_with_transform<P, T, X>(
+        iter: T,
+        transform: &X,
+        config: TC,
+    ) -> CFTree<CF, DIMS, TC>
+    where
+        T: IntoIterator<Item = P>,
+        X: Transform<P, FeaturePoint<CF, DIMS>>,
+    {
+        Self::from_iter(iter.into_iter().map(|p| transform.transform(p)), config)
+    }
+
+    /// Inserts a single point into this tree. If the tree tracks ids (see
+    /// [TreeConfig::track_ids]), the id of the point is its index in insertion order, i.e. the
+    /// number of points inserted before it.
+    pub fn insert(&mut self, p: FeaturePoint<CF, DIMS>) -> InsertOutcome {
+        self.insert_with_id(p, self.metrics.inserted)
+    }
+
+    /// Inserts a single point with the id `id` (e.g. the key of the record it was derived from)
+    /// into this tree. The id is only kept if the tree tracks ids (see [TreeConfig::track_ids]).
+    pub fn insert_with_id(&mut self, p: FeaturePoint<CF, DIMS>, id: u64) -> InsertOutcome {
+        let p = self.root.complete(p, &self.config);
+        self.insert_entry(NodeEntry::with_point(p, id, &self.config))
+    }
+
+    /// Inserts a cluster feature (summarizing any number of points) into this tree. The feature
+    /// is absorbed into the closest leaf entry if the threshold allows, and otherwise becomes a
+    /// new leaf entry.
+    pub fn insert_feature(&mut self, feature: CF) -> InsertOutcome {
+        self.insert_entry(NodeEntry::with_feature(feature))
+    }
+
+    /// Absorbs the cluster feature `feature` into the leaf entry it's closest to (as found by
+    /// [CFTree::insert_feature]) if the threshold allows, or gives it back without inserting it
+    /// otherwise.
+    pub fn try_absorb_feature(&mut self, feature: CF) -> Result<(), CF> {
+        let mut node = &self.root;
+        let fits = loop {
+            match node.closest_to_feature(&feature, &self.config) {
+                Some(idx) => match &node.entries[idx].child {
+                    Some(child) => node = child,
+                    None => {
+                        let absorbed = node.entries[idx].feature.clone() + &feature;
+                        break within_leaf_threshold(&absorbed, &self.config);
+                    }
+                },
+                None => break false,
+            }
+        };
+        match fits {
+            true => {
+                self.insert_feature(feature);
+                Ok(())
+            }
+            false => Err(feature),
+        }
+    }
+
+    /// Inserts a leaf entry (with any ids it tracks) into this tree; see
+    /// [CFTree::insert_feature].
+    pub(crate) fn insert_entry(&mut self, entry: NodeEntry<CF, DIMS>) -> InsertOutcome {
+        let new_id = self.new_cluster_id();
+        self.insert_identified_entry(entry, new_id).0
+    }
+
+    /// Inserts a leaf entry like [CFTree::insert_entry], giving the leaf entry which ends up
+    /// holding it a stable id if it has none, whether or not the tree assigns them. Returns the
+    /// stable id of that leaf entry.
+    pub(crate) fn insert_held_entry(&mut self, entry: NodeEntry<CF, DIMS>) -> (InsertOutcome, u64) {
+        let new_id = self.next_cluster_id;
+        self.insert_identified_entry(entry, new_id)
+    }
+
+    /// Inserts a leaf entry like [CFTree::insert_entry], giving the leaf entry which ends up
+    /// holding it the stable id `new_id` (if any; see [NewId]) if it has none. Returns the stable
+    /// id of that leaf entry.
+    fn insert_identified_entry<I: NewId>(
+        &mut self,
+        entry: NodeEntry<CF, DIMS>,
+        new_id: I,
+    ) -> (InsertOutcome, I) {
+        let root = core::mem::replace(&mut self.root, Node::new(&self.config));
+        let mut split_depths = vec![];
+        // only summarize the entry and collect splits if they're recorded
+        let traced = self
+            .recorder
+            .as_ref()
+            .map(|_| (TracedEntry::of(&entry.feature), vec![]));
+        let (traced_entry, mut splits) = traced.unzip();
+        // leaf entries are numbered in order of creation; reinserted entries (e.g. while
+        // rebuilding) keep their ids, and so never take `new_id`
+        let identified = match entry.identity {
+            Some(ref identity) => {
+                self.next_cluster_id = self.next_cluster_id.max(identity.next_id());
+                true
+            }
+            None => false,
+        };
+        let (root, outcome, holder) = root.insert_root(
+            entry,
+            &self.config,
+            &mut split_depths,
+            splits.as_mut(),
+            new_id,
+            self.constraints.as_ref(),
+        );
+        if let (Some(recorder), Some(entry), Some(splits)) =
+            (&mut self.recorder, traced_entry, splits)
+        {
+            let index = self.metrics.inserted;
+            recorder.push(match outcome {
+                InsertOutcome::Absorbed => TraceEvent::Absorbed { index, entry },
+                InsertOutcome::NewEntry | InsertOutcome::Split => {
+                    TraceEvent::NewEntry { index, entry }
+                }
+            });
+            for split in splits {
+                recorder.push(split);
+            }
+        }
+        self.root = root;
+        self.metrics.record(outcome, &split_depths);
+        if !identified && new_id.id().is_some() && holder == new_id {
+            self.next_cluster_id += 1;
+        }
+        (outcome, holder)
+    }
+
+    /// Stable id for the next new leaf entry, if the tree assigns them.
+    pub(crate) fn new_cluster_id(&self) -> Option<u64> {
+        self.config
+            .stable_cluster_ids()
+            .then_some(self.next_cluster_id)
+    }
+
+    /// Accounts for `count` new leaf entries, numbered from [CFTree::new_cluster_id].
+    pub(crate) fn skip_cluster_ids(&mut self, count: u64) {
+        if self.config.stable_cluster_ids() {
+            self.next_cluster_id += count;
+        }
+    }
+
+    /// Removes the leaf clusters of this tree whose size is less than `min_weight` (e.g. noise
+    /// which shouldn't take part in a global clustering of the leaves), returning their features
+    /// so they can be kept aside as outliers or reinserted later.
+    pub fn prune_min_size(&mut self, min_weight: CF::Scalar) -> Vec<CF> {
+        let mut pruned = Vec::new();
+        self.root.prune_min_size(min_weight, &mut pruned);
+        self.metrics.pruned += pruned.len() as u64;
+        pruned
+    }
+
+    /// Inserts a batch of points into this tree, returning the outcome of each insertion. Use
+    /// [Extend::extend] instead if the outcomes aren't needed.
+    pub fn extend_from_iter<T: IntoIterator<Item = FeaturePoint<CF, DIMS>>>(
+        &mut self,
+        iter: T,
+    ) -> Vec<InsertOutcome> {
+        iter.into_iter().map(|p| self.insert(p)).collect()
+    }
+}
+
+impl<CF, TC, const DIMS: usize> Extend<FeaturePoint<CF, DIMS>> for CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    TC: TreeConfig,
+{
+    fn extend<T: IntoIterator<Item = FeaturePoint<CF, DIMS>>>(&mut self, iter: T) {
+        for p in iter {
+            self.insert(p);
+        }
+    }
+}
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC> {
+    /// Creates a tree from an already-built root node.
+    pub(crate) fn from_root(root: Node<CF, DIMS>, config: TC) -> CFTree<CF, DIMS, TC> {
+        let next_cluster_id = next_cluster_id(&root);
+        CFTree {
+            root,
+            config,
+            metrics: TreeMetrics::default(),
+            recorder: None,
+            constraints: None,
+            next_cluster_id,
+        }
+    }
+
+    pub fn root(&self) -> &Node<CF, DIMS> {
+        &self.root
+    }
+
+    /// Whether no points have been inserted into this tree (or all of them were pruned).
+    pub fn is_empty(&self) -> bool {
+        self.root.entries.is_empty()
+    }
+
+    pub(crate) fn root_mut(&mut self) -> &mut Node<CF, DIMS> {
+        &mut self.root
+    }
+
+    pub fn config(&self) -> &TC {
+        &self.config
+    }
+
+    /// Counters of the operations performed on this tree so far.
+    pub fn metrics(&self) -> &TreeMetrics {
+        &self.metrics
+    }
+
+    /// Attaches `recorder` to this tree, to log the structural changes of later insertions (see
+    /// [trace](crate::trace)).
+    pub fn with_recorder(mut self, recorder: TraceRecorder<DIMS>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    pub fn recorder(&self) -> Option<&TraceRecorder<DIMS>> {
+        self.recorder.as_ref()
+    }
+
+    /// Detaches the recorder of this tree, if any, e.g. to save its events.
+    pub fn take_recorder(&mut self) -> Option<TraceRecorder<DIMS>> {
+        self.recorder.take()
+    }
+
+    pub(crate) fn recorder_mut(&mut self) -> &mut Option<TraceRecorder<DIMS>> {
+        &mut self.recorder
+    }
+
+    /// Attaches `constraints` to this tree, to route later insertions of points by their ids
+    /// (see [constraints](crate::constraints)).
+    pub fn with_constraints(mut self, constraints: ConstraintSet) -> Self {
+        self.constraints = Some(constraints);
+        self
+    }
+
+    pub fn constraints(&self) -> Option<&ConstraintSet> {
+        self.constraints.as_ref()
+    }
+
+    /// Mutable access to the constraints of this tree, if any, e.g. to add pairs as they become
+    /// known.
+    pub fn constraints_mut(&mut self) -> Option<&mut ConstraintSet> {
+        self.constraints.as_mut()
+    }
+
+    /// Detaches the constraints of this tree, if any.
+    pub fn take_constraints(&mut self) -> Option<ConstraintSet> {
+        self.constraints.take()
+    }
+
+    pub(crate) fn metrics_mut(&mut self) -> &mut TreeMetrics {
+        &mut self.metrics
+    }
+
+    /// Estimate of the memory used by this tree, in bytes. See [Node::estimated_bytes].
+    pub fn estimated_bytes(&self) -> usize
+    where
+        CF: CFeature<DIMS>,
+    {
+        size_of::<TC>() + self.root.estimated_bytes()
+    }
+
+    pub fn into_root(self) -> Node<CF, DIMS> {
+        self.root
+    }
+}
+
+impl<CF: Clone, TC: Clone, const DIMS: usize> CFTree<CF, DIMS, TC> {
+    /// Returns a read-only snapshot of the current state of this tree, e.g. for computing metrics
+    /// in another thread while insertion continues.
+    ///
+    /// Snapshots are cheap: only the entries of the root node are copied, while all other nodes
+    /// are shared between the tree and the snapshot until insertion modifies them (at which point
+    /// the tree copies the modified nodes, leaving the snapshot unchanged).
+    pub fn snapshot(&self) -> CFTree<CF, DIMS, TC> {
+        CFTree {
+            root: self.root.clone(),
+            config: self.config.clone(),
+            metrics: self.metrics.clone(),
+            recorder: None,
+            constraints: None,
+            next_cluster_id: self.next_cluster_id,
+        }
+    }
+}
+
+pub type BirchTree<const DIMS: usize> = Node<BirchFeature<DIMS>, DIMS>;
+pub type BetulaTree<const DIMS: usize> = Node<BetulaFeature<DIMS>, DIMS>;
+pub type BirchCFTree<const DIMS: usize, TC = BasicConfig> = CFTree<BirchFeature<DIMS>, DIMS, TC>;
+pub type BetulaCFTree<const DIMS: usize, TC = BasicConfig> = CFTree<BetulaFeature<DIMS>, DIMS, TC>;
+pub type CovarianceCFTree<const DIMS: usize, TC = BasicConfig> =
+    CFTree<CovarianceFeature<DIMS>, DIMS, TC>;
+/// Tree of [quantized](crate::cfeature::quantized) features, stored as `Q` (`f32` by default).
+pub type QuantizedCFTree<const DIMS: usize, Q = f32, TC = BasicConfig> =
+    CFTree<QuantizedFeature<DIMS, Q>, DIMS, TC>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::Point;
+
+    #[test]
+    fn from_arr() {
+        let mut points = vec![
+            Point::from_arr([1.0, 2.0, 3.0]),
+            Point::from_arr([2.0, 2.0, 3.0]),
+            Point::from_arr([1.0, 3.0, 3.0]),
+            Point::from_arr([1.0, 2.0, 4.0]),
+        ];
+        let root = BirchTree::from_iter(
+            points.drain(..),
+            &BasicConfig::builder()
+                .capacity(1, 3)
+                .threshold(0.5)
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(root.weight(), 4.0);
+    }
+
+    #[test]
+    fn f32_tree() {
+        let points = vec![
+            Point::from_arr([1.0f32, 2.0, 3.0]),
+            Point::from_arr([1.1f32, 2.0, 3.0]),
+            Point::from_arr([8.0f32, 8.0, 8.0]),
+        ];
+        let config = BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let root = Node::<BirchFeature<3, f32>, 3>::from_iter(points.clone(), &config);
+        assert_eq!(
+            root.entries
+                .iter()
+                .map(|entry| entry.feature.size())
+                .sum::<f32>(),
+            3.0
+        );
+        let root = Node::<BetulaFeature<3, f32>, 3>::from_iter(points, &config);
+        assert_eq!(
+            root.entries
+                .iter()
+                .map(|entry| entry.feature.size())
+                .sum::<f32>(),
+            3.0
+        );
+    }
+
+    #[test]
+    fn split_policies() {
+        use crate::split::Split;
+
+        let points = (0..60)
+            .map(|i| Point::from_arr([(i % 6) as f64, (i / 6) as f64]))
+            .collect::<Vec<_>>();
+        for split_policy in [
+            Split::FarthestPair,
+            Split::Pca,
+            Split::Random(crate::split::RandomSeeds { seed: 7 }),
+            Split::Balanced,
+            Split::Linear,
+        ] {
+            let config = BasicConfig::builder()
+                .capacity(1, 3)
+                .threshold(0.5)
+                .split(split_policy)
+                .build()
+                .unwrap();
+            let tree = BirchCFTree::from_iter(points.clone(), config);
+            assert_eq!(tree.clusters().map(|c| c.size).sum::<f64>(), 60.0);
+            assert!(tree.root().entries.len() < 3);
+        }
+    }
+
+    #[test]
+    fn merge_refinement() {
+        // sorted insertion order is adversarial: every split happens at the edge of the data
+        let points = (0..200)
+            .map(|i| Point::from_arr([i as f64, ((i * 7) % 5) as f64]))
+            .collect::<Vec<_>>();
+        let config = |merge_refinement| {
+            BasicConfig::builder()
+                .capacity(1, 5)
+                .threshold(0.5)
+                .merge_refinement(merge_refinement)
+                .build()
+                .unwrap()
+        };
+        let plain = BetulaCFTree::from_iter(points.clone(), config(false));
+        let refined = BetulaCFTree::from_iter(points, config(true));
+        assert_eq!(refined.clusters().map(|c| c.size).sum::<f64>(), 200.0);
+        assert!(refined.root().node_count() < plain.root().node_count());
+    }
+
+    #[test]
+    fn config_builder() {
+        let config = BasicConfig::builder()
+            .capacity(1, 3)
+            .leaf_capacity(2, 10)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        assert_eq!(config.node_capacity().max, 3);
+        assert_eq!(config.leaf_capacity().max, 10);
+        let config = BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(0.0)
+            .build()
+            .unwrap();
+        assert_eq!(config.leaf_capacity().max, 3);
+
+        assert_eq!(
+            BasicConfig::builder().threshold(0.5).build().unwrap_err(),
+            BorschtError::Config(ConfigError::Missing("capacity"))
+        );
+        assert_eq!(
+            BasicConfig::builder()
+                .capacity(4, 3)
+                .threshold(0.5)
+                .build()
+                .unwrap_err(),
+            BorschtError::Config(ConfigError::InvalidCapacity { min: 4, max: 3 })
+        );
+        assert_eq!(
+            BasicConfig::builder()
+                .capacity(1, 3)
+                .leaf_capacity(0, 1)
+                .threshold(0.5)
+                .build()
+                .unwrap_err(),
+            BorschtError::Config(ConfigError::InvalidCapacity { min: 0, max: 1 })
+        );
+        assert_eq!(
+            BasicConfig::builder()
+                .capacity(1, 3)
+                .threshold(-1.0)
+                .build()
+                .unwrap_err(),
+            BorschtError::Config(ConfigError::InvalidThreshold(-1.0))
+        );
+        assert!(BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(Scalar::NAN)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn leaf_capacity() {
+        let points = (0..5)
+            .map(|i| Point::from_arr([i as f64, 0.0]))
+            .collect::<Vec<_>>();
+        let config = BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let tree = BirchCFTree::from_iter(points.clone(), config);
+        assert!(!tree.root().is_leaf());
+
+        let config = BasicConfig::builder()
+            .capacity(1, 3)
+            .leaf_capacity(1, 10)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let tree = BirchCFTree::from_iter(points, config);
+        assert!(tree.root().is_leaf());
+        assert_eq!(tree.root().entries.len(), 5);
+    }
+
+    #[test]
+    fn extend() {
+        let config = BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let points = (0..40)
+            .map(|i| Point::from_arr([(i % 8) as f64, (i / 8) as f64]))
+            .collect::<Vec<_>>();
+        let mut tree = BetulaCFTree::new(config.clone());
+        tree.extend(points[..20].iter().cloned());
+        tree.extend(points[20..].iter().cloned());
+        let rebuilt = BetulaCFTree::from_iter(points, config);
+        assert_eq!(format!("{:?}", tree), format!("{:?}", rebuilt));
+
+        let mut tree = BetulaCFTree::new(tree.config().clone());
+        let outcomes = tree.extend_from_iter(vec![
+            Point::from_arr([0.0, 0.0]),
+            Point::from_arr([0.1, 0.0]),
+            Point::from_arr([5.0, 5.0]),
+            Point::from_arr([9.0, 9.0]),
+        ]);
+        assert_eq!(
+            outcomes,
+            vec![
+                InsertOutcome::NewEntry,
+                InsertOutcome::Absorbed,
+                InsertOutcome::NewEntry,
+                InsertOutcome::Split
+            ]
+        );
+        assert_eq!(tree.clusters().map(|c| c.size).sum::<f64>(), 4.0);
+    }
+
+    #[test]
+    fn deep_insertion() {
+        let config = BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(0.1)
+            .build()
+            .unwrap();
+        // sorted insertion: each point lands in the most recently split leaf
+        let tree = BirchCFTree::from_iter((0..5000).map(|i| Point::from_arr([i as f64])), config);
+        assert!(tree.root().height() > 10);
+        assert_eq!(tree.clusters().map(|c| c.size).sum::<f64>(), 5000.0);
+    }
+
+    #[test]
+    fn closest_entry() {
+        let config = BasicConfig::builder()
+            .capacity(1, 10)
+            .threshold(0.0)
+            .build()
+            .unwrap();
+        let root = BirchTree::from_iter(
+            vec![
+                Point::from_arr([5.0, 5.0]),
+                Point::from_arr([0.0, 0.0]),
+                Point::from_arr([1.0, 1.0]),
+                Point::from_arr([9.0, 9.0]),
+            ],
+            &config,
+        );
+        assert_eq!(root.entries.len(), 4);
+        // closest entry is followed by farther entries
+        let (idx, d2) = root.closest_entry(&Point::from_arr([0.9, 1.2])).unwrap();
+        assert_eq!(idx, 2);
+        assert!((d2 - 0.05).abs() < 1e-12);
+        assert_eq!(
+            root.closest_entry(&Point::from_arr([4.0, 5.0])).unwrap().0,
+            0
+        );
+        assert_eq!(
+            root.closest_entry(&Point::from_arr([-1.0, 0.0])).unwrap().0,
+            1
+        );
+        assert_eq!(
+            BirchTree::<2>::new(&config).closest_entry(&Point::from_arr([0.0, 0.0])),
+            None
+        );
+    }
+
+    #[test]
+    fn mahalanobis_metric() {
+        // a cluster spread widely along x, and a tight cluster off to its side
+        let wide = [-50.0, 0.0, 50.0]
+            .iter()
+            .fold(BetulaFeature::zero(), |acc, &x| {
+                acc + Point::from_arr([x, 0.0])
+            });
+        let tight = [29.9, 30.1].iter().fold(BetulaFeature::zero(), |acc, &x| {
+            acc + Point::from_arr([x, 0.0])
+        });
+        let build = |metric| {
+            let config = BasicConfig::builder()
+                .capacity(1, 10)
+                .threshold(0.0)
+                .metric(metric)
+                .build()
+                .unwrap();
+            let mut tree = BetulaCFTree::<2>::new(config);
+            tree.insert_feature(wide.clone());
+            tree.insert_feature(tight.clone());
+            tree
+        };
+
+        let p = Point::from_arr([45.0, 0.0]);
+        let euclidean = build(Metric::Euclidean);
+        assert_eq!(euclidean.root().entries.len(), 2);
+        assert_eq!(
+            euclidean
+                .root()
+                .closest_entry_with(&p, euclidean.config())
+                .unwrap()
+                .0,
+            1
+        );
+        assert_eq!(euclidean.labels([&p]), vec![Some(1)]);
+
+        // relative to their spreads, the point is much closer to the wide cluster
+        let mahalanobis = build(Metric::Mahalanobis);
+        let (idx, d2) = mahalanobis
+            .root()
+            .closest_entry_with(&p, mahalanobis.config())
+            .unwrap();
+        assert_eq!(idx, 0);
+        assert!((d2 - wide.mahalanobis2(&p)).abs() < 1e-12);
+        assert_eq!(mahalanobis.labels([&p]), vec![Some(0)]);
+    }
+
+    #[test]
+    fn missing_values() {
+        let points = (0..10)
+            .map(|i| {
+                let offset = (i / 2) as f64 * 0.01;
+                match i % 2 {
+                    0 => Point::from_arr([offset, offset]),
+                    _ => Point::from_arr([10.0 + offset, 10.0 - offset]),
+                }
+            })
+            .collect::<Vec<_>>();
+        let build = |missing_values| {
+            let config = BasicConfig::builder()
+                .capacity(1, 3)
+                .threshold(1.0)
+                .missing_values(missing_values)
+                .build()
+                .unwrap();
+            BetulaCFTree::<2>::from_iter(points.clone(), config)
+        };
+        let incomplete = Point::from_arr([Scalar::NAN, 10.1]);
+
+        let mut tree = build(MissingValues::Propagate);
+        tree.insert(incomplete.clone());
+        assert!(tree.clusters().any(|c| c.center[0].is_nan()));
+
+        for missing_values in [MissingValues::Skip, MissingValues::Impute] {
+            let mut tree = build(missing_values);
+            let before = tree.clusters().collect::<Vec<_>>();
+            assert_eq!(tree.insert(incomplete.clone()), InsertOutcome::Absorbed);
+            let after = tree.clusters().collect::<Vec<_>>();
+            assert_eq!(after.len(), 2);
+            let (idx, cluster) = after
+                .iter()
+                .enumerate()
+                .find(|(_, c)| c.size == 6.0)
+                .unwrap();
+            // the filled-in coordinate doesn't move the cluster
+            assert!((cluster.center[0] - before[idx].center[0]).abs() < 1e-12);
+            assert!(cluster.center[1] > before[idx].center[1]);
+
+            let query = Point::from_arr([0.2, Scalar::NAN]);
+            let label = tree.labels([&query])[0].unwrap();
+            assert!(after[label].center[0] < 1.0);
+            let nearest = tree.root().nearest_leaf(&query, tree.config()).unwrap();
+            let d2 = Metric::Euclidean.dist2(nearest, &query, tree.config());
+            let expected = (0.2 - after[label].center[0]).powi(2);
+            match missing_values {
+                MissingValues::Skip => assert!((d2 - 2.0 * expected).abs() < 1e-12),
+                _ => assert!((d2 - expected).abs() < 1e-12),
+            }
+        }
+    }
+
+    #[test]
+    fn known_blobs() {
+        // four tight, well-separated blobs of ten points each, interleaved
+        let centers = [[0.0, 0.0], [10.0, 0.0], [0.0, 10.0], [10.0, 10.0]];
+        let points = (0..40)
+            .map(|i| {
+                let c = centers[i % 4];
+                let offset = (i / 4) as f64 * 0.01;
+                Point::from_arr([c[0] + offset, c[1] - offset])
+            })
+            .collect::<Vec<_>>();
+        let config = BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(1.0)
+            .build()
+            .unwrap();
+
+        let tree = BirchCFTree::from_iter(points.clone(), config.clone());
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.len(), 4);
+        assert!(clusters.iter().all(|c| c.size == 10.0));
+        // every leaf sits at the same depth
+        let height = tree.root().height();
+        assert!(clusters.iter().all(|c| c.depth == height - 1));
+
+        let tree = BetulaCFTree::from_iter(points.clone(), config.clone());
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.len(), 4);
+        assert!(clusters.iter().all(|c| c.size == 10.0));
+
+        let tree = CovarianceCFTree::from_iter(points, config);
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.len(), 4);
+        assert!(clusters.iter().all(|c| c.size == 10.0));
+    }
+
+    fn check_capacity<CF, TC: TreeConfig, const DIMS: usize>(
+        node: &Node<CF, DIMS>,
+        config: &TC,
+        is_root: bool,
+    ) {
+        let capacity = match node.entries.iter().all(|entry| entry.child.is_none()) {
+            true => config.leaf_capacity(),
+            false => config.node_capacity(),
+        };
+        assert!(node.entries.len() <= capacity.max);
+        assert!(is_root || node.entries.len() >= capacity.min);
+        for child in node
+            .entries
+            .iter()
+            .filter_map(|entry| entry.child.as_deref())
+        {
+            check_capacity(child, config, false);
+        }
+    }
+
+    #[test]
+    fn min_capacity() {
+        let points = (0..500u64)
+            .map(|i| {
+                let h = i.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                Point::from_arr([(h % 1000) as f64 / 10.0, ((h >> 20) % 1000) as f64 / 10.0])
+            })
+            .collect::<Vec<_>>();
+        for merge_refinement in [false, true] {
+            let config = BasicConfig::builder()
+                .capacity(3, 6)
+                .leaf_capacity(2, 5)
+                .threshold(0.5)
+                .merge_refinement(merge_refinement)
+                .build()
+                .unwrap();
+            let tree = BirchCFTree::from_iter(points.clone(), config);
+            assert!(tree.root().height() > 2);
+            check_capacity(tree.root(), tree.config(), true);
+        }
+    }
+
+    #[test]
+    fn snapshot() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.1)
+            .build()
+            .unwrap();
+        let mut tree = BirchCFTree::from_iter(
+            (0..200).map(|i| Point::from_arr([i as f64, (i % 3) as f64])),
+            config,
+        );
+        let snapshot = tree.snapshot();
+        let before = format!("{:?}", snapshot);
+        assert_eq!(format!("{:?}", tree), before);
+
+        let handle = std::thread::spawn(move || snapshot.clusters().count());
+        // points far to the left only modify the leftmost path of the tree
+        tree.extend((0..50).map(|i| Point::from_arr([-1.0 - i as f64, 0.0])));
+        assert_eq!(handle.join().unwrap(), 200);
+
+        let snapshot = tree.snapshot();
+        tree.insert(Point::from_arr([-100.0, 0.0]));
+        assert_eq!(snapshot.clusters().count(), 250);
+        assert_eq!(tree.clusters().count(), 251);
+        // untouched subtrees are still shared
+        let shared = tree
+            .root()
+            .entries
+            .iter()
+            .zip(&snapshot.root().entries)
+            .filter(|(l, r)| match (&l.child, &r.child) {
+                (Some(l), Some(r)) => Arc::ptr_eq(l, r),
+                _ => false,
+            })
+            .count();
+        assert!(shared > 0);
+        assert!(before != format!("{:?}", tree));
+    }
+
+    #[test]
+    fn prune_min_size() {
+        // dense groups of points on a grid, plus scattered noise points
+        let points = (0..200)
+            .map(|i| match i % 10 {
+                9 => Point::from_arr([5.0 + i as f64 * 0.37, 50.0 - i as f64 * 0.23]),
+                _ => {
+                    let offset = (i % 5) as f64 * 0.05;
+                    Point::from_arr([(i % 4) as f64 * 10.0 + offset, offset])
+                }
+            })
+            .collect::<Vec<_>>();
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(1.0)
+            .build()
+            .unwrap();
+        let mut tree = BirchCFTree::<2>::from_iter(points, config);
+        let before = tree.clusters().count();
+
+        let pruned = tree.prune_min_size(5.0);
+        assert_eq!(pruned.len(), 20);
+        assert!(pruned.iter().all(|feature| feature.size() < 5.0));
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.len(), before - 20);
+        assert!(clusters.iter().all(|c| c.center[1] < 1.0));
+        // ancestor features only summarize the remaining clusters
+        let total = tree
+            .root()
+            .entries
+            .iter()
+            .map(|entry| entry.feature.size())
+            .sum::<f64>();
+        assert_eq!(total, 180.0);
+    }
+
+    #[test]
+    fn estimated_bytes() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let mut tree = BirchCFTree::<2>::new(config.clone());
+        let empty = tree.estimated_bytes();
+        tree.extend((0..100).map(|i| Point::from_arr([i as f64, 0.0])));
+        let full = tree.estimated_bytes();
+        let entry = size_of::<NodeEntry<BirchFeature<2>, 2>>();
+        // at least one leaf entry per point, since none are within the threshold
+        assert!(full - empty >= 100 * entry);
+        assert!(full < empty + 100 * 4 * (entry + size_of::<Node<BirchFeature<2>, 2>>()));
+
+        // covariance features also account for their co-moment matrices
+        let mut tree = CovarianceCFTree::<2>::new(config);
+        tree.extend((0..100).map(|i| Point::from_arr([i as f64, 0.0])));
+        assert!(tree.estimated_bytes() > full + 100 * 2 * size_of::<Point<2>>());
+    }
+
+    #[test]
+    fn deterministic() {
+        use crate::split::{RandomSeeds, Split};
+
+        // points on a lattice, so that many distances are tied
+        let points = (0..400)
+            .map(|i| Point::from_arr([((i * 7) % 20) as f64, ((i * 13) % 20) as f64]))
+            .collect::<Vec<_>>();
+        for split in [
+            Split::FarthestPair,
+            Split::Pca,
+            Split::Random(RandomSeeds { seed: 3 }),
+            Split::Balanced,
+            Split::Linear,
+        ] {
+            let build = || {
+                let config = BasicConfig::builder()
+                    .capacity(2, 4)
+                    .threshold(1.5)
+                    .split(split)
+                    .merge_refinement(true)
+                    .build()
+                    .unwrap();
+                BetulaCFTree::<2, _>::from_iter(points.clone(), config)
+            };
+            assert_eq!(format!("{:?}", build()), format!("{:?}", build()));
+        }
+
+        // equally close entries resolve to the first one
+        let config = BasicConfig::builder()
+            .capacity(1, 10)
+            .threshold(0.0)
+            .build()
+            .unwrap();
+        let root = BirchTree::from_iter(
+            [[1.0, 0.0], [0.0, 1.0], [-1.0, 0.0]].map(Point::from_arr),
+            &config,
+        );
+        assert_eq!(root.closest_entry(&Point::zero()).unwrap().0, 0);
+    }
+
+    #[test]
+    fn tree_metrics() {
+        let config = BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        // ten well-separated groups of five points each
+        let points = (0..50)
+            .map(|i| Point::from_arr([(i % 10) as f64 * 10.0, (i / 10) as f64 * 0.01]))
+            .collect::<Vec<_>>();
+        let mut tree = BirchCFTree::<2>::from_iter(points, config);
+
+        let metrics = tree.metrics();
+        assert_eq!(metrics.inserted, 50);
+        assert_eq!(metrics.new_entries as usize, tree.clusters().count());
+        assert_eq!(metrics.absorbed + metrics.new_entries, 50);
+        // every split of a node at depth 0 grows the tree by one level
+        assert_eq!(metrics.splits[0] as usize, tree.root().height() - 1);
+        assert!(metrics.splits.len() <= tree.root().height());
+        assert!(metrics.total_splits() > metrics.splits[0]);
+
+        tree.insert(Point::from_arr([200.0, 0.0]));
+        let pruned = tree.prune_min_size(2.0);
+        assert!(!pruned.is_empty());
+        assert_eq!(tree.metrics().pruned as usize, pruned.len());
+        assert_eq!(tree.metrics().inserted, 51);
+    }
+
+    #[test]
+    fn progress() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let points = (0..250).map(|i| Point::from_arr([i as f64, 0.0]));
+
+        let mut reports = vec![];
+        let tree = BirchCFTree::<2>::from_iter_with_progress(
+            points.clone(),
+            config.clone(),
+            100,
+            |processed, metrics| {
+                reports.push((processed, metrics.inserted));
+                ControlFlow::Continue(())
+            },
+        );
+        assert_eq!(reports, vec![(100, 100), (200, 200), (250, 250)]);
+        assert_eq!(tree.metrics().inserted, 250);
+
+        let tree = BirchCFTree::<2>::from_iter_with_progress(points, config, 50, |processed, _| {
+            match processed < 100 {
+                true => ControlFlow::Continue(()),
+                false => ControlFlow::Break(()),
+            }
+        });
+        assert_eq!(tree.metrics().inserted, 100);
+    }
+
+    #[test]
+    fn cancellable() {
+        use core::cell::Cell;
+
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let points = (0..100).map(|i| Point::from_arr([i as f64, 0.0]));
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tree, rest) =
+            BirchCFTree::<2>::from_iter_cancellable(points.clone(), config.clone(), &*cancel);
+        assert_eq!(tree.metrics().inserted, 100);
+        assert!(rest.is_none());
+
+        // cancel after 30 points, then resume
+        let checks = Cell::new(0);
+        let (mut tree, rest) =
+            BirchCFTree::<2>::from_iter_cancellable(points, config.clone(), &|| {
+                checks.set(checks.get() + 1);
+                checks.get() > 30
+            });
+        assert_eq!(tree.metrics().inserted, 30);
+        tree.extend(rest.unwrap());
+        assert_eq!(tree.metrics().inserted, 100);
+        assert_eq!(tree.clusters().map(|c| c.size).sum::<f64>(), 100.0);
+
+        cancel.store(true, Ordering::Relaxed);
+        let (tree, rest) = BirchCFTree::<2>::from_iter_cancellable(
+            [Point::from_arr([0.0, 0.0])],
+            config,
+            &*cancel,
+        );
+        assert_eq!(tree.metrics().inserted, 0);
+        assert_eq!(rest.unwrap().count(), 1);
+    }
+
+    #[test]
+    fn track_ids() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .track_ids(true)
+            .build()
+            .unwrap();
+        // ten well-separated groups
+        let group = |id: u64| (id * 7 % 10) as f64 * 10.0;
+        let points = (0..100)
+            .map(|id| Point::from_arr([group(id), (id % 3) as f64 * 0.1]))
+            .collect::<Vec<_>>();
+
+        let mut tree = BirchCFTree::<2>::new(config.clone());
+        tree.extend(points[..40].iter().cloned());
+        tree.insert_batch(&points[40..80]);
+        for (p, id) in points[80..].iter().zip(80..) {
+            tree.insert_with_id(p.clone(), id);
+        }
+        let ids = tree.cluster_ids();
+        assert_eq!(ids.len(), tree.clusters().count());
+        for (cluster, ids) in tree.clusters().zip(&ids) {
+            assert_eq!(ids.len() as f64, cluster.size);
+            assert!(ids.iter().all(|&id| group(id) == cluster.center[0].round()));
+        }
+        let mut all = ids.concat();
+        all.sort_unstable();
+        assert_eq!(all, (0..100).collect::<Vec<_>>());
+
+        let untracked = BirchCFTree::<2>::from_iter(
+            points,
+            BasicConfig {
+                track_ids: false,
+                reservoir_size: 0,
+                ..config
+            },
+        );
+        assert!(untracked.cluster_ids().iter().all(|ids| ids.is_empty()));
+    }
+
+    #[test]
+    fn empty_and_singleton_trees() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(1.0)
+            .build()
+            .unwrap();
+        let p = Point::from_arr([1.0, 2.0]);
+
+        let empty = BirchCFTree::<2>::from_iter(vec![], config.clone());
+        assert!(empty.is_empty());
+        assert_eq!(empty.root().height(), 1);
+        assert_eq!(empty.clusters().count(), 0);
+        assert_eq!(empty.labels([&p]), vec![None]);
+        assert!(empty.query_radius(&p, 10.0).is_empty());
+        assert!(empty.anomaly_score(&p).is_none());
+        assert!(empty.offline_cluster(1.0, 1.0).is_empty());
+        assert_eq!(crate::tune::davies_bouldin(&empty), None);
+
+        let single = BirchCFTree::<2>::from_iter(vec![p.clone()], config);
+        assert!(!single.is_empty());
+        assert_eq!(single.root().height(), 1);
+        let clusters = single.clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(
+            (clusters[0].center.clone(), clusters[0].size),
+            (p.clone(), 1.0)
+        );
+        assert_eq!(single.labels([&p]), vec![Some(0)]);
+        assert_eq!(single.anomaly_score(&p), Some(0.0));
+        assert_eq!(single.predict_proba(&p, 3), vec![(0, 1.0)]);
+    }
+
+    #[test]
+    fn incremental_features() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .merge_refinement(true)
+            .build()
+            .unwrap();
+        let points = (0..500).map(|i| {
+            let x = (i * 37 % 101) as f64;
+            Point::from_arr([x, (i * 53 % 89) as f64 * 0.5])
+        });
+        let tree = BirchCFTree::<2>::from_iter(points, config);
+        assert!(tree.root().height() > 2);
+
+        // the features of internal entries, maintained incrementally, match their children
+        fn check<CF: CFeature<2, Scalar = f64> + Debug>(node: &Node<CF, 2>) {
+            for entry in &node.entries {
+                if let Some(child) = &entry.child {
+                    let computed = child.compute_feature();
+                    assert_eq!(entry.feature.size(), computed.size());
+                    let (sum, expected) = (entry.feature.sum(), computed.sum());
+                    assert!((&sum - &expected).norm2() < 1e-12 * expected.norm2());
+                    check(child);
+                }
+            }
+        }
+        check(tree.root());
+    }
+
+    #[test]
+    fn cached_height_and_weight() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .merge_refinement(true)
+            .build()
+            .unwrap();
+        let points = (0..500)
+            .map(|i| Point::from_arr([(i * 37 % 101) as f64, (i * 53 % 89) as f64 * 0.5]))
+            .collect::<Vec<_>>();
+
+        // the cached height and weight of every node match the recomputed ones
+        fn check<CF: CFeature<2, Scalar = f64> + Debug>(node: &Node<CF, 2>) {
+            assert_eq!(node.height(), node.compute_height());
+            let weight = node
+                .entries
+                .iter()
+                .map(|entry| entry.feature.size())
+                .sum::<f64>();
+            assert!((node.weight() - weight).abs() < 1e-9);
+            let min_leaf = node
+                .clusters()
+                .map(|c| c.size)
+                .fold(f64::INFINITY, f64::min);
+            assert_eq!(node.min_leaf_weight(), min_leaf);
+            for child in node.entries.iter().filter_map(|entry| entry.child.as_ref()) {
+                check(child);
+            }
+        }
+
+        let mut tree = BirchCFTree::<2>::from_iter(points.clone(), config.clone());
+        assert!(tree.root().height() > 2);
+        assert_eq!(tree.root().weight(), 500.0);
+        check(tree.root());
+        tree.prune_min_size(2.0);
+        check(tree.root());
+
+        let mut batched = BirchCFTree::<2>::new(config.clone());
+        batched.insert_batch(&points);
+        check(batched.root());
+        check(BirchCFTree::<2>::bulk_load(points, config).root());
+    }
+
+    #[test]
+    fn per_dimension_thresholds() {
+        // rows of points spread out along the first dimension, apart along the second
+        let points = (0..40)
+            .map(|i| Point::from_arr([(i % 10) as f64, (i / 10) as f64 * 10.0]))
+            .collect::<Vec<_>>();
+        // a single threshold tight enough for the second dimension cuts the rows into pieces
+        let tight = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(1.0)
+            .build()
+            .unwrap();
+        assert!(
+            BirchCFTree::<2>::from_iter(points.clone(), tight)
+                .clusters()
+                .count()
+                > 4
+        );
+
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .dimension_thresholds(vec![25.0, 1.0])
+            .build()
+            .unwrap();
+        assert_eq!(config.threshold(), 26.0);
+        let birch = BirchCFTree::<2>::from_iter(points.clone(), config.clone());
+        let betula = BetulaCFTree::<2>::from_iter(points.clone(), config.clone());
+        let bulk = BirchCFTree::<2>::bulk_load(points, config);
+        for clusters in [
+            birch.clusters().collect::<Vec<_>>(),
+            betula.clusters().collect(),
+        ] {
+            // one cluster per row
+            assert_eq!(clusters.len(), 4);
+            assert!(clusters
+                .iter()
+                .all(|c| c.size == 10.0 && c.center[0] == 4.5));
+        }
+        // bulk loads only absorb consecutive points, but never across rows either
+        assert!(bulk.clusters().all(|c| c.center[1] % 10.0 == 0.0));
+
+        assert_eq!(
+            BasicConfig::builder()
+                .capacity(2, 4)
+                .dimension_thresholds(vec![1.0, -1.0])
+                .build()
+                .unwrap_err(),
+            BorschtError::Config(ConfigError::InvalidThreshold(-1.0))
+        );
+    }
+
+    #[test]
+    fn per_level_thresholds() {
+        let builder = BasicConfig::builder().capacity(2, 4).threshold(0.5);
+        let points = (0..500)
+            .map(|i| Point::from_arr([(i * 37 % 101) as f64, (i * 53 % 89) as f64 * 0.5]))
+            .collect::<Vec<_>>();
+
+        let tight = BirchCFTree::<2>::from_iter(points.clone(), builder.clone().build().unwrap());
+        let config = builder
+            .clone()
+            .upper_thresholds(vec![0.0, 200.0])
+            .build()
+            .unwrap();
+        assert_eq!(config.threshold_at(0), 0.5);
+        assert_eq!(config.threshold_at(2), 200.0);
+        assert_eq!(config.threshold_at(3), 0.0);
+        let loose = BirchCFTree::<2>::from_iter(points, config);
+
+        // entries two levels up absorb points within their threshold, so the tree is shallower
+        // and has fewer leaf entries
+        assert!(loose.root().height() < tight.root().height());
+        assert!(loose.root().leaf_count() < tight.root().leaf_count());
+        assert_eq!(loose.root().height(), loose.root().compute_height());
+        assert_eq!(loose.root().weight(), 500.0);
+        // leaf entries which absorb points past the leaf threshold stay within the threshold of
+        // the levels which absorbed them
+        assert!(loose.clusters().any(|c| c.diameter.powi(2) > 0.5));
+        assert!(loose.clusters().all(|c| c.diameter.powi(2) <= 200.0));
+
+        let scattered = (0..2000u64).map(|i| {
+            let h = i.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            Point::from_arr([(h % 1000) as f64 / 10.0, ((h >> 20) % 1000) as f64 / 10.0])
+        });
+        let config = builder.upper_thresholds(vec![20.0]).build().unwrap();
+        let tree = BirchCFTree::<2>::from_iter(scattered, config);
+        assert!(tree.clusters().any(|c| c.diameter.powi(2) > 0.5));
+        assert!(tree.clusters().all(|c| c.diameter.powi(2) <= 20.0));
+    }
+
+    #[test]
+    fn from_page_size() {
+        // 40-byte leaf entries and 48-byte non-leaf entries
+        let config = BasicConfig::from_page_size::<BirchFeature<2>, 2>(4096)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        assert_eq!((config.capacity.min, config.capacity.max), (43, 86));
+        let leaf_capacity = config.leaf_capacity();
+        assert_eq!((leaf_capacity.min, leaf_capacity.max), (51, 103));
+
+        assert_eq!(
+            BasicConfig::from_page_size::<BirchFeature<2>, 2>(32)
+                .threshold(0.5)
+                .build()
+                .err(),
+            Some(BorschtError::Config(ConfigError::InvalidCapacity {
+                min: 0,
+                max: 1
+            }))
         );
-        println!("{:#?}", root);
     }
 }
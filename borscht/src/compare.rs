@@ -0,0 +1,303 @@
+/*!
+ * Comparison of the leaf clusters of two trees, e.g. two checkpoints of the same stream.
+ *
+ * [compare] matches the leaf clusters of one tree to those of the other with a minimum-cost
+ * assignment (the [Hungarian algorithm](https://en.wikipedia.org/wiki/Hungarian_algorithm)) on
+ * the distances between cluster centers, so that each cluster is matched to at most one cluster of
+ * the other tree and the total distance between matched centers is minimal. Matched clusters are
+ * reported as [moved](TreeDiff::moved) (by the distance between their centers, which may be zero),
+ * and unmatched clusters as having [appeared](TreeDiff::appeared) or
+ * [disappeared](TreeDiff::disappeared). [compare_within] only matches clusters up to a maximum
+ * distance apart, so that a cluster which vanished isn't matched to an unrelated new one.
+ *
+ * Matching takes time cubic in the number of leaf clusters, so is best suited to trees with up to a
+ * few thousand leaves.
+ */
+
+use alloc::{vec, vec::Vec};
+
+use num_traits::Float as _;
+
+use crate::{
+    cfeature::CFeature,
+    cftree::CFTree,
+    point::{Float, Scalar},
+    summary::ClusterSummary,
+};
+
+/// A leaf cluster of the first tree matched to a leaf cluster of the second tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovedCluster<const DIMS: usize, T = Scalar> {
+    pub before: ClusterSummary<DIMS, T>,
+    pub after: ClusterSummary<DIMS, T>,
+    /// Distance between the centers of the matched clusters.
+    pub distance: T,
+}
+
+/// Differences between the leaf clusters of two trees. Created by [compare].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeDiff<const DIMS: usize, T = Scalar> {
+    /// Matched clusters, in order of their id in the first tree.
+    pub moved: Vec<MovedCluster<DIMS, T>>,
+    /// Clusters of the second tree without a match in the first.
+    pub appeared: Vec<ClusterSummary<DIMS, T>>,
+    /// Clusters of the first tree without a match in the second.
+    pub disappeared: Vec<ClusterSummary<DIMS, T>>,
+}
+
+impl<T: Float, const DIMS: usize> TreeDiff<DIMS, T> {
+    /// Total distance between the centers of matched clusters.
+    pub fn total_distance(&self) -> T {
+        self.moved
+            .iter()
+            .fold(T::zero(), |acc, moved| acc + moved.distance)
+    }
+
+    /// Matched clusters whose centers moved by more than `distance`.
+    pub fn moved_farther_than(&self, distance: T) -> impl Iterator<Item = &MovedCluster<DIMS, T>> {
+        self.moved
+            .iter()
+            .filter(move |moved| moved.distance > distance)
+    }
+
+    /// Whether every cluster was matched to a cluster at the same center.
+    pub fn is_unchanged(&self) -> bool {
+        self.appeared.is_empty()
+            && self.disappeared.is_empty()
+            && self.moved.iter().all(|moved| moved.distance == T::zero())
+    }
+}
+
+/// Matches the leaf clusters of `before` to those of `after` (see the
+/// [module documentation](self)). The trees may have different configurations.
+///
+/// As many clusters as possible are matched, however far apart; use [compare_within] to leave
+/// clusters farther apart than some distance unmatched.
+pub fn compare<CF, TA, TB, const DIMS: usize>(
+    before: &CFTree<CF, DIMS, TA>,
+    after: &CFTree<CF, DIMS, TB>,
+) -> TreeDiff<DIMS, CF::Scalar>
+where
+    CF: CFeature<DIMS>,
+{
+    compare_within(before, after, CF::Scalar::infinity())
+}
+
+/// Matches the leaf clusters of `before` to those of `after` like [compare], but only matches
+/// clusters whose centers are at most `max_distance` apart: a cluster which moved farther is
+/// reported as having disappeared (from `before`) and appeared (in `after`).
+pub fn compare_within<CF, TA, TB, const DIMS: usize>(
+    before: &CFTree<CF, DIMS, TA>,
+    after: &CFTree<CF, DIMS, TB>,
+    max_distance: CF::Scalar,
+) -> TreeDiff<DIMS, CF::Scalar>
+where
+    CF: CFeature<DIMS>,
+{
+    let before = before.clusters().collect::<Vec<_>>();
+    let after = after.clusters().collect::<Vec<_>>();
+    let distances = before
+        .iter()
+        .map(|b| {
+            after
+                .iter()
+                .map(|a| (&b.center - &a.center).norm2().sqrt())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let assignment = match max_distance.is_finite() {
+        true => {
+            // every cluster of `before` can be left unmatched at a cost of `max_distance`
+            let costs = distances
+                .iter()
+                .map(|row| {
+                    let mut row = row.clone();
+                    row.resize(after.len() + before.len(), max_distance);
+                    row
+                })
+                .collect::<Vec<_>>();
+            assign(&costs, after.len() + before.len())
+                .into_iter()
+                .map(|to| to.filter(|&to| to < after.len()))
+                .collect()
+        }
+        false => assign(&distances, after.len()),
+    };
+
+    let mut matched = vec![false; after.len()];
+    let mut moved = vec![];
+    let mut disappeared = vec![];
+    for ((cluster, to), distances) in before.into_iter().zip(assignment).zip(&distances) {
+        match to {
+            Some(to) => {
+                matched[to] = true;
+                moved.push(MovedCluster {
+                    before: cluster,
+                    after: after[to].clone(),
+                    distance: distances[to],
+                });
+            }
+            None => disappeared.push(cluster),
+        }
+    }
+    let appeared = after
+        .into_iter()
+        .zip(matched)
+        .filter(|(_, matched)| !matched)
+        .map(|(cluster, _)| cluster)
+        .collect();
+    TreeDiff {
+        moved,
+        appeared,
+        disappeared,
+    }
+}
+
+/// Minimum-cost assignment of the rows of `costs` (each with `cols` columns) to distinct columns:
+/// returns the column assigned to each row, or `None` for the rows left over if there are more
+/// rows than columns.
+fn assign<T: Float>(costs: &[Vec<T>], cols: usize) -> Vec<Option<usize>> {
+    let rows = costs.len();
+    if rows > cols {
+        // assign the columns to the rows instead
+        let transposed = (0..cols)
+            .map(|col| costs.iter().map(|row| row[col]).collect())
+            .collect::<Vec<_>>();
+        let mut assignment = vec![None; rows];
+        for (col, row) in assign(&transposed, rows).into_iter().enumerate() {
+            if let Some(row) = row {
+                assignment[row] = Some(col);
+            }
+        }
+        return assignment;
+    }
+
+    // shortest augmenting paths with potentials, over 1-based indices (0 is a sentinel column)
+    let cost = |row: usize, col: usize| costs[row - 1][col - 1].to_scalar();
+    let mut row_potential = vec![0.0; rows + 1];
+    let mut col_potential = vec![0.0; cols + 1];
+    // row assigned to each column
+    let mut col_row = vec![0; cols + 1];
+    // previous column on the augmenting path
+    let mut way = vec![0; cols + 1];
+    for row in 1..=rows {
+        col_row[0] = row;
+        let mut col = 0;
+        let mut min_slack = vec![Scalar::INFINITY; cols + 1];
+        let mut used = vec![false; cols + 1];
+        loop {
+            used[col] = true;
+            let current_row = col_row[col];
+            let mut delta = Scalar::INFINITY;
+            let mut next_col = 0;
+            for c in 1..=cols {
+                if used[c] {
+                    continue;
+                }
+                let slack = cost(current_row, c) - row_potential[current_row] - col_potential[c];
+                if slack < min_slack[c] {
+                    min_slack[c] = slack;
+                    way[c] = col;
+                }
+                if min_slack[c] < delta {
+                    delta = min_slack[c];
+                    next_col = c;
+                }
+            }
+            for c in 0..=cols {
+                match used[c] {
+                    true => {
+                        row_potential[col_row[c]] += delta;
+                        col_potential[c] -= delta;
+                    }
+                    false => min_slack[c] -= delta,
+                }
+            }
+            col = next_col;
+            if col_row[col] == 0 {
+                break;
+            }
+        }
+        // augment along the path
+        while col != 0 {
+            let prev = way[col];
+            col_row[col] = col_row[prev];
+            col = prev;
+        }
+    }
+
+    let mut assignment = vec![None; rows];
+    for col in 1..=cols {
+        if col_row[col] != 0 {
+            assignment[col_row[col] - 1] = Some(col - 1);
+        }
+    }
+    assignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cftree::{BasicConfig, BirchCFTree},
+        point::Point,
+    };
+
+    #[test]
+    fn compare_trees() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(1.0)
+            .build()
+            .unwrap();
+        let tree = |centers: &[[f64; 2]]| {
+            BirchCFTree::<2>::from_iter(
+                centers.iter().flat_map(|&[x, y]| {
+                    (0..5).map(move |i| Point::from_arr([x + i as f64 * 0.1, y]))
+                }),
+                config.clone(),
+            )
+        };
+        let before = tree(&[[0.0, 0.0], [10.0, 0.0], [20.0, 0.0], [30.0, 0.0]]);
+        assert!(compare(&before, &before).is_unchanged());
+
+        // one cluster moved, one disappeared and two appeared
+        let after = tree(&[
+            [0.0, 0.0],
+            [10.0, 3.0],
+            [30.0, 0.0],
+            [50.0, 0.0],
+            [60.0, 0.0],
+        ]);
+        let diff = compare_within(&before, &after, 5.0);
+        assert!(!diff.is_unchanged());
+        assert_eq!(diff.moved.len(), 3);
+        let moved = diff.moved_farther_than(0.0).collect::<Vec<_>>();
+        assert_eq!(moved.len(), 1);
+        assert_eq!(moved[0].before.center[1], 0.0);
+        assert_eq!(moved[0].after.center[1], 3.0);
+        assert!((diff.total_distance() - 3.0).abs() < 1e-9);
+        assert_eq!(diff.disappeared.len(), 1);
+        assert_eq!(diff.disappeared[0].center[0].round(), 20.0);
+        let mut appeared = diff
+            .appeared
+            .iter()
+            .map(|cluster| cluster.center[0].round())
+            .collect::<Vec<_>>();
+        appeared.sort_by(f64::total_cmp);
+        assert_eq!(appeared, vec![50.0, 60.0]);
+
+        // without a maximum distance, the vanished cluster is matched to the closest new one
+        let diff = compare(&before, &after);
+        assert_eq!(diff.moved.len(), 4);
+        assert!(diff.disappeared.is_empty());
+        assert_eq!(diff.appeared.len(), 1);
+        assert_eq!(diff.appeared[0].center[0].round(), 60.0);
+        assert!((diff.total_distance() - 33.0).abs() < 1e-9);
+
+        // the minimum-cost assignment isn't greedy
+        let costs = [vec![1.0, 2.0], vec![1.5, 10.0]];
+        assert_eq!(assign(&costs, 2), vec![Some(1), Some(0)]);
+        assert_eq!(assign(&[vec![3.0], vec![1.0]], 1), vec![None, Some(0)]);
+    }
+}
@@ -0,0 +1,159 @@
+/*!
+ * Concurrent ingestion into sharded trees.
+ *
+ * A [ConcurrentCFTree] holds several independent [CFTree] shards, each behind its own mutex.
+ * Inserting threads pick whichever shard is free, so concurrent inserts don't serialize on a
+ * single root. [ConcurrentCFTree::snapshot] combines the leaf clusters of all shards into a single
+ * tree.
+ */
+
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard, TryLockError,
+    },
+};
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{BasicConfig, CFTree, InsertOutcome, Node, TreeConfig},
+};
+
+/// A cluster feature tree which can be inserted into from multiple threads at once.
+#[derive(Debug)]
+pub struct ConcurrentCFTree<CF, const DIMS: usize, TC = BasicConfig> {
+    shards: Vec<Mutex<CFTree<CF, DIMS, TC>>>,
+    /// Shard at which the next insertion starts looking for a free shard.
+    next: AtomicUsize,
+    config: TC,
+}
+
+impl<CF, TC, const DIMS: usize> ConcurrentCFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    TC: TreeConfig + Clone,
+{
+    /// Creates a new concurrent tree with `shards` (at least one) empty shards.
+    pub fn new(shards: usize, config: TC) -> ConcurrentCFTree<CF, DIMS, TC> {
+        ConcurrentCFTree {
+            shards: (0..shards.max(1))
+                .map(|_| Mutex::new(CFTree::new(config.clone())))
+                .collect(),
+            next: AtomicUsize::new(0),
+            config,
+        }
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn config(&self) -> &TC {
+        &self.config
+    }
+
+    /// Locks a shard, preferring one which isn't currently locked by another thread.
+    fn lock_shard(&self) -> MutexGuard<'_, CFTree<CF, DIMS, TC>> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        for offset in 0..self.shards.len() {
+            match self.shards[(start + offset) % self.shards.len()].try_lock() {
+                Ok(shard) => return shard,
+                Err(TryLockError::Poisoned(poisoned)) => return poisoned.into_inner(),
+                Err(TryLockError::WouldBlock) => {}
+            }
+        }
+        // all shards are busy; wait for the one we started with
+        self.shards[start % self.shards.len()]
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Inserts a single point into one of the shards.
+    pub fn insert(&self, p: FeaturePoint<CF, DIMS>) -> InsertOutcome {
+        self.lock_shard().insert(p)
+    }
+
+    /// Inserts a batch of points into a single shard, holding its lock for the whole batch.
+    pub fn insert_batch<T: IntoIterator<Item = FeaturePoint<CF, DIMS>>>(&self, iter: T) {
+        self.lock_shard().extend(iter);
+    }
+
+    /// Combines the leaf clusters of all shards into a single tree. Insertion can continue while
+    /// (and after) the snapshot is taken; each shard is only locked while its leaf clusters are
+    /// copied.
+    pub fn snapshot(&self) -> CFTree<CF, DIMS, TC> {
+        let mut features = vec![];
+        for shard in &self.shards {
+            let shard = shard
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            collect_leaf_features(shard.root(), &mut features);
+        }
+        CFTree::bulk_load_features(features, self.config.clone())
+    }
+
+    /// Consumes this concurrent tree, returning its shards.
+    pub fn into_shards(self) -> Vec<CFTree<CF, DIMS, TC>> {
+        self.shards
+            .into_iter()
+            .map(|shard| {
+                shard
+                    .into_inner()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+            })
+            .collect()
+    }
+}
+
+fn collect_leaf_features<CF: Clone, const DIMS: usize>(node: &Node<CF, DIMS>, out: &mut Vec<CF>) {
+    out.extend(
+        node.iter_entries_with_depth()
+            .filter(|(_, entry)| entry.child.is_none())
+            .map(|(_, entry)| entry.feature.clone()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cfeature::betula::CFeature as BetulaFeature, point::Point};
+
+    #[test]
+    fn concurrent_insert() {
+        let config = BasicConfig::builder()
+            .capacity(2, 6)
+            .threshold(1.0)
+            .build()
+            .unwrap();
+        let tree = ConcurrentCFTree::<BetulaFeature<2>, 2>::new(4, config);
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let tree = &tree;
+                scope.spawn(move || {
+                    for i in 0..500 {
+                        let i = (thread * 500 + i) as f64;
+                        tree.insert(Point::from_arr([i % 37.0, (i * 7.0) % 41.0]));
+                    }
+                });
+            }
+        });
+        tree.insert_batch((0..100).map(|i| Point::from_arr([i as f64, 0.0])));
+
+        let snapshot = tree.snapshot();
+        assert_eq!(snapshot.clusters().map(|c| c.size).sum::<f64>(), 4100.0);
+        let height = snapshot.root().height();
+        assert!(snapshot.clusters().all(|c| c.depth == height - 1));
+
+        let shards = tree.into_shards();
+        assert_eq!(shards.len(), 4);
+        assert_eq!(
+            shards
+                .iter()
+                .flat_map(|shard| shard.clusters())
+                .map(|c| c.size)
+                .sum::<f64>(),
+            4100.0
+        );
+    }
+}
@@ -0,0 +1,191 @@
+/*!
+ * Confidence intervals on the centers of leaf clusters.
+ *
+ * The center of a leaf cluster is the mean of the points it absorbed, so its uncertainty shrinks
+ * with the size of the cluster: for each dimension, the half-width of a confidence interval at
+ * level `1 - alpha` is `t * s / sqrt(n)`, where `n` is the size of the cluster, `s` the sample
+ * standard deviation of its points and `t` the `1 - alpha / 2` quantile of Student's
+ * t-distribution with `n - 1` degrees of freedom. Sizes are treated as counts of points, and
+ * variances are most accurate for numerically stable features such as
+ * [BETULA's](crate::cfeature::betula::CFeature).
+ */
+
+use alloc::{vec, vec::Vec};
+use core::f64::consts::PI;
+
+use num_traits::Float as _;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{CFTree, NodeEntry},
+    point::{Float, Point, Scalar},
+    summary::collect_leaves,
+};
+
+/// Half-widths of the per-dimension confidence intervals at level `1 - alpha` on the center of
+/// `feature` (see the [module documentation](self)). Infinite if `feature` summarizes a single
+/// point or less, and NaN if `alpha` isn't within (0, 1).
+pub fn center_confidence_interval<CF: CFeature<DIMS>, const DIMS: usize>(
+    feature: &CF,
+    alpha: Scalar,
+) -> FeaturePoint<CF, DIMS> {
+    let n = feature.size().to_scalar();
+    if !(alpha > 0.0 && alpha < 1.0) {
+        return Point::from_fn(|_| CF::Scalar::nan());
+    }
+    if n <= 1.0 {
+        return Point::from_fn(|_| CF::Scalar::infinity());
+    }
+    let t = student_t_quantile(1.0 - alpha / 2.0, n - 1.0);
+    let variance = feature.variance();
+    // the standard error of the mean, from the (unbiased) sample variance
+    Point::from_fn(|d| CF::Scalar::from_scalar(t * (variance[d].to_scalar() / (n - 1.0)).sqrt()))
+}
+
+impl<CF: CFeature<DIMS>, const DIMS: usize> NodeEntry<CF, DIMS> {
+    /// Half-widths of the per-dimension confidence intervals at level `1 - alpha` on the center
+    /// of this entry; see [center_confidence_interval].
+    pub fn center_confidence_interval(&self, alpha: Scalar) -> FeaturePoint<CF, DIMS> {
+        center_confidence_interval(&self.feature, alpha)
+    }
+}
+
+impl<CF: CFeature<DIMS>, TC, const DIMS: usize> CFTree<CF, DIMS, TC> {
+    /// Half-widths of the per-dimension confidence intervals at level `1 - alpha` on the centers
+    /// of the leaf clusters of this tree, indexed by
+    /// [ClusterSummary::id](crate::summary::ClusterSummary::id); see
+    /// [center_confidence_interval].
+    pub fn center_confidence_intervals(&self, alpha: Scalar) -> Vec<FeaturePoint<CF, DIMS>> {
+        let mut leaves = vec![];
+        collect_leaves(self.root(), &mut leaves);
+        leaves
+            .into_iter()
+            .map(|feature| center_confidence_interval(feature, alpha))
+            .collect()
+    }
+}
+
+/// Quantile function of the standard normal distribution, by Acklam's rational approximation
+/// (relative error below 1.2e-9).
+fn normal_quantile(p: Scalar) -> Scalar {
+    const A: [Scalar; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [Scalar; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [Scalar; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [Scalar; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    const P_LOW: Scalar = 0.02425;
+    let poly =
+        |coefficients: &[Scalar], x: Scalar| coefficients.iter().fold(0.0, |acc, &c| acc * x + c);
+    let tail = |p: Scalar| {
+        let q = (-2.0 * p.ln()).sqrt();
+        poly(&C, q) / (poly(&D, q) * q + 1.0)
+    };
+    match p {
+        p if p < P_LOW => tail(p),
+        p if p <= 1.0 - P_LOW => {
+            let q = p - 0.5;
+            let r = q * q;
+            poly(&A, r) * q / (poly(&B, r) * r + 1.0)
+        }
+        p => -tail(1.0 - p),
+    }
+}
+
+/// Quantile function of Student's t-distribution with `df` degrees of freedom: exact for one and
+/// two degrees of freedom, and by the Cornish-Fisher expansion around the normal quantile for
+/// more (within 0.3% for three degrees of freedom, and closer for more).
+fn student_t_quantile(p: Scalar, df: Scalar) -> Scalar {
+    match df {
+        df if df <= 1.0 => (PI * (p - 0.5)).tan(),
+        df if df <= 2.0 => (2.0 * p - 1.0) / (2.0 * p * (1.0 - p)).sqrt(),
+        df => {
+            let z = normal_quantile(p);
+            let z2 = z * z;
+            let g1 = (z2 + 1.0) * z / 4.0;
+            let g2 = ((5.0 * z2 + 16.0) * z2 + 3.0) * z / 96.0;
+            let g3 = (((3.0 * z2 + 19.0) * z2 + 17.0) * z2 - 15.0) * z / 384.0;
+            let g4 =
+                ((((79.0 * z2 + 776.0) * z2 + 1482.0) * z2 - 1920.0) * z2 - 945.0) * z / 92160.0;
+            z + g1 / df + g2 / (df * df) + g3 / df.powi(3) + g4 / df.powi(4)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_traits::Zero;
+
+    use super::*;
+    use crate::{
+        cfeature::betula::CFeature as BetulaFeature,
+        cftree::{BasicConfig, BetulaCFTree},
+    };
+
+    #[test]
+    fn center_confidence_intervals() {
+        for (p, expected) in [(0.975, 1.959964), (0.5, 0.0), (0.01, -2.326348)] {
+            assert!((normal_quantile(p) - expected).abs() < 1e-6);
+        }
+        for (df, expected) in [(1.0, 12.7062), (2.0, 4.3027), (3.0, 3.1824), (10.0, 2.2281)] {
+            assert!((student_t_quantile(0.975, df) - expected).abs() < expected * 3e-3);
+        }
+
+        // a large and a small cluster with the same spread, and a single point
+        let spread =
+            |i: usize| Point::from_arr([(i % 2) as Scalar * 2.0, (i / 2 % 2) as Scalar * 4.0]);
+        let feature = |points: usize| {
+            (0..points).fold(BetulaFeature::<2>::zero(), |feature, i| feature + spread(i))
+        };
+        // 1.98 standard errors of the mean for a large cluster, with sample standard deviations
+        // of about 1 and 2
+        let large = center_confidence_interval(&feature(100), 0.05);
+        let expected = 1.984 * (100.0 / 99.0 as Scalar).sqrt() / 10.0;
+        assert!((large[0] - expected).abs() < 1e-3);
+        assert!((large[1] - 2.0 * expected).abs() < 2e-3);
+        // the same spread is much less certain with few points
+        assert!(center_confidence_interval(&feature(4), 0.05)[0] > 5.0 * large[0]);
+        assert!(center_confidence_interval(&feature(1), 0.05)[0].is_infinite());
+        assert!(center_confidence_interval(&feature(100), 1.5)[0].is_nan());
+
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(1.0)
+            .build()
+            .unwrap();
+        let tree = BetulaCFTree::<2>::from_iter((0..100).map(spread), config);
+        let intervals = tree.center_confidence_intervals(0.05);
+        assert_eq!(intervals.len(), tree.clusters().count());
+        let mut leaf = tree.root();
+        while let Some(ref child) = leaf.entries[0].child {
+            leaf = child;
+        }
+        assert_eq!(
+            intervals[0],
+            leaf.entries[0].center_confidence_interval(0.05)
+        );
+    }
+}
@@ -0,0 +1,180 @@
+/*!
+ * Must-link and cannot-link constraints between points, for semi-supervised clustering.
+ *
+ * A [ConstraintSet] holds pairs of point ids which must end up in the same leaf entry
+ * (*must-link*, e.g. records known to be duplicates) or must not (*cannot-link*, e.g. records
+ * known to be distinct). Once attached to a tree (see [CFTree::with_constraints]), it's consulted
+ * whenever a point is inserted one at a time:
+ *
+ * - a point must-linked to points already in a leaf entry is routed to that entry and absorbed
+ *   into it, even past the threshold;
+ * - a point cannot-linked to a point of the leaf entry it would be absorbed into starts a new leaf
+ *   entry instead. Cannot-links take precedence over conflicting must-links.
+ *
+ * Constraints refer to the ids leaf entries keep of the points they absorbed, so they're only
+ * enforced by trees which track ids (see
+ * [TreeConfig::track_ids](crate::cftree::TreeConfig::track_ids)). Batch and bulk loads don't
+ * consult constraints.
+ */
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec,
+    vec::Vec,
+};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(doc)]
+use crate::cftree::CFTree;
+use crate::cftree::Node;
+
+/// Symmetric must-link and cannot-link constraints between point ids (see the
+/// [module documentation](self)).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConstraintSet {
+    must_link: BTreeMap<u64, BTreeSet<u64>>,
+    cannot_link: BTreeMap<u64, BTreeSet<u64>>,
+}
+
+impl ConstraintSet {
+    pub fn new() -> ConstraintSet {
+        ConstraintSet::default()
+    }
+
+    /// Creates a constraint set from must-link and cannot-link pairs of ids.
+    pub fn from_pairs<M, C>(must_link: M, cannot_link: C) -> ConstraintSet
+    where
+        M: IntoIterator<Item = (u64, u64)>,
+        C: IntoIterator<Item = (u64, u64)>,
+    {
+        let mut constraints = ConstraintSet::new();
+        for (a, b) in must_link {
+            constraints.must_link(a, b);
+        }
+        for (a, b) in cannot_link {
+            constraints.cannot_link(a, b);
+        }
+        constraints
+    }
+
+    /// Requires the points with ids `a` and `b` to end up in the same leaf entry.
+    pub fn must_link(&mut self, a: u64, b: u64) {
+        link(&mut self.must_link, a, b);
+    }
+
+    /// Forbids the points with ids `a` and `b` from ending up in the same leaf entry.
+    pub fn cannot_link(&mut self, a: u64, b: u64) {
+        link(&mut self.cannot_link, a, b);
+    }
+
+    /// Whether the points with ids `a` and `b` are must-linked.
+    pub fn linked(&self, a: u64, b: u64) -> bool {
+        self.must_link.get(&a).is_some_and(|ids| ids.contains(&b))
+    }
+
+    /// Whether the points with ids `a` and `b` are cannot-linked.
+    pub fn separated(&self, a: u64, b: u64) -> bool {
+        self.cannot_link.get(&a).is_some_and(|ids| ids.contains(&b))
+    }
+
+    /// Number of must-link and cannot-link pairs.
+    pub fn len(&self) -> usize {
+        let pairs = |links: &BTreeMap<u64, BTreeSet<u64>>| {
+            links.values().map(BTreeSet::len).sum::<usize>() / 2
+        };
+        pairs(&self.must_link) + pairs(&self.cannot_link)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.must_link.is_empty() && self.cannot_link.is_empty()
+    }
+
+    /// Whether any of the ids `left` is cannot-linked to any of the ids `right`.
+    pub(crate) fn conflict(&self, left: &[u64], right: &[u64]) -> bool {
+        any_linked(&self.cannot_link, left, right)
+    }
+
+    /// Path (as entry indices from `node`) to the first leaf entry holding a point must-linked to
+    /// any of the ids `ids`, if any.
+    pub(crate) fn route<CF, const DIMS: usize>(
+        &self,
+        node: &Node<CF, DIMS>,
+        ids: &[u64],
+    ) -> Option<Vec<usize>> {
+        if !ids.iter().any(|id| self.must_link.contains_key(id)) {
+            return None;
+        }
+        node.entries
+            .iter()
+            .enumerate()
+            .find_map(|(idx, entry)| match &entry.child {
+                Some(child) => self.route(child, ids).map(|mut route| {
+                    route.insert(0, idx);
+                    route
+                }),
+                None if any_linked(&self.must_link, ids, &entry.ids) => Some(vec![idx]),
+                None => None,
+            })
+    }
+}
+
+fn link(links: &mut BTreeMap<u64, BTreeSet<u64>>, a: u64, b: u64) {
+    links.entry(a).or_default().insert(b);
+    links.entry(b).or_default().insert(a);
+}
+
+fn any_linked(links: &BTreeMap<u64, BTreeSet<u64>>, left: &[u64], right: &[u64]) -> bool {
+    left.iter().any(|id| {
+        links
+            .get(id)
+            .is_some_and(|linked| right.iter().any(|other| linked.contains(other)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cftree::{BasicConfig, BirchCFTree},
+        point::Point,
+    };
+
+    #[test]
+    fn constraints() {
+        let constraints = ConstraintSet::from_pairs([(0, 3)], [(1, 2), (4, 5)]);
+        assert_eq!(constraints.len(), 3);
+        assert!(constraints.linked(3, 0) && !constraints.linked(0, 1));
+        assert!(constraints.separated(2, 1) && !constraints.separated(0, 3));
+
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(1.0)
+            .track_ids(true)
+            .build()
+            .unwrap();
+        let points = [
+            // 0 and 3 are far apart, but must be together
+            Point::from_arr([0.0, 0.0]),
+            // 1 and 2 are close, but must be apart
+            Point::from_arr([10.0, 0.0]),
+            Point::from_arr([10.1, 0.0]),
+            Point::from_arr([20.0, 0.0]),
+            // 4 and 5 are identical, but must be apart
+            Point::from_arr([30.0, 0.0]),
+            Point::from_arr([30.0, 0.0]),
+        ];
+        let mut tree = BirchCFTree::<2>::new(config.clone()).with_constraints(constraints);
+        tree.extend(points.clone());
+        let mut clusters = tree.cluster_ids();
+        clusters.sort();
+        assert_eq!(clusters, [&[0, 3][..], &[1], &[2], &[4], &[5]]);
+        assert_eq!(tree.constraints().map(ConstraintSet::len), Some(3));
+
+        // without constraints, the points are clustered by distance alone
+        let tree = BirchCFTree::<2>::from_iter(points, config);
+        let mut clusters = tree.cluster_ids();
+        clusters.sort();
+        assert_eq!(clusters, [&[0][..], &[1, 2], &[3], &[4, 5]]);
+    }
+}
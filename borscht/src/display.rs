@@ -19,6 +19,10 @@ pub trait DisplayTree {
 
 impl<CF: CFeature<DIMS>, const DIMS: usize> DisplayTree for Node<CF, DIMS> {
     fn display_tree_at_level(&self, level: usize, max_depth: Option<usize>) {
+        if level == 0 && self.entries.is_empty() {
+            println!("Empty tree");
+            return;
+        }
         self.entries.iter().for_each(|entry| {
             entry.display_tree_at_level(level, max_depth);
         })
@@ -0,0 +1,323 @@
+/*!
+ * Detection of concept drift in a stream of insertions.
+ *
+ * While a stream is stationary, insertions into a tree mostly land in existing leaf clusters:
+ * the rate of insertions creating new leaf entries falls as the tree covers the distribution, as
+ * does the rate of outliers (points far from every leaf cluster, see [CFTree::anomaly_score]).
+ * A [DriftDetector] compares both rates over the last `window` insertions with the same rates
+ * over the `window` insertions before them, and reports drift when either grows by more than a
+ * tolerance. It then sets a flag, calls its callback if it has one, and starts over with empty
+ * windows, so that a shift is reported once rather than on every insertion until it settles.
+ *
+ * [CFTree::insert_monitored] inserts points while feeding a detector, optionally rebuilding the
+ * tree whenever drift is detected (see [CFTree::rebuild]).
+ */
+
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+use core::fmt::{self, Debug};
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{CFTree, InsertOutcome, TreeConfig, TreeMetrics},
+    point::{Float, Scalar},
+    summary::collect_leaf_entries,
+};
+
+/// Rates observed by a [DriftDetector] when it detected drift.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriftEvent {
+    /// Number of insertions observed by the detector, including the one which revealed drift.
+    pub observed: u64,
+    /// Fraction of the last `window` insertions which created a new leaf entry.
+    pub new_entry_rate: Scalar,
+    /// Fraction of the `window` insertions before those which created a new leaf entry.
+    pub reference_new_entry_rate: Scalar,
+    /// Fraction of the last `window` insertions which were outliers.
+    pub outlier_rate: Scalar,
+    /// Fraction of the `window` insertions before those which were outliers.
+    pub reference_outlier_rate: Scalar,
+}
+
+/// What a [DriftDetector] records about an insertion.
+#[derive(Debug, Clone, Copy)]
+struct Observation {
+    new_entry: bool,
+    outlier: bool,
+}
+
+/// Running counts of new entries and outliers over a window of observations.
+#[derive(Debug, Clone, Copy, Default)]
+struct Counts {
+    new_entries: usize,
+    outliers: usize,
+}
+
+impl Counts {
+    fn add(&mut self, observation: Observation) {
+        self.new_entries += observation.new_entry as usize;
+        self.outliers += observation.outlier as usize;
+    }
+
+    fn remove(&mut self, observation: Observation) {
+        self.new_entries -= observation.new_entry as usize;
+        self.outliers -= observation.outlier as usize;
+    }
+}
+
+/// Callback of a [DriftDetector], called whenever it detects drift.
+pub type DriftCallback = Box<dyn FnMut(&DriftEvent)>;
+
+/// Monitors the rates of new leaf entries and outliers over a sliding window of insertions (see
+/// the [module documentation](self)).
+pub struct DriftDetector {
+    window: usize,
+    tolerance: Scalar,
+    outlier_score: Option<Scalar>,
+    auto_rebuild: bool,
+    callback: Option<DriftCallback>,
+    /// The last `2 * window` observations at most, oldest first
+    observations: VecDeque<Observation>,
+    /// Counts over the older half of `observations`
+    reference: Counts,
+    /// Counts over the last `window` observations
+    recent: Counts,
+    observed: u64,
+    drifted: bool,
+}
+
+impl Debug for DriftDetector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DriftDetector")
+            .field("window", &self.window)
+            .field("tolerance", &self.tolerance)
+            .field("outlier_score", &self.outlier_score)
+            .field("auto_rebuild", &self.auto_rebuild)
+            .field("callback", &self.callback.is_some())
+            .field("observed", &self.observed)
+            .field("drifted", &self.drifted)
+            .finish()
+    }
+}
+
+impl DriftDetector {
+    /// Creates a detector comparing rates over windows of `window` (at least one) insertions,
+    /// which reports drift when a rate grows by more than `tolerance` (a fraction of the window)
+    /// from one window to the next. Outliers aren't counted unless configured with
+    /// [DriftDetector::outlier_score].
+    pub fn new(window: usize, tolerance: Scalar) -> DriftDetector {
+        let window = window.max(1);
+        DriftDetector {
+            window,
+            tolerance,
+            outlier_score: None,
+            auto_rebuild: false,
+            callback: None,
+            observations: VecDeque::with_capacity(2 * window),
+            reference: Counts::default(),
+            recent: Counts::default(),
+            observed: 0,
+            drifted: false,
+        }
+    }
+
+    /// Counts inserted points whose anomaly score (see [CFTree::anomaly_score]) is above
+    /// `outlier_score` as outliers, when fed by [CFTree::insert_monitored].
+    pub fn outlier_score(mut self, outlier_score: Scalar) -> Self {
+        self.outlier_score = Some(outlier_score);
+        self
+    }
+
+    /// Sets whether [CFTree::insert_monitored] rebuilds the tree whenever drift is detected.
+    pub fn auto_rebuild(mut self, auto_rebuild: bool) -> Self {
+        self.auto_rebuild = auto_rebuild;
+        self
+    }
+
+    /// Calls `callback` whenever drift is detected.
+    pub fn on_drift<F: FnMut(&DriftEvent) + 'static>(mut self, callback: F) -> Self {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Number of insertions observed so far.
+    pub fn observed(&self) -> u64 {
+        self.observed
+    }
+
+    /// Whether drift was detected since the detector was created or last cleared.
+    pub fn drifted(&self) -> bool {
+        self.drifted
+    }
+
+    /// Clears the drift flag, returning whether it was set.
+    pub fn clear(&mut self) -> bool {
+        core::mem::take(&mut self.drifted)
+    }
+
+    /// Records an insertion with outcome `outcome`, which was an outlier if `outlier`. Returns the
+    /// rates which revealed drift, if it did.
+    pub fn observe(&mut self, outcome: InsertOutcome, outlier: bool) -> Option<DriftEvent> {
+        let observation = Observation {
+            new_entry: outcome != InsertOutcome::Absorbed,
+            outlier,
+        };
+        self.observed += 1;
+        self.observations.push_back(observation);
+        self.recent.add(observation);
+        if self.observations.len() > self.window {
+            // the observation leaving the recent window enters the reference window
+            let moved = self.observations[self.observations.len() - 1 - self.window];
+            self.recent.remove(moved);
+            self.reference.add(moved);
+        }
+        if self.observations.len() > 2 * self.window {
+            let oldest = self.observations.pop_front().expect("non-empty window");
+            self.reference.remove(oldest);
+        }
+        if self.observations.len() < 2 * self.window {
+            return None;
+        }
+
+        let rate = |count: usize| count as Scalar / self.window as Scalar;
+        let event = DriftEvent {
+            observed: self.observed,
+            new_entry_rate: rate(self.recent.new_entries),
+            reference_new_entry_rate: rate(self.reference.new_entries),
+            outlier_rate: rate(self.recent.outliers),
+            reference_outlier_rate: rate(self.reference.outliers),
+        };
+        if event.new_entry_rate - event.reference_new_entry_rate <= self.tolerance
+            && event.outlier_rate - event.reference_outlier_rate <= self.tolerance
+        {
+            return None;
+        }
+        debug_event!(
+            observed = event.observed,
+            new_entry_rate = event.new_entry_rate,
+            outlier_rate = event.outlier_rate,
+            "drift detected"
+        );
+        self.drifted = true;
+        self.observations.clear();
+        self.reference = Counts::default();
+        self.recent = Counts::default();
+        if let Some(ref mut callback) = self.callback {
+            callback(&event);
+        }
+        Some(event)
+    }
+}
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    TC: TreeConfig + Clone,
+{
+    /// Inserts point `p`, recording the insertion with `detector` (checking first whether `p` is
+    /// an outlier, if the detector counts them). If this reveals drift and the detector is set to
+    /// rebuild automatically, the tree is then rebuilt. Returns the outcome of the insertion.
+    pub fn insert_monitored(
+        &mut self,
+        p: FeaturePoint<CF, DIMS>,
+        detector: &mut DriftDetector,
+    ) -> InsertOutcome {
+        let outlier = detector.outlier_score.is_some_and(|outlier_score| {
+            self.anomaly_score(&p)
+                .is_some_and(|score| score.to_scalar() > outlier_score)
+        });
+        let outcome = self.insert(p);
+        if detector.observe(outcome, outlier).is_some() && detector.auto_rebuild {
+            self.rebuild();
+        }
+        outcome
+    }
+
+    /// Rebuilds this tree by reinserting its leaf entries into a new tree with the same
+    /// configuration, so that nodes shaped by earlier parts of a stream are regrouped around the
+    /// current leaf clusters. Leaf clusters themselves are kept as they are.
+    pub fn rebuild(&mut self) {
+        let mut entries = Vec::new();
+        collect_leaf_entries(self.root(), &mut entries);
+        debug_event!(leaves = entries.len(), "rebuilding tree");
+        let mut tree = CFTree::new(self.config().clone());
+        for entry in entries {
+            tree.insert_entry(entry.clone());
+        }
+        // the reinsertions aren't counted as insertions
+        *tree.metrics_mut() = TreeMetrics {
+            rebuilds: self.metrics().rebuilds + 1,
+            ..self.metrics().clone()
+        };
+        tree.trace_rebuild(self);
+        *self = tree;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{rc::Rc, vec};
+    use core::cell::RefCell;
+
+    use super::*;
+    use crate::{
+        cftree::{BasicConfig, BirchCFTree},
+        point::Point,
+    };
+
+    #[test]
+    fn drift() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        // a stationary stream over a grid of 70 points, which shifts after 300 points
+        let point = |i: usize| {
+            let offset = if i < 300 { 0.0 } else { 100.0 };
+            Point::from_arr([(i % 10) as Scalar + offset, (i % 7) as Scalar])
+        };
+
+        let events = Rc::new(RefCell::new(vec![]));
+        let sink = events.clone();
+        let mut detector = DriftDetector::new(50, 0.3)
+            .outlier_score(5.0)
+            .on_drift(move |event| sink.borrow_mut().push(*event));
+        let mut tree = BirchCFTree::<2>::new(config.clone());
+        for i in 0..300 {
+            tree.insert_monitored(point(i), &mut detector);
+        }
+        assert!(!detector.drifted());
+        assert!(events.borrow().is_empty());
+        for i in 300..400 {
+            tree.insert_monitored(point(i), &mut detector);
+        }
+        // reported once, soon after the shift
+        let events = events.borrow();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].observed > 300 && events[0].observed < 320);
+        assert!(events[0].new_entry_rate > events[0].reference_new_entry_rate + 0.3);
+        assert!(events[0].outlier_rate > 0.0 && events[0].reference_outlier_rate == 0.0);
+        assert!(detector.clear());
+        assert!(!detector.drifted());
+        assert_eq!(detector.observed(), 400);
+
+        // rebuilding automatically keeps the points of the leaf clusters
+        let mut detector = DriftDetector::new(50, 0.3).auto_rebuild(true);
+        let mut tree = BirchCFTree::<2>::new(config);
+        for i in 0..400 {
+            tree.insert_monitored(point(i), &mut detector);
+        }
+        assert!(detector.drifted());
+        assert_eq!(tree.metrics().rebuilds, 1);
+        assert_eq!(tree.metrics().inserted, 400);
+        assert!(tree.clusters().count() >= 140);
+        assert_eq!(
+            tree.clusters().map(|cluster| cluster.size).sum::<Scalar>(),
+            400.0
+        );
+    }
+}
@@ -0,0 +1,12 @@
+/*!
+ * Runtime-dimensional variants of the point, cluster feature, and tree types.
+ *
+ * The types in [crate::point], [crate::cfeature], and [crate::cftree] fix dimensionality at
+ * compile time via a const generic. The types in this module instead carry their dimensionality
+ * at runtime, for use when the width of the input data isn't known until it's read (e.g. when
+ * loading an arbitrary CSV file).
+ */
+
+pub mod cfeature;
+pub mod cftree;
+pub mod point;
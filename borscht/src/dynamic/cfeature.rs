@@ -0,0 +1,47 @@
+/*!
+ * Provides the runtime-dimensional cluster feature ([CFeature]) trait.
+ */
+
+use std::{fmt::Debug, ops::Add};
+
+use crate::{cfeature::Dist, point::Scalar};
+
+pub mod betula;
+pub mod birch;
+pub mod categorical;
+pub mod mixed;
+pub mod sparse;
+
+/// Shorthand for the point type accepted by a runtime-dimensional cluster feature.
+pub type FeaturePoint<CF> = <CF as CFeature>::Point;
+
+pub trait CFeature:
+    Add<Self, Output = Self>
+    + for<'a> Add<&'a Self, Output = Self>
+    + Add<FeaturePoint<Self>, Output = Self>
+    + for<'a> Add<&'a FeaturePoint<Self>, Output = Self>
+    + Clone
+    + Sized
+    + Dist<Self>
+    + Dist<FeaturePoint<Self>>
+    + From<FeaturePoint<Self>>
+{
+    /// Type of the points summarized by this cluster feature (e.g.
+    /// [DynPoint](crate::dynamic::point::DynPoint) for the numeric features).
+    type Point: Clone + Debug;
+
+    /// Creates an empty cluster feature of the specified dimensionality.
+    fn empty(dims: usize) -> Self;
+    fn dims(&self) -> usize;
+    fn diam2(&self) -> Scalar;
+    fn diam(&self) -> Scalar {
+        self.diam2().sqrt()
+    }
+    /// Squared radius: the average squared distance of the summarized points from the center.
+    fn radius2(&self) -> Scalar;
+    fn radius(&self) -> Scalar {
+        self.radius2().sqrt()
+    }
+    fn center(&self) -> Self::Point;
+    fn size(&self) -> Scalar;
+}
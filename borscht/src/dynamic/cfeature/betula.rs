@@ -0,0 +1,108 @@
+/*!
+ * Runtime-dimensional Betula cluster feature implementation.
+ */
+
+use std::ops::Add;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{cfeature::Dist, dynamic::point::DynPoint, point::Scalar};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CFeature {
+    /// Sum of weights
+    n: Scalar,
+    /// Weighted mean
+    mu: DynPoint,
+    /// Weighted sum of squared deviations from mean
+    s: DynPoint,
+}
+
+impl Add<Self> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl Add<&Self> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: &Self) -> Self::Output {
+        let n = self.n + rhs.n;
+        let mu = &self.mu + rhs.n / n * (&rhs.mu - &self.mu);
+        CFeature {
+            n,
+            mu: mu.clone(),
+            s: self.s + &rhs.s + rhs.n * (&self.mu - &rhs.mu) * (mu - &rhs.mu),
+        }
+    }
+}
+
+impl Add<&DynPoint> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: &DynPoint) -> Self::Output {
+        self + CFeature {
+            n: 1.0,
+            mu: rhs.clone(),
+            s: DynPoint::zeros(rhs.dims()),
+        }
+    }
+}
+impl Add<DynPoint> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: DynPoint) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl Dist<DynPoint> for CFeature {
+    fn dist2(&self, r: &DynPoint) -> Scalar {
+        (&self.mu - r).norm2()
+    }
+}
+
+impl Dist<Self> for CFeature {
+    fn dist2(&self, r: &Self) -> Scalar {
+        (&self.mu - &r.mu).norm2()
+    }
+}
+
+impl From<DynPoint> for CFeature {
+    fn from(orig: DynPoint) -> CFeature {
+        <Self as crate::dynamic::cfeature::CFeature>::empty(orig.dims()) + orig
+    }
+}
+
+impl crate::dynamic::cfeature::CFeature for CFeature {
+    type Point = DynPoint;
+
+    fn empty(dims: usize) -> CFeature {
+        CFeature {
+            n: 0.0,
+            mu: DynPoint::zeros(dims),
+            s: DynPoint::zeros(dims),
+        }
+    }
+    fn dims(&self) -> usize {
+        self.mu.dims()
+    }
+    fn diam2(&self) -> Scalar {
+        2.0 / self.n * self.s.norm2()
+    }
+    fn radius2(&self) -> Scalar {
+        match self.n == 0.0 {
+            true => 0.0,
+            false => self.s.as_slice().iter().sum::<Scalar>() / self.n,
+        }
+    }
+    fn size(&self) -> Scalar {
+        self.n
+    }
+    fn center(&self) -> DynPoint {
+        self.mu.clone()
+    }
+}
@@ -0,0 +1,116 @@
+/*!
+ * Runtime-dimensional standard cluster feature implementation.
+ */
+
+use std::ops::Add;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{cfeature::Dist, dynamic::point::DynPoint, point::Scalar};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CFeature {
+    /// Linear Sum
+    ls: DynPoint,
+    /// Sum of Squares
+    ss: Scalar,
+    /// Size
+    n: usize,
+}
+
+impl Add<Self> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl Add<&Self> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: &Self) -> Self::Output {
+        CFeature {
+            ls: self.ls + &rhs.ls,
+            ss: self.ss + rhs.ss,
+            n: self.n + rhs.n,
+        }
+    }
+}
+
+impl Add<&DynPoint> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: &DynPoint) -> Self::Output {
+        CFeature {
+            ls: self.ls + rhs,
+            ss: self.ss + rhs.norm2(),
+            n: self.n + 1,
+        }
+    }
+}
+
+impl Add<DynPoint> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: DynPoint) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl Dist<DynPoint> for CFeature {
+    fn dist2(&self, r: &DynPoint) -> Scalar {
+        (&crate::dynamic::cfeature::CFeature::center(self) - r).norm2()
+    }
+}
+
+impl Dist<Self> for CFeature {
+    fn dist2(&self, r: &Self) -> Scalar {
+        use crate::dynamic::cfeature::CFeature as _;
+        (&self.center() - &r.center()).norm2()
+    }
+}
+
+impl From<DynPoint> for CFeature {
+    fn from(orig: DynPoint) -> CFeature {
+        <Self as crate::dynamic::cfeature::CFeature>::empty(orig.dims()) + orig
+    }
+}
+
+impl crate::dynamic::cfeature::CFeature for CFeature {
+    type Point = DynPoint;
+
+    fn empty(dims: usize) -> CFeature {
+        CFeature {
+            ls: DynPoint::zeros(dims),
+            ss: 0.0,
+            n: 0,
+        }
+    }
+    fn dims(&self) -> usize {
+        self.ls.dims()
+    }
+    fn diam2(&self) -> Scalar {
+        (2.0 * self.n as Scalar * self.ss - 2.0 * self.ls.norm2())
+            / if self.n < 2 {
+                1 as Scalar
+            } else {
+                (self.n * (self.n - 1)) as Scalar
+            }
+    }
+    fn radius2(&self) -> Scalar {
+        match self.n {
+            0 => 0.0,
+            _ => {
+                let n = self.n as Scalar;
+                ((n * self.ss - self.ls.norm2()) / (n * n)).max(0.0)
+            }
+        }
+    }
+    fn size(&self) -> Scalar {
+        self.n as Scalar
+    }
+    fn center(&self) -> DynPoint {
+        self.ls.clone() / (self.n as Scalar)
+    }
+}
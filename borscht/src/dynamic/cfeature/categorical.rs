@@ -0,0 +1,187 @@
+/*!
+ * Categorical cluster feature implementation, in the style of k-modes.
+ *
+ * The feature keeps the frequency of every category in every dimension, and distances count
+ * mismatching categories. Between a point and a feature, the distance is the expected number of
+ * mismatches between the point and a random member of the cluster (Huang's frequency-based
+ * dissimilarity), which reduces to the plain mismatch count for single-point clusters. The
+ * squared diameter is the average number of mismatches between pairs of members, so the tree
+ * threshold bounds the number of differing dimensions within a cluster.
+ */
+
+use std::{collections::BTreeMap, ops::Add};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{cfeature::Dist, dynamic::point::CategoricalPoint, point::Scalar};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CFeature {
+    /// Per-dimension category frequencies
+    counts: Vec<BTreeMap<u32, Scalar>>,
+    /// Size
+    n: Scalar,
+}
+
+impl Add<Self> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl Add<&Self> for CFeature {
+    type Output = CFeature;
+
+    fn add(mut self, rhs: &Self) -> Self::Output {
+        assert_eq!(
+            self.counts.len(),
+            rhs.counts.len(),
+            "dimensionality mismatch between features"
+        );
+        for (counts, rhs_counts) in self.counts.iter_mut().zip(&rhs.counts) {
+            for (&category, &count) in rhs_counts {
+                *counts.entry(category).or_default() += count;
+            }
+        }
+        self.n += rhs.n;
+        self
+    }
+}
+
+impl Add<&CategoricalPoint> for CFeature {
+    type Output = CFeature;
+
+    fn add(mut self, rhs: &CategoricalPoint) -> Self::Output {
+        assert_eq!(
+            self.counts.len(),
+            rhs.dims(),
+            "dimensionality mismatch between feature and point"
+        );
+        for (counts, &category) in self.counts.iter_mut().zip(rhs.as_slice()) {
+            *counts.entry(category).or_default() += 1.0;
+        }
+        self.n += 1.0;
+        self
+    }
+}
+
+impl Add<CategoricalPoint> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: CategoricalPoint) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl CFeature {
+    /// Per-dimension frequencies of the categories of the summarized points.
+    pub fn counts(&self) -> &[BTreeMap<u32, Scalar>] {
+        &self.counts
+    }
+    /// Relative frequency of `category` in dimension `dim` among the summarized points.
+    pub fn frequency(&self, dim: usize, category: u32) -> Scalar {
+        match self.n == 0.0 {
+            true => 0.0,
+            false => self.counts[dim].get(&category).copied().unwrap_or(0.0) / self.n,
+        }
+    }
+    /// Sum over dimensions of the number of ordered pairs of members of this cluster which have
+    /// different categories.
+    fn impurity(&self) -> Scalar {
+        self.counts
+            .iter()
+            .map(|counts| {
+                let same = counts.values().map(|count| count * count).sum::<Scalar>();
+                self.n * self.n - same
+            })
+            .sum::<Scalar>()
+    }
+}
+
+impl Dist<CategoricalPoint> for CFeature {
+    fn dist2(&self, r: &CategoricalPoint) -> Scalar {
+        r.as_slice()
+            .iter()
+            .enumerate()
+            .map(|(dim, &category)| 1.0 - self.frequency(dim, category))
+            .sum()
+    }
+}
+
+impl Dist<Self> for CFeature {
+    fn dist2(&self, r: &Self) -> Scalar {
+        if self.n == 0.0 || r.n == 0.0 {
+            return self.counts.len() as Scalar;
+        }
+        self.counts
+            .iter()
+            .zip(&r.counts)
+            .map(|(counts, r_counts)| {
+                let same = counts
+                    .iter()
+                    .filter_map(|(category, count)| r_counts.get(category).map(|rc| count * rc))
+                    .sum::<Scalar>();
+                1.0 - same / (self.n * r.n)
+            })
+            .sum()
+    }
+}
+
+impl From<CategoricalPoint> for CFeature {
+    fn from(orig: CategoricalPoint) -> CFeature {
+        <Self as crate::dynamic::cfeature::CFeature>::empty(orig.dims()) + orig
+    }
+}
+
+impl crate::dynamic::cfeature::CFeature for CFeature {
+    type Point = CategoricalPoint;
+
+    fn empty(dims: usize) -> CFeature {
+        CFeature {
+            counts: vec![BTreeMap::new(); dims],
+            n: 0.0,
+        }
+    }
+    fn dims(&self) -> usize {
+        self.counts.len()
+    }
+    fn diam2(&self) -> Scalar {
+        match self.n < 2.0 {
+            true => 0.0,
+            false => self.impurity() / (self.n * (self.n - 1.0)),
+        }
+    }
+    fn radius2(&self) -> Scalar {
+        match self.n == 0.0 {
+            true => 0.0,
+            false => self.impurity() / (self.n * self.n),
+        }
+    }
+    fn size(&self) -> Scalar {
+        self.n
+    }
+    /// The mode of the summarized points: the most frequent category of each dimension (ties are
+    /// resolved in favor of the lowest category code).
+    fn center(&self) -> CategoricalPoint {
+        CategoricalPoint::from_vec(
+            self.counts
+                .iter()
+                .map(|counts| {
+                    counts
+                        .iter()
+                        .fold(
+                            None,
+                            |mode: Option<(u32, Scalar)>, (&category, &count)| match mode {
+                                Some((_, mode_count)) if mode_count >= count => mode,
+                                _ => Some((category, count)),
+                            },
+                        )
+                        .map(|(category, _)| category)
+                        .unwrap_or(0)
+                })
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,144 @@
+/*!
+ * Mixed numeric / categorical cluster feature implementation, in the style of k-prototypes.
+ *
+ * Combines a [BIRCH](super::birch) feature over the numeric values of [MixedPoint]s with a
+ * [categorical](super::categorical) feature over their categorical values. Squared distances,
+ * diameters and radii are the numeric quantity plus the categorical one weighted by the points'
+ * `gamma`, so a categorical mismatch costs as much as a squared numeric distance of `gamma`.
+ * Numeric columns should be scaled comparably to one another (and to `gamma`) beforehand.
+ */
+
+use std::ops::Add;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cfeature::Dist,
+    dynamic::{
+        cfeature::{
+            birch::CFeature as BirchFeature, categorical::CFeature as CategoricalFeature,
+            CFeature as _,
+        },
+        point::{CategoricalPoint, DynPoint, MixedPoint},
+    },
+    point::Scalar,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CFeature {
+    numeric: BirchFeature,
+    categorical: CategoricalFeature,
+    /// Weight of categorical mismatches
+    gamma: Scalar,
+}
+
+impl Add<Self> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl Add<&Self> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: &Self) -> Self::Output {
+        // empty features don't know how their dimensions are split, so take on the other's
+        if self.numeric.size() == 0.0 {
+            return rhs.clone();
+        }
+        if rhs.numeric.size() == 0.0 {
+            return self;
+        }
+        CFeature {
+            numeric: self.numeric + &rhs.numeric,
+            categorical: self.categorical + &rhs.categorical,
+            gamma: self.gamma,
+        }
+    }
+}
+
+impl Add<&MixedPoint> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: &MixedPoint) -> Self::Output {
+        self + CFeature {
+            numeric: BirchFeature::from(rhs.numeric.clone()),
+            categorical: CategoricalFeature::from(rhs.categorical.clone()),
+            gamma: rhs.gamma,
+        }
+    }
+}
+
+impl Add<MixedPoint> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: MixedPoint) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl CFeature {
+    /// Summary of the numeric values of the summarized points.
+    pub fn numeric(&self) -> &BirchFeature {
+        &self.numeric
+    }
+    /// Summary of the categorical values of the summarized points.
+    pub fn categorical(&self) -> &CategoricalFeature {
+        &self.categorical
+    }
+    /// Weight of a categorical mismatch relative to a unit of squared numeric distance.
+    pub fn gamma(&self) -> Scalar {
+        self.gamma
+    }
+}
+
+impl Dist<MixedPoint> for CFeature {
+    fn dist2(&self, r: &MixedPoint) -> Scalar {
+        Dist::<DynPoint>::dist2(&self.numeric, &r.numeric)
+            + self.gamma * Dist::<CategoricalPoint>::dist2(&self.categorical, &r.categorical)
+    }
+}
+
+impl Dist<Self> for CFeature {
+    fn dist2(&self, r: &Self) -> Scalar {
+        Dist::<BirchFeature>::dist2(&self.numeric, &r.numeric)
+            + self.gamma * Dist::<CategoricalFeature>::dist2(&self.categorical, &r.categorical)
+    }
+}
+
+impl From<MixedPoint> for CFeature {
+    fn from(orig: MixedPoint) -> CFeature {
+        <Self as crate::dynamic::cfeature::CFeature>::empty(orig.dims()) + orig
+    }
+}
+
+impl crate::dynamic::cfeature::CFeature for CFeature {
+    type Point = MixedPoint;
+
+    /// Creates an empty feature. Since `dims` doesn't say how many dimensions are numeric, the
+    /// empty feature takes on the layout of the first point or feature added to it.
+    fn empty(_dims: usize) -> CFeature {
+        CFeature {
+            numeric: BirchFeature::empty(0),
+            categorical: CategoricalFeature::empty(0),
+            gamma: 1.0,
+        }
+    }
+    fn dims(&self) -> usize {
+        self.numeric.dims() + self.categorical.dims()
+    }
+    fn diam2(&self) -> Scalar {
+        self.numeric.diam2() + self.gamma * self.categorical.diam2()
+    }
+    fn radius2(&self) -> Scalar {
+        self.numeric.radius2() + self.gamma * self.categorical.radius2()
+    }
+    fn size(&self) -> Scalar {
+        self.numeric.size()
+    }
+    fn center(&self) -> MixedPoint {
+        MixedPoint::new(self.numeric.center(), self.categorical.center(), self.gamma)
+    }
+}
@@ -0,0 +1,160 @@
+/*!
+ * Sparse cluster feature implementation, using cosine distance.
+ *
+ * Points are normalized to unit length before being summarized, so clusters are formed by
+ * direction alone (as in spherical k-means), which suits e.g. TF-IDF vectors of documents. The
+ * feature keeps a sparse linear sum of the normalized points, so summarizing high-dimensional
+ * data never requires densifying it.
+ *
+ * Distances are chord lengths between normalized vectors: the squared distance between two
+ * directions with cosine similarity `cos` is `2 (1 - cos)`, which keeps diameters and radii
+ * consistent with the (Euclidean) BIRCH definitions applied to the normalized points.
+ */
+
+use std::ops::Add;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{cfeature::Dist, dynamic::point::SparsePoint, point::Scalar};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CFeature {
+    /// Linear Sum of normalized points
+    ls: SparsePoint,
+    /// Squared norm of the linear sum
+    ls_norm2: Scalar,
+    /// Sum of squared norms of normalized points (the number of non-zero points)
+    ss: Scalar,
+    /// Size
+    n: Scalar,
+}
+
+impl Add<Self> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl Add<&Self> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: &Self) -> Self::Output {
+        let ls = self.ls.add_scaled(&rhs.ls, 1.0);
+        CFeature {
+            ls_norm2: ls.norm2(),
+            ls,
+            ss: self.ss + rhs.ss,
+            n: self.n + rhs.n,
+        }
+    }
+}
+
+impl Add<&SparsePoint> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: &SparsePoint) -> Self::Output {
+        let norm2 = rhs.norm2();
+        // zero points have no direction, so only count towards the size
+        let normalized = match norm2 > 0.0 {
+            true => rhs.scale(norm2.sqrt().recip()),
+            false => SparsePoint::zeros(rhs.dims()),
+        };
+        self + CFeature {
+            ls_norm2: normalized.norm2(),
+            ss: normalized.norm2(),
+            ls: normalized,
+            n: 1.0,
+        }
+    }
+}
+
+impl Add<SparsePoint> for CFeature {
+    type Output = CFeature;
+
+    fn add(self, rhs: SparsePoint) -> Self::Output {
+        self.add(&rhs)
+    }
+}
+
+impl CFeature {
+    /// Linear sum of the (normalized) summarized points.
+    pub fn ls(&self) -> &SparsePoint {
+        &self.ls
+    }
+    /// Cosine similarity between `p` and the center of this feature, or zero if either has no
+    /// direction.
+    pub fn cosine_similarity(&self, p: &SparsePoint) -> Scalar {
+        cosine(&self.ls, self.ls_norm2, p, p.norm2())
+    }
+}
+
+fn cosine(
+    left: &SparsePoint,
+    left_norm2: Scalar,
+    right: &SparsePoint,
+    right_norm2: Scalar,
+) -> Scalar {
+    match left_norm2 > 0.0 && right_norm2 > 0.0 {
+        true => left.dot(right) / (left_norm2 * right_norm2).sqrt(),
+        false => 0.0,
+    }
+}
+
+impl Dist<SparsePoint> for CFeature {
+    fn dist2(&self, r: &SparsePoint) -> Scalar {
+        (2.0 * (1.0 - self.cosine_similarity(r))).max(0.0)
+    }
+}
+
+impl Dist<Self> for CFeature {
+    fn dist2(&self, r: &Self) -> Scalar {
+        (2.0 * (1.0 - cosine(&self.ls, self.ls_norm2, &r.ls, r.ls_norm2))).max(0.0)
+    }
+}
+
+impl From<SparsePoint> for CFeature {
+    fn from(orig: SparsePoint) -> CFeature {
+        <Self as crate::dynamic::cfeature::CFeature>::empty(orig.dims()) + orig
+    }
+}
+
+impl crate::dynamic::cfeature::CFeature for CFeature {
+    type Point = SparsePoint;
+
+    fn empty(dims: usize) -> CFeature {
+        CFeature {
+            ls: SparsePoint::zeros(dims),
+            ls_norm2: 0.0,
+            ss: 0.0,
+            n: 0.0,
+        }
+    }
+    fn dims(&self) -> usize {
+        self.ls.dims()
+    }
+    fn diam2(&self) -> Scalar {
+        match self.n < 2.0 {
+            true => 0.0,
+            false => {
+                (2.0 * (self.n * self.ss - self.ls_norm2) / (self.n * (self.n - 1.0))).max(0.0)
+            }
+        }
+    }
+    fn radius2(&self) -> Scalar {
+        match self.n == 0.0 {
+            true => 0.0,
+            false => ((self.n * self.ss - self.ls_norm2) / (self.n * self.n)).max(0.0),
+        }
+    }
+    fn size(&self) -> Scalar {
+        self.n
+    }
+    fn center(&self) -> SparsePoint {
+        match self.n == 0.0 {
+            true => self.ls.clone(),
+            false => self.ls.scale(self.n.recip()),
+        }
+    }
+}
@@ -0,0 +1,436 @@
+/*!
+ * Runtime-dimensional cluster feature tree struct and implementation.
+ *
+ * Mirrors [crate::cftree], but operates on runtime-dimensional cluster features (and the points
+ * they summarize, e.g. [DynPoint](crate::dynamic::point::DynPoint)s).
+ */
+
+use std::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+use itertools::{Either, Itertools};
+
+use crate::{
+    cftree::{EntryInsertion, NodeInsertion, TreeConfig},
+    dynamic::cfeature::{
+        betula::CFeature as BetulaFeature, birch::CFeature as BirchFeature,
+        categorical::CFeature as CategoricalFeature, mixed::CFeature as MixedFeature,
+        sparse::CFeature as SparseFeature, CFeature, FeaturePoint,
+    },
+    error::BorschtError,
+    point::Scalar,
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Node<CF> {
+    pub entries: Vec<NodeEntry<CF>>,
+}
+
+impl<CF: CFeature> Node<CF> {
+    pub fn new<TC: TreeConfig>(config: &TC) -> Node<CF> {
+        Node {
+            entries: Vec::with_capacity(
+                config.node_capacity().max.max(config.leaf_capacity().max) + 1,
+            ),
+        }
+    }
+
+    pub fn with_entries(entries: Vec<NodeEntry<CF>>) -> Node<CF> {
+        Node { entries }
+    }
+
+    /// Number of levels in the tree rooted at this node. A node without entries (such as the
+    /// root of an empty tree) is a single level.
+    pub fn height(&self) -> usize {
+        1 + self
+            .entries
+            .iter()
+            .map(|entry| entry.height())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Whether the tree rooted at this node is empty (has no entries).
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the dimensionality of the points stored in this tree, or `None` if the tree is
+    /// empty.
+    pub fn dims(&self) -> Option<usize> {
+        self.entries.first().map(|entry| entry.feature.dims())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NodeEntry<CF> {
+    pub feature: CF,
+    pub child: Option<Node<CF>>,
+}
+
+impl<CF: CFeature> NodeEntry<CF> {
+    fn with_point(orig: FeaturePoint<CF>) -> NodeEntry<CF> {
+        NodeEntry {
+            feature: CF::from(orig),
+            child: None,
+        }
+    }
+    fn height(&self) -> usize {
+        self.child.as_ref().map(|node| node.height()).unwrap_or(0)
+    }
+    fn insert<TC: TreeConfig>(
+        &mut self,
+        p: FeaturePoint<CF>,
+        config: &TC,
+    ) -> EntryInsertion<FeaturePoint<CF>> {
+        // check if feature can absorb point
+        let feature_with_point = self.feature.clone() + &p;
+        match feature_with_point.diam2() <= config.threshold() {
+            true => {
+                self.feature = feature_with_point;
+                EntryInsertion::Success
+            }
+            false => EntryInsertion::Failure(p),
+        }
+    }
+}
+
+impl<CF> Node<CF>
+where
+    CF: CFeature + Debug,
+{
+    fn compute_feature(&self) -> CF {
+        let dims = self.dims().unwrap_or(0);
+        self.entries
+            .iter()
+            .map(|entry| &entry.feature)
+            .fold(CF::empty(dims), |acc, feature| acc + feature)
+    }
+
+    fn check_split<TC: TreeConfig>(mut self, config: &TC) -> NodeInsertion<Self> {
+        let capacity = match self.entries.iter().all(|entry| entry.child.is_none()) {
+            true => config.leaf_capacity(),
+            false => config.node_capacity(),
+        };
+        if self.entries.len() < capacity.max {
+            return NodeInsertion::Single(self);
+        }
+        // find farthest pair of entries and assign remaining entries to the closer of the two
+        let (lidx, ridx) = self.farthest();
+        let partition = (0..self.entries.len())
+            .map(|idx| {
+                idx == lidx
+                    || (idx != ridx
+                        && self.entries[lidx].feature.dist2(&self.entries[idx].feature)
+                            < self.entries[ridx].feature.dist2(&self.entries[idx].feature))
+            })
+            .collect::<Vec<_>>();
+        let (left, right) = self
+            .entries
+            .drain(..)
+            .zip(partition)
+            .partition_map(|(entry, left)| match left {
+                true => Either::Left(entry),
+                false => Either::Right(entry),
+            });
+        NodeInsertion::Split(Node::with_entries(left), Node::with_entries(right))
+    }
+
+    fn farthest(&self) -> (usize, usize) {
+        let (lidx, ridx, _) = self
+            .entries
+            .iter()
+            .enumerate()
+            .tuple_combinations()
+            .map(|((lidx, lentry), (ridx, rentry))| {
+                (lidx, ridx, lentry.feature.dist2(&rentry.feature))
+            })
+            .fold((0, 0, Scalar::NEG_INFINITY), |farthest, candidate| {
+                match candidate.2 > farthest.2 {
+                    true => candidate,
+                    false => farthest,
+                }
+            });
+        (lidx, ridx)
+    }
+
+    fn insert<TC: TreeConfig>(mut self, p: FeaturePoint<CF>, config: &TC) -> NodeInsertion<Self> {
+        // find closest cluster
+        let closest = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(idx, entry)| (idx, entry.feature.dist2(&p)))
+            .fold(
+                None,
+                |closest: Option<(usize, Scalar)>, (idx, d2)| match closest {
+                    Some((_, closest_d2)) if closest_d2 <= d2 => closest,
+                    _ => Some((idx, d2)),
+                },
+            );
+        let idx = match closest {
+            Some((idx, _)) => idx,
+            None => {
+                self.entries.push(NodeEntry::with_point(p));
+                return NodeInsertion::Single(self);
+            }
+        };
+        let entry = &mut self.entries[idx];
+        match entry.child.take() {
+            Some(child) => match child.insert(p.clone(), config) {
+                NodeInsertion::Split(left, right) => {
+                    entry.feature = left.compute_feature();
+                    entry.child = Some(left);
+                    self.entries.push(NodeEntry {
+                        feature: right.compute_feature(),
+                        child: Some(right),
+                    });
+                    self.check_split(config)
+                }
+                NodeInsertion::Single(node) => {
+                    // without a split below, the child feature just grows by the point
+                    let feature = std::mem::replace(&mut entry.feature, CF::empty(0));
+                    entry.feature = feature + &p;
+                    entry.child = Some(node);
+                    NodeInsertion::Single(self)
+                }
+            },
+            None => match entry.insert(p, config) {
+                EntryInsertion::Success => NodeInsertion::Single(self),
+                EntryInsertion::Failure(p) => {
+                    self.entries.push(NodeEntry::with_point(p));
+                    self.check_split(config)
+                }
+            },
+        }
+    }
+
+    /// Builds a tree from `iter`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the points don't all have the same dimensionality; see [Node::try_from_iter].
+    pub fn from_iter<T: IntoIterator<Item = FeaturePoint<CF>>, TC: TreeConfig>(
+        iter: T,
+        config: &TC,
+    ) -> Self {
+        let mut root = Node::new(config);
+        for p in iter {
+            root = root.insert_root(p, config);
+        }
+        root
+    }
+
+    /// Builds a tree from `iter`, failing at the first point with a different dimensionality than
+    /// the points before it.
+    pub fn try_from_iter<T: IntoIterator<Item = FeaturePoint<CF>>, TC: TreeConfig>(
+        iter: T,
+        config: &TC,
+    ) -> Result<Self, BorschtError> {
+        let mut root = Node::new(config);
+        for p in iter {
+            root.check_point(&p)?;
+            root = root.insert_root(p, config);
+        }
+        Ok(root)
+    }
+
+    /// Checks that `p` has the dimensionality of the points stored in the tree rooted at this
+    /// node (any point can be inserted into an empty tree).
+    pub fn check_point(&self, p: &FeaturePoint<CF>) -> Result<(), BorschtError> {
+        match self.dims() {
+            Some(dims) => BorschtError::check_dims(dims, CF::from(p.clone()).dims()),
+            None => Ok(()),
+        }
+    }
+
+    /// Inserts a single point into the tree rooted at this node, returning the new root (which
+    /// grows a level if the insertion splits this node).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `p` doesn't have the dimensionality of the points stored in the tree; see
+    /// [Node::check_point].
+    pub fn insert_root<TC: TreeConfig>(self, p: FeaturePoint<CF>, config: &TC) -> Self {
+        match self.insert(p, config) {
+            NodeInsertion::Single(node) => node,
+            NodeInsertion::Split(left, right) => Node::with_entries(vec![
+                NodeEntry {
+                    feature: left.compute_feature(),
+                    child: Some(left),
+                },
+                NodeEntry {
+                    feature: right.compute_feature(),
+                    child: Some(right),
+                },
+            ]),
+        }
+    }
+}
+
+pub type BirchTree = Node<BirchFeature>;
+pub type BetulaTree = Node<BetulaFeature>;
+pub type SparseTree = Node<SparseFeature>;
+pub type CategoricalTree = Node<CategoricalFeature>;
+pub type MixedTree = Node<MixedFeature>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cfeature::Dist,
+        cftree::BasicConfig,
+        dynamic::point::{CategoricalPoint, DynPoint, MixedPoint, SparsePoint},
+    };
+
+    fn config() -> BasicConfig {
+        BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(0.5)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn from_vecs() {
+        let points = vec![
+            vec![1.0, 2.0, 3.0, 4.0, 5.0],
+            vec![2.0, 2.0, 3.0, 4.0, 5.0],
+            vec![1.0, 3.0, 3.0, 4.0, 5.0],
+            vec![1.0, 2.0, 4.0, 4.0, 5.0],
+            vec![9.0, 9.0, 9.0, 9.0, 9.0],
+        ];
+        let root = BirchTree::from_iter(points.into_iter().map(DynPoint::from), &config());
+        assert_eq!(root.dims(), Some(5));
+        assert_eq!(
+            root.check_point(&DynPoint::zeros(4)),
+            Err(BorschtError::DimensionMismatch {
+                expected: 5,
+                found: 4
+            })
+        );
+        let mismatched = [vec![1.0; 5], vec![2.0; 5], vec![3.0; 3]].map(DynPoint::from);
+        assert!(BirchTree::try_from_iter(mismatched, &config()).is_err());
+        let total = root
+            .entries
+            .iter()
+            .map(|entry| entry.feature.size())
+            .sum::<Scalar>();
+        assert_eq!(total, 5.0);
+
+        let root = BetulaTree::from_iter(
+            vec![
+                DynPoint::from(vec![1.0, 2.0]),
+                DynPoint::from(vec![1.1, 2.0]),
+            ],
+            &config(),
+        );
+        assert_eq!(root.dims(), Some(2));
+        assert_eq!(root.entries.len(), 1);
+        assert_eq!(root.entries[0].feature.size(), 2.0);
+    }
+
+    fn leaves<CF>(node: &Node<CF>) -> Vec<&NodeEntry<CF>> {
+        node.entries
+            .iter()
+            .flat_map(|entry| match entry.child {
+                Some(ref child) => leaves(child),
+                None => vec![entry],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sparse() {
+        // two "topics" over a large vocabulary, with documents of very different lengths
+        let dims = 10_000;
+        let points = (0..12)
+            .map(|i| {
+                let scale = (1 + i % 3) as Scalar;
+                match i % 2 {
+                    0 => SparsePoint::new(dims, vec![(3, 2.0 * scale), (1200, scale)]),
+                    _ => SparsePoint::new(dims, vec![(7, scale), (9000, 2.0 * scale)]),
+                }
+            })
+            .collect::<Vec<_>>();
+        let root = SparseTree::from_iter(points.clone(), &config());
+        assert_eq!(root.dims(), Some(dims));
+        let leaves = leaves(&root);
+        assert_eq!(leaves.len(), 2);
+        assert!(leaves.iter().all(|entry| entry.feature.size() == 6.0));
+        assert!(leaves.iter().all(|entry| entry.feature.diam2() < 1e-12));
+        assert!(leaves.iter().all(|entry| entry.feature.center().nnz() == 2));
+
+        let feature = SparseFeature::from(points[0].clone());
+        assert!((feature.cosine_similarity(&points[2]) - 1.0).abs() < 1e-12);
+        assert_eq!(feature.cosine_similarity(&points[1]), 0.0);
+        assert!((Dist::<SparsePoint>::dist2(&feature, &points[1]) - 2.0).abs() < 1e-12);
+        assert_eq!(points[0].to_dense().as_slice()[3], 2.0);
+        assert_eq!(SparsePoint::from(&points[0].to_dense()), points[0]);
+    }
+
+    #[test]
+    fn categorical() {
+        // two kinds of events, one of which occasionally varies in its last field
+        let points = (0..8)
+            .map(|i| match (i % 2, i) {
+                (0, 6) => vec![0, 1, 3],
+                (0, _) => vec![0, 1, 2],
+                _ => vec![5, 5, 5],
+            })
+            .map(CategoricalPoint::from)
+            .collect::<Vec<_>>();
+        let root = CategoricalTree::from_iter(points.clone(), &config());
+        assert_eq!(root.dims(), Some(3));
+        let leaves = leaves(&root);
+        assert_eq!(leaves.len(), 2);
+        assert!(leaves.iter().all(|entry| entry.feature.size() == 4.0));
+
+        let varied = leaves
+            .iter()
+            .map(|entry| &entry.feature)
+            .find(|feature| feature.center() == CategoricalPoint::from(vec![0, 1, 2]))
+            .unwrap();
+        // 6 of the 12 ordered pairs of members differ in one field
+        assert!((varied.diam2() - 0.5).abs() < 1e-12);
+        assert_eq!(varied.frequency(2, 3), 0.25);
+        assert!((varied.dist2(&CategoricalPoint::from(vec![0, 4, 3])) - 1.75).abs() < 1e-12);
+        assert_eq!(
+            CategoricalFeature::from(points[0].clone()).dist2(&points[1]),
+            points[0].mismatches(&points[1]) as Scalar
+        );
+    }
+
+    #[test]
+    fn mixed() {
+        // numerically indistinguishable rows which differ in a categorical column
+        let points = (0..8)
+            .map(|i| {
+                MixedPoint::new(
+                    DynPoint::from(vec![0.01 * i as Scalar, 1.0]),
+                    CategoricalPoint::from(vec![i % 2]),
+                    1.0,
+                )
+            })
+            .collect::<Vec<_>>();
+        let root = MixedTree::from_iter(points.clone(), &config());
+        assert_eq!(root.dims(), Some(3));
+        let clusters = leaves(&root);
+        assert_eq!(clusters.len(), 2);
+        assert!(clusters.iter().all(|entry| entry.feature.size() == 4.0));
+        assert!(clusters
+            .iter()
+            .all(|entry| entry.feature.categorical().diam2() == 0.0));
+
+        let feature = MixedFeature::from(points[0].clone());
+        let numeric = Dist::<DynPoint>::dist2(feature.numeric(), &points[1].numeric);
+        assert!((feature.dist2(&points[1]) - (numeric + 1.0)).abs() < 1e-12);
+        assert_eq!(feature.center(), points[0]);
+
+        // at a lower weight, categorical mismatches no longer keep the rows apart
+        let points = points.into_iter().map(|p| MixedPoint { gamma: 0.1, ..p });
+        let root = MixedTree::from_iter(points, &config());
+        assert_eq!(leaves(&root).len(), 1);
+    }
+}
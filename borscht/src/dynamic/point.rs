@@ -0,0 +1,436 @@
+/*!
+ * Runtime-dimensional data point structure and associated trait implementations.
+ *
+ * Arithmetic between two [DynPoint]s requires both points to have the same dimensionality;
+ * mismatched dimensions cause a panic.
+ */
+
+use std::ops::{
+    Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Rem, RemAssign, Sub,
+    SubAssign,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::BorschtError,
+    point::{Point, Scalar},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DynPoint(Vec<Scalar>);
+
+impl DynPoint {
+    pub fn from_vec(values: Vec<Scalar>) -> DynPoint {
+        DynPoint(values)
+    }
+    pub fn zeros(dims: usize) -> DynPoint {
+        DynPoint(vec![0.0; dims])
+    }
+    pub fn dims(&self) -> usize {
+        self.0.len()
+    }
+    pub fn as_slice(&self) -> &[Scalar] {
+        &self.0
+    }
+    pub fn as_mut_slice(&mut self) -> &mut [Scalar] {
+        &mut self.0
+    }
+    pub fn into_vec(self) -> Vec<Scalar> {
+        self.0
+    }
+    pub fn norm2(&self) -> Scalar {
+        self.0.iter().fold(Scalar::default(), |acc, x| acc + x * x)
+    }
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|&x| x == 0.0)
+    }
+}
+
+impl From<Vec<Scalar>> for DynPoint {
+    fn from(values: Vec<Scalar>) -> DynPoint {
+        DynPoint(values)
+    }
+}
+
+impl From<&[Scalar]> for DynPoint {
+    fn from(values: &[Scalar]) -> DynPoint {
+        DynPoint(values.to_vec())
+    }
+}
+
+impl<const DIMS: usize> From<Point<DIMS>> for DynPoint {
+    fn from(point: Point<DIMS>) -> DynPoint {
+        DynPoint(point.as_slice().to_vec())
+    }
+}
+
+impl<I> Index<I> for DynPoint
+where
+    [Scalar]: Index<I>,
+{
+    type Output = <[Scalar] as Index<I>>::Output;
+    fn index(&self, index: I) -> &Self::Output {
+        self.0.as_slice().index(index)
+    }
+}
+
+impl<I> IndexMut<I> for DynPoint
+where
+    [Scalar]: IndexMut<I>,
+{
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        self.0.as_mut_slice().index_mut(index)
+    }
+}
+
+fn check_dims(left: &[Scalar], right: &[Scalar]) {
+    assert_eq!(
+        left.len(),
+        right.len(),
+        "dimensionality mismatch between points"
+    );
+}
+
+macro_rules! impl_op {
+    ($op_trait:ident $fname:ident $op:tt $op_assign:tt $op_assign_trait:ident $fname_assign:ident) => {
+        #[allow(clippy::assign_op_pattern)]
+        mod $fname {
+            use super::{check_dims, Scalar};
+            pub fn assign_l(left: &mut [Scalar], right: &[Scalar]) {
+                check_dims(left, right);
+                for (l, r) in left.iter_mut().zip(right) {
+                    *l $op_assign r
+                }
+            }
+            pub fn assign_l_scalar(left: &mut [Scalar], right: Scalar) {
+                for l in left.iter_mut() {
+                    *l $op_assign right
+                }
+            }
+            pub fn assign_r(left: &[Scalar], right: &mut [Scalar]) {
+                check_dims(left, right);
+                for (l, r) in left.iter().zip(right.iter_mut()) {
+                    *r = l $op *r
+                }
+            }
+            pub fn assign_r_scalar(left: Scalar, right: &mut [Scalar]) {
+                for r in right.iter_mut() {
+                    *r = left $op *r
+                }
+            }
+        }
+
+        impl $op_trait<DynPoint> for DynPoint {
+            type Output = DynPoint;
+
+            fn $fname(mut self, rhs: DynPoint) -> Self::Output {
+                $fname::assign_l(self.as_mut_slice(), rhs.as_slice());
+                self
+            }
+        }
+
+        impl $op_trait<&DynPoint> for DynPoint {
+            type Output = DynPoint;
+
+            fn $fname(mut self, rhs: &DynPoint) -> Self::Output {
+                $fname::assign_l(self.as_mut_slice(), rhs.as_slice());
+                self
+            }
+        }
+
+        impl $op_trait<DynPoint> for &DynPoint {
+            type Output = DynPoint;
+
+            fn $fname(self, mut rhs: DynPoint) -> Self::Output {
+                $fname::assign_r(self.as_slice(), rhs.as_mut_slice());
+                rhs
+            }
+        }
+
+        impl $op_trait<&DynPoint> for &DynPoint {
+            type Output = DynPoint;
+
+            fn $fname(self, rhs: &DynPoint) -> Self::Output {
+                let mut out = self.clone();
+                $fname::assign_l(out.as_mut_slice(), rhs.as_slice());
+                out
+            }
+        }
+
+        impl $op_assign_trait<DynPoint> for DynPoint {
+            fn $fname_assign(&mut self, rhs: DynPoint) {
+                $fname::assign_l(self.as_mut_slice(), rhs.as_slice());
+            }
+        }
+
+        impl $op_assign_trait<&DynPoint> for DynPoint {
+            fn $fname_assign(&mut self, rhs: &DynPoint) {
+                $fname::assign_l(self.as_mut_slice(), rhs.as_slice());
+            }
+        }
+
+        impl $op_trait<Scalar> for DynPoint {
+            type Output = DynPoint;
+
+            fn $fname(mut self, rhs: Scalar) -> Self::Output {
+                $fname::assign_l_scalar(self.as_mut_slice(), rhs);
+                self
+            }
+        }
+
+        impl $op_trait<Scalar> for &DynPoint {
+            type Output = DynPoint;
+
+            fn $fname(self, rhs: Scalar) -> Self::Output {
+                let mut ret = self.clone();
+                $fname::assign_l_scalar(ret.as_mut_slice(), rhs);
+                ret
+            }
+        }
+
+        impl $op_trait<DynPoint> for Scalar {
+            type Output = DynPoint;
+
+            fn $fname(self, mut rhs: DynPoint) -> Self::Output {
+                $fname::assign_r_scalar(self, rhs.as_mut_slice());
+                rhs
+            }
+        }
+
+        impl $op_trait<&DynPoint> for Scalar {
+            type Output = DynPoint;
+
+            fn $fname(self, rhs: &DynPoint) -> Self::Output {
+                let mut ret = rhs.clone();
+                $fname::assign_r_scalar(self, ret.as_mut_slice());
+                ret
+            }
+        }
+    };
+}
+impl_op!(Add add + += AddAssign add_assign);
+impl_op!(Sub sub - -= SubAssign sub_assign);
+impl_op!(Mul mul * *= MulAssign mul_assign);
+impl_op!(Div div / /= DivAssign div_assign);
+impl_op!(Rem rem % %= RemAssign rem_assign);
+
+impl Neg for DynPoint {
+    type Output = DynPoint;
+    fn neg(mut self) -> Self::Output {
+        for x in self.0.iter_mut() {
+            *x = -*x
+        }
+        self
+    }
+}
+impl Neg for &DynPoint {
+    type Output = DynPoint;
+    fn neg(self) -> Self::Output {
+        let out = self.clone();
+        -out
+    }
+}
+
+/// A runtime-dimensional point which stores only its non-zero coordinates, as `(index, value)`
+/// pairs sorted by index. Suited to high-dimensional, mostly-zero data such as term frequencies.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SparsePoint {
+    dims: usize,
+    entries: Vec<(usize, Scalar)>,
+}
+
+impl SparsePoint {
+    /// Creates a point of dimensionality `dims` from its non-zero coordinates, given as
+    /// `(index, value)` pairs in any order. Values of repeated indices are summed, and zero values
+    /// are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any index is not less than `dims`; see [SparsePoint::try_new].
+    pub fn new<I: IntoIterator<Item = (usize, Scalar)>>(dims: usize, entries: I) -> SparsePoint {
+        match SparsePoint::try_new(dims, entries) {
+            Ok(point) => point,
+            Err(err) => panic!("invalid sparse point: {}", err),
+        }
+    }
+    /// Creates a point like [SparsePoint::new], failing if any index is not less than `dims`.
+    pub fn try_new<I: IntoIterator<Item = (usize, Scalar)>>(
+        dims: usize,
+        entries: I,
+    ) -> Result<SparsePoint, BorschtError> {
+        let mut entries = entries.into_iter().collect::<Vec<_>>();
+        if let Some(&(index, _)) = entries.iter().find(|&&(idx, _)| idx >= dims) {
+            return Err(BorschtError::IndexOutOfBounds { index, dims });
+        }
+        entries.sort_by_key(|&(idx, _)| idx);
+        let mut merged: Vec<(usize, Scalar)> = Vec::with_capacity(entries.len());
+        for (idx, value) in entries {
+            match merged.last_mut() {
+                Some(last) if last.0 == idx => last.1 += value,
+                _ => merged.push((idx, value)),
+            }
+        }
+        merged.retain(|&(_, value)| value != 0.0);
+        Ok(SparsePoint {
+            dims,
+            entries: merged,
+        })
+    }
+    /// Creates an all-zero point of dimensionality `dims`.
+    pub fn zeros(dims: usize) -> SparsePoint {
+        SparsePoint {
+            dims,
+            entries: vec![],
+        }
+    }
+    pub fn dims(&self) -> usize {
+        self.dims
+    }
+    /// Non-zero coordinates of this point, as `(index, value)` pairs sorted by index.
+    pub fn entries(&self) -> &[(usize, Scalar)] {
+        &self.entries
+    }
+    /// Number of non-zero coordinates.
+    pub fn nnz(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn norm2(&self) -> Scalar {
+        self.entries.iter().fold(0.0, |acc, (_, x)| acc + x * x)
+    }
+    pub fn is_zero(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// Dot product of this point with `other`.
+    pub fn dot(&self, other: &SparsePoint) -> Scalar {
+        check_sparse_dims(self, other);
+        let (mut left, mut right) = (self.entries.iter().peekable(), other.entries.iter());
+        let mut dot = 0.0;
+        for &(idx, value) in right.by_ref() {
+            while left.next_if(|&&(lidx, _)| lidx < idx).is_some() {}
+            if let Some(&(_, lvalue)) = left.next_if(|&&(lidx, _)| lidx == idx) {
+                dot += lvalue * value;
+            }
+        }
+        dot
+    }
+    /// Returns `self + factor * other`.
+    pub fn add_scaled(&self, other: &SparsePoint, factor: Scalar) -> SparsePoint {
+        check_sparse_dims(self, other);
+        SparsePoint::new(
+            self.dims,
+            self.entries.iter().copied().chain(
+                other
+                    .entries
+                    .iter()
+                    .map(|&(idx, value)| (idx, factor * value)),
+            ),
+        )
+    }
+    /// Returns this point scaled by `factor`.
+    pub fn scale(&self, factor: Scalar) -> SparsePoint {
+        SparsePoint::new(
+            self.dims,
+            self.entries
+                .iter()
+                .map(|&(idx, value)| (idx, factor * value)),
+        )
+    }
+    /// Converts this point into a dense [DynPoint].
+    pub fn to_dense(&self) -> DynPoint {
+        let mut dense = DynPoint::zeros(self.dims);
+        for &(idx, value) in &self.entries {
+            dense[idx] = value;
+        }
+        dense
+    }
+}
+
+impl From<&DynPoint> for SparsePoint {
+    fn from(point: &DynPoint) -> SparsePoint {
+        SparsePoint::new(point.dims(), point.as_slice().iter().copied().enumerate())
+    }
+}
+
+fn check_sparse_dims(left: &SparsePoint, right: &SparsePoint) {
+    assert_eq!(
+        left.dims, right.dims,
+        "dimensionality mismatch between points"
+    );
+}
+
+/// A runtime-dimensional point of categorical values, each encoded as an integer category code
+/// (codes are only compared for equality).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CategoricalPoint(Vec<u32>);
+
+impl CategoricalPoint {
+    pub fn from_vec(values: Vec<u32>) -> CategoricalPoint {
+        CategoricalPoint(values)
+    }
+    pub fn dims(&self) -> usize {
+        self.0.len()
+    }
+    pub fn as_slice(&self) -> &[u32] {
+        &self.0
+    }
+    pub fn into_vec(self) -> Vec<u32> {
+        self.0
+    }
+    /// Number of dimensions in which this point and `other` have different categories.
+    pub fn mismatches(&self, other: &CategoricalPoint) -> usize {
+        assert_eq!(
+            self.dims(),
+            other.dims(),
+            "dimensionality mismatch between points"
+        );
+        self.0.iter().zip(&other.0).filter(|(l, r)| l != r).count()
+    }
+}
+
+impl From<Vec<u32>> for CategoricalPoint {
+    fn from(values: Vec<u32>) -> CategoricalPoint {
+        CategoricalPoint(values)
+    }
+}
+
+impl From<&[u32]> for CategoricalPoint {
+    fn from(values: &[u32]) -> CategoricalPoint {
+        CategoricalPoint(values.to_vec())
+    }
+}
+
+impl<I> Index<I> for CategoricalPoint
+where
+    [u32]: Index<I>,
+{
+    type Output = <[u32] as Index<I>>::Output;
+    fn index(&self, index: I) -> &Self::Output {
+        self.0.as_slice().index(index)
+    }
+}
+
+/// A runtime-dimensional point with both numeric and categorical values, for clustering with
+/// the [mixed](crate::dynamic::cfeature::mixed) cluster feature.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MixedPoint {
+    pub numeric: DynPoint,
+    pub categorical: CategoricalPoint,
+    /// Weight of a categorical mismatch relative to a unit of squared numeric distance (the γ of
+    /// k-prototypes). Every point clustered together should use the same weight.
+    pub gamma: Scalar,
+}
+
+impl MixedPoint {
+    pub fn new(numeric: DynPoint, categorical: CategoricalPoint, gamma: Scalar) -> MixedPoint {
+        MixedPoint {
+            numeric,
+            categorical,
+            gamma,
+        }
+    }
+    /// Total number of (numeric and categorical) dimensions.
+    pub fn dims(&self) -> usize {
+        self.numeric.dims() + self.categorical.dims()
+    }
+}
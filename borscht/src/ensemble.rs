@@ -0,0 +1,227 @@
+/*!
+ * Consensus clustering over an ensemble of trees.
+ *
+ * A single tree depends on the order of its points and on its configuration. An [Ensemble] holds
+ * several trees over the same points (e.g. built from different insertion orders with
+ * [Ensemble::from_shuffles], or with different configurations with [Ensemble::from_configs]),
+ * labels the points with the leaf cluster of each tree (see [CFTree::labels]), and combines the
+ * labels through their *co-association matrix*: the fraction of trees which put each pair of
+ * points in the same leaf cluster. The consensus partition groups the points connected by pairs
+ * which enough trees agree on, and each tree can be scored against it with the
+ * [metrics](crate::metrics).
+ *
+ * The co-association matrix is quadratic in the number of points, so the ensemble is best suited
+ * to (samples of) up to a few thousand points.
+ */
+
+use core::fmt::Debug;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{BasicConfig, CFTree, TreeConfig},
+    metrics::{adjusted_rand_index, MetricsError},
+    point::Scalar,
+    rng::SplitMix64,
+    stability::shuffle,
+};
+
+/// Several trees over the same points (see the [module documentation](self)).
+#[derive(Debug)]
+pub struct Ensemble<CF, const DIMS: usize, TC = BasicConfig> {
+    trees: Vec<CFTree<CF, DIMS, TC>>,
+}
+
+impl<CF, TC, const DIMS: usize> Default for Ensemble<CF, DIMS, TC> {
+    fn default() -> Self {
+        Ensemble { trees: vec![] }
+    }
+}
+
+impl<CF, TC, const DIMS: usize> Ensemble<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    TC: TreeConfig,
+{
+    /// Creates an empty ensemble.
+    pub fn new() -> Ensemble<CF, DIMS, TC> {
+        Ensemble::default()
+    }
+
+    /// Adds a tree to the ensemble.
+    pub fn push(&mut self, tree: CFTree<CF, DIMS, TC>) {
+        self.trees.push(tree);
+    }
+
+    /// Builds `members` trees with configuration `config` over `points`, each inserting them in a
+    /// different random order (reproducible for a given `seed`).
+    pub fn from_shuffles(
+        points: &[FeaturePoint<CF, DIMS>],
+        config: TC,
+        members: usize,
+        seed: u64,
+    ) -> Ensemble<CF, DIMS, TC>
+    where
+        TC: Clone,
+    {
+        let mut rng = SplitMix64(seed);
+        let trees = (0..members)
+            .map(|_| CFTree::from_iter(shuffle(points, &mut rng), config.clone()))
+            .collect();
+        Ensemble { trees }
+    }
+
+    /// Builds a tree over `points` for each configuration of `configs`.
+    pub fn from_configs<I: IntoIterator<Item = TC>>(
+        points: &[FeaturePoint<CF, DIMS>],
+        configs: I,
+    ) -> Ensemble<CF, DIMS, TC> {
+        let trees = configs
+            .into_iter()
+            .map(|config| CFTree::from_iter(points.iter().cloned(), config))
+            .collect();
+        Ensemble { trees }
+    }
+
+    pub fn trees(&self) -> &[CFTree<CF, DIMS, TC>] {
+        &self.trees
+    }
+
+    /// Leaf cluster of each point in each tree (see [CFTree::labels]), indexed by tree.
+    pub fn labels(&self, points: &[FeaturePoint<CF, DIMS>]) -> Vec<Vec<Option<usize>>> {
+        self.trees.iter().map(|tree| tree.labels(points)).collect()
+    }
+
+    /// Fraction of the trees which put each pair of `points` in the same leaf cluster, as a
+    /// symmetric matrix with ones on the diagonal (or zeros, if the ensemble is empty).
+    pub fn co_association(&self, points: &[FeaturePoint<CF, DIMS>]) -> Vec<Vec<Scalar>> {
+        co_association(&self.labels(points), points.len())
+    }
+
+    /// Consensus partition of `points`: points are grouped together if they're connected by pairs
+    /// which at least a fraction `min_agreement` of the trees put in the same leaf cluster.
+    /// Groups are numbered from zero in order of first appearance.
+    pub fn consensus(
+        &self,
+        points: &[FeaturePoint<CF, DIMS>],
+        min_agreement: Scalar,
+    ) -> Vec<usize> {
+        consensus_labels(&self.co_association(points), min_agreement)
+    }
+
+    /// Agreement of each tree with the `consensus` partition of `points`, as the adjusted Rand
+    /// index (see [adjusted_rand_index]) of the leaf clusters of the tree.
+    pub fn agreement(
+        &self,
+        points: &[FeaturePoint<CF, DIMS>],
+        consensus: &[usize],
+    ) -> Result<Vec<Scalar>, MetricsError> {
+        self.labels(points)
+            .iter()
+            .map(|labels| adjusted_rand_index(labels, consensus))
+            .collect()
+    }
+}
+
+/// Co-association matrix of the `points` points labeled by each of `labels`.
+fn co_association(labels: &[Vec<Option<usize>>], points: usize) -> Vec<Vec<Scalar>> {
+    let mut counts = vec![vec![0; points]; points];
+    for labels in labels {
+        for (i, left) in labels.iter().enumerate() {
+            for (j, right) in labels.iter().enumerate().skip(i) {
+                if left.is_some() && left == right {
+                    counts[i][j] += 1;
+                }
+            }
+        }
+    }
+    let trees = labels.len().max(1) as Scalar;
+    // the lower triangle mirrors the upper one
+    (0..points)
+        .map(|i| {
+            (0..points)
+                .map(|j| counts[i.min(j)][i.max(j)] as Scalar / trees)
+                .collect()
+        })
+        .collect()
+}
+
+/// Connected components of the graph linking the pairs whose co-association is at least
+/// `min_agreement`.
+fn consensus_labels(co_association: &[Vec<Scalar>], min_agreement: Scalar) -> Vec<usize> {
+    // points not labelled yet
+    const UNLABELLED: usize = usize::MAX;
+    let mut labels = vec![UNLABELLED; co_association.len()];
+    let mut next_label = 0;
+    for start in 0..co_association.len() {
+        if labels[start] != UNLABELLED {
+            continue;
+        }
+        labels[start] = next_label;
+        let mut frontier = vec![start];
+        while let Some(i) = frontier.pop() {
+            for (j, &agreement) in co_association[i].iter().enumerate() {
+                if labels[j] == UNLABELLED && agreement >= min_agreement {
+                    labels[j] = next_label;
+                    frontier.push(j);
+                }
+            }
+        }
+        next_label += 1;
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cfeature::birch::CFeature as BirchFeature, point::Point};
+
+    #[test]
+    fn consensus() {
+        let config = |threshold| {
+            BasicConfig::builder()
+                .capacity(2, 4)
+                .threshold(threshold)
+                .build()
+                .unwrap()
+        };
+        // three blobs, each wide enough to be split differently by different insertion orders
+        let points = (0..60)
+            .map(|i| {
+                let blob = (i % 3) as Scalar * 100.0;
+                Point::from_arr([blob + (i / 3 % 5) as Scalar, (i / 15) as Scalar])
+            })
+            .collect::<Vec<_>>();
+        let ensemble = Ensemble::<BirchFeature<2>, 2>::from_shuffles(&points, config(8.0), 10, 3);
+        assert_eq!(ensemble.trees().len(), 10);
+        let matrix = ensemble.co_association(&points);
+        assert!((0..60).all(|i| matrix[i][i] == 1.0));
+        assert!((0..60).all(|i| (0..60).all(|j| matrix[i][j] == matrix[j][i])));
+        // points of different blobs are never together
+        assert_eq!(matrix[0][1], 0.0);
+
+        // the consensus recovers the blobs, although no single tree does
+        let consensus = ensemble.consensus(&points, 0.5);
+        assert!((0..60).all(|i| consensus[i] == consensus[i % 3]));
+        assert_eq!(consensus[..3], [0, 1, 2]);
+        let agreement = ensemble.agreement(&points, &consensus).unwrap();
+        assert!(agreement.iter().all(|&ari| ari > 0.0 && ari < 1.0));
+        // requiring unanimity only links points which every tree keeps together
+        let unanimous = ensemble.consensus(&points, 1.0);
+        assert!(unanimous.iter().max() > consensus.iter().max());
+
+        // trees with different configurations: a threshold large enough for the blobs agrees
+        let ensemble =
+            Ensemble::<BirchFeature<2>, 2>::from_configs(&points, [config(1000.0), config(2000.0)]);
+        let consensus = ensemble.consensus(&points, 1.0);
+        assert_eq!(
+            ensemble.agreement(&points, &consensus).unwrap(),
+            vec![1.0, 1.0]
+        );
+        assert!(Ensemble::<BirchFeature<2>, 2>::new()
+            .co_association(&points[..2])
+            .iter()
+            .flatten()
+            .all(|&x| x == 0.0));
+    }
+}
@@ -0,0 +1,42 @@
+/*!
+ * Crate-wide error type.
+ *
+ * Operations which can fail on user input (invalid configurations, points or sample matrices of
+ * the wrong dimensionality, out-of-bounds sparse indices), or on the environment (failures to
+ * read or write nodes kept on disk), report a [BorschtError] rather than panicking. Panics are
+ * reserved for violated internal invariants of trees, which indicate a bug in this crate.
+ */
+
+use alloc::string::String;
+
+use thiserror::Error;
+
+use crate::cftree::ConfigError;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum BorschtError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("dimensionality mismatch: expected {expected} dimensions, found {found}")]
+    DimensionMismatch { expected: usize, found: usize },
+    #[error("index {index} out of bounds for {dims} dimensions")]
+    IndexOutOfBounds { index: usize, dims: usize },
+    /// Failure to read or write the nodes of a tree kept out of memory (e.g. a full disk, or a
+    /// truncated page file; see [NodeStore](crate::arena::NodeStore)), with its description.
+    #[error("i/o error: {0}")]
+    Io(String),
+}
+
+/// Shorthand for results with a [BorschtError].
+pub type Result<T> = core::result::Result<T, BorschtError>;
+
+impl BorschtError {
+    /// Checks that `found` dimensions are the `expected` ones.
+    #[cfg(feature = "std")]
+    pub(crate) fn check_dims(expected: usize, found: usize) -> Result<()> {
+        match found == expected {
+            true => Ok(()),
+            false => Err(BorschtError::DimensionMismatch { expected, found }),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+/*!
+ * Explanations of where insertions go, and why.
+ *
+ * [CFTree::explain_insert] performs a dry run of inserting a point: it descends the tree the way
+ * the insertion would, without modifying it, and reports at each level the distances of the
+ * point from all the entries of the node, which entry was chosen, and the threshold comparison
+ * which decides whether the point is absorbed (see [TreeConfig::threshold_at]). Comparing the
+ * explanations of two points shows where their paths diverge, e.g. to find out why they end up
+ * in different leaf clusters.
+ */
+
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use num_traits::Float as _;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{within_leaf_threshold, CFTree, InsertOutcome, TreeConfig},
+    point::{Float, Scalar},
+};
+
+/// What an insertion considers at one level of the tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelExplanation {
+    /// Depth of the node (the root node has depth 0).
+    pub depth: usize,
+    /// Whether the node is a leaf node.
+    pub leaf: bool,
+    /// Distance of the point from each entry of the node, as measured to choose between them
+    /// (see [TreeConfig::metric]).
+    pub distances: Vec<Scalar>,
+    /// Index of the entry the insertion continues with: the closest one.
+    pub chosen: usize,
+    /// Squared diameter of the chosen entry with the point added.
+    pub merged_diam2: Scalar,
+    /// Threshold on the squared diameter which applies at this level.
+    pub threshold: Scalar,
+    /// Whether the merged diameter is within the threshold, i.e. whether this level lets the
+    /// chosen entry absorb the point. Above the leaves, a zero threshold never does.
+    pub within_threshold: bool,
+}
+
+/// Dry run of an insertion: the levels it descends through, and its outcome.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InsertExplanation {
+    /// The levels of the tree, from the root down to the leaf node the point reaches. Empty if
+    /// the tree is empty.
+    pub levels: Vec<LevelExplanation>,
+    /// What inserting the point would do.
+    pub outcome: InsertOutcome,
+    /// Depth of the level whose threshold lets the point be absorbed, if it is: the first level
+    /// above the leaves whose threshold bounds both the entry it chose and the leaf entry the
+    /// point would join, or otherwise the leaf level.
+    pub absorbed_at: Option<usize>,
+}
+
+impl InsertExplanation {
+    /// Indices of the entries chosen at each level, from the root down.
+    pub fn path(&self) -> Vec<usize> {
+        self.levels.iter().map(|level| level.chosen).collect()
+    }
+}
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    TC: TreeConfig,
+{
+    /// Explains what inserting `p` into this tree would do, without inserting it (see the
+    /// [module documentation](self)). Missing coordinates of `p` are handled as configured (see
+    /// [TreeConfig::missing_values]).
+    pub fn explain_insert(&self, p: &FeaturePoint<CF, DIMS>) -> InsertExplanation {
+        let config = self.config();
+        let feature = CF::from(self.root().complete(p.clone(), config));
+        let mut levels = Vec::new();
+        let mut node = self.root();
+        loop {
+            let distances2 = node
+                .distances2_to_feature(&feature, config)
+                .collect::<Vec<_>>();
+            let chosen = match node.closest_to_feature(&feature, config) {
+                Some(idx) => idx,
+                None => break,
+            };
+            let depth = levels.len();
+            let leaf = node.entries[chosen].child.is_none();
+            let merged_diam2 = (node.entries[chosen].feature.clone() + &feature)
+                .diam2()
+                .to_scalar();
+            let threshold = config.threshold_at(node.height() - 1);
+            let within_threshold = match leaf {
+                true => within_leaf_threshold(
+                    &(node.entries[chosen].feature.clone() + &feature),
+                    config,
+                ),
+                false => threshold > 0.0 && merged_diam2 <= threshold,
+            };
+            levels.push(LevelExplanation {
+                depth,
+                leaf,
+                distances: distances2
+                    .into_iter()
+                    .map(|d2| d2.sqrt().to_scalar())
+                    .collect(),
+                chosen,
+                merged_diam2,
+                threshold,
+                within_threshold,
+            });
+            match node.entries[chosen].child {
+                Some(ref child) => node = child,
+                None => break,
+            }
+        }
+        // the last level is the leaf level, whose merged diameter bounds the threshold of any
+        // level above it which absorbs the point
+        let absorbed_at = levels.last().and_then(|last| {
+            levels.iter().position(|level| {
+                level.within_threshold && (level.leaf || last.merged_diam2 <= level.threshold)
+            })
+        });
+        let outcome = match (absorbed_at, levels.is_empty()) {
+            (Some(_), _) => InsertOutcome::Absorbed,
+            (None, true) => InsertOutcome::NewEntry,
+            (None, false) if node.entries.len() + 1 >= config.leaf_capacity().max => {
+                InsertOutcome::Split
+            }
+            (None, false) => InsertOutcome::NewEntry,
+        };
+        InsertExplanation {
+            levels,
+            outcome,
+            absorbed_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cftree::{BasicConfig, BirchCFTree, InsertOutcome},
+        point::{Point, Scalar},
+    };
+
+    #[test]
+    fn explain_insert() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let points = (0..200)
+            .map(|i| Point::from_arr([(i * 7 % 31) as Scalar, (i % 13) as Scalar]))
+            .collect::<Vec<_>>();
+        let tree = BirchCFTree::<2>::from_iter(points.clone(), config.clone());
+
+        let explanation = BirchCFTree::<2>::new(config.clone()).explain_insert(&points[0]);
+        assert!(explanation.levels.is_empty());
+        assert_eq!(explanation.outcome, InsertOutcome::NewEntry);
+
+        // explanations predict the outcomes of actual insertions
+        let mut outcomes = [0; 3];
+        for i in 0..100 {
+            let p = Point::from_arr([(i % 37) as Scalar * 0.9, (i % 11) as Scalar * 1.3]);
+            let explanation = tree.explain_insert(&p);
+            assert_eq!(explanation.levels.len(), tree.root().height());
+            for level in &explanation.levels {
+                let closest = level
+                    .distances
+                    .iter()
+                    .cloned()
+                    .fold(Scalar::INFINITY, Scalar::min);
+                assert_eq!(level.distances[level.chosen], closest);
+                assert_eq!(level.leaf, level.depth + 1 == explanation.levels.len());
+            }
+            let outcome = tree.snapshot().insert(p);
+            assert_eq!(explanation.outcome, outcome);
+            outcomes[match outcome {
+                InsertOutcome::Absorbed => 0,
+                InsertOutcome::NewEntry => 1,
+                InsertOutcome::Split => 2,
+            }] += 1;
+        }
+        assert!(outcomes.iter().all(|&count| count > 0));
+
+        // a threshold above the leaves absorbs the point there
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .upper_thresholds(vec![50.0])
+            .build()
+            .unwrap();
+        let tree = BirchCFTree::<2>::from_iter(points, config);
+        let explanation = tree.explain_insert(&Point::from_arr([2.5, 2.5]));
+        let depth = explanation.levels.len() - 2;
+        assert_eq!(explanation.absorbed_at, Some(depth));
+        assert!(explanation.levels[depth].merged_diam2 <= 50.0);
+        assert!(!explanation.levels[depth + 1].within_threshold);
+        assert_eq!(explanation.outcome, InsertOutcome::Absorbed);
+        assert_eq!(explanation.path().len(), explanation.levels.len());
+    }
+}
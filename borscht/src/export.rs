@@ -0,0 +1,242 @@
+/*!
+ * Export of trees to formats understood by standard visualization tooling: GraphViz DOT graphs
+ * ([to_dot]) and D3-style JSON hierarchies ([to_json_hierarchy]).
+ */
+
+use std::fmt::Write;
+
+use serde_json::{json, Map, Value};
+
+use crate::{
+    cfeature::CFeature,
+    cftree::{Node, NodeEntry},
+    point::{Float, Point},
+};
+
+/// Formats a point as `(x, y, ...)`.
+fn format_point<T: Float, const DIMS: usize>(point: &Point<DIMS, T>) -> String {
+    let coords = point
+        .as_slice()
+        .iter()
+        .map(|x| format!("{}", x))
+        .collect::<Vec<_>>();
+    format!("({})", coords.join(", "))
+}
+
+fn dot_label<CF: CFeature<DIMS>, const DIMS: usize>(entry: &NodeEntry<CF, DIMS>) -> String {
+    format!(
+        "size={}\\ndiameter={}\\ncenter={}",
+        entry.feature.size(),
+        entry.feature.diam(),
+        format_point(&entry.feature.center())
+    )
+}
+
+/// Renders the tree rooted at `root` as a [GraphViz](https://graphviz.org/) DOT graph. Every entry
+/// of the tree becomes a graph node (labeled with the size, diameter and center of its feature)
+/// with edges to the entries of its child node; leaf entries are drawn as ellipses.
+pub fn to_dot<CF: CFeature<DIMS>, const DIMS: usize>(root: &Node<CF, DIMS>) -> String {
+    let mut out =
+        String::from("digraph cftree {\n    node [shape=box];\n    n0 [label=\"root\"];\n");
+    let mut next_id = 1;
+    // (parent graph node id, entries of the node under it)
+    let mut stack = vec![(0, root)];
+    while let Some((parent, node)) = stack.pop() {
+        for entry in &node.entries {
+            let id = next_id;
+            next_id += 1;
+            let shape = match entry.child {
+                Some(_) => "",
+                None => ", shape=ellipse",
+            };
+            // writing to a String never fails
+            let _ = writeln!(
+                out,
+                "    n{} [label=\"{}\"{}];",
+                id,
+                dot_label(entry),
+                shape
+            );
+            let _ = writeln!(out, "    n{} -> n{};", parent, id);
+            if let Some(ref child) = entry.child {
+                stack.push((id, child));
+            }
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Which cluster feature statistics [to_json_hierarchy] embeds at each node, in addition to the
+/// size (which is always included as `value`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JsonOptions {
+    pub center: bool,
+    pub radius: bool,
+    pub diameter: bool,
+    pub variance: bool,
+}
+
+impl JsonOptions {
+    /// Options embedding every available statistic.
+    pub fn all() -> JsonOptions {
+        JsonOptions {
+            center: true,
+            radius: true,
+            diameter: true,
+            variance: true,
+        }
+    }
+}
+
+fn json_point<T: Float, const DIMS: usize>(point: &Point<DIMS, T>) -> Value {
+    point.as_slice().iter().map(|x| x.to_scalar()).collect()
+}
+
+fn json_entry<CF: CFeature<DIMS>, const DIMS: usize>(
+    entry: &NodeEntry<CF, DIMS>,
+    options: &JsonOptions,
+    next_leaf: &mut usize,
+) -> Value {
+    let feature = &entry.feature;
+    let mut object = Map::new();
+    match entry.child {
+        Some(ref child) => {
+            object.insert("name".into(), json!("node"));
+            object.insert(
+                "children".into(),
+                child
+                    .entries
+                    .iter()
+                    .map(|entry| json_entry(entry, options, next_leaf))
+                    .collect(),
+            );
+        }
+        None => {
+            object.insert("name".into(), json!(format!("cluster {}", next_leaf)));
+            object.insert("id".into(), json!(*next_leaf));
+            *next_leaf += 1;
+        }
+    }
+    object.insert("value".into(), json!(feature.size().to_scalar()));
+    if options.center {
+        object.insert("center".into(), json_point(&feature.center()));
+    }
+    if options.radius {
+        object.insert("radius".into(), json!(feature.radius().to_scalar()));
+    }
+    if options.diameter {
+        object.insert("diameter".into(), json!(feature.diam().to_scalar()));
+    }
+    if options.variance {
+        object.insert("variance".into(), json_point(&feature.variance()));
+    }
+    Value::Object(object)
+}
+
+/// Renders the tree rooted at `root` as nested `{name, value, children}` JSON, as consumed by
+/// D3's hierarchy layouts (treemaps, trees, sunbursts). Every entry of the tree becomes an object
+/// whose `value` is the size of its feature; leaf entries are named `cluster <id>` (with ids
+/// consistent with [Node::clusters]) and carry an `id` field, while internal entries carry their
+/// child entries as `children`. `options` selects further statistics to embed at every level.
+pub fn to_json_hierarchy<CF: CFeature<DIMS>, const DIMS: usize>(
+    root: &Node<CF, DIMS>,
+    options: &JsonOptions,
+) -> String {
+    let mut next_leaf = 0;
+    let children = root
+        .entries
+        .iter()
+        .map(|entry| json_entry(entry, options, &mut next_leaf))
+        .collect::<Vec<_>>();
+    let size = root
+        .entries
+        .iter()
+        .map(|entry| entry.feature.size().to_scalar())
+        .fold(0.0, |total, size| total + size);
+    json!({ "name": "root", "value": size, "children": children }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cftree::{BasicConfig, BirchCFTree},
+        point::Point,
+    };
+
+    #[test]
+    fn dot() {
+        let config = BasicConfig::builder()
+            .capacity(1, 2)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let tree = BirchCFTree::from_iter(
+            (0..4).map(|i| Point::from_arr([i as f64 * 10.0, 1.0])),
+            config,
+        );
+        let dot = to_dot(tree.root());
+
+        assert!(dot.starts_with("digraph cftree {"));
+        assert!(dot.trim_end().ends_with('}'));
+        assert_eq!(dot.matches("shape=ellipse").count(), 4);
+        let edges = dot.matches(" -> ").count();
+        let graph_nodes = dot.matches("[label=").count();
+        // every graph node but the root has exactly one incoming edge
+        assert_eq!(edges, graph_nodes - 1);
+        assert!(dot.contains("size=1\\ndiameter=0\\ncenter=(30, 1)"));
+    }
+
+    #[test]
+    fn json_hierarchy() {
+        let config = BasicConfig::builder()
+            .capacity(1, 2)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let tree = BirchCFTree::from_iter(
+            (0..4).map(|i| Point::from_arr([i as f64 * 10.0, 1.0])),
+            config,
+        );
+
+        fn leaves(value: &Value, out: &mut Vec<Value>) {
+            match value.get("children") {
+                Some(children) => {
+                    for child in children.as_array().unwrap() {
+                        leaves(child, out);
+                    }
+                }
+                None => out.push(value.clone()),
+            }
+        }
+
+        let json = to_json_hierarchy(tree.root(), &JsonOptions::default());
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["name"], "root");
+        assert_eq!(value["value"], 4.0);
+        assert!(value["children"][0].get("center").is_none());
+        let mut found = vec![];
+        leaves(&value, &mut found);
+        assert_eq!(found.len(), 4);
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        for leaf in &found {
+            let id = leaf["id"].as_u64().unwrap() as usize;
+            assert_eq!(leaf["name"], format!("cluster {}", id));
+            assert_eq!(leaf["value"], 1.0);
+            assert!(clusters[id].id == id);
+        }
+
+        let json = to_json_hierarchy(tree.root(), &JsonOptions::all());
+        let value: Value = serde_json::from_str(&json).unwrap();
+        let mut found = vec![];
+        leaves(&value, &mut found);
+        for leaf in &found {
+            let id = leaf["id"].as_u64().unwrap() as usize;
+            let center = leaf["center"].as_array().unwrap();
+            assert_eq!(center[0], clusters[id].center[0]);
+            assert_eq!(leaf["radius"], 0.0);
+            assert_eq!(leaf["variance"].as_array().unwrap().len(), 2);
+        }
+    }
+}
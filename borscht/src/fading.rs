@@ -0,0 +1,202 @@
+/*!
+ * Cluster feature trees over data streams, where older points fade out (as in CluStream and
+ * DenStream).
+ *
+ * A point inserted at time `t` has weight `2^(-λ(now - t))` at a later time `now`. Rather than
+ * decaying every feature in the tree whenever time advances, the tree applies the decay lazily:
+ * points are stored with weight `2^(λ(t - origin))` relative to a fixed origin time, so all stored
+ * features decay at the same rate and their relative weights never need updating. Decayed
+ * weights are computed on access, and the stored weights are renormalized to a new origin before
+ * they grow large enough to lose precision.
+ */
+
+use std::sync::Arc;
+
+use crate::{
+    cfeature::decay::CFeature as DecayFeature,
+    cftree::{BasicConfig, CFTree, InsertOutcome, Node, TreeConfig},
+    offline::macro_labels,
+    point::{Point, Scalar},
+    summary::ClusterSummary,
+};
+
+/// Largest exponent (in powers of two) stored weights are allowed to reach before the tree is
+/// renormalized to a new origin time.
+const MAX_EXPONENT: Scalar = 64.0;
+
+/// A cluster feature tree whose points fade out over time.
+#[derive(Debug)]
+pub struct FadingCFTree<const DIMS: usize, TC = BasicConfig> {
+    tree: CFTree<DecayFeature<DIMS>, DIMS, TC>,
+    /// Decay rate: weights halve every `1 / lambda` time units.
+    lambda: Scalar,
+    /// Time at which stored weights are equal to decayed weights.
+    origin: Scalar,
+}
+
+impl<TC: TreeConfig, const DIMS: usize> FadingCFTree<DIMS, TC> {
+    /// Creates a new empty tree with decay rate `lambda`, starting at time `start`.
+    pub fn new(config: TC, lambda: Scalar, start: Scalar) -> FadingCFTree<DIMS, TC> {
+        FadingCFTree {
+            tree: CFTree::new(config),
+            lambda,
+            origin: start,
+        }
+    }
+
+    pub fn lambda(&self) -> Scalar {
+        self.lambda
+    }
+
+    /// The underlying tree, with weights relative to the current origin time.
+    pub fn tree(&self) -> &CFTree<DecayFeature<DIMS>, DIMS, TC> {
+        &self.tree
+    }
+
+    /// Factor to multiply stored weights by to get their decayed weights at time `now`.
+    pub fn decay_factor(&self, now: Scalar) -> Scalar {
+        (-self.lambda * (now - self.origin)).exp2()
+    }
+
+    /// Inserts point `p`, which arrived at time `time`.
+    pub fn insert(&mut self, p: Point<DIMS>, time: Scalar) -> InsertOutcome {
+        if self.lambda * (time - self.origin) > MAX_EXPONENT {
+            self.renormalize(time);
+        }
+        let weight = (self.lambda * (time - self.origin)).exp2();
+        self.tree.insert_feature(DecayFeature::weighted(&p, weight))
+    }
+
+    /// Moves the origin time to `origin`, rescaling all stored weights accordingly. Clusters
+    /// whose weight underflows to zero are removed.
+    fn renormalize(&mut self, origin: Scalar) {
+        debug_event!(from = self.origin, to = origin, "renormalizing weights");
+        let factor = self.decay_factor(origin);
+        scale_node(self.tree.root_mut(), factor);
+        self.tree.prune_min_size(Scalar::MIN_POSITIVE);
+        self.origin = origin;
+    }
+
+    /// Total decayed weight of all points in the tree at time `now`.
+    pub fn weight_at(&self, now: Scalar) -> Scalar {
+        self.tree
+            .root()
+            .entries
+            .iter()
+            .map(|entry| entry.feature.weight())
+            .sum::<Scalar>()
+            * self.decay_factor(now)
+    }
+
+    /// Summaries of the leaf clusters of the tree, with sizes decayed to time `now`.
+    pub fn clusters_at(&self, now: Scalar) -> Vec<ClusterSummary<DIMS>> {
+        let factor = self.decay_factor(now);
+        self.tree
+            .clusters()
+            .map(|cluster| ClusterSummary {
+                size: cluster.size * factor,
+                ..cluster
+            })
+            .collect()
+    }
+
+    /// Groups the leaf clusters of the tree into density-based macro-clusters using their
+    /// decayed weights at time `now`. See [Node::offline_cluster].
+    pub fn offline_cluster_at(
+        &self,
+        now: Scalar,
+        eps: Scalar,
+        min_weight: Scalar,
+    ) -> Vec<Option<usize>> {
+        macro_labels(&self.clusters_at(now), eps, min_weight)
+    }
+
+    /// Removes leaf clusters whose decayed weight at time `now` is below `min_weight`, along with
+    /// any nodes left empty. Returns the number of removed clusters.
+    pub fn prune(&mut self, now: Scalar, min_weight: Scalar) -> usize {
+        // compare stored weights against the threshold scaled up to the origin time
+        let min_stored = min_weight / self.decay_factor(now);
+        self.tree.prune_min_size(min_stored).len()
+    }
+}
+
+fn scale_node<const DIMS: usize>(node: &mut Node<DecayFeature<DIMS>, DIMS>, factor: Scalar) {
+    for entry in &mut node.entries {
+        entry.feature.scale(factor);
+        if let Some(ref mut child) = entry.child {
+            scale_node(Arc::make_mut(child), factor);
+        }
+    }
+    node.refresh();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> BasicConfig {
+        BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(1.0)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn decay() {
+        let mut tree = FadingCFTree::<2>::new(config(), 0.1, 0.0);
+        for i in 0..10 {
+            tree.insert(Point::from_arr([0.0, i as f64 * 0.01]), 0.0);
+        }
+        for i in 0..10 {
+            tree.insert(Point::from_arr([50.0, i as f64 * 0.01]), 100.0);
+        }
+
+        // the old points have faded to 2^-10 of their original weight
+        let clusters = tree.clusters_at(100.0);
+        let old = clusters.iter().find(|c| c.center[0] < 25.0).unwrap();
+        let new = clusters.iter().find(|c| c.center[0] > 25.0).unwrap();
+        assert!((old.size - 10.0 / 1024.0).abs() < 1e-9);
+        assert!((new.size - 10.0).abs() < 1e-9);
+        assert!((old.center[1] - 0.045).abs() < 1e-9);
+        assert!((tree.weight_at(110.0) - (10.0 + 10.0 / 1024.0) / 2.0).abs() < 1e-9);
+
+        // the faded old cluster is no longer dense enough to form a macro-cluster
+        let labels = tree.offline_cluster_at(100.0, 1.0, 1.0);
+        let old = clusters.iter().position(|c| c.center[0] < 25.0).unwrap();
+        assert_eq!(labels[old], None);
+        assert_eq!(labels.iter().flatten().count(), clusters.len() - 1);
+
+        assert_eq!(tree.prune(100.0, 0.5), 1);
+        let clusters = tree.clusters_at(100.0);
+        assert_eq!(clusters.len(), 1);
+        assert!(clusters[0].center[0] > 25.0);
+    }
+
+    #[test]
+    fn renormalize() {
+        let mut tree = FadingCFTree::<2>::new(config(), 1.0, 0.0);
+        for t in 0..5000 {
+            let t = t as f64;
+            tree.insert(Point::from_arr([t % 3.0, 0.0]), t);
+        }
+        let weight = tree.weight_at(4999.0);
+        assert!(weight.is_finite());
+        // geometric series 1 + 1/2 + 1/4 + ...
+        assert!((weight - 2.0).abs() < 1e-9);
+        assert!(tree.tree().clusters().all(|c| c.center[0].is_finite()));
+
+        // pruning everything older than a few steps leaves nodes consistent with their children
+        tree.prune(4999.0, 0.01);
+        assert!(tree.clusters_at(4999.0).len() <= 3);
+        assert!((tree.weight_at(4999.0) - 2.0).abs() < 0.01);
+
+        // clusters which fade to nothing are dropped when the tree is renormalized
+        let mut tree = FadingCFTree::<2>::new(config(), 1.0, 0.0);
+        tree.insert(Point::from_arr([100.0, 100.0]), 0.0);
+        tree.insert(Point::from_arr([0.0, 0.0]), 5000.0);
+        let clusters = tree.clusters_at(5000.0);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].size, 1.0);
+    }
+}
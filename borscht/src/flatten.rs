@@ -0,0 +1,128 @@
+/*!
+ * Flattening of the hierarchy of a tree into clusters of a chosen granularity.
+ *
+ * Every entry of a tree summarizes all the points under it, so cutting the tree at some set of
+ * entries gives a flat clustering, from the few coarse clusters of the root entries down to the
+ * leaf clusters, without running a separate global clustering:
+ * - [Node::flatten_at_level] cuts the tree at a fixed depth;
+ * - [Node::flatten_by_max_diameter] descends each branch only until its entries are small enough,
+ *   so dense regions stay coarse while sparse ones are refined.
+ *
+ * Either way, every point summarized by the tree belongs to exactly one of the returned features.
+ */
+
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    cfeature::CFeature,
+    cftree::{CFTree, Node},
+};
+
+impl<CF: CFeature<DIMS> + Clone, const DIMS: usize> Node<CF, DIMS> {
+    /// Features of the entries at depth `level` of the tree rooted at this node (the entries of
+    /// this node have depth 0), in depth-first order. Leaf entries above that depth are included
+    /// as they are, so a level below the leaves gives the leaf clusters.
+    pub fn flatten_at_level(&self, level: usize) -> Vec<CF> {
+        self.iter_entries_with_depth()
+            .filter(|(depth, entry)| *depth == level || *depth < level && entry.child.is_none())
+            .map(|(_, entry)| entry.feature.clone())
+            .collect()
+    }
+
+    /// Features of the highest entries of the tree rooted at this node whose diameter is at most
+    /// `max_diameter`, in depth-first order. Leaf entries are included even if they're larger.
+    pub fn flatten_by_max_diameter(&self, max_diameter: CF::Scalar) -> Vec<CF> {
+        let mut flat = vec![];
+        let mut stack = vec![self.entries.iter()];
+        while let Some(entries) = stack.last_mut() {
+            match entries.next() {
+                Some(entry) => match entry.child {
+                    Some(ref child) if entry.feature.diam() > max_diameter => {
+                        stack.push(child.entries.iter());
+                    }
+                    _ => flat.push(entry.feature.clone()),
+                },
+                None => {
+                    stack.pop();
+                }
+            }
+        }
+        flat
+    }
+}
+
+impl<CF: CFeature<DIMS> + Clone, TC, const DIMS: usize> CFTree<CF, DIMS, TC> {
+    /// Features of the entries at depth `level` of this tree; see [Node::flatten_at_level].
+    pub fn flatten_at_level(&self, level: usize) -> Vec<CF> {
+        self.root().flatten_at_level(level)
+    }
+
+    /// Features of the highest entries of this tree whose diameter is at most `max_diameter`; see
+    /// [Node::flatten_by_max_diameter].
+    pub fn flatten_by_max_diameter(&self, max_diameter: CF::Scalar) -> Vec<CF> {
+        self.root().flatten_by_max_diameter(max_diameter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cfeature::{birch::CFeature as BirchFeature, CFeature},
+        cftree::{BasicConfig, BirchCFTree},
+        point::{Point, Scalar},
+    };
+
+    #[test]
+    fn flatten() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        // a dense blob and sparse points around it
+        let points = (0..300).map(|i| match i % 3 {
+            0 => Point::from_arr([(i % 50) as Scalar * 4.0, (i % 7) as Scalar * 20.0]),
+            _ => Point::from_arr([(i % 5) as Scalar * 0.2, (i % 4) as Scalar * 0.2]),
+        });
+        let tree = BirchCFTree::<2>::from_iter(points, config);
+        let height = tree.root().height();
+        assert!(height > 3);
+        let total = |features: &[BirchFeature<2>]| {
+            features
+                .iter()
+                .map(|feature| feature.size())
+                .sum::<Scalar>()
+        };
+
+        // levels get finer, from the root entries down to the leaf clusters
+        let leaves = tree.clusters().count();
+        let mut previous = 0;
+        for level in 0..height + 1 {
+            let flat = tree.flatten_at_level(level);
+            assert_eq!(total(&flat), 300.0);
+            assert!(flat.len() >= previous);
+            previous = flat.len();
+        }
+        assert_eq!(tree.flatten_at_level(0).len(), tree.root().entries.len());
+        assert_eq!(tree.flatten_at_level(height - 1).len(), leaves);
+        assert_eq!(tree.flatten_at_level(height + 5).len(), leaves);
+
+        // by diameter: coarser clusters for larger diameters
+        let sizes = |features: Vec<BirchFeature<2>>| {
+            features
+                .iter()
+                .map(|feature| feature.size())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sizes(tree.flatten_by_max_diameter(Scalar::INFINITY)),
+            sizes(tree.flatten_at_level(0))
+        );
+        assert_eq!(tree.flatten_by_max_diameter(0.0).len(), leaves);
+        let flat = tree.flatten_by_max_diameter(5.0);
+        assert_eq!(total(&flat), 300.0);
+        assert!(flat.len() > tree.flatten_at_level(0).len() && flat.len() < leaves);
+        // the dense blob stays in a single cluster
+        assert!(flat.iter().any(|feature| feature.size() >= 200.0));
+    }
+}
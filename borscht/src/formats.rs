@@ -0,0 +1,130 @@
+/*!
+ * Helpers for serializing [CFTree]s (along with their configuration) to common formats. Each
+ * format is enabled by its own feature: `json`, `msgpack`, and `bincode`.
+ *
+ * Unlike [persist](crate::persist), these helpers don't add a header or version to the output,
+ * so they can be used to embed trees in larger documents or messages.
+ */
+
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::{cfeature::CFeature, cftree::CFTree};
+
+#[derive(Error, Debug)]
+pub enum FormatError {
+    #[cfg(feature = "json")]
+    #[error("json error")]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "msgpack")]
+    #[error("msgpack encoding error")]
+    MsgpackEncode(#[from] rmp_serde::encode::Error),
+    #[cfg(feature = "msgpack")]
+    #[error("msgpack decoding error")]
+    MsgpackDecode(#[from] rmp_serde::decode::Error),
+    #[cfg(feature = "bincode")]
+    #[error("bincode error")]
+    Bincode(#[from] bincode::Error),
+}
+
+type Result<T> = std::result::Result<T, FormatError>;
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Serialize + DeserializeOwned,
+    TC: Serialize + DeserializeOwned,
+{
+    /// Serializes this tree (and its configuration) to a JSON string.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Deserializes a tree previously serialized with [CFTree::to_json].
+    #[cfg(feature = "json")]
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Serializes this tree (and its configuration) to MessagePack.
+    #[cfg(feature = "msgpack")]
+    pub fn to_msgpack(&self) -> Result<Vec<u8>> {
+        Ok(rmp_serde::to_vec(self)?)
+    }
+
+    /// Deserializes a tree previously serialized with [CFTree::to_msgpack].
+    #[cfg(feature = "msgpack")]
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self> {
+        Ok(rmp_serde::from_slice(bytes)?)
+    }
+
+    /// Serializes this tree (and its configuration) with bincode.
+    #[cfg(feature = "bincode")]
+    pub fn to_bincode(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    /// Deserializes a tree previously serialized with [CFTree::to_bincode].
+    #[cfg(feature = "bincode")]
+    pub fn from_bincode(bytes: &[u8]) -> Result<Self> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use crate::{
+        cftree::{BasicConfig, BetulaCFTree},
+        point::Point,
+    };
+
+    fn tree() -> BetulaCFTree<2> {
+        let config = BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        BetulaCFTree::from_iter(
+            (0..30).map(|i| Point::from_arr([(i % 7) as f64, (i % 5) as f64 * 0.5])),
+            config,
+        )
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json() {
+        let tree = tree();
+        let loaded = BetulaCFTree::<2>::from_json(&tree.to_json().unwrap()).unwrap();
+        assert_eq!(format!("{:?}", tree), format!("{:?}", loaded));
+        assert!(matches!(
+            BetulaCFTree::<2>::from_json("{}"),
+            Err(FormatError::Json(_))
+        ));
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack() {
+        let tree = tree();
+        let loaded = BetulaCFTree::<2>::from_msgpack(&tree.to_msgpack().unwrap()).unwrap();
+        assert_eq!(format!("{:?}", tree), format!("{:?}", loaded));
+        assert!(matches!(
+            BetulaCFTree::<2>::from_msgpack(&[0xc1]),
+            Err(FormatError::MsgpackDecode(_))
+        ));
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode() {
+        let tree = tree();
+        let loaded = BetulaCFTree::<2>::from_bincode(&tree.to_bincode().unwrap()).unwrap();
+        assert_eq!(format!("{:?}", tree), format!("{:?}", loaded));
+        assert!(matches!(
+            BetulaCFTree::<2>::from_bincode(&[1, 2, 3]),
+            Err(FormatError::Bincode(_))
+        ));
+    }
+}
@@ -0,0 +1,152 @@
+/*!
+ * Clustering of geographic (latitude and longitude) coordinates.
+ *
+ * Degrees of latitude and longitude aren't Euclidean coordinates: a degree of longitude spans
+ * about 111 km at the equator but nothing at the poles, and longitudes wrap around at ±180°, so
+ * clustering them directly distorts distances and splits clusters straddling the antimeridian.
+ * Instead, [Geo] is a [Transform] mapping each [GeoPoint] to its position on a sphere with the
+ * radius of the Earth, in 3-dimensional space. There, straight-line (chord) distances grow with
+ * great-circle ([haversine](Geo::haversine)) distances, and nearly equal them at the scale of
+ * most clusters. Cluster centers (the means of these positions) lie inside the sphere;
+ * [Transform::inverse] projects them back onto it, which gives the spherical centroid of each
+ * cluster.
+ */
+
+use num_traits::Float;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    point::{Point, Scalar},
+    preprocess::Transform,
+};
+
+/// Mean radius of the Earth, in kilometers.
+pub const EARTH_RADIUS_KM: Scalar = 6371.0088;
+
+/// A geographic location, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GeoPoint {
+    /// Latitude, from -90° (south) to 90° (north).
+    pub lat: Scalar,
+    /// Longitude, from -180° (west) to 180° (east).
+    pub lon: Scalar,
+}
+
+impl GeoPoint {
+    pub fn new(lat: Scalar, lon: Scalar) -> GeoPoint {
+        GeoPoint { lat, lon }
+    }
+}
+
+/// Maps [GeoPoint]s onto a sphere in 3-dimensional space (and back), with distances in the units
+/// of its radius (kilometers on the Earth, by default).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Geo {
+    radius: Scalar,
+}
+
+impl Default for Geo {
+    fn default() -> Geo {
+        Geo::new(EARTH_RADIUS_KM)
+    }
+}
+
+impl Geo {
+    /// Sphere of radius `radius` (e.g. [EARTH_RADIUS_KM], or the radius of the Earth in another
+    /// unit of distance).
+    pub fn new(radius: Scalar) -> Geo {
+        Geo { radius }
+    }
+
+    pub fn radius(&self) -> Scalar {
+        self.radius
+    }
+
+    /// Great-circle distance between `a` and `b` (by the haversine formula).
+    pub fn haversine(&self, a: &GeoPoint, b: &GeoPoint) -> Scalar {
+        let (lat_a, lat_b) = (a.lat.to_radians(), b.lat.to_radians());
+        let half_dlat = (lat_b - lat_a) / 2.0;
+        let half_dlon = (b.lon - a.lon).to_radians() / 2.0;
+        let h = Float::powi(Float::sin(half_dlat), 2)
+            + Float::cos(lat_a) * Float::cos(lat_b) * Float::powi(Float::sin(half_dlon), 2);
+        2.0 * self.radius * Float::asin(Float::sqrt(h).min(1.0))
+    }
+
+    /// Straight-line distance through the sphere between two points a great-circle distance
+    /// `distance` apart.
+    pub fn chord(&self, distance: Scalar) -> Scalar {
+        let half_angle = (distance / (2.0 * self.radius)).min(core::f64::consts::FRAC_PI_2);
+        2.0 * self.radius * Float::sin(half_angle)
+    }
+
+    /// Tree threshold (see [TreeConfig::threshold](crate::cftree::TreeConfig::threshold)) which
+    /// bounds the diameter of leaf clusters of transformed points to about `diameter`, as a
+    /// great-circle distance.
+    pub fn threshold(&self, diameter: Scalar) -> Scalar {
+        Float::powi(self.chord(diameter), 2)
+    }
+}
+
+impl Transform<GeoPoint, Point<3>> for Geo {
+    fn transform(&self, p: GeoPoint) -> Point<3> {
+        let (lat, lon) = (p.lat.to_radians(), p.lon.to_radians());
+        Point::from_arr([
+            self.radius * Float::cos(lat) * Float::cos(lon),
+            self.radius * Float::cos(lat) * Float::sin(lon),
+            self.radius * Float::sin(lat),
+        ])
+    }
+
+    /// Projects `q` radially onto the sphere. The origin (e.g. the center of points spread
+    /// evenly around the sphere) has no direction, and maps to latitude and longitude 0.
+    fn inverse(&self, q: Point<3>) -> GeoPoint {
+        let (x, y, z) = (q[0], q[1], q[2]);
+        GeoPoint {
+            lat: Float::atan2(z, Float::hypot(x, y)).to_degrees(),
+            lon: Float::atan2(y, x).to_degrees(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cftree::{BasicConfig, BirchCFTree};
+
+    #[test]
+    fn geo() {
+        let geo = Geo::default();
+        let (paris, london) = (
+            GeoPoint::new(48.8566, 2.3522),
+            GeoPoint::new(51.5074, -0.1278),
+        );
+        assert!((geo.haversine(&paris, &london) - 343.6).abs() < 0.5);
+        let chord = (geo.transform(paris) - geo.transform(london))
+            .norm2()
+            .sqrt();
+        assert!((chord - geo.chord(geo.haversine(&paris, &london))).abs() < 1e-6);
+
+        // points a few kilometers apart on either side of the antimeridian form a single cluster,
+        // centered on it
+        let points = (0..20)
+            .map(|i| {
+                let offset = (i / 2) as Scalar * 0.002;
+                match i % 2 {
+                    0 => GeoPoint::new(-17.0 + offset, 179.98 - offset),
+                    _ => GeoPoint::new(-17.0 - offset, -179.98 + offset),
+                }
+            })
+            .collect::<Vec<_>>();
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(geo.threshold(20.0))
+            .build()
+            .unwrap();
+        let tree = BirchCFTree::<3>::from_iter_with_transform(points, &geo, config);
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.len(), 1);
+        let center = geo.inverse(clusters[0].center.clone());
+        assert!((center.lat + 17.0).abs() < 0.01);
+        assert!(center.lon.abs() > 179.99);
+    }
+}
@@ -0,0 +1,213 @@
+/*!
+ * Stable identities of leaf clusters across incremental updates.
+ *
+ * Ids of leaf clusters by position (see [ClusterSummary::id](crate::summary::ClusterSummary::id))
+ * change whenever an insertion adds a leaf cluster or splits a node. If the tree assigns stable
+ * ids (see [TreeConfig::stable_cluster_ids](crate::cftree::TreeConfig::stable_cluster_ids)), every
+ * leaf entry gets a [ClusterIdentity] when it is created, numbered in order of creation, which it
+ * keeps however nodes are split or rearranged. When a leaf entry is absorbed into another (e.g. as
+ * the tree is rebuilt with a larger threshold), the identity of the absorbing entry records the
+ * absorbed ids, so that [CFTree::cluster_id_map] can map every id ever assigned to the cluster now
+ * holding its points.
+ */
+
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cftree::{CFTree, Node, NodeEntry};
+
+/// Stable identity of a leaf cluster.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClusterIdentity {
+    pub id: u64,
+    /// Ids of the clusters absorbed into this one, in order of absorption (including the ids
+    /// absorbed into those).
+    pub merged: Vec<u64>,
+}
+
+impl ClusterIdentity {
+    pub fn new(id: u64) -> ClusterIdentity {
+        ClusterIdentity {
+            id,
+            merged: Vec::new(),
+        }
+    }
+
+    /// Smallest id greater than all the ids of this identity.
+    pub(crate) fn next_id(&self) -> u64 {
+        self.merged.iter().fold(self.id, |max, &id| max.max(id)) + 1
+    }
+}
+
+/// Stable id for the leaf entry an insertion creates, or for the leaf entry absorbing it if that
+/// has none: an `Option<u64>` for insertions which only assign ids if the tree does, and a `u64`
+/// for those which always do.
+pub(crate) trait NewId: Copy + PartialEq {
+    /// The id itself, if any.
+    fn id(self) -> Option<u64>;
+
+    /// Stable id of the leaf entry `entry`, after giving it this id if it has none.
+    fn identify<CF, const DIMS: usize>(self, entry: &mut NodeEntry<CF, DIMS>) -> Self;
+}
+
+impl NewId for Option<u64> {
+    fn id(self) -> Option<u64> {
+        self
+    }
+
+    fn identify<CF, const DIMS: usize>(self, entry: &mut NodeEntry<CF, DIMS>) -> Option<u64> {
+        if entry.identity.is_none() {
+            entry.identity = self.map(ClusterIdentity::new);
+        }
+        entry.identity.as_ref().map(|identity| identity.id)
+    }
+}
+
+impl NewId for u64 {
+    fn id(self) -> Option<u64> {
+        Some(self)
+    }
+
+    fn identify<CF, const DIMS: usize>(self, entry: &mut NodeEntry<CF, DIMS>) -> u64 {
+        entry
+            .identity
+            .get_or_insert_with(|| ClusterIdentity::new(self))
+            .id
+    }
+}
+
+/// Smallest id greater than all the ids of the leaf entries of the tree rooted at `root`.
+pub(crate) fn next_cluster_id<CF, const DIMS: usize>(root: &Node<CF, DIMS>) -> u64 {
+    root.leaf_entries()
+        .filter_map(|entry| entry.identity.as_ref())
+        .map(ClusterIdentity::next_id)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Records that the entry with identity `absorbed` was absorbed into the entry with identity
+/// `identity` (taking it over if the absorbing entry has none).
+pub(crate) fn merge_identity(
+    identity: &mut Option<ClusterIdentity>,
+    absorbed: Option<ClusterIdentity>,
+) {
+    match (identity, absorbed) {
+        (Some(identity), Some(mut absorbed)) => {
+            identity.merged.push(absorbed.id);
+            identity.merged.append(&mut absorbed.merged);
+        }
+        (identity @ None, absorbed) => *identity = absorbed,
+        (Some(_), None) => {}
+    }
+}
+
+impl<CF, const DIMS: usize> Node<CF, DIMS> {
+    /// Stable ids of the leaf clusters of the tree rooted at this node (`None` for leaf entries
+    /// created without one), in the order of [Node::clusters].
+    pub fn stable_cluster_ids(&self) -> Vec<Option<u64>> {
+        self.leaf_entries()
+            .map(|entry| entry.identity.as_ref().map(|identity| identity.id))
+            .collect()
+    }
+
+    /// Maps every stable id held or absorbed by the leaf clusters of the tree rooted at this node
+    /// to the stable id of the leaf cluster now holding its points.
+    pub fn cluster_id_map(&self) -> BTreeMap<u64, u64> {
+        let mut map = BTreeMap::new();
+        for identity in self
+            .leaf_entries()
+            .filter_map(|entry| entry.identity.as_ref())
+        {
+            map.insert(identity.id, identity.id);
+            map.extend(identity.merged.iter().map(|&merged| (merged, identity.id)));
+        }
+        map
+    }
+
+    fn leaf_entries(&self) -> impl Iterator<Item = &NodeEntry<CF, DIMS>> {
+        self.iter_entries_with_depth()
+            .map(|(_, entry)| entry)
+            .filter(|entry| entry.child.is_none())
+    }
+}
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC> {
+    /// Stable ids of the leaf clusters of this tree; see [Node::stable_cluster_ids].
+    pub fn stable_cluster_ids(&self) -> Vec<Option<u64>> {
+        self.root().stable_cluster_ids()
+    }
+
+    /// Maps every stable id assigned in this tree (unless its points were pruned) to the stable id
+    /// of the leaf cluster now holding its points: itself, or the cluster it was absorbed into.
+    /// Ids from before a rebuild map to the clusters of the rebuilt tree.
+    pub fn cluster_id_map(&self) -> BTreeMap<u64, u64> {
+        self.root().cluster_id_map()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        birch::Birch,
+        cfeature::birch::CFeature as BirchFeature,
+        cftree::{BasicConfig, BirchCFTree},
+        point::{Point, Scalar},
+    };
+
+    #[test]
+    fn stable_cluster_ids() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .stable_cluster_ids(true)
+            .build()
+            .unwrap();
+        let point = |i: usize| Point::from_arr([(i * 7 % 31) as Scalar, (i % 13) as Scalar]);
+
+        // ids follow clusters through the splits of later insertions, one at a time or in batches
+        let mut tree = BirchCFTree::<2>::new(config.clone());
+        tree.extend((0..20).map(point));
+        let before = tree
+            .clusters()
+            .zip(tree.stable_cluster_ids())
+            .map(|(cluster, id)| (id.unwrap(), cluster.center))
+            .collect::<Vec<_>>();
+        let splits = tree.metrics().total_splits();
+        tree.extend((20..100).map(point));
+        tree.insert_batch(&(100..200).map(point).collect::<Vec<_>>());
+        assert!(tree.metrics().total_splits() > splits);
+        let ids = tree.stable_cluster_ids();
+        let mut sorted = ids.iter().map(|id| id.unwrap()).collect::<Vec<_>>();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..tree.metrics().new_entries).collect::<Vec<_>>());
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        for (id, center) in before {
+            let idx = ids.iter().position(|&other| other == Some(id)).unwrap();
+            // points 20.. only revisit the positions of the first points after 31 insertions
+            assert!((&clusters[idx].center - &center).norm2() < 1.0);
+        }
+        assert!(tree.cluster_id_map().iter().all(|(id, new)| id == new));
+
+        // rebuilds merge clusters, and map their ids to the absorbing clusters
+        let mut birch = Birch::<BirchFeature<2>, 2>::new(config.clone()).max_leaves(10);
+        birch.fit((0..200).map(point));
+        assert!(birch.tree().metrics().rebuilds > 0);
+        let map = birch.tree().cluster_id_map();
+        let ids = birch.tree().stable_cluster_ids();
+        assert_eq!(map.len() as u64, birch.tree().metrics().new_entries);
+        assert!(map.values().all(|new| ids.contains(&Some(*new))));
+        assert!(map.iter().any(|(id, new)| id != new));
+
+        // trees without stable ids don't assign any
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let tree = BirchCFTree::<2>::from_iter((0..200).map(point), config);
+        assert!(tree.stable_cluster_ids().iter().all(Option::is_none));
+        assert!(tree.cluster_id_map().is_empty());
+    }
+}
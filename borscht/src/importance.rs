@@ -0,0 +1,120 @@
+/*!
+ * Characterization of leaf clusters by the dimensions they stand out on.
+ *
+ * For every leaf cluster, [CFTree::feature_importance] standardizes the difference between the
+ * center of the cluster and the global mean of all the points of the tree by the global standard
+ * deviation of each dimension, i.e. a z-score of the cluster center. Dimensions with the largest
+ * absolute scores are those which characterize the cluster ("high on dimension 2, low on
+ * dimension 7"). Variances are most accurate for numerically stable features such as
+ * [BETULA's](crate::cfeature::betula::CFeature).
+ */
+
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use num_traits::{Float as _, Zero};
+
+use crate::{
+    cfeature::CFeature,
+    cftree::CFTree,
+    point::{Float, Point, Scalar},
+    summary::ClusterSummary,
+};
+
+/// How a leaf cluster deviates from the global mean, per dimension.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterImportance<const DIMS: usize, T = Scalar> {
+    pub cluster: ClusterSummary<DIMS, T>,
+    /// Standardized difference between the center of the cluster and the global mean, for each
+    /// dimension (zero for dimensions without global variance).
+    pub deviations: Point<DIMS, T>,
+}
+
+impl<T: Float, const DIMS: usize> ClusterImportance<DIMS, T> {
+    /// Dimensions ordered from the most to the least deviating (by absolute deviation), with
+    /// their deviations.
+    pub fn ranked(&self) -> Vec<(usize, T)> {
+        let mut ranked = self
+            .deviations
+            .as_slice()
+            .iter()
+            .copied()
+            .enumerate()
+            .collect::<Vec<_>>();
+        // stable, so ties stay in order of dimension
+        ranked.sort_by(|(_, left), (_, right)| {
+            right.abs().to_scalar().total_cmp(&left.abs().to_scalar())
+        });
+        ranked
+    }
+
+    /// The (at most) `k` most deviating dimensions; see [ClusterImportance::ranked].
+    pub fn top(&self, k: usize) -> Vec<(usize, T)> {
+        let mut ranked = self.ranked();
+        ranked.truncate(k);
+        ranked
+    }
+}
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+{
+    /// Deviations of the centers of the leaf clusters of this tree from the global mean (see the
+    /// [module documentation](self)), indexed by
+    /// [ClusterSummary::id](crate::summary::ClusterSummary::id).
+    pub fn feature_importance(&self) -> Vec<ClusterImportance<DIMS, CF::Scalar>> {
+        let global = self.root().compute_feature();
+        let mean = global.center();
+        let variance = global.variance();
+        self.clusters()
+            .map(|cluster| {
+                let deviations = Point::from_fn(|d| match variance[d].is_zero() {
+                    true => CF::Scalar::zero(),
+                    false => (cluster.center[d] - mean[d]) / variance[d].sqrt(),
+                });
+                ClusterImportance {
+                    cluster,
+                    deviations,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cftree::{BasicConfig, BetulaCFTree};
+
+    #[test]
+    fn feature_importance() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(2.0)
+            .build()
+            .unwrap();
+        // three segments, each standing out on one dimension; the last dimension is constant
+        let points = (0..90)
+            .map(|i| {
+                let jitter = (i / 3 % 3) as Scalar * 0.1;
+                let mut p = Point::from_arr([jitter, jitter, jitter, 5.0]);
+                p[i % 3] += 10.0;
+                p
+            })
+            .collect::<Vec<_>>();
+        let tree = BetulaCFTree::<4>::from_iter(points, config);
+        let importance = tree.feature_importance();
+        assert_eq!(importance.len(), 3);
+        for cluster in &importance {
+            let top = cluster.top(1)[0];
+            assert_eq!(cluster.cluster.center[top.0].round(), 10.0);
+            // one standard deviation is about 4.7, for a difference of about 6.7 from the mean
+            assert!((top.1 - 2.0_f64.sqrt()).abs() < 0.05);
+            let ranked = cluster.ranked();
+            assert_eq!(ranked.len(), 4);
+            assert!(ranked[1].1 < 0.0 && ranked[2].1 < 0.0);
+            assert_eq!(ranked[3], (3, 0.0));
+        }
+    }
+}
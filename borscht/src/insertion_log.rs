@@ -0,0 +1,224 @@
+/*!
+ * Per-insertion logging of the dynamics of a tree, for research.
+ *
+ * [CFTree::insert_logged] inserts a point like [CFTree::insert], and writes an [InsertionRecord]
+ * about the insertion to an [InsertionLog]: the leaf cluster the insertion reached and the
+ * distance of the point from it, whether the point was absorbed, and the size of the tree after
+ * the insertion. Logs are written as CSV (with a header) or as JSON lines, e.g. to analyze the
+ * growth of a tree in a notebook.
+ *
+ * Logging walks the tree to count its nodes and to number the reached leaf cluster, so logged
+ * insertions are much slower than plain ones.
+ */
+
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{CFTree, InsertOutcome, Node, TreeConfig},
+    point::{Float, Scalar},
+};
+
+#[derive(Error, Debug)]
+pub enum InsertionLogError {
+    #[error("csv error")]
+    Csv(#[from] csv::Error),
+    #[error("json error")]
+    Json(#[from] serde_json::Error),
+    #[error("i/o error")]
+    Io(#[from] std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, InsertionLogError>;
+
+/// What happened when inserting a single point into a tree.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InsertionRecord {
+    /// Index of the insertion (counting from zero), i.e. the number of points inserted before.
+    pub index: u64,
+    /// Id (see [ClusterSummary::id](crate::summary::ClusterSummary::id)) of the leaf cluster the
+    /// insertion reached, as numbered before the insertion, or `None` if the tree was empty.
+    pub leaf: Option<usize>,
+    /// Distance of the point from that leaf cluster, measured with the metric of the tree's
+    /// configuration (see [TreeConfig::metric]).
+    pub distance: Option<Scalar>,
+    pub outcome: InsertOutcome,
+    /// Height of the tree after the insertion.
+    pub height: usize,
+    /// Number of nodes of the tree after the insertion.
+    pub nodes: usize,
+}
+
+/// Format of the rows of an [InsertionLog].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Comma-separated values, with a header row. Missing values (before the first insertion)
+    /// are empty.
+    Csv,
+    /// One JSON object per line. Missing values are `null`.
+    JsonLines,
+}
+
+enum Sink<W: Write> {
+    Csv(Box<csv::Writer<W>>),
+    JsonLines(W),
+}
+
+/// A sink of [InsertionRecord]s.
+pub struct InsertionLog<W: Write> {
+    sink: Sink<W>,
+}
+
+impl<W: Write> InsertionLog<W> {
+    pub fn new(writer: W, format: LogFormat) -> InsertionLog<W> {
+        let sink = match format {
+            LogFormat::Csv => Sink::Csv(Box::new(csv::Writer::from_writer(writer))),
+            LogFormat::JsonLines => Sink::JsonLines(writer),
+        };
+        InsertionLog { sink }
+    }
+
+    /// Writes a single record to this log.
+    pub fn write(&mut self, record: &InsertionRecord) -> Result<()> {
+        match self.sink {
+            Sink::Csv(ref mut writer) => writer.serialize(record)?,
+            Sink::JsonLines(ref mut writer) => {
+                serde_json::to_writer(&mut *writer, record)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        match self.sink {
+            Sink::Csv(ref mut writer) => writer.flush()?,
+            Sink::JsonLines(ref mut writer) => writer.flush()?,
+        }
+        Ok(())
+    }
+
+    /// Flushes this log and returns the underlying writer.
+    pub fn into_inner(self) -> Result<W> {
+        match self.sink {
+            Sink::Csv(writer) => writer
+                .into_inner()
+                .map_err(|err| InsertionLogError::Io(err.into_error())),
+            Sink::JsonLines(mut writer) => {
+                writer.flush()?;
+                Ok(writer)
+            }
+        }
+    }
+}
+
+impl<CF: CFeature<DIMS>, const DIMS: usize> Node<CF, DIMS> {
+    /// As [Node::nearest_leaf], but returning the id of the leaf cluster and its squared distance
+    /// from `p`.
+    fn nearest_leaf_id<TC: TreeConfig>(
+        &self,
+        p: &FeaturePoint<CF, DIMS>,
+        config: &TC,
+    ) -> Option<(usize, CF::Scalar)> {
+        let mut node = self;
+        let mut id = 0;
+        loop {
+            let (idx, d2) = node.closest_entry_with(p, config)?;
+            id += node.entries[..idx]
+                .iter()
+                .map(|entry| entry.child.as_ref().map_or(1, |child| child.leaf_count()))
+                .sum::<usize>();
+            match node.entries[idx].child {
+                Some(ref child) => node = child,
+                None => return Some((id, d2)),
+            }
+        }
+    }
+}
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + core::fmt::Debug + Clone,
+    TC: TreeConfig,
+{
+    /// Inserts a single point into this tree (see [CFTree::insert]), and writes a record of the
+    /// insertion to `log`. The point is inserted even if writing the record fails.
+    pub fn insert_logged<W: Write>(
+        &mut self,
+        p: FeaturePoint<CF, DIMS>,
+        log: &mut InsertionLog<W>,
+    ) -> Result<InsertOutcome> {
+        let index = self.metrics().inserted;
+        let p = self.root().complete(p, self.config());
+        let nearest = self.root().nearest_leaf_id(&p, self.config());
+        let outcome = self.insert(p);
+        log.write(&InsertionRecord {
+            index,
+            leaf: nearest.map(|(id, _)| id),
+            distance: nearest.map(|(_, d2)| d2.to_scalar().sqrt()),
+            outcome,
+            height: self.root().height(),
+            nodes: self.root().node_count(),
+        })?;
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cftree::BasicConfig, cftree::BirchCFTree, point::Point};
+
+    #[test]
+    fn insertion_log() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(1.0)
+            .build()
+            .unwrap();
+        let points = (0..50)
+            .map(|i| Point::from_arr([(i % 10) as Scalar * 3.0, (i / 10) as Scalar]))
+            .collect::<Vec<_>>();
+
+        let mut tree = BirchCFTree::<2>::new(config.clone());
+        let mut log = InsertionLog::new(vec![], LogFormat::JsonLines);
+        for p in &points {
+            tree.insert_logged(p.clone(), &mut log).unwrap();
+        }
+        let out = String::from_utf8(log.into_inner().unwrap()).unwrap();
+        let records = out
+            .lines()
+            .map(|line| serde_json::from_str::<InsertionRecord>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 50);
+        assert_eq!((records[0].leaf, records[0].distance), (None, None));
+        assert_eq!((records[0].height, records[0].nodes), (1, 1));
+        assert_eq!(records[1].outcome, InsertOutcome::NewEntry);
+        assert_eq!((records[1].leaf, records[1].distance), (Some(0), Some(3.0)));
+        let last = records.last().unwrap();
+        assert_eq!(last.index, 49);
+        assert_eq!(last.height, tree.root().height());
+        assert_eq!(last.nodes, tree.root().node_count());
+        let absorbed = records
+            .iter()
+            .filter(|record| record.outcome == InsertOutcome::Absorbed)
+            .count();
+        assert_eq!(absorbed as u64, tree.metrics().absorbed);
+
+        // the same insertions, logged as CSV
+        let mut tree = BirchCFTree::<2>::new(config);
+        let mut log = InsertionLog::new(vec![], LogFormat::Csv);
+        for p in points {
+            tree.insert_logged(p, &mut log).unwrap();
+        }
+        let out = String::from_utf8(log.into_inner().unwrap()).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 51);
+        assert_eq!(lines[0], "index,leaf,distance,outcome,height,nodes");
+        assert_eq!(lines[1], "0,,,NewEntry,1,1");
+        assert_eq!(lines[2], "1,0,3.0,NewEntry,1,1");
+    }
+}
@@ -0,0 +1,32 @@
+/*!
+ * Instrumentation of tree construction with [tracing](https://docs.rs/tracing) spans and events,
+ * when the `tracing` feature is enabled. Without it, the macros below expand to nothing.
+ *
+ * Insertions are traced in an `insert` span (at trace level), with trace-level events for each
+ * step of the descent and for how the inserted feature was placed. Structural changes (node
+ * splits, merge refinements, rebuilds and renormalizations) are debug-level events.
+ */
+
+/// Emits a trace-level event.
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
+
+/// Emits a debug-level event.
+macro_rules! debug_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+
+/// Enters a trace-level span until the end of the enclosing block.
+macro_rules! enter_trace_span {
+    ($name:expr) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!($name).entered();
+    };
+}
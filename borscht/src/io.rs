@@ -0,0 +1,209 @@
+/*!
+ * Reading points from delimited text (CSV) files.
+ */
+
+use std::io::Read;
+
+use itertools::Either;
+use thiserror::Error;
+
+use crate::point::{Point, Scalar};
+
+#[derive(Error, Debug)]
+pub enum IoError {
+    #[error("csv error")]
+    Csv(#[from] csv::Error),
+    #[error("unknown column '{0}'")]
+    UnknownColumn(String),
+    #[error("column names given, but the input has no header")]
+    MissingHeader,
+    #[error("line {line}: expected {expected} columns, found {found}")]
+    ColumnCount {
+        line: u64,
+        expected: usize,
+        found: usize,
+    },
+    #[error("line {line}, column {column}: invalid number '{value}'")]
+    InvalidNumber {
+        line: u64,
+        column: usize,
+        value: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, IoError>;
+
+/// Which columns of each record hold the coordinates of a point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Columns {
+    /// The first `DIMS` columns.
+    First,
+    /// Columns at the given (zero-based) indices.
+    Indices(Vec<usize>),
+    /// Columns with the given header names.
+    Names(Vec<String>),
+}
+
+/// Options for [points_from_csv].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    has_header: bool,
+    delimiter: u8,
+    columns: Columns,
+}
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        CsvOptions {
+            has_header: true,
+            delimiter: b',',
+            columns: Columns::First,
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Whether the first record is a header (default `true`).
+    pub fn has_header(mut self, has_header: bool) -> Self {
+        self.has_header = has_header;
+        self
+    }
+    /// Field delimiter (default `,`).
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+    /// Columns to read coordinates from (default the first `DIMS` columns). Exactly `DIMS`
+    /// columns must be given.
+    pub fn columns(mut self, columns: Columns) -> Self {
+        self.columns = columns;
+        self
+    }
+}
+
+/// Reads points from CSV data in `reader`. Each record yields one point (or an error, if its
+/// coordinates are missing or not valid numbers); reading can continue past invalid records.
+///
+/// If the selected columns can't be resolved (e.g. unknown column names), the iterator yields a
+/// single error instead.
+pub fn points_from_csv<R: Read, const DIMS: usize>(
+    reader: R,
+    options: CsvOptions,
+) -> impl Iterator<Item = Result<Point<DIMS>>> {
+    match records(reader, options) {
+        Ok(points) => Either::Left(points),
+        Err(err) => Either::Right(std::iter::once(Err(err))),
+    }
+}
+
+fn records<R: Read, const DIMS: usize>(
+    reader: R,
+    options: CsvOptions,
+) -> Result<impl Iterator<Item = Result<Point<DIMS>>>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(options.has_header)
+        .delimiter(options.delimiter)
+        .flexible(true)
+        .from_reader(reader);
+    let indices = match options.columns {
+        Columns::First => (0..DIMS).collect::<Vec<_>>(),
+        Columns::Indices(indices) => indices,
+        Columns::Names(names) => {
+            if !options.has_header {
+                return Err(IoError::MissingHeader);
+            }
+            let headers = reader.headers()?;
+            names
+                .into_iter()
+                .map(|name| {
+                    headers
+                        .iter()
+                        .position(|header| header.trim() == name)
+                        .ok_or(IoError::UnknownColumn(name))
+                })
+                .collect::<Result<Vec<_>>>()?
+        }
+    };
+    if indices.len() != DIMS {
+        return Err(IoError::ColumnCount {
+            line: 0,
+            expected: DIMS,
+            found: indices.len(),
+        });
+    }
+
+    Ok(reader.into_records().map(move |record| {
+        let record = record?;
+        let line = record.position().map_or(0, |position| position.line());
+        let mut coords = [0.0; DIMS];
+        for (coord, &column) in coords.iter_mut().zip(&indices) {
+            let value = record.get(column).ok_or(IoError::ColumnCount {
+                line,
+                expected: column + 1,
+                found: record.len(),
+            })?;
+            *coord = value
+                .trim()
+                .parse::<Scalar>()
+                .map_err(|_| IoError::InvalidNumber {
+                    line,
+                    column,
+                    value: value.to_string(),
+                })?;
+        }
+        Ok(Point::from_arr(coords))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &str = "label,x,y\na,1.0,2.0\nb, 3.5 ,-4\nc,oops,1\n";
+
+    #[test]
+    fn read_csv() {
+        let points = points_from_csv::<_, 2>(
+            DATA.as_bytes(),
+            CsvOptions::default().columns(Columns::Names(vec!["x".into(), "y".into()])),
+        )
+        .collect::<Vec<_>>();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].as_ref().unwrap(), &Point::from_arr([1.0, 2.0]));
+        assert_eq!(points[1].as_ref().unwrap(), &Point::from_arr([3.5, -4.0]));
+        assert!(matches!(
+            points[2],
+            Err(IoError::InvalidNumber {
+                line: 4,
+                column: 1,
+                ..
+            })
+        ));
+
+        let points = points_from_csv::<_, 2>(
+            "1;2;3\n4;5;6\n".as_bytes(),
+            CsvOptions::default()
+                .has_header(false)
+                .delimiter(b';')
+                .columns(Columns::Indices(vec![2, 0])),
+        )
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+        assert_eq!(
+            points,
+            vec![Point::from_arr([3.0, 1.0]), Point::from_arr([6.0, 4.0])]
+        );
+
+        let mut points = points_from_csv::<_, 2>(
+            DATA.as_bytes(),
+            CsvOptions::default().columns(Columns::Names(vec!["x".into(), "z".into()])),
+        );
+        assert!(matches!(points.next(), Some(Err(IoError::UnknownColumn(name))) if name == "z"));
+        assert!(points.next().is_none());
+        assert!(matches!(
+            points_from_csv::<_, 3>("1,2\n".as_bytes(), CsvOptions::default().has_header(false))
+                .next(),
+            Some(Err(IoError::ColumnCount { line: 1, .. }))
+        ));
+    }
+}
@@ -0,0 +1,405 @@
+/*!
+ * Global clustering of the leaf clusters of a tree into `k` clusters with k-means, and selection
+ * of `k`.
+ *
+ * [kmeans] runs a weighted k-means over the leaf clusters, each standing in for the points it
+ * summarizes (at its center, weighted by its size), so it costs the same however many points the
+ * tree was built from. Its [inertia](LeafKMeans::inertia) is nevertheless exactly the sum of
+ * squared distances of the summarized points from the centers of their clusters, as long as each
+ * leaf cluster is kept whole.
+ *
+ * [elbow] and [gap_statistic] run it for a range of `k` and recommend one, using the
+ * [elbow method](https://en.wikipedia.org/wiki/Elbow_method_(clustering)) and the
+ * [gap statistic](https://doi.org/10.1111/1467-9868.00293) respectively.
+ */
+
+use alloc::{vec, vec::Vec};
+use core::ops::RangeInclusive;
+
+use crate::{
+    cfeature::CFeature,
+    cftree::CFTree,
+    point::{Float, Point, Scalar},
+    rng::SplitMix64,
+    summary::collect_leaves,
+};
+
+/// Maximum number of Lloyd iterations of each k-means run.
+const MAX_ITERATIONS: usize = 100;
+
+/// Result of clustering the leaf clusters of a tree with [kmeans].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeafKMeans<const DIMS: usize> {
+    pub centers: Vec<Point<DIMS>>,
+    /// Cluster of each leaf cluster, indexed by
+    /// [ClusterSummary::id](crate::summary::ClusterSummary::id).
+    pub labels: Vec<usize>,
+    /// Sum of squared distances of the summarized points from the centers of their clusters.
+    pub inertia: Scalar,
+}
+
+/// Leaf clusters of `tree` as points weighted by their size, along with the sum of squared
+/// distances of their summarized points from their centers.
+fn weighted_leaves<CF, TC, const DIMS: usize>(
+    tree: &CFTree<CF, DIMS, TC>,
+) -> (Vec<(Point<DIMS>, Scalar)>, Scalar)
+where
+    CF: CFeature<DIMS>,
+{
+    let mut leaves = vec![];
+    collect_leaves(tree.root(), &mut leaves);
+    let within = leaves
+        .iter()
+        .map(|feature| (feature.size() * feature.radius2()).to_scalar())
+        .sum();
+    let points = leaves
+        .into_iter()
+        .map(|feature| (feature.center().cast(), feature.size().to_scalar()))
+        .collect();
+    (points, within)
+}
+
+/// Clusters the leaf clusters of `tree` into (at most) `k` clusters, starting from centers chosen
+/// by k-means++ with a generator seeded by `seed`. Returns fewer clusters if the tree has fewer
+/// than `k` leaf clusters.
+pub fn kmeans<CF, TC, const DIMS: usize>(
+    tree: &CFTree<CF, DIMS, TC>,
+    k: usize,
+    seed: u64,
+) -> LeafKMeans<DIMS>
+where
+    CF: CFeature<DIMS>,
+{
+    let (points, within) = weighted_leaves(tree);
+    let (centers, labels, between) = weighted_kmeans(&points, k, &mut SplitMix64(seed));
+    LeafKMeans {
+        centers,
+        labels,
+        inertia: within + between,
+    }
+}
+
+/// Weighted k-means of `points`, returning the centers, the cluster of each point, and the
+/// weighted sum of squared distances of the points from the centers of their clusters.
+fn weighted_kmeans<const DIMS: usize>(
+    points: &[(Point<DIMS>, Scalar)],
+    k: usize,
+    rng: &mut SplitMix64,
+) -> (Vec<Point<DIMS>>, Vec<usize>, Scalar) {
+    let k = k.min(points.len());
+    if k == 0 {
+        return (vec![], vec![0; points.len()], 0.0);
+    }
+    let closest = |centers: &[Point<DIMS>], p: &Point<DIMS>| {
+        centers
+            .iter()
+            .map(|center| (center - p).norm2())
+            .enumerate()
+            .fold((0, Scalar::INFINITY), |closest, (idx, d2)| {
+                match d2 < closest.1 {
+                    true => (idx, d2),
+                    false => closest,
+                }
+            })
+    };
+    // draws the index of a point with probability proportional to `weight`
+    let mut draw = |weight: &dyn Fn(usize) -> Scalar| {
+        let total = (0..points.len()).map(weight).sum::<Scalar>();
+        let mut target = rng.uniform() * total;
+        for idx in 0..points.len() {
+            target -= weight(idx);
+            if target < 0.0 {
+                return idx;
+            }
+        }
+        points.len() - 1
+    };
+
+    // k-means++ initialization
+    let mut centers = vec![points[draw(&|idx| points[idx].1)].0.clone()];
+    while centers.len() < k {
+        let weight = |idx: usize| {
+            let (p, size) = &points[idx];
+            size * closest(&centers, p).1
+        };
+        let next = match (0..points.len()).map(weight).sum::<Scalar>() > 0.0 {
+            true => draw(&weight),
+            // every point coincides with a center already
+            false => break,
+        };
+        centers.push(points[next].0.clone());
+    }
+
+    let mut labels = vec![usize::MAX; points.len()];
+    for _ in 0..MAX_ITERATIONS {
+        let mut changed = false;
+        for ((p, _), label) in points.iter().zip(labels.iter_mut()) {
+            let (closest, _) = closest(&centers, p);
+            changed |= *label != closest;
+            *label = closest;
+        }
+        if !changed {
+            break;
+        }
+        let mut sums = vec![(Point::<DIMS>::default(), 0.0); centers.len()];
+        for ((p, size), &label) in points.iter().zip(&labels) {
+            sums[label].0 += p * *size;
+            sums[label].1 += size;
+        }
+        for (center, (sum, size)) in centers.iter_mut().zip(sums) {
+            // clusters left empty keep their previous center
+            if size > 0.0 {
+                *center = sum / size;
+            }
+        }
+    }
+    let inertia = points
+        .iter()
+        .zip(&labels)
+        .map(|((p, size), &label)| size * (p - &centers[label]).norm2())
+        .sum();
+    (centers, labels, inertia)
+}
+
+/// Inertias of k-means over the leaf clusters of a tree for a range of `k`, with a recommended
+/// `k`. Created by [elbow].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Elbow {
+    pub ks: Vec<usize>,
+    /// [Inertia](LeafKMeans::inertia) for each `k` of `ks`.
+    pub inertias: Vec<Scalar>,
+    /// The `k` at the elbow of the inertia curve: the one farthest below the straight line
+    /// between the first and last inertias (after scaling both axes to `[0, 1]`), or `None` if
+    /// `ks` or the tree is empty.
+    pub recommended: Option<usize>,
+}
+
+/// Runs [kmeans] over the leaf clusters of `tree` for every `k` of `ks` (with generators seeded by
+/// `seed`), and recommends the `k` at the elbow of the inertia curve.
+pub fn elbow<CF, TC, const DIMS: usize>(
+    tree: &CFTree<CF, DIMS, TC>,
+    ks: RangeInclusive<usize>,
+    seed: u64,
+) -> Elbow
+where
+    CF: CFeature<DIMS>,
+{
+    let ks = ks.collect::<Vec<_>>();
+    let inertias = ks
+        .iter()
+        .map(|&k| kmeans(tree, k, seed).inertia)
+        .collect::<Vec<_>>();
+    let (first, last) = match (ks.first(), ks.last()) {
+        (Some(&first), Some(&last)) if !tree.is_empty() => (first, last),
+        _ => {
+            return Elbow {
+                ks,
+                inertias,
+                recommended: None,
+            }
+        }
+    };
+    let (high, low) = (inertias[0], inertias[inertias.len() - 1]);
+    let scale = |value: Scalar, from: Scalar, to: Scalar| match to != from {
+        true => (value - from) / (to - from),
+        false => 0.0,
+    };
+    let recommended = ks
+        .iter()
+        .zip(&inertias)
+        .map(|(&k, &inertia)| {
+            let x = scale(k as Scalar, first as Scalar, last as Scalar);
+            // height of the line from (0, 1) to (1, 0) above the (scaled) inertia
+            (k, (1.0 - x) - scale(inertia, low, high))
+        })
+        .fold(
+            None,
+            |best: Option<(usize, Scalar)>, (k, depth)| match best {
+                Some((_, best_depth)) if best_depth >= depth => best,
+                _ => Some((k, depth)),
+            },
+        )
+        .map(|(k, _)| k);
+    Elbow {
+        ks,
+        inertias,
+        recommended,
+    }
+}
+
+/// Gap statistics of k-means over the leaf clusters of a tree for a range of `k`, with a
+/// recommended `k`. Created by [gap_statistic].
+#[derive(Debug, Clone, PartialEq)]
+pub struct GapStatistic {
+    pub ks: Vec<usize>,
+    /// Gap for each `k` of `ks`: how much smaller the (log) inertia of the leaf clusters is than
+    /// that expected of uniformly spread reference data.
+    pub gaps: Vec<Scalar>,
+    /// Standard error of the reference (log) inertias for each `k` of `ks`, adjusted for the
+    /// number of reference data sets.
+    pub errors: Vec<Scalar>,
+    /// The smallest `k` whose gap is at least the gap of the next `k` minus its error (or the
+    /// largest `k`, if there is none), or `None` if `ks` or the tree is empty.
+    pub recommended: Option<usize>,
+}
+
+/// Computes the gap statistic of [kmeans] over the leaf clusters of `tree` for every `k` of `ks`,
+/// and recommends a `k`. The reference data sets (`references` of them) each place as many points
+/// as the tree has leaf clusters, with the same weights, uniformly within the bounding box of the
+/// leaf cluster centers, drawing from a generator seeded by `seed`.
+///
+/// Inertias are those of the leaf cluster centers (i.e. without the spread of the points within
+/// each leaf cluster, which reference points don't have), so the gap statistic compares like with
+/// like.
+pub fn gap_statistic<CF, TC, const DIMS: usize>(
+    tree: &CFTree<CF, DIMS, TC>,
+    ks: RangeInclusive<usize>,
+    references: usize,
+    seed: u64,
+) -> GapStatistic
+where
+    CF: CFeature<DIMS>,
+{
+    let (points, _) = weighted_leaves(tree);
+    let ks = ks.collect::<Vec<_>>();
+    let mut rng = SplitMix64(seed);
+    let log_inertia = |points: &[(Point<DIMS>, Scalar)], k: usize, rng: &mut SplitMix64| {
+        let (_, _, inertia) = weighted_kmeans(points, k, rng);
+        num_traits::Float::ln(inertia.max(Scalar::MIN_POSITIVE))
+    };
+
+    let mut low = Point::<DIMS>::from_arr([Scalar::INFINITY; DIMS]);
+    let mut high = Point::<DIMS>::from_arr([Scalar::NEG_INFINITY; DIMS]);
+    for (p, _) in &points {
+        for d in 0..DIMS {
+            low[d] = low[d].min(p[d]);
+            high[d] = high[d].max(p[d]);
+        }
+    }
+    let reference_sets = (0..references)
+        .map(|_| {
+            points
+                .iter()
+                .map(|(_, size)| {
+                    let mut p = low.clone();
+                    for d in 0..DIMS {
+                        p[d] += rng.uniform() * (high[d] - low[d]);
+                    }
+                    (p, *size)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut gaps = vec![];
+    let mut errors = vec![];
+    for &k in &ks {
+        let observed = log_inertia(&points, k, &mut rng);
+        let expected = reference_sets
+            .iter()
+            .map(|reference| log_inertia(reference, k, &mut rng))
+            .collect::<Vec<_>>();
+        let count = expected.len().max(1) as Scalar;
+        let mean = expected.iter().sum::<Scalar>() / count;
+        let variance = expected
+            .iter()
+            .map(|e| (e - mean) * (e - mean))
+            .sum::<Scalar>()
+            / count;
+        gaps.push(mean - observed);
+        errors.push(num_traits::Float::sqrt(variance * (1.0 + 1.0 / count)));
+    }
+    let recommended = match points.is_empty() {
+        true => None,
+        false => (0..ks.len())
+            .find(|&i| i + 1 == ks.len() || gaps[i] >= gaps[i + 1] - errors[i + 1])
+            .map(|i| ks[i]),
+    };
+    GapStatistic {
+        ks,
+        gaps,
+        errors,
+        recommended,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cftree::{BasicConfig, BirchCFTree};
+
+    #[test]
+    fn kmeans() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.05)
+            .build()
+            .unwrap();
+        // four blobs of 100 points each, at the corners of a square
+        let points = (0..400)
+            .map(|i| {
+                let (x, y) = ((i % 2) as f64 * 20.0, (i / 2 % 2) as f64 * 20.0);
+                let angle = i as f64 * 0.7;
+                let radius = (i / 4 % 5) as f64 * 0.3;
+                Point::from_arr([x + radius * angle.cos(), y + radius * angle.sin()])
+            })
+            .collect::<Vec<_>>();
+        let tree = BirchCFTree::<2>::from_iter(points.clone(), config);
+        let leaves = tree.clusters().collect::<Vec<_>>();
+        assert!(leaves.len() > 8);
+
+        let result = super::kmeans(&tree, 4, 1);
+        assert_eq!(result.centers.len(), 4);
+        assert_eq!(result.labels.len(), leaves.len());
+        // every blob is a cluster of its own
+        for (leaf, &label) in leaves.iter().zip(&result.labels) {
+            assert!((&result.centers[label] - &leaf.center).norm2() < 4.0);
+        }
+        let mut corners = result
+            .centers
+            .iter()
+            .map(|c| {
+                (
+                    (c[0] / 20.0).round() as usize,
+                    (c[1] / 20.0).round() as usize,
+                )
+            })
+            .collect::<Vec<_>>();
+        corners.sort();
+        assert_eq!(corners, vec![(0, 0), (0, 1), (1, 0), (1, 1)]);
+        // the inertia is that of the points themselves
+        let inertia = points
+            .iter()
+            .map(|p| {
+                result
+                    .centers
+                    .iter()
+                    .map(|c| (c - p).norm2())
+                    .fold(Scalar::INFINITY, Scalar::min)
+            })
+            .sum::<Scalar>();
+        assert!((result.inertia - inertia).abs() < 1e-6 * inertia);
+
+        let by_elbow = elbow(&tree, 1..=8, 1);
+        assert_eq!(by_elbow.ks, (1..=8).collect::<Vec<_>>());
+        assert!(by_elbow.inertias.windows(2).all(|w| w[1] <= w[0] + 1e-9));
+        assert_eq!(by_elbow.recommended, Some(4));
+
+        let by_gap = gap_statistic(&tree, 1..=8, 10, 1);
+        assert_eq!(by_gap.gaps.len(), 8);
+        assert_eq!(by_gap.recommended, Some(4));
+
+        let empty = BirchCFTree::<2>::new(
+            BasicConfig::builder()
+                .capacity(2, 4)
+                .threshold(1.0)
+                .build()
+                .unwrap(),
+        );
+        assert!(super::kmeans(&empty, 3, 1).centers.is_empty());
+        let by_elbow = elbow(&empty, 1..=3, 1);
+        assert!(by_elbow.inertias.iter().all(|&inertia| inertia == 0.0));
+        assert_eq!(by_elbow.recommended, None);
+        assert_eq!(gap_statistic(&empty, 1..=3, 2, 1).recommended, None);
+    }
+}
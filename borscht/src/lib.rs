@@ -6,9 +6,93 @@
  *
  * Includes modifications to the original BIRCH algorithm; currently only
  * [BETULA](https://arxiv.org/abs/2006.12881), but with more planned.
+ *
+ * The core of the crate (points, cluster features, trees and queries over them) is `no_std`
+ * compatible, requiring only `alloc`: disable the default `std` feature to build it for embedded
+ * targets, e.g. `cargo build --no-default-features --target thumbv7em-none-eabihf`.
  */
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[macro_use]
+mod instrument;
+mod rng;
+
+pub mod anomaly;
+pub mod applications;
+pub mod arena;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod bench;
+pub mod birch;
+pub mod bulk;
 pub mod cfeature;
 pub mod cftree;
+pub mod compare;
+#[cfg(feature = "std")]
+pub mod concurrent;
+pub mod confidence;
+pub mod constraints;
+#[cfg(feature = "std")]
 pub mod display;
+pub mod drift;
+#[cfg(feature = "std")]
+pub mod dynamic;
+#[cfg(feature = "std")]
+pub mod ensemble;
+pub mod error;
+pub mod explain;
+#[cfg(feature = "std")]
+pub mod export;
+#[cfg(feature = "std")]
+pub mod fading;
+pub mod flatten;
+#[cfg(any(feature = "json", feature = "msgpack", feature = "bincode"))]
+pub mod formats;
+pub mod geo;
+pub mod identity;
+pub mod importance;
+#[cfg(feature = "std")]
+pub mod insertion_log;
+#[cfg(feature = "std")]
+pub mod io;
+pub mod kmeans;
+#[cfg(feature = "linfa")]
+pub mod linfa;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "nalgebra")]
+pub mod nalgebra;
+#[cfg(feature = "ndarray")]
+pub mod ndarray;
+pub mod offline;
+#[cfg(feature = "fs")]
+pub mod paged;
+#[cfg(feature = "std")]
+pub mod persist;
 pub mod point;
+pub mod preprocess;
+pub mod projection;
+pub mod quantiles;
+pub mod query;
+pub mod reservoir;
+pub mod soft;
+pub mod sparse;
+pub mod split;
+pub mod stability;
+pub mod standardized;
+#[cfg(feature = "async")]
+pub mod stream;
+pub mod subtree;
+pub mod summary;
+pub mod surrogate;
+pub mod trace;
+pub mod traverse;
+pub mod tune;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod window;
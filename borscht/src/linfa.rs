@@ -0,0 +1,230 @@
+/*!
+ * Integration with the [linfa](https://docs.rs/linfa) machine learning toolkit. Requires the
+ * `linfa` feature.
+ *
+ * [BirchParams] implements linfa's [Fit] (and [FitWith], for incremental fitting) over datasets of
+ * sample matrices (one sample per row, as in [crate::ndarray]), producing a [Birch] estimator,
+ * which implements [PredictInplace] to label the rows of a sample matrix with their final cluster
+ * (or `None` for noise, as linfa's DBSCAN does). This lets borscht take the place of other linfa
+ * clustering algorithms in existing pipelines.
+ *
+ * [Birch] has an inherent `predict` method over points, which takes precedence over linfa's
+ * [Predict](linfa::traits::Predict) in method calls; call the latter as
+ * `Predict::predict(&birch, &records)`.
+ */
+
+use std::fmt::Debug;
+
+use ::linfa::{
+    traits::{Fit, FitWith, PredictInplace},
+    DatasetBase,
+};
+use ::ndarray::{Array1, ArrayBase, Data, Ix2};
+use thiserror::Error;
+
+use crate::{
+    birch::{Birch, GlobalClustering},
+    cfeature::CFeature,
+    cftree::BasicConfig,
+    point::Point,
+};
+
+#[derive(Error, Debug)]
+pub enum LinfaError {
+    #[error("linfa error")]
+    Linfa(#[from] ::linfa::Error),
+    #[error("expected {expected} columns, found {found}")]
+    ColumnCount { expected: usize, found: usize },
+}
+
+/// Hyperparameters of a [Birch] estimator, which fit one to linfa datasets. See [Birch::new],
+/// [Birch::max_leaves] and [Birch::global_clustering] for their meaning.
+#[derive(Debug, Clone)]
+pub struct BirchParams<CF: CFeature<DIMS>, const DIMS: usize> {
+    config: BasicConfig,
+    max_leaves: Option<usize>,
+    global: GlobalClustering<CF::Scalar>,
+}
+
+impl<CF: CFeature<DIMS>, const DIMS: usize> BirchParams<CF, DIMS> {
+    pub fn new(config: BasicConfig) -> BirchParams<CF, DIMS> {
+        BirchParams {
+            config,
+            max_leaves: None,
+            global: GlobalClustering::None,
+        }
+    }
+
+    pub fn max_leaves(mut self, max_leaves: usize) -> Self {
+        self.max_leaves = Some(max_leaves);
+        self
+    }
+
+    pub fn global_clustering(mut self, global: GlobalClustering<CF::Scalar>) -> Self {
+        self.global = global;
+        self
+    }
+
+    /// An unfitted estimator with these hyperparameters.
+    fn estimator(&self) -> Birch<CF, DIMS>
+    where
+        CF: Debug + Clone,
+    {
+        let birch = Birch::new(self.config.clone()).global_clustering(self.global);
+        match self.max_leaves {
+            Some(max_leaves) => birch.max_leaves(max_leaves),
+            None => birch,
+        }
+    }
+}
+
+/// Checks that the sample matrix `records` has one column per dimension.
+fn check_columns<S: Data, const DIMS: usize>(
+    records: &ArrayBase<S, Ix2>,
+) -> Result<(), LinfaError> {
+    match records.ncols() {
+        found if found == DIMS => Ok(()),
+        found => Err(LinfaError::ColumnCount {
+            expected: DIMS,
+            found,
+        }),
+    }
+}
+
+impl<CF, S, T, const DIMS: usize> Fit<ArrayBase<S, Ix2>, T, LinfaError> for BirchParams<CF, DIMS>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    S: Data<Elem = CF::Scalar>,
+{
+    type Object = Birch<CF, DIMS>;
+
+    fn fit(
+        &self,
+        dataset: &DatasetBase<ArrayBase<S, Ix2>, T>,
+    ) -> Result<Birch<CF, DIMS>, LinfaError> {
+        check_columns::<_, DIMS>(&dataset.records)?;
+        let mut birch = self.estimator();
+        birch.fit(dataset.records.rows().into_iter().map(Point::from));
+        Ok(birch)
+    }
+}
+
+impl<'a, CF, S, T, const DIMS: usize> FitWith<'a, ArrayBase<S, Ix2>, T, LinfaError>
+    for BirchParams<CF, DIMS>
+where
+    CF: CFeature<DIMS> + Debug + Clone + 'a,
+    S: Data<Elem = CF::Scalar>,
+{
+    /// Estimator to keep fitting, or `None` to start a new one.
+    type ObjectIn = Option<Birch<CF, DIMS>>;
+    type ObjectOut = Birch<CF, DIMS>;
+
+    fn fit_with(
+        &self,
+        model: Option<Birch<CF, DIMS>>,
+        dataset: &'a DatasetBase<ArrayBase<S, Ix2>, T>,
+    ) -> Result<Birch<CF, DIMS>, LinfaError> {
+        check_columns::<_, DIMS>(&dataset.records)?;
+        let mut birch = model.unwrap_or_else(|| self.estimator());
+        birch.partial_fit(dataset.records.rows().into_iter().map(Point::from));
+        Ok(birch)
+    }
+}
+
+impl<CF, S, const DIMS: usize> PredictInplace<ArrayBase<S, Ix2>, Array1<Option<usize>>>
+    for Birch<CF, DIMS>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    S: Data<Elem = CF::Scalar>,
+{
+    /// Labels each row of `records` with its final cluster (see [Birch::predict]).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `records` does not have `DIMS` columns, or `targets` has a different number of
+    /// rows.
+    fn predict_inplace(&self, records: &ArrayBase<S, Ix2>, targets: &mut Array1<Option<usize>>) {
+        assert_eq!(
+            records.ncols(),
+            DIMS,
+            "sample matrix must have one column per dimension"
+        );
+        assert_eq!(
+            records.nrows(),
+            targets.len(),
+            "targets must have one row per sample"
+        );
+        let points = records
+            .rows()
+            .into_iter()
+            .map(Point::from)
+            .collect::<Vec<_>>();
+        for (target, label) in targets.iter_mut().zip(self.predict(&points)) {
+            *target = label;
+        }
+    }
+
+    fn default_target(&self, records: &ArrayBase<S, Ix2>) -> Array1<Option<usize>> {
+        Array1::from_elem(records.nrows(), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::linfa::{traits::Predict, Dataset};
+    use ::ndarray::Array2;
+
+    use super::*;
+    use crate::cfeature::birch::CFeature as BirchCF;
+
+    #[test]
+    fn linfa() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        // three groups of nearby points
+        let samples = Array2::from_shape_fn((60, 2), |(row, col)| {
+            ((row % 3) * 10 * (col + 1)) as f64 + (row / 3) as f64 * 0.01
+        });
+        let dataset = Dataset::from(samples.clone());
+        let params = BirchParams::<BirchCF<2>, 2>::new(config).global_clustering(
+            GlobalClustering::Density {
+                eps: 1.0,
+                min_weight: 5.0,
+            },
+        );
+
+        let birch = params.fit(&dataset).unwrap();
+        let labels: Array1<Option<usize>> = Predict::predict(&birch, &samples);
+        assert_eq!(labels.len(), 60);
+        assert!(labels.iter().all(Option::is_some));
+        for (row, label) in labels.iter().enumerate() {
+            assert_eq!(*label, labels[row % 3]);
+        }
+        assert_ne!(labels[0], labels[1]);
+        assert_ne!(labels[1], labels[2]);
+
+        // fitting incrementally in two parts finds the same groups
+        let first = Dataset::from(samples.slice(::ndarray::s![..30, ..]).to_owned());
+        let second = Dataset::from(samples.slice(::ndarray::s![30.., ..]).to_owned());
+        let birch = params.fit_with(None, &first).unwrap();
+        let birch = params.fit_with(Some(birch), &second).unwrap();
+        assert_eq!(birch.tree().metrics().inserted, 60);
+        let incremental: Array1<Option<usize>> = Predict::predict(&birch, &samples);
+        assert!(incremental.iter().all(Option::is_some));
+        for (row, label) in incremental.iter().enumerate() {
+            assert_eq!(*label, incremental[row % 3]);
+        }
+
+        let wide = Dataset::from(Array2::<f64>::zeros((4, 3)));
+        assert!(matches!(
+            params.fit(&wide),
+            Err(LinfaError::ColumnCount {
+                expected: 2,
+                found: 3
+            })
+        ));
+    }
+}
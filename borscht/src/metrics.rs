@@ -0,0 +1,225 @@
+/*!
+ * External evaluation of a clustering against ground-truth labels.
+ *
+ * All metrics compare a predicted assignment (e.g. from
+ * [CFTree::labels](crate::cftree::CFTree::labels)) with the true labels of the same points.
+ * Labels of either assignment can be of any hashable type, and need not use the same values; only
+ * which points share a label matters.
+ */
+
+use std::{collections::HashMap, hash::Hash};
+
+use thiserror::Error;
+
+use crate::point::Scalar;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MetricsError {
+    #[error("label count mismatch: {predicted} predicted, {truth} true")]
+    LengthMismatch { predicted: usize, truth: usize },
+    #[error("no labels to compare")]
+    Empty,
+}
+
+type Result<T> = std::result::Result<T, MetricsError>;
+
+/// Contingency table between a predicted and a true assignment.
+struct Contingency {
+    /// Number of points for each (predicted, true) label index pair which occurs.
+    cells: Vec<((usize, usize), usize)>,
+    /// Number of points with each predicted label.
+    predicted: Vec<usize>,
+    /// Number of points with each true label.
+    truth: Vec<usize>,
+    n: usize,
+}
+
+impl Contingency {
+    fn new<P: Eq + Hash, T: Eq + Hash>(predicted: &[P], truth: &[T]) -> Result<Contingency> {
+        if predicted.len() != truth.len() {
+            return Err(MetricsError::LengthMismatch {
+                predicted: predicted.len(),
+                truth: truth.len(),
+            });
+        }
+        if predicted.is_empty() {
+            return Err(MetricsError::Empty);
+        }
+        let (predicted_ids, num_predicted) = index_labels(predicted);
+        let (truth_ids, num_truth) = index_labels(truth);
+        let mut cells = HashMap::new();
+        let mut predicted_counts = vec![0; num_predicted];
+        let mut truth_counts = vec![0; num_truth];
+        for (&p, &t) in predicted_ids.iter().zip(&truth_ids) {
+            *cells.entry((p, t)).or_insert(0) += 1;
+            predicted_counts[p] += 1;
+            truth_counts[t] += 1;
+        }
+        Ok(Contingency {
+            cells: cells.into_iter().collect(),
+            predicted: predicted_counts,
+            truth: truth_counts,
+            n: predicted.len(),
+        })
+    }
+
+    fn mutual_information(&self) -> Scalar {
+        let n = self.n as Scalar;
+        self.cells
+            .iter()
+            .map(|&((p, t), count)| {
+                let count = count as Scalar;
+                let expected = self.predicted[p] as Scalar * self.truth[t] as Scalar;
+                count / n * (count * n / expected).ln()
+            })
+            .sum()
+    }
+}
+
+/// Maps each label to a dense index (in order of first appearance), returning the indices and
+/// the number of distinct labels.
+fn index_labels<L: Eq + Hash>(labels: &[L]) -> (Vec<usize>, usize) {
+    let mut ids = HashMap::new();
+    let indices = labels
+        .iter()
+        .map(|label| {
+            let next = ids.len();
+            *ids.entry(label).or_insert(next)
+        })
+        .collect();
+    (indices, ids.len())
+}
+
+/// Number of unordered pairs among `count` items.
+fn pairs(count: usize) -> Scalar {
+    let count = count as Scalar;
+    count * (count - 1.0) / 2.0
+}
+
+/// Entropy (in nats) of the distribution given by `counts`, which sum to `n`.
+fn entropy(counts: &[usize], n: usize) -> Scalar {
+    let n = n as Scalar;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as Scalar / n;
+            -p * p.ln()
+        })
+        .sum()
+}
+
+/// Adjusted Rand index: agreement between the assignments over all pairs of points, corrected
+/// for chance. 1 for identical assignments, around 0 for random ones (and possibly negative).
+pub fn adjusted_rand_index<P: Eq + Hash, T: Eq + Hash>(
+    predicted: &[P],
+    truth: &[T],
+) -> Result<Scalar> {
+    let table = Contingency::new(predicted, truth)?;
+    let index = table
+        .cells
+        .iter()
+        .map(|&(_, count)| pairs(count))
+        .sum::<Scalar>();
+    let predicted_pairs = table.predicted.iter().map(|&c| pairs(c)).sum::<Scalar>();
+    let truth_pairs = table.truth.iter().map(|&c| pairs(c)).sum::<Scalar>();
+    let expected = predicted_pairs * truth_pairs / pairs(table.n).max(1.0);
+    let max = (predicted_pairs + truth_pairs) / 2.0;
+    if max == expected {
+        // both assignments are trivial (all points together, or all apart)
+        return Ok(1.0);
+    }
+    Ok((index - expected) / (max - expected))
+}
+
+/// Normalized mutual information, using the arithmetic mean of the entropies of the two
+/// assignments as normalization. Between 0 and 1, with 1 for identical assignments.
+pub fn normalized_mutual_info<P: Eq + Hash, T: Eq + Hash>(
+    predicted: &[P],
+    truth: &[T],
+) -> Result<Scalar> {
+    let table = Contingency::new(predicted, truth)?;
+    let mean_entropy = (entropy(&table.predicted, table.n) + entropy(&table.truth, table.n)) / 2.0;
+    if mean_entropy <= 0.0 {
+        return Ok(1.0);
+    }
+    Ok((table.mutual_information() / mean_entropy).clamp(0.0, 1.0))
+}
+
+/// Homogeneity: 1 if every predicted cluster only contains points of a single true class.
+pub fn homogeneity<P: Eq + Hash, T: Eq + Hash>(predicted: &[P], truth: &[T]) -> Result<Scalar> {
+    let table = Contingency::new(predicted, truth)?;
+    let truth_entropy = entropy(&table.truth, table.n);
+    if truth_entropy <= 0.0 {
+        return Ok(1.0);
+    }
+    Ok((table.mutual_information() / truth_entropy).clamp(0.0, 1.0))
+}
+
+/// Completeness: 1 if all points of each true class are in the same predicted cluster.
+pub fn completeness<P: Eq + Hash, T: Eq + Hash>(predicted: &[P], truth: &[T]) -> Result<Scalar> {
+    homogeneity(truth, predicted)
+}
+
+/// Purity: the fraction of points belonging to the majority true class of their predicted
+/// cluster.
+pub fn purity<P: Eq + Hash, T: Eq + Hash>(predicted: &[P], truth: &[T]) -> Result<Scalar> {
+    let table = Contingency::new(predicted, truth)?;
+    let mut majority = vec![0; table.predicted.len()];
+    for &((p, _), count) in &table.cells {
+        majority[p] = majority[p].max(count);
+    }
+    Ok(majority.iter().sum::<usize>() as Scalar / table.n as Scalar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(left: Scalar, right: Scalar) -> bool {
+        (left - right).abs() < 1e-9
+    }
+
+    #[test]
+    fn metrics() {
+        let truth = [0, 0, 0, 1, 1, 1];
+
+        // identical up to renaming
+        let predicted = ["b", "b", "b", "a", "a", "a"];
+        assert!(close(adjusted_rand_index(&predicted, &truth).unwrap(), 1.0));
+        assert!(close(
+            normalized_mutual_info(&predicted, &truth).unwrap(),
+            1.0
+        ));
+        assert!(close(homogeneity(&predicted, &truth).unwrap(), 1.0));
+        assert!(close(completeness(&predicted, &truth).unwrap(), 1.0));
+        assert!(close(purity(&predicted, &truth).unwrap(), 1.0));
+
+        // every class split in two: homogeneous, but not complete
+        let predicted = [0, 0, 1, 2, 2, 3];
+        assert!(close(homogeneity(&predicted, &truth).unwrap(), 1.0));
+        assert!(completeness(&predicted, &truth).unwrap() < 1.0);
+        assert!(close(purity(&predicted, &truth).unwrap(), 1.0));
+
+        // reference values from scikit-learn
+        let predicted = [0, 0, 1, 1, 2, 2];
+        assert!(close(
+            adjusted_rand_index(&predicted, &truth).unwrap(),
+            0.24242424242424246
+        ));
+        assert!(close(
+            normalized_mutual_info(&predicted, &truth).unwrap(),
+            0.5158037429793888
+        ));
+        assert!(close(purity(&predicted, &truth).unwrap(), 5.0 / 6.0));
+
+        assert_eq!(
+            purity(&[0, 1], &[0]),
+            Err(MetricsError::LengthMismatch {
+                predicted: 2,
+                truth: 1
+            })
+        );
+        assert_eq!(purity::<usize, usize>(&[], &[]), Err(MetricsError::Empty));
+    }
+}
@@ -0,0 +1,84 @@
+/*!
+ * Interoperability with [nalgebra](https://nalgebra.org/) vectors and matrices. Requires the
+ * `nalgebra` feature.
+ *
+ * Sample matrices are laid out with one sample per row and one dimension per column.
+ */
+
+use std::fmt::Debug;
+
+use ::nalgebra::{DMatrix, SVector};
+
+use crate::{
+    cfeature::CFeature,
+    cftree::{CFTree, TreeConfig},
+    error::{BorschtError, Result},
+    point::{Float, Point},
+};
+
+impl<T: Float, const DIMS: usize> From<SVector<T, DIMS>> for Point<DIMS, T> {
+    fn from(vector: SVector<T, DIMS>) -> Point<DIMS, T> {
+        Point::from_arr(vector.into())
+    }
+}
+
+impl<T: Float, const DIMS: usize> From<Point<DIMS, T>> for SVector<T, DIMS> {
+    fn from(point: Point<DIMS, T>) -> SVector<T, DIMS> {
+        SVector::from_column_slice(point.as_slice())
+    }
+}
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    TC: TreeConfig,
+{
+    /// Builds a tree from the rows of a sample matrix, which must have `DIMS` columns.
+    pub fn from_dmatrix(samples: &DMatrix<CF::Scalar>, config: TC) -> Result<CFTree<CF, DIMS, TC>> {
+        BorschtError::check_dims(DIMS, samples.ncols())?;
+        Ok(CFTree::from_iter(
+            samples.row_iter().map(|row| {
+                let mut coords = [CF::Scalar::default(); DIMS];
+                for (coord, &value) in coords.iter_mut().zip(row.iter()) {
+                    *coord = value;
+                }
+                Point::from_arr(coords)
+            }),
+            config,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::nalgebra::{DMatrix, SVector, Vector3};
+
+    use super::*;
+    use crate::cftree::{BasicConfig, BetulaCFTree};
+
+    #[test]
+    fn nalgebra() {
+        let vector = Vector3::new(1.0, 2.0, 3.0);
+        let point = Point::from(vector);
+        assert_eq!(point, Point::from_arr([1.0, 2.0, 3.0]));
+        assert_eq!(SVector::from(point), vector);
+
+        let config = BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let samples = DMatrix::from_row_slice(4, 2, &[0.0, 0.0, 0.1, 0.0, 10.0, 10.0, 10.0, 10.1]);
+        assert!(matches!(
+            BetulaCFTree::<3>::from_dmatrix(&samples, config.clone()),
+            Err(BorschtError::DimensionMismatch {
+                expected: 3,
+                found: 2
+            })
+        ));
+        let tree = BetulaCFTree::<2>::from_dmatrix(&samples, config).unwrap();
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.len(), 2);
+        assert!(clusters.iter().all(|c| c.size == 2.0));
+    }
+}
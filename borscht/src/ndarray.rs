@@ -0,0 +1,97 @@
+/*!
+ * Interoperability with [ndarray](https://docs.rs/ndarray) arrays. Requires the `ndarray`
+ * feature.
+ *
+ * Sample matrices are laid out with one sample per row and one dimension per column.
+ */
+
+use std::fmt::Debug;
+
+use ::ndarray::{Array2, ArrayView1, AsArray, Ix2};
+
+use crate::{
+    cfeature::CFeature,
+    cftree::{CFTree, TreeConfig},
+    error::{BorschtError, Result},
+    point::{Float, Point},
+};
+
+impl<'a, T: Float, const DIMS: usize> From<ArrayView1<'a, T>> for Point<DIMS, T> {
+    /// Converts a one-dimensional array view into a point.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `view` is not `DIMS`.
+    fn from(view: ArrayView1<'a, T>) -> Point<DIMS, T> {
+        assert_eq!(view.len(), DIMS, "array length must match point dimension");
+        let mut coords = [T::zero(); DIMS];
+        for (coord, &value) in coords.iter_mut().zip(view.iter()) {
+            *coord = value;
+        }
+        Point::from_arr(coords)
+    }
+}
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    TC: TreeConfig,
+{
+    /// Builds a tree from the rows of a sample matrix, which must have `DIMS` columns.
+    pub fn from_ndarray<'a, V>(samples: V, config: TC) -> Result<CFTree<CF, DIMS, TC>>
+    where
+        V: AsArray<'a, CF::Scalar, Ix2>,
+    {
+        let samples = samples.into();
+        BorschtError::check_dims(DIMS, samples.ncols())?;
+        Ok(CFTree::from_iter(
+            samples.rows().into_iter().map(Point::from),
+            config,
+        ))
+    }
+}
+
+impl<CF: CFeature<DIMS>, TC, const DIMS: usize> CFTree<CF, DIMS, TC> {
+    /// Centers of the leaf clusters of this tree, one per row (in order of cluster id).
+    pub fn centers_ndarray(&self) -> Array2<CF::Scalar> {
+        let centers = self.clusters().map(|c| c.center).collect::<Vec<_>>();
+        Array2::from_shape_fn((centers.len(), DIMS), |(row, col)| centers[row][col])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::ndarray::{array, Array2};
+
+    use super::*;
+    use crate::cftree::{BasicConfig, BirchCFTree};
+
+    #[test]
+    fn ndarray() {
+        let row = array![1.0, 2.0];
+        assert_eq!(Point::<2>::from(row.view()), Point::from_arr([1.0, 2.0]));
+
+        let config = BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let samples = Array2::from_shape_fn((6, 2), |(row, col)| {
+            ((row / 2) * 10 + col) as f64 + 0.01 * (row % 2) as f64
+        });
+        assert_eq!(
+            BirchCFTree::<3>::from_ndarray(&samples, config.clone()).unwrap_err(),
+            BorschtError::DimensionMismatch {
+                expected: 3,
+                found: 2
+            }
+        );
+        let tree = BirchCFTree::<2>::from_ndarray(&samples, config).unwrap();
+        let centers = tree.centers_ndarray();
+        assert_eq!(centers.dim(), (3, 2));
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        for (row, cluster) in centers.rows().into_iter().zip(&clusters) {
+            assert_eq!(Point::<2>::from(row), cluster.center);
+        }
+    }
+}
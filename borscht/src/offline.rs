@@ -0,0 +1,124 @@
+/*!
+ * Offline (macro) clustering of the leaf clusters of a tree, in the style of DenStream.
+ *
+ * The tree maintains fine-grained micro-clusters online; on demand, a density-based grouping
+ * (DBSCAN over the micro-cluster centers, with each micro-cluster weighted by its size) merges
+ * them into arbitrarily shaped macro-clusters:
+ *
+ * * a micro-cluster is a *core* if the total size of the micro-clusters whose centers lie within
+ *   `eps` of its center (itself included) is at least `min_weight`;
+ * * core micro-clusters within `eps` of each other belong to the same macro-cluster, along with
+ *   any non-core micro-clusters within `eps` of one of its cores;
+ * * all other micro-clusters are noise.
+ */
+
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    cfeature::CFeature,
+    cftree::{CFTree, Node},
+    point::Float,
+    summary::ClusterSummary,
+};
+
+/// Groups the micro-clusters `clusters` into macro-clusters, returning the macro-cluster label of
+/// each micro-cluster (or `None` for noise). Labels are numbered from zero in order of first
+/// appearance.
+pub(crate) fn macro_labels<T: Float, const DIMS: usize>(
+    clusters: &[ClusterSummary<DIMS, T>],
+    eps: T,
+    min_weight: T,
+) -> Vec<Option<usize>> {
+    let neighbors = |i: usize| {
+        let center = &clusters[i].center;
+        (0..clusters.len()).filter(move |&j| (&clusters[j].center - center).norm2().sqrt() <= eps)
+    };
+    let is_core = (0..clusters.len())
+        .map(|i| {
+            neighbors(i)
+                .map(|j| clusters[j].size)
+                .fold(T::zero(), |acc, size| acc + size)
+                >= min_weight
+        })
+        .collect::<Vec<_>>();
+
+    let mut labels = vec![None; clusters.len()];
+    let mut next_label = 0;
+    for start in 0..clusters.len() {
+        if !is_core[start] || labels[start].is_some() {
+            continue;
+        }
+        labels[start] = Some(next_label);
+        let mut frontier = vec![start];
+        while let Some(i) = frontier.pop() {
+            for j in neighbors(i) {
+                if labels[j].is_none() {
+                    labels[j] = Some(next_label);
+                    // only core micro-clusters extend the macro-cluster further
+                    if is_core[j] {
+                        frontier.push(j);
+                    }
+                }
+            }
+        }
+        next_label += 1;
+    }
+    labels
+}
+
+impl<CF: CFeature<DIMS>, const DIMS: usize> Node<CF, DIMS> {
+    /// Groups the leaf clusters of the tree rooted at this node into density-based
+    /// macro-clusters, returning the macro-cluster label of each leaf cluster (indexed by
+    /// [ClusterSummary::id]), or `None` if the leaf cluster is noise. See the
+    /// [module documentation](crate::offline) for details.
+    pub fn offline_cluster(&self, eps: CF::Scalar, min_weight: CF::Scalar) -> Vec<Option<usize>> {
+        macro_labels(&self.clusters().collect::<Vec<_>>(), eps, min_weight)
+    }
+}
+
+impl<CF: CFeature<DIMS>, TC, const DIMS: usize> CFTree<CF, DIMS, TC> {
+    /// Groups the leaf clusters of this tree into density-based macro-clusters. See
+    /// [Node::offline_cluster].
+    pub fn offline_cluster(&self, eps: CF::Scalar, min_weight: CF::Scalar) -> Vec<Option<usize>> {
+        self.root().offline_cluster(eps, min_weight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cftree::{BasicConfig, BetulaCFTree},
+        point::Point,
+    };
+
+    #[test]
+    fn offline_cluster() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.2)
+            .build()
+            .unwrap();
+        // two elongated groups of points, plus a single outlier
+        let points = (0..40)
+            .map(|i| Point::from_arr([i as f64 * 0.25, 0.0]))
+            .chain((0..40).map(|i| Point::from_arr([i as f64 * 0.25, 20.0])))
+            .chain(std::iter::once(Point::from_arr([50.0, 50.0])));
+        let tree = BetulaCFTree::from_iter(points, config);
+
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        let labels = tree.offline_cluster(2.0, 3.0);
+        assert_eq!(labels.len(), clusters.len());
+        let bottom = clusters.iter().position(|c| c.center[1] < 10.0).unwrap();
+        assert!(labels[bottom].is_some());
+        for (cluster, label) in clusters.iter().zip(&labels) {
+            match (cluster.center[0], cluster.center[1]) {
+                (x, _) if x > 40.0 => assert_eq!(*label, None),
+                (_, y) if y < 10.0 => assert_eq!(*label, labels[bottom]),
+                _ => {
+                    assert!(label.is_some());
+                    assert_ne!(*label, labels[bottom]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,296 @@
+/*!
+ * Disk-backed node storage for out-of-core trees.
+ *
+ * [PagedStore] is a [NodeStore] which keeps the nodes of an [ArenaTree](crate::arena::ArenaTree)
+ * in a file rather than in memory, so that trees too large for memory can spill to disk, as in
+ * the paged design of the original BIRCH algorithm. Each node occupies one fixed-size page of the
+ * file (node `i` starts at byte `i * page_size`), encoded with [bincode] and prefixed with its
+ * length. The page size follows from the branching factors of the tree (see
+ * [PagedStore::page_size_for]).
+ *
+ * A bounded number of pages is cached in memory: nodes are read from the file as they're
+ * visited, and nodes being modified are kept in the cache until it fills up (or the store is
+ * [flushed](PagedStore::flush)), at which point they are written back to their pages.
+ */
+
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, HashMap},
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::{
+    arena::{ArenaEntry, ArenaNode, NodeId, NodeStore},
+    cfeature::CFeature,
+    cftree::TreeConfig,
+    error::{self, BorschtError},
+};
+
+/// Default number of pages cached in memory by a [PagedStore].
+pub const DEFAULT_CACHE_PAGES: usize = 1024;
+
+/// Length of the prefix holding the encoded length of the node in a page.
+const LEN_BYTES: usize = 4;
+
+#[derive(Error, Debug)]
+pub enum PagedError {
+    #[error("i/o error")]
+    Io(#[from] io::Error),
+    #[error("encoding error")]
+    Encoding(#[from] bincode::Error),
+    #[error("encoded node of {size} bytes does not fit in a page of {page_size} bytes")]
+    PageOverflow { size: usize, page_size: usize },
+}
+
+type Result<T> = std::result::Result<T, PagedError>;
+
+impl From<PagedError> for BorschtError {
+    fn from(err: PagedError) -> BorschtError {
+        BorschtError::Io(match err {
+            PagedError::Io(err) => err.to_string(),
+            PagedError::Encoding(err) => format!("encoding error: {}", err),
+            err => err.to_string(),
+        })
+    }
+}
+
+/// A [NodeStore] keeping nodes in fixed-size pages of a file, with a bounded in-memory cache.
+///
+/// Failures to read or write pages (e.g. a full disk, or a truncated file) are reported by the
+/// [NodeStore] methods as [BorschtError::Io].
+#[derive(Debug)]
+pub struct PagedStore<CF> {
+    file: File,
+    page_size: usize,
+    /// Number of nodes (pages) in this store.
+    len: usize,
+    /// Cached nodes, newer than their pages whenever they've been modified.
+    cache: HashMap<NodeId, ArenaNode<CF>>,
+    cache_pages: usize,
+}
+
+impl<CF> PagedStore<CF>
+where
+    CF: Serialize + DeserializeOwned + Clone,
+{
+    /// Creates an empty store backed by the file at `path` (truncating any existing file), with
+    /// pages of `page_size` bytes.
+    pub fn create<P: AsRef<Path>>(path: P, page_size: usize) -> Result<PagedStore<CF>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(PagedStore {
+            file,
+            page_size: page_size.max(LEN_BYTES),
+            len: 0,
+            cache: HashMap::new(),
+            cache_pages: DEFAULT_CACHE_PAGES,
+        })
+    }
+
+    /// Sets the number of pages cached in memory (at least one).
+    pub fn with_cache_pages(mut self, cache_pages: usize) -> PagedStore<CF> {
+        self.cache_pages = cache_pages.max(1);
+        self
+    }
+
+    /// Size of the pages of this store, in bytes.
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Size of the pages needed for the nodes of trees built with `config`, in bytes. Splits may
+    /// briefly leave up to twice the maximum capacity of entries in a node, so pages have room for
+    /// that many.
+    pub fn page_size_for<TC: TreeConfig, const DIMS: usize>(config: &TC) -> usize
+    where
+        CF: CFeature<DIMS>,
+    {
+        let entry = ArenaEntry {
+            feature: CF::zero(),
+            child: Some(NodeId::new(0)),
+        };
+        let entry_size = bincode::serialized_size(&entry).expect("encodable entry") as usize;
+        let max_entries = config.node_capacity().max.max(config.leaf_capacity().max) * 2;
+        let empty_size = bincode::serialized_size(&ArenaNode::<CF> { entries: vec![] })
+            .expect("encodable node") as usize;
+        LEN_BYTES + empty_size + max_entries * entry_size
+    }
+
+    /// Writes all cached nodes back to their pages.
+    pub fn flush(&mut self) -> Result<()> {
+        let mut ids = self.cache.keys().copied().collect::<Vec<_>>();
+        ids.sort_by_key(|id| id.index());
+        for id in ids {
+            write_page(&self.file, self.page_size, id, &self.cache[&id])?;
+        }
+        self.file.flush()?;
+        Ok(())
+    }
+
+    /// Makes room in the cache for another node, writing back and evicting all cached nodes if
+    /// it is full.
+    fn make_room(&mut self) -> Result<()> {
+        if self.cache.len() >= self.cache_pages {
+            self.flush()?;
+            self.cache.clear();
+        }
+        Ok(())
+    }
+}
+
+fn read_page<CF: DeserializeOwned>(
+    file: &File,
+    page_size: usize,
+    id: NodeId,
+) -> Result<ArenaNode<CF>> {
+    let mut file = file;
+    let mut page = vec![0u8; page_size];
+    file.seek(SeekFrom::Start((id.index() * page_size) as u64))?;
+    file.read_exact(&mut page)?;
+    let mut len = [0u8; LEN_BYTES];
+    len.copy_from_slice(&page[..LEN_BYTES]);
+    let len = u32::from_le_bytes(len) as usize;
+    if LEN_BYTES + len > page_size {
+        return Err(PagedError::PageOverflow {
+            size: len,
+            page_size,
+        });
+    }
+    Ok(bincode::deserialize(&page[LEN_BYTES..LEN_BYTES + len])?)
+}
+
+fn write_page<CF: Serialize>(
+    file: &File,
+    page_size: usize,
+    id: NodeId,
+    node: &ArenaNode<CF>,
+) -> Result<()> {
+    let encoded = bincode::serialize(node)?;
+    if LEN_BYTES + encoded.len() > page_size {
+        return Err(PagedError::PageOverflow {
+            size: encoded.len(),
+            page_size,
+        });
+    }
+    let mut page = vec![0u8; page_size];
+    page[..LEN_BYTES].copy_from_slice(&(encoded.len() as u32).to_le_bytes());
+    page[LEN_BYTES..LEN_BYTES + encoded.len()].copy_from_slice(&encoded);
+    let mut file = file;
+    file.seek(SeekFrom::Start((id.index() * page_size) as u64))?;
+    file.write_all(&page)?;
+    Ok(())
+}
+
+impl<CF> NodeStore<CF> for PagedStore<CF>
+where
+    CF: Serialize + DeserializeOwned + Clone,
+{
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, id: NodeId) -> error::Result<Cow<'_, ArenaNode<CF>>> {
+        Ok(match self.cache.get(&id) {
+            Some(node) => Cow::Borrowed(node),
+            None => Cow::Owned(read_page(&self.file, self.page_size, id)?),
+        })
+    }
+
+    fn get_mut(&mut self, id: NodeId) -> error::Result<&mut ArenaNode<CF>> {
+        if !self.cache.contains_key(&id) {
+            self.make_room()?;
+        }
+        Ok(match self.cache.entry(id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(read_page(&self.file, self.page_size, id)?),
+        })
+    }
+
+    fn set(&mut self, id: NodeId, node: ArenaNode<CF>) -> error::Result<()> {
+        if !self.cache.contains_key(&id) {
+            self.make_room()?;
+        }
+        self.cache.insert(id, node);
+        Ok(())
+    }
+
+    fn push(&mut self, node: ArenaNode<CF>) -> error::Result<NodeId> {
+        let id = NodeId::new(self.len);
+        self.set(id, node)?;
+        self.len += 1;
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arena::ArenaTree,
+        cfeature::birch::CFeature as BirchFeature,
+        cftree::{BasicConfig, BirchCFTree},
+        point::Point,
+    };
+
+    #[test]
+    fn out_of_core() {
+        let config = BasicConfig::builder()
+            .capacity(2, 5)
+            .leaf_capacity(1, 4)
+            .threshold(2.0)
+            .merge_refinement(true)
+            .build()
+            .unwrap();
+        let points = (0..2000u64)
+            .map(|i| {
+                let h = i.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                Point::from_arr([(h % 1000) as f64 / 10.0, ((h >> 20) % 1000) as f64 / 10.0])
+            })
+            .collect::<Vec<_>>();
+
+        let path = std::env::temp_dir().join(format!("borscht-paged-{}", std::process::id()));
+        let page_size = PagedStore::<BirchFeature<2>>::page_size_for(&config);
+        // a tiny cache, so most nodes live only on disk
+        let store = PagedStore::create(&path, page_size)
+            .unwrap()
+            .with_cache_pages(4);
+        let mut paged =
+            ArenaTree::<BirchFeature<2>, 2, _, _>::with_store(config.clone(), store).unwrap();
+        paged.try_extend(points.iter().cloned()).unwrap();
+        assert!(paged.store().len() > 4);
+        paged.store_mut().flush().unwrap();
+        let file_size = std::fs::metadata(&path).unwrap().len() as usize;
+        assert_eq!(file_size, paged.store().len() * page_size);
+
+        let tree = BirchCFTree::from_iter(points, config);
+        assert_eq!(
+            format!("{:?}", tree.root()),
+            format!("{:?}", paged.to_node().unwrap())
+        );
+        assert_eq!(
+            tree.clusters().collect::<Vec<_>>(),
+            paged.clusters().collect::<error::Result<Vec<_>>>().unwrap()
+        );
+
+        // pages lost from the file are reported rather than panicking
+        let store = paged.store_mut();
+        store.cache.clear();
+        store.file.set_len(page_size as u64).unwrap();
+        assert!(matches!(paged.height(), Err(BorschtError::Io(_))));
+        assert!(matches!(
+            paged.insert(Point::from_arr([0.0, 0.0])),
+            Err(BorschtError::Io(_))
+        ));
+        assert!(paged.clusters().any(|cluster| cluster.is_err()));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,755 @@
+/*!
+ * Saving and loading of [CFTree]s to and from disk.
+ *
+ * Trees are stored in a compact binary format (using [bincode]) prefixed with a short header
+ * containing a magic number, a format version and the type of the cluster features (see
+ * [FeatureTag]), so loading a tree as a tree of another type fails with a clear error. The tree
+ * configuration is stored alongside the tree itself, so a loaded tree can continue to absorb
+ * points exactly as the original would have.
+ *
+ * Saving to and loading from files requires the `fs` feature (enabled by default); trees can
+ * always be written to and read from arbitrary readers and writers.
+ *
+ * Long ingestion jobs can checkpoint the tree to a file while it is being built (see
+ * [CheckpointPolicy]). Checkpoints are written atomically, so a crash leaves either the previous or
+ * the new checkpoint in place. The [metrics](CFTree::metrics) of a loaded checkpoint record how
+ * many points it had absorbed, i.e. where to resume the input from.
+ *
+ * Serialized trees of high-dimensional features get large, so trees can also be written with
+ * [SaveOptions]: compressed with zstd (`zstd` feature) or LZ4 (`lz4` feature), after optionally
+ * delta-encoding the features of the leaf entries and/or shuffling the bytes of their floats,
+ * which can make them more compressible. Loading detects the encoding from the header.
+ */
+
+#[cfg(feature = "fs")]
+use std::{
+    fmt::Debug,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::{
+    cfeature::CFeature,
+    cftree::{CFTree, Node},
+};
+#[cfg(feature = "fs")]
+use crate::{cfeature::FeaturePoint, cftree::TreeConfig};
+
+/// Identifies a file as a serialized borscht tree.
+const MAGIC: [u8; 8] = *b"BORSCHT\0";
+
+/// Current version of the on-disk format. Incremented whenever the format changes in a
+/// backwards-incompatible way.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Header tags of the [Compression]s.
+const UNCOMPRESSED: u8 = 0;
+#[cfg(feature = "zstd")]
+const ZSTD: u8 = 1;
+#[cfg(feature = "lz4")]
+const LZ4: u8 = 2;
+
+/// Compression of a written tree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    /// Zstandard compression at the given level (1 to 22, or 0 for the zstd default).
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+    /// LZ4 compression: faster to write and read than zstd, but larger.
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+
+impl Compression {
+    fn tag(self) -> u8 {
+        match self {
+            Compression::None => UNCOMPRESSED,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(_) => ZSTD,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => LZ4,
+        }
+    }
+}
+
+/// Header flags of the filters applied before compression.
+const DELTA: u8 = 1;
+const SHUFFLE: u8 = 2;
+
+/// Size of the words transposed by the shuffle filter: that of a [Scalar](crate::point::Scalar).
+const WORD_BYTES: usize = 8;
+
+/// How a tree is encoded when it is written (see [CFTree::write_with]). The default writes the
+/// plain serialized tree.
+///
+/// The filters only pay off along with compression, and how much depends on the data; see the
+/// `persist` benchmark for the size and load-time trade-offs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SaveOptions {
+    pub compression: Compression,
+    /// Whether to delta-encode the features of the leaf entries: they are written after the rest
+    /// of the tree, in depth-first order, each XORed with the one before it. Leaf entries next to
+    /// each other in the tree have similar sizes and centers, so their coordinates share sign,
+    /// exponent and leading mantissa bits, which XOR to zero.
+    pub delta: bool,
+    /// Whether to shuffle the serialized tree: the bytes of its 8-byte words are regrouped by
+    /// their position in the word, so the sign and exponent bytes of the floats, which vary
+    /// little, end up next to each other.
+    pub shuffle: bool,
+}
+
+impl SaveOptions {
+    pub fn compressed(compression: Compression) -> SaveOptions {
+        SaveOptions {
+            compression,
+            delta: false,
+            shuffle: false,
+        }
+    }
+
+    pub fn with_delta(mut self, delta: bool) -> SaveOptions {
+        self.delta = delta;
+        self
+    }
+
+    pub fn with_shuffle(mut self, shuffle: bool) -> SaveOptions {
+        self.shuffle = shuffle;
+        self
+    }
+
+    fn filters(&self) -> u8 {
+        let mut filters = 0;
+        if self.delta {
+            filters |= DELTA;
+        }
+        if self.shuffle {
+            filters |= SHUFFLE;
+        }
+        filters
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum PersistError {
+    #[error("i/o error")]
+    Io(#[from] std::io::Error),
+    #[error("encoding error")]
+    Encoding(#[from] bincode::Error),
+    #[error("invalid header: not a serialized tree")]
+    InvalidHeader,
+    #[error("unsupported format version {found} (expected {expected})")]
+    UnsupportedVersion { expected: u32, found: u32 },
+    #[error("unsupported compression (tag {0}); is the corresponding feature enabled?")]
+    UnsupportedCompression(u8),
+    #[error("format mismatch: expected a tree of {expected}, found a tree of {found}")]
+    FormatMismatch {
+        expected: FeatureTag,
+        found: FeatureTag,
+    },
+    #[error("found {found} delta-encoded leaf features for a tree of {expected} leaf entries")]
+    LeafCountMismatch { expected: usize, found: usize },
+}
+
+/// The type of the cluster features of a saved tree: their [kind](CFeature::KIND), number of
+/// dimensions and scalar size. Recorded in the header, so that a tree is only loaded as a tree of
+/// the same type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeatureTag {
+    pub kind: String,
+    pub dims: usize,
+    pub scalar_bytes: usize,
+}
+
+impl FeatureTag {
+    /// Tag of the cluster feature type `CF`.
+    pub fn of<CF: CFeature<DIMS>, const DIMS: usize>() -> FeatureTag {
+        FeatureTag {
+            kind: CF::KIND.to_string(),
+            dims: DIMS,
+            scalar_bytes: std::mem::size_of::<CF::Scalar>(),
+        }
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&[self.kind.len() as u8])?;
+        writer.write_all(self.kind.as_bytes())?;
+        writer.write_all(&(self.dims as u32).to_le_bytes())?;
+        writer.write_all(&[self.scalar_bytes as u8])?;
+        Ok(())
+    }
+
+    fn read<R: Read>(reader: &mut R) -> Result<FeatureTag> {
+        let mut len = [0u8; 1];
+        reader.read_exact(&mut len)?;
+        let mut kind = vec![0u8; len[0] as usize];
+        reader.read_exact(&mut kind)?;
+        let mut dims = [0u8; 4];
+        reader.read_exact(&mut dims)?;
+        let mut scalar_bytes = [0u8; 1];
+        reader.read_exact(&mut scalar_bytes)?;
+        Ok(FeatureTag {
+            kind: String::from_utf8(kind).map_err(|_| PersistError::InvalidHeader)?,
+            dims: u32::from_le_bytes(dims) as usize,
+            scalar_bytes: scalar_bytes[0] as usize,
+        })
+    }
+}
+
+impl std::fmt::Display for FeatureTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} features of {} dimensions ({}-byte scalars)",
+            self.kind, self.dims, self.scalar_bytes
+        )
+    }
+}
+
+type Result<T> = std::result::Result<T, PersistError>;
+
+/// Type and encoding of a tree, as recorded in its header.
+struct Encoding {
+    tag: FeatureTag,
+    compression: u8,
+    filters: u8,
+}
+
+/// Writes the header, followed by the type and encoding of the tree.
+fn write_header<W: Write>(writer: &mut W, encoding: &Encoding) -> Result<()> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    encoding.tag.write(writer)?;
+    writer.write_all(&[encoding.compression, encoding.filters])?;
+    Ok(())
+}
+
+/// Reads the header, returning the type and encoding of the tree.
+fn read_header<R: Read>(reader: &mut R) -> Result<Encoding> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(PersistError::InvalidHeader);
+    }
+    let mut version = [0u8; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != FORMAT_VERSION {
+        return Err(PersistError::UnsupportedVersion {
+            expected: FORMAT_VERSION,
+            found: version,
+        });
+    }
+    let tag = FeatureTag::read(reader)?;
+    let mut tags = [0u8; 2];
+    reader.read_exact(&mut tags)?;
+    Ok(Encoding {
+        tag,
+        compression: tags[0],
+        filters: tags[1],
+    })
+}
+
+/// Moves the features of the leaf entries of the subtree rooted at `node` into `features`, in
+/// depth-first order, leaving zero features in their place.
+fn take_leaf_features<CF: CFeature<DIMS>, const DIMS: usize>(
+    node: &mut Node<CF, DIMS>,
+    features: &mut Vec<CF>,
+) {
+    for entry in &mut node.entries {
+        match entry.child {
+            Some(ref mut child) => take_leaf_features(Arc::make_mut(child), features),
+            None => features.push(std::mem::replace(&mut entry.feature, CF::zero())),
+        }
+    }
+}
+
+/// Number of leaf entries in the subtree rooted at `node`.
+fn leaf_entries<CF, const DIMS: usize>(node: &Node<CF, DIMS>) -> usize {
+    node.entries
+        .iter()
+        .map(|entry| match entry.child {
+            Some(ref child) => leaf_entries(child),
+            None => 1,
+        })
+        .sum()
+}
+
+/// Reverses [take_leaf_features], refreshing the cached values of the nodes along the way.
+fn restore_leaf_features<CF: CFeature<DIMS>, const DIMS: usize>(
+    node: &mut Node<CF, DIMS>,
+    features: &mut impl Iterator<Item = CF>,
+) {
+    for entry in &mut node.entries {
+        match entry.child {
+            Some(ref mut child) => restore_leaf_features(Arc::make_mut(child), features),
+            None => entry.feature = features.next().expect("as many features as leaf entries"),
+        }
+    }
+    node.refresh();
+}
+
+/// Serializes each of `features`, XORing its bytes with those of the feature before it.
+fn delta_encode<CF: Serialize>(features: &[CF]) -> Result<Vec<Vec<u8>>> {
+    let mut previous: Vec<u8> = vec![];
+    let mut encoded = Vec::with_capacity(features.len());
+    for feature in features {
+        let bytes = bincode::serialize(feature)?;
+        let mut delta = bytes.clone();
+        for (byte, prev) in delta.iter_mut().zip(&previous) {
+            *byte ^= prev;
+        }
+        encoded.push(delta);
+        previous = bytes;
+    }
+    Ok(encoded)
+}
+
+/// Reverses [delta_encode].
+fn delta_decode<CF: DeserializeOwned>(encoded: Vec<Vec<u8>>) -> Result<Vec<CF>> {
+    let mut previous: Vec<u8> = vec![];
+    let mut features = Vec::with_capacity(encoded.len());
+    for mut bytes in encoded {
+        for (byte, prev) in bytes.iter_mut().zip(&previous) {
+            *byte ^= prev;
+        }
+        features.push(bincode::deserialize(&bytes)?);
+        previous = bytes;
+    }
+    Ok(features)
+}
+
+/// Transposes the [WORD_BYTES]-byte words of `bytes`: all their first bytes come first, then all
+/// their second bytes, and so on. Trailing bytes are left in place.
+fn shuffle(bytes: &[u8]) -> Vec<u8> {
+    let words = bytes.len() / WORD_BYTES;
+    let mut shuffled = bytes.to_vec();
+    for (w, word) in bytes.chunks_exact(WORD_BYTES).enumerate() {
+        for (b, &byte) in word.iter().enumerate() {
+            shuffled[b * words + w] = byte;
+        }
+    }
+    shuffled
+}
+
+/// Reverses [shuffle].
+fn unshuffle(bytes: &[u8]) -> Vec<u8> {
+    let words = bytes.len() / WORD_BYTES;
+    let mut unshuffled = bytes.to_vec();
+    for (w, word) in unshuffled.chunks_exact_mut(WORD_BYTES).enumerate() {
+        for (b, byte) in word.iter_mut().enumerate() {
+            *byte = bytes[b * words + w];
+        }
+    }
+    unshuffled
+}
+
+fn compress<W: Write>(bytes: &[u8], compression: Compression, writer: &mut W) -> Result<()> {
+    match compression {
+        Compression::None => writer.write_all(bytes)?,
+        #[cfg(feature = "zstd")]
+        Compression::Zstd(level) => zstd::stream::copy_encode(bytes, writer, level)?,
+        #[cfg(feature = "lz4")]
+        Compression::Lz4 => {
+            let mut encoder = lz4_flex::frame::FrameEncoder::new(writer);
+            encoder.write_all(bytes)?;
+            encoder.finish().map_err(std::io::Error::from)?;
+        }
+    }
+    Ok(())
+}
+
+fn decompress<R: Read>(mut reader: R, compression: u8) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    match compression {
+        UNCOMPRESSED => {
+            reader.read_to_end(&mut bytes)?;
+        }
+        #[cfg(feature = "zstd")]
+        ZSTD => zstd::stream::copy_decode(reader, &mut bytes)?,
+        #[cfg(feature = "lz4")]
+        LZ4 => {
+            lz4_flex::frame::FrameDecoder::new(reader).read_to_end(&mut bytes)?;
+        }
+        tag => return Err(PersistError::UnsupportedCompression(tag)),
+    }
+    Ok(bytes)
+}
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Serialize + DeserializeOwned,
+    TC: Clone + Serialize + DeserializeOwned,
+{
+    /// Writes this tree (and its configuration) to `writer`.
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<()> {
+        self.write_with(writer, SaveOptions::default())
+    }
+
+    /// Writes this tree (and its configuration) to `writer`, encoded according to `options`.
+    pub fn write_with<W: Write>(&self, mut writer: W, options: SaveOptions) -> Result<()> {
+        let encoding = Encoding {
+            tag: FeatureTag::of::<CF, DIMS>(),
+            compression: options.compression.tag(),
+            filters: options.filters(),
+        };
+        write_header(&mut writer, &encoding)?;
+        if options == SaveOptions::default() {
+            bincode::serialize_into(&mut writer, self)?;
+        } else {
+            let mut bytes = vec![];
+            if options.delta {
+                // the tree without its leaf features, followed by the delta-encoded features
+                let mut tree = self.snapshot();
+                let mut features = vec![];
+                take_leaf_features(tree.root_mut(), &mut features);
+                bincode::serialize_into(&mut bytes, &tree)?;
+                bincode::serialize_into(&mut bytes, &delta_encode(&features)?)?;
+            } else {
+                bincode::serialize_into(&mut bytes, self)?;
+            }
+            if options.shuffle {
+                bytes = shuffle(&bytes);
+            }
+            compress(&bytes, options.compression, &mut writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a tree previously written with [CFTree::write_to] or [CFTree::write_with] from
+    /// `reader`.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self> {
+        let encoding = read_header(&mut reader)?;
+        let expected = FeatureTag::of::<CF, DIMS>();
+        if encoding.tag != expected {
+            return Err(PersistError::FormatMismatch {
+                expected,
+                found: encoding.tag,
+            });
+        }
+        if encoding.compression == UNCOMPRESSED && encoding.filters == 0 {
+            return Ok(bincode::deserialize_from(reader)?);
+        }
+        let mut bytes = decompress(reader, encoding.compression)?;
+        if encoding.filters & SHUFFLE != 0 {
+            bytes = unshuffle(&bytes);
+        }
+        if encoding.filters & DELTA == 0 {
+            return Ok(bincode::deserialize(&bytes)?);
+        }
+        let mut bytes = bytes.as_slice();
+        let mut tree: Self = bincode::deserialize_from(&mut bytes)?;
+        let features = delta_decode::<CF>(bincode::deserialize_from(&mut bytes)?)?;
+        let expected = leaf_entries(tree.root());
+        if features.len() != expected {
+            return Err(PersistError::LeafCountMismatch {
+                expected,
+                found: features.len(),
+            });
+        }
+        restore_leaf_features(tree.root_mut(), &mut features.into_iter());
+        Ok(tree)
+    }
+
+    /// Saves this tree (and its configuration) to the file at `path`, overwriting any existing
+    /// file.
+    #[cfg(feature = "fs")]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    /// Saves this tree to the file at `path` like [CFTree::save], encoded according to
+    /// `options`.
+    #[cfg(feature = "fs")]
+    pub fn save_with<P: AsRef<Path>>(&self, path: P, options: SaveOptions) -> Result<()> {
+        self.write_with(BufWriter::new(File::create(path)?), options)
+    }
+
+    /// Saves this tree to the file at `path` atomically: the tree is first written (and synced)
+    /// to a temporary file next to `path`, which then replaces any existing file at `path`.
+    #[cfg(feature = "fs")]
+    pub fn save_atomic<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        self.write_to(&mut writer)?;
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Loads a tree previously saved with [CFTree::save] or [CFTree::save_with] from the file at
+    /// `path`.
+    #[cfg(feature = "fs")]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+/// When to checkpoint a tree while it is being built: after a number of points, after an amount
+/// of time, or whichever comes first if both are set.
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CheckpointPolicy {
+    /// Number of inserted points between checkpoints.
+    pub points: Option<usize>,
+    /// Time between checkpoints.
+    pub interval: Option<Duration>,
+}
+
+#[cfg(feature = "fs")]
+impl CheckpointPolicy {
+    /// Checkpoints after every `points` inserted points.
+    pub fn every_points(points: usize) -> CheckpointPolicy {
+        CheckpointPolicy {
+            points: Some(points.max(1)),
+            interval: None,
+        }
+    }
+
+    /// Checkpoints after every `interval` of time (checked as points are inserted).
+    pub fn every(interval: Duration) -> CheckpointPolicy {
+        CheckpointPolicy {
+            points: None,
+            interval: Some(interval),
+        }
+    }
+
+    fn is_due(&self, points: usize, elapsed: Duration) -> bool {
+        self.points.is_some_and(|every| points >= every)
+            || self.interval.is_some_and(|every| elapsed >= every)
+    }
+}
+
+#[cfg(feature = "fs")]
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone + Serialize + DeserializeOwned,
+    TC: TreeConfig + Clone + Serialize + DeserializeOwned,
+{
+    /// Creates a tree from `iter`, [atomically saving](CFTree::save_atomic) it to `path` whenever
+    /// `policy` calls for a checkpoint, and once more after the last point.
+    pub fn from_iter_with_checkpoints<T, P>(
+        iter: T,
+        config: TC,
+        policy: CheckpointPolicy,
+        path: P,
+    ) -> Result<CFTree<CF, DIMS, TC>>
+    where
+        T: IntoIterator<Item = FeaturePoint<CF, DIMS>>,
+        P: AsRef<Path>,
+    {
+        let mut tree = CFTree::new(config);
+        let mut since_points = 0;
+        let mut since = Instant::now();
+        for p in iter {
+            tree.insert(p);
+            since_points += 1;
+            if policy.is_due(since_points, since.elapsed()) {
+                tree.save_atomic(&path)?;
+                since_points = 0;
+                since = Instant::now();
+            }
+        }
+        tree.save_atomic(&path)?;
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cftree::{BasicConfig, BetulaCFTree, BirchCFTree},
+        point::Point,
+    };
+
+    fn config() -> BasicConfig {
+        BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(0.5)
+            .build()
+            .unwrap()
+    }
+
+    fn points() -> Vec<Point<3>> {
+        (0..50)
+            .map(|i| {
+                let i = i as f64;
+                Point::from_arr([i % 7.0, (i * 3.0) % 11.0, i / 10.0])
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn checkpoints() {
+        let path =
+            std::env::temp_dir().join(format!("borscht-checkpoint-{}.bin", std::process::id()));
+
+        let mut checkpointed = vec![];
+        let tree = BirchCFTree::from_iter_with_checkpoints(
+            points().into_iter().enumerate().map(|(i, p)| {
+                if i % 10 == 0 && path.exists() {
+                    let checkpoint = BirchCFTree::<3>::load(&path).expect("load failed");
+                    checkpointed.push(checkpoint.metrics().inserted);
+                }
+                p
+            }),
+            config(),
+            CheckpointPolicy::every_points(20),
+            &path,
+        )
+        .expect("build failed");
+        assert_eq!(checkpointed, vec![20, 20, 40]);
+
+        let loaded = BirchCFTree::<3>::load(&path).expect("load failed");
+        std::fs::remove_file(&path).expect("cleanup failed");
+        assert_eq!(loaded.metrics().inserted, 50);
+        assert_eq!(format!("{:?}", loaded.root()), format!("{:?}", tree.root()));
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn round_trip() {
+        let path = std::env::temp_dir().join(format!("borscht-persist-{}.bin", std::process::id()));
+
+        let tree = BirchCFTree::from_iter(points(), config());
+        tree.save(&path).expect("save failed");
+        let loaded = BirchCFTree::<3>::load(&path).expect("load failed");
+        std::fs::remove_file(&path).expect("cleanup failed");
+
+        assert_eq!(format!("{:?}", tree), format!("{:?}", loaded));
+        assert_eq!(loaded.config().threshold, 0.5);
+        assert_eq!(loaded.config().capacity.max, 3);
+    }
+
+    #[test]
+    fn buffer_round_trip() {
+        let mut buffer = vec![];
+        let tree = BetulaCFTree::from_iter(points(), config());
+        tree.write_to(&mut buffer).expect("write failed");
+        let loaded = BetulaCFTree::<3>::read_from(buffer.as_slice()).expect("read failed");
+        assert_eq!(format!("{:?}", tree), format!("{:?}", loaded));
+
+        // inline node entries are stored exactly as a `Vec` of entries would be
+        let entries = &tree.root().entries;
+        assert!(!entries.spilled());
+        assert_eq!(
+            bincode::serialize(entries).unwrap(),
+            bincode::serialize(&entries.to_vec()).unwrap()
+        );
+    }
+
+    #[test]
+    fn encoded_round_trip() {
+        let tree = BirchCFTree::from_iter(points(), config());
+        let mut plain = vec![];
+        tree.write_to(&mut plain).expect("write failed");
+
+        let compressions = [
+            Compression::None,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(3),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4,
+        ];
+        for compression in compressions {
+            for (delta, shuffle) in [(false, false), (true, false), (false, true), (true, true)] {
+                let options = SaveOptions::compressed(compression)
+                    .with_delta(delta)
+                    .with_shuffle(shuffle);
+                let mut buffer = vec![];
+                tree.write_with(&mut buffer, options).expect("write failed");
+                if compression != Compression::None {
+                    assert!(buffer.len() < plain.len());
+                }
+                let loaded = BirchCFTree::<3>::read_from(buffer.as_slice()).expect("read failed");
+                assert_eq!(format!("{:?}", tree), format!("{:?}", loaded));
+            }
+        }
+
+        // neighbouring leaf features share leading bytes, which delta-encode to zeros
+        let mut delta = vec![];
+        tree.write_with(&mut delta, SaveOptions::default().with_delta(true))
+            .expect("write failed");
+        let zeros = |bytes: &[u8]| bytes.iter().filter(|&&byte| byte == 0).count();
+        assert!(zeros(&delta) > zeros(&plain));
+
+        let mut unsupported = plain;
+        // after the magic number, version and feature tag
+        unsupported[MAGIC.len() + 4 + 1 + "birch".len() + 4 + 1] = 99;
+        assert!(matches!(
+            BirchCFTree::<3>::read_from(unsupported.as_slice()),
+            Err(PersistError::UnsupportedCompression(99))
+        ));
+    }
+
+    #[test]
+    fn format_mismatch() {
+        let mut buffer = vec![];
+        BirchCFTree::from_iter(points(), config())
+            .write_to(&mut buffer)
+            .expect("write failed");
+        let birch3 = FeatureTag {
+            kind: "birch".to_string(),
+            dims: 3,
+            scalar_bytes: 8,
+        };
+
+        let err = BirchCFTree::<2>::read_from(buffer.as_slice()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "format mismatch: expected a tree of birch features of 2 dimensions (8-byte scalars), \
+             found a tree of birch features of 3 dimensions (8-byte scalars)"
+        );
+        match BetulaCFTree::<3>::read_from(buffer.as_slice()) {
+            Err(PersistError::FormatMismatch { expected, found }) => {
+                assert_eq!(expected.kind, "betula");
+                assert_eq!(found, birch3);
+            }
+            other => panic!("expected a format mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_header() {
+        let mut buffer = vec![];
+        BirchCFTree::from_iter(points(), config())
+            .write_to(&mut buffer)
+            .expect("write failed");
+
+        let mut bad_magic = buffer.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            BirchCFTree::<3>::read_from(bad_magic.as_slice()),
+            Err(PersistError::InvalidHeader)
+        ));
+
+        let mut bad_version = buffer;
+        bad_version[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&99u32.to_le_bytes());
+        assert!(matches!(
+            BirchCFTree::<3>::read_from(bad_version.as_slice()),
+            Err(PersistError::UnsupportedVersion {
+                expected: FORMAT_VERSION,
+                found: 99
+            })
+        ));
+    }
+}
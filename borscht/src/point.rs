@@ -2,9 +2,13 @@
  * Main data point structure and associated trait implementations.
  */
 
-use std::ops::{
-    Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Rem, RemAssign, Sub,
-    SubAssign,
+use core::{
+    fmt::{Debug, Display},
+    marker::PhantomData,
+    ops::{
+        Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Rem, RemAssign, Sub,
+        SubAssign,
+    },
 };
 
 use num_traits::Zero;
@@ -14,12 +18,53 @@ use serde::{
     Deserialize, Deserializer, Serialize, Serializer,
 };
 
+/// Default scalar type used for points, cluster features, and tree configuration.
 pub type Scalar = f64;
 
+/// Floating-point types usable as the scalar type of a [Point] (and therefore of the cluster
+/// features and trees built from it). Implemented for `f32` and `f64`.
+pub trait Float:
+    num_traits::Float
+    + Default
+    + Debug
+    + Display
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + DivAssign
+    + RemAssign
+    + Serialize
+    + for<'de> Deserialize<'de>
+    + 'static
+{
+    /// Converts from the default [Scalar] type (used for e.g. constants and configuration).
+    fn from_scalar(value: Scalar) -> Self;
+    /// Converts to the default [Scalar] type.
+    fn to_scalar(self) -> Scalar;
+}
+
+impl Float for f32 {
+    fn from_scalar(value: Scalar) -> f32 {
+        value as f32
+    }
+    fn to_scalar(self) -> Scalar {
+        self as Scalar
+    }
+}
+
+impl Float for f64 {
+    fn from_scalar(value: Scalar) -> f64 {
+        value
+    }
+    fn to_scalar(self) -> Scalar {
+        self
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
-pub struct Point<const DIMS: usize>([Scalar; DIMS]);
+pub struct Point<const DIMS: usize, T = Scalar>([T; DIMS]);
 
-impl<const DIMS: usize> Serialize for Point<DIMS> {
+impl<T: Serialize, const DIMS: usize> Serialize for Point<DIMS, T> {
     #[inline]
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -33,13 +78,15 @@ impl<const DIMS: usize> Serialize for Point<DIMS> {
     }
 }
 
-struct PointVisitor<const DIMS: usize>;
+struct PointVisitor<const DIMS: usize, T>(PhantomData<T>);
 
-impl<'de, const DIMS: usize> Visitor<'de> for PointVisitor<DIMS> {
-    // type Value = [T; DIMS];
-    type Value = Point<DIMS>;
+impl<'de, T, const DIMS: usize> Visitor<'de> for PointVisitor<DIMS, T>
+where
+    T: Deserialize<'de> + Default + Copy,
+{
+    type Value = Point<DIMS, T>;
 
-    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
         formatter.write_fmt(format_args!("a point of dimensionality {}", DIMS))
     }
 
@@ -48,7 +95,7 @@ impl<'de, const DIMS: usize> Visitor<'de> for PointVisitor<DIMS> {
     where
         A: SeqAccess<'de>,
     {
-        let mut p = Point::default();
+        let mut p = Point([T::default(); DIMS]);
         for i in 0..DIMS {
             match seq.next_element()? {
                 Some(val) => p.0[i] = val,
@@ -59,33 +106,56 @@ impl<'de, const DIMS: usize> Visitor<'de> for PointVisitor<DIMS> {
     }
 }
 
-impl<'de, const DIMS: usize> Deserialize<'de> for Point<DIMS> {
+impl<'de, T, const DIMS: usize> Deserialize<'de> for Point<DIMS, T>
+where
+    T: Deserialize<'de> + Default + Copy,
+{
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        deserializer.deserialize_tuple(DIMS, PointVisitor::<DIMS>)
+        deserializer.deserialize_tuple(DIMS, PointVisitor::<DIMS, T>(PhantomData))
     }
 }
 
-impl<const DIMS: usize> Point<DIMS> {
-    pub fn from_arr(arr: [Scalar; DIMS]) -> Point<DIMS> {
+impl<T, const DIMS: usize> Point<DIMS, T> {
+    /// Point whose component in each dimension `d` is `f(d)`. Unlike [Point::from_arr], usable
+    /// with non-float components (e.g. for compact storage).
+    pub fn from_fn<F: FnMut(usize) -> T>(f: F) -> Point<DIMS, T> {
+        Point(core::array::from_fn(f))
+    }
+}
+
+impl<T: Float, const DIMS: usize> Point<DIMS, T> {
+    pub fn from_arr(arr: [T; DIMS]) -> Point<DIMS, T> {
         Point(arr)
     }
-    pub fn as_slice(&self) -> &[Scalar] {
+    pub fn as_slice(&self) -> &[T] {
         &self.0
     }
-    pub fn as_mut_slice(&mut self) -> &mut [Scalar] {
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
         &mut self.0
     }
-    pub fn norm2(&self) -> Scalar {
-        self.0.iter().fold(Scalar::default(), |acc, x| acc + x * x)
+    pub fn norm2(&self) -> T {
+        self.0.iter().fold(T::zero(), |acc, &x| acc + x * x)
+    }
+    /// Sum of the components of this point.
+    pub fn sum(&self) -> T {
+        self.0.iter().fold(T::zero(), |acc, &x| acc + x)
+    }
+    /// Converts this point to a point with a different scalar type.
+    pub fn cast<U: Float>(&self) -> Point<DIMS, U> {
+        let mut out = Point::<DIMS, U>::zero();
+        for i in 0..DIMS {
+            out.0[i] = U::from_scalar(self.0[i].to_scalar());
+        }
+        out
     }
 }
 
-impl<const DIMS: usize> Zero for Point<DIMS> {
-    fn zero() -> Point<DIMS> {
-        Point([Scalar::zero(); DIMS])
+impl<T: Float, const DIMS: usize> Zero for Point<DIMS, T> {
+    fn zero() -> Point<DIMS, T> {
+        Point([T::zero(); DIMS])
     }
 
     fn is_zero(&self) -> bool {
@@ -93,138 +163,146 @@ impl<const DIMS: usize> Zero for Point<DIMS> {
     }
 }
 
-impl<const DIMS: usize> Default for Point<DIMS> {
-    fn default() -> Point<DIMS> {
+impl<T: Float, const DIMS: usize> Default for Point<DIMS, T> {
+    fn default() -> Point<DIMS, T> {
         Self::zero()
     }
 }
 
-impl<I, const DIMS: usize> Index<I> for Point<DIMS>
+impl<I, T, const DIMS: usize> Index<I> for Point<DIMS, T>
 where
-    [Scalar]: Index<I>,
+    [T]: Index<I>,
 {
-    type Output = <[Scalar] as Index<I>>::Output;
+    type Output = <[T] as Index<I>>::Output;
     fn index(&self, index: I) -> &Self::Output {
         self.0.index(index)
     }
 }
 
-impl<I, const DIMS: usize> IndexMut<I> for Point<DIMS>
+impl<I, T, const DIMS: usize> IndexMut<I> for Point<DIMS, T>
 where
-    [Scalar]: IndexMut<I>,
+    [T]: IndexMut<I>,
 {
     fn index_mut(&mut self, index: I) -> &mut Self::Output {
         self.0.index_mut(index)
     }
 }
 
+macro_rules! impl_scalar_left_op {
+    ($op_trait:ident $fname:ident $scalar:ty) => {
+        impl<const DIMS: usize> $op_trait<Point<DIMS, $scalar>> for $scalar {
+            type Output = Point<DIMS, $scalar>;
+
+            fn $fname(self, mut rhs: Point<DIMS, $scalar>) -> Self::Output {
+                $fname::assign_l_scalar::<$scalar, DIMS>(rhs.as_mut_slice(), self);
+                rhs
+            }
+        }
+
+        impl<const DIMS: usize> $op_trait<&Point<DIMS, $scalar>> for $scalar {
+            type Output = Point<DIMS, $scalar>;
+
+            fn $fname(self, rhs: &Point<DIMS, $scalar>) -> Self::Output {
+                let mut ret = rhs.clone();
+                $fname::assign_l_scalar::<$scalar, DIMS>(ret.as_mut_slice(), self);
+                ret
+            }
+        }
+    };
+}
+
 macro_rules! impl_op {
     ($op_trait:ident $fname:ident $op:tt $op_assign:tt $op_assign_trait:ident $fname_assign:ident) => {
+        #[allow(clippy::assign_op_pattern)]
         mod $fname {
-            use super::Scalar;
-            pub fn assign_l<const DIMS: usize>(left: &mut [Scalar], right: &[Scalar]) {
+            use super::Float;
+            pub fn assign_l<T: Float, const DIMS: usize>(left: &mut [T], right: &[T]) {
                 for i in 0..DIMS {
                     left[i] $op_assign right[i]
                 }
             }
-            pub fn assign_l_scalar<const DIMS: usize>(left: &mut [Scalar], right: Scalar) {
+            pub fn assign_l_scalar<T: Float, const DIMS: usize>(left: &mut [T], right: T) {
                 for i in 0..DIMS {
                     left[i] $op_assign right
                 }
             }
-            pub fn assign_r<const DIMS: usize>(left: &[Scalar], right: &mut [Scalar]) {
+            pub fn assign_r<T: Float, const DIMS: usize>(left: &[T], right: &mut [T]) {
                 for i in 0..DIMS {
                     right[i] = left[i] $op right[i]
                 }
             }
         }
 
-        impl<const DIMS: usize> $op_trait<Point<DIMS>> for Point<DIMS> {
-            type Output = Point<DIMS>;
+        impl<T: Float, const DIMS: usize> $op_trait<Point<DIMS, T>> for Point<DIMS, T> {
+            type Output = Point<DIMS, T>;
 
-            fn $fname(mut self, rhs: Point<DIMS>) -> Self::Output {
-                $fname::assign_l::<DIMS>(self.as_mut_slice(), rhs.as_slice());
+            fn $fname(mut self, rhs: Point<DIMS, T>) -> Self::Output {
+                $fname::assign_l::<T, DIMS>(self.as_mut_slice(), rhs.as_slice());
                 self
             }
         }
 
-        impl<const DIMS: usize> $op_trait<&Point<DIMS>> for Point<DIMS> {
-            type Output = Point<DIMS>;
+        impl<T: Float, const DIMS: usize> $op_trait<&Point<DIMS, T>> for Point<DIMS, T> {
+            type Output = Point<DIMS, T>;
 
-            fn $fname(mut self, rhs: &Point<DIMS>) -> Self::Output {
-                $fname::assign_l::<DIMS>(self.as_mut_slice(), rhs.as_slice());
+            fn $fname(mut self, rhs: &Point<DIMS, T>) -> Self::Output {
+                $fname::assign_l::<T, DIMS>(self.as_mut_slice(), rhs.as_slice());
                 self
             }
         }
 
-        impl<const DIMS: usize> $op_trait<Point<DIMS>> for &Point<DIMS> {
-            type Output = Point<DIMS>;
+        impl<T: Float, const DIMS: usize> $op_trait<Point<DIMS, T>> for &Point<DIMS, T> {
+            type Output = Point<DIMS, T>;
 
-            fn $fname(self, mut rhs: Point<DIMS>) -> Self::Output {
-                $fname::assign_r::<DIMS>(self.as_slice(), rhs.as_mut_slice());
+            fn $fname(self, mut rhs: Point<DIMS, T>) -> Self::Output {
+                $fname::assign_r::<T, DIMS>(self.as_slice(), rhs.as_mut_slice());
                 rhs
             }
         }
 
-        impl<const DIMS: usize> $op_trait<&Point<DIMS>> for &Point<DIMS> {
-            type Output = Point<DIMS>;
+        impl<T: Float, const DIMS: usize> $op_trait<&Point<DIMS, T>> for &Point<DIMS, T> {
+            type Output = Point<DIMS, T>;
 
-            fn $fname(self, rhs: &Point<DIMS>) -> Self::Output {
+            fn $fname(self, rhs: &Point<DIMS, T>) -> Self::Output {
                 let mut out = self.clone();
-                $fname::assign_l::<DIMS>(out.as_mut_slice(), rhs.as_slice());
+                $fname::assign_l::<T, DIMS>(out.as_mut_slice(), rhs.as_slice());
                 out
             }
         }
 
-        impl<const DIMS: usize> $op_assign_trait<Point<DIMS>> for Point<DIMS> {
-            fn $fname_assign(&mut self, rhs: Point<DIMS>) {
-                $fname::assign_l::<DIMS>(self.as_mut_slice(), rhs.as_slice());
+        impl<T: Float, const DIMS: usize> $op_assign_trait<Point<DIMS, T>> for Point<DIMS, T> {
+            fn $fname_assign(&mut self, rhs: Point<DIMS, T>) {
+                $fname::assign_l::<T, DIMS>(self.as_mut_slice(), rhs.as_slice());
             }
         }
 
-        impl<const DIMS: usize> $op_assign_trait<&Point<DIMS>> for Point<DIMS> {
-            fn $fname_assign(&mut self, rhs: &Point<DIMS>) {
-                $fname::assign_l::<DIMS>(self.as_mut_slice(), rhs.as_slice());
+        impl<T: Float, const DIMS: usize> $op_assign_trait<&Point<DIMS, T>> for Point<DIMS, T> {
+            fn $fname_assign(&mut self, rhs: &Point<DIMS, T>) {
+                $fname::assign_l::<T, DIMS>(self.as_mut_slice(), rhs.as_slice());
             }
         }
 
-        impl<const DIMS: usize> $op_trait<Scalar> for Point<DIMS> {
-            type Output = Point<DIMS>;
+        impl<T: Float, const DIMS: usize> $op_trait<T> for Point<DIMS, T> {
+            type Output = Point<DIMS, T>;
 
-            fn $fname(mut self, rhs: Scalar) -> Self::Output {
-                $fname::assign_l_scalar::<DIMS>(self.as_mut_slice(), rhs);
+            fn $fname(mut self, rhs: T) -> Self::Output {
+                $fname::assign_l_scalar::<T, DIMS>(self.as_mut_slice(), rhs);
                 self
             }
         }
 
-        impl<const DIMS: usize> $op_trait<Scalar> for &Point<DIMS> {
-            type Output = Point<DIMS>;
+        impl<T: Float, const DIMS: usize> $op_trait<T> for &Point<DIMS, T> {
+            type Output = Point<DIMS, T>;
 
-            fn $fname(self, rhs: Scalar) -> Self::Output {
+            fn $fname(self, rhs: T) -> Self::Output {
                 let mut ret = self.clone();
-                $fname::assign_l_scalar::<DIMS>(ret.as_mut_slice(), rhs);
+                $fname::assign_l_scalar::<T, DIMS>(ret.as_mut_slice(), rhs);
                 ret
             }
         }
 
-        impl<const DIMS: usize> $op_trait<Point<DIMS>> for Scalar {
-            type Output = Point<DIMS>;
-
-            fn $fname(self, mut rhs: Point<DIMS>) -> Self::Output {
-                $fname::assign_l_scalar::<DIMS>(rhs.as_mut_slice(), self);
-                rhs
-            }
-        }
-
-        impl<const DIMS: usize> $op_trait<&Point<DIMS>> for Scalar {
-            type Output = Point<DIMS>;
-
-            fn $fname(self, rhs: &Point<DIMS>) -> Self::Output {
-                let mut ret = rhs.clone();
-                $fname::assign_l_scalar::<DIMS>(ret.as_mut_slice(), self);
-                ret
-            }
-        }
+        impl_scalar_left_op!($op_trait $fname f32);
+        impl_scalar_left_op!($op_trait $fname f64);
     };
 }
 impl_op!(Add add + += AddAssign add_assign);
@@ -233,8 +311,8 @@ impl_op!(Mul mul * *= MulAssign mul_assign);
 impl_op!(Div div / /= DivAssign div_assign);
 impl_op!(Rem rem % %= RemAssign rem_assign);
 
-impl<const DIMS: usize> Neg for Point<DIMS> {
-    type Output = Point<DIMS>;
+impl<T: Float, const DIMS: usize> Neg for Point<DIMS, T> {
+    type Output = Point<DIMS, T>;
     fn neg(mut self) -> Self::Output {
         for i in 0..DIMS {
             self.0[i] = -self.0[i]
@@ -242,8 +320,8 @@ impl<const DIMS: usize> Neg for Point<DIMS> {
         self
     }
 }
-impl<const DIMS: usize> Neg for &Point<DIMS> {
-    type Output = Point<DIMS>;
+impl<T: Float, const DIMS: usize> Neg for &Point<DIMS, T> {
+    type Output = Point<DIMS, T>;
     fn neg(self) -> Self::Output {
         let out = self.clone();
         -out
@@ -0,0 +1,306 @@
+/*!
+ * Feature scaling applied to points before they are clustered.
+ *
+ * Tree thresholds are distances, so a dimension with a large range dominates clustering unless
+ * the dimensions are brought to comparable scales first. A scaler is fitted to (a sample of) the
+ * data and then applied to each point as it is inserted, e.g. with
+ * [CFTree::from_iter_with_transform](crate::cftree::CFTree::from_iter_with_transform). Cluster
+ * centers can be mapped back to the original scale with [Transform::inverse].
+ *
+ * Missing (NaN) coordinates are ignored when fitting, and stay missing when transformed.
+ */
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    point::{Float, Point, Scalar},
+};
+
+/// An invertible transformation of points of type `P` into points of type `Q` (by default, of the
+/// same type).
+pub trait Transform<P, Q = P> {
+    /// Applies this transformation to `p`.
+    fn transform(&self, p: P) -> Q;
+    /// Reverts this transformation (exactly or approximately), mapping a transformed point back to
+    /// the original space.
+    fn inverse(&self, q: Q) -> P;
+}
+
+/// Rescales each dimension to the unit interval, based on the minimum and maximum values seen when
+/// fitting. Dimensions which are constant (or never observed) are mapped to zero.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct MinMaxScaler<const DIMS: usize, T = Scalar> {
+    min: Point<DIMS, T>,
+    max: Point<DIMS, T>,
+}
+
+impl<T: Float, const DIMS: usize> MinMaxScaler<DIMS, T> {
+    /// Fits a scaler to the range of the points in `iter`.
+    pub fn fit<'a, I: IntoIterator<Item = &'a Point<DIMS, T>>>(iter: I) -> MinMaxScaler<DIMS, T> {
+        let mut min = Point::from_arr([T::infinity(); DIMS]);
+        let mut max = Point::from_arr([T::neg_infinity(); DIMS]);
+        for p in iter {
+            for (i, &x) in p.as_slice().iter().enumerate() {
+                if !x.is_nan() {
+                    min[i] = min[i].min(x);
+                    max[i] = max[i].max(x);
+                }
+            }
+        }
+        for i in 0..DIMS {
+            if min[i] > max[i] {
+                min[i] = T::zero();
+                max[i] = T::zero();
+            }
+        }
+        MinMaxScaler { min, max }
+    }
+
+    /// Minimum value of each dimension.
+    pub fn min(&self) -> &Point<DIMS, T> {
+        &self.min
+    }
+
+    /// Maximum value of each dimension.
+    pub fn max(&self) -> &Point<DIMS, T> {
+        &self.max
+    }
+
+    fn range(&self, i: usize) -> T {
+        match self.max[i] > self.min[i] {
+            true => self.max[i] - self.min[i],
+            false => T::one(),
+        }
+    }
+}
+
+impl<T: Float, const DIMS: usize> Transform<Point<DIMS, T>> for MinMaxScaler<DIMS, T> {
+    fn transform(&self, mut p: Point<DIMS, T>) -> Point<DIMS, T> {
+        for i in 0..DIMS {
+            p[i] = (p[i] - self.min[i]) / self.range(i);
+        }
+        p
+    }
+
+    fn inverse(&self, mut p: Point<DIMS, T>) -> Point<DIMS, T> {
+        for i in 0..DIMS {
+            p[i] = p[i] * self.range(i) + self.min[i];
+        }
+        p
+    }
+}
+
+/// Centers each dimension on zero and scales it to unit variance, based on the mean and (population)
+/// standard deviation seen when fitting. Dimensions with zero variance are only centered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct StandardScaler<const DIMS: usize, T = Scalar> {
+    mean: Point<DIMS, T>,
+    std_dev: Point<DIMS, T>,
+}
+
+impl<T: Float, const DIMS: usize> StandardScaler<DIMS, T> {
+    /// Fits a scaler to the mean and standard deviation of the points in `iter`, in a single pass.
+    pub fn fit<'a, I: IntoIterator<Item = &'a Point<DIMS, T>>>(iter: I) -> StandardScaler<DIMS, T> {
+        let mut running = RunningStandardizer::new();
+        for p in iter {
+            running.update(p);
+        }
+        running.scaler()
+    }
+
+    /// Mean of each dimension.
+    pub fn mean(&self) -> &Point<DIMS, T> {
+        &self.mean
+    }
+
+    /// Standard deviation of each dimension.
+    pub fn std_dev(&self) -> &Point<DIMS, T> {
+        &self.std_dev
+    }
+
+    fn scale(&self, i: usize) -> T {
+        match self.std_dev[i] > T::zero() {
+            true => self.std_dev[i],
+            false => T::one(),
+        }
+    }
+
+    /// How far `other` has moved from this scaler: the largest change over all dimensions of
+    /// either the mean (in units of this scaler's standard deviation) or the relative change of
+    /// the standard deviation.
+    pub fn drift(&self, other: &StandardScaler<DIMS, T>) -> T {
+        (0..DIMS).fold(T::zero(), |drift, i| {
+            let shift = (other.mean[i] - self.mean[i]).abs() / self.scale(i);
+            let stretch = (other.scale(i) / self.scale(i) - T::one()).abs();
+            drift.max(shift).max(stretch)
+        })
+    }
+
+    /// Per-dimension `(scale, shift)` mapping points standardized by this scaler to points
+    /// standardized by `other`, i.e. `other.transform(self.inverse(x)) == x * scale + shift`.
+    pub fn rebase(&self, other: &StandardScaler<DIMS, T>) -> (Point<DIMS, T>, Point<DIMS, T>) {
+        let mut scale = Point::default();
+        let mut shift = Point::default();
+        for i in 0..DIMS {
+            scale[i] = self.scale(i) / other.scale(i);
+            shift[i] = (self.mean[i] - other.mean[i]) / other.scale(i);
+        }
+        (scale, shift)
+    }
+}
+
+impl<T: Float, const DIMS: usize> Transform<Point<DIMS, T>> for StandardScaler<DIMS, T> {
+    fn transform(&self, mut p: Point<DIMS, T>) -> Point<DIMS, T> {
+        for i in 0..DIMS {
+            p[i] = (p[i] - self.mean[i]) / self.scale(i);
+        }
+        p
+    }
+
+    fn inverse(&self, mut p: Point<DIMS, T>) -> Point<DIMS, T> {
+        for i in 0..DIMS {
+            p[i] = p[i] * self.scale(i) + self.mean[i];
+        }
+        p
+    }
+}
+
+/// Per-dimension mean and standard deviation of a stream of points, updated online with Welford's
+/// algorithm. Transforming a point standardizes it with the current estimates; see
+/// [StandardizedCFTree](crate::standardized::StandardizedCFTree) for a tree which keeps its
+/// features consistent as the estimates change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct RunningStandardizer<const DIMS: usize, T = Scalar> {
+    /// Number of observed (non-missing) values of each dimension
+    n: Point<DIMS, T>,
+    mean: Point<DIMS, T>,
+    /// Sum of squared deviations from the mean of each dimension
+    m2: Point<DIMS, T>,
+}
+
+impl<T: Float, const DIMS: usize> Default for RunningStandardizer<DIMS, T> {
+    fn default() -> RunningStandardizer<DIMS, T> {
+        RunningStandardizer::new()
+    }
+}
+
+impl<T: Float, const DIMS: usize> RunningStandardizer<DIMS, T> {
+    pub fn new() -> RunningStandardizer<DIMS, T> {
+        RunningStandardizer {
+            n: Point::default(),
+            mean: Point::default(),
+            m2: Point::default(),
+        }
+    }
+
+    /// Updates the estimates with the (non-missing) coordinates of `p`.
+    pub fn update(&mut self, p: &Point<DIMS, T>) {
+        for (i, &x) in p.as_slice().iter().enumerate() {
+            if !x.is_nan() {
+                self.n[i] += T::one();
+                let delta = x - self.mean[i];
+                self.mean[i] += delta / self.n[i];
+                self.m2[i] += delta * (x - self.mean[i]);
+            }
+        }
+    }
+
+    /// Number of values observed so far in each dimension.
+    pub fn count(&self) -> &Point<DIMS, T> {
+        &self.n
+    }
+
+    /// Snapshot of the current estimates, as a fixed scaler.
+    pub fn scaler(&self) -> StandardScaler<DIMS, T> {
+        let mut std_dev = Point::default();
+        for i in 0..DIMS {
+            if self.n[i] > T::zero() {
+                std_dev[i] = (self.m2[i] / self.n[i]).sqrt();
+            }
+        }
+        StandardScaler {
+            mean: self.mean.clone(),
+            std_dev,
+        }
+    }
+}
+
+impl<T: Float, const DIMS: usize> Transform<Point<DIMS, T>> for RunningStandardizer<DIMS, T> {
+    fn transform(&self, p: Point<DIMS, T>) -> Point<DIMS, T> {
+        self.scaler().transform(p)
+    }
+
+    fn inverse(&self, p: Point<DIMS, T>) -> Point<DIMS, T> {
+        self.scaler().inverse(p)
+    }
+}
+
+/// Cluster features which can be re-expressed in terms of the points they summarize mapped through
+/// the per-dimension affine transformation `x * scale + shift`, without access to those points.
+pub trait Rescale<const DIMS: usize>: CFeature<DIMS> {
+    fn rescale(&self, scale: &FeaturePoint<Self, DIMS>, shift: &FeaturePoint<Self, DIMS>) -> Self;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cftree::{BasicConfig, BirchCFTree};
+
+    #[test]
+    fn scalers() {
+        // the second dimension has a thousand times the range of the first
+        let points = (0..200)
+            .map(|i| {
+                let offset = if i < 100 { 0.0 } else { 10.0 };
+                Point::from_arr([offset + (i % 10) as f64 * 0.1, (i % 7) as f64 * 1000.0])
+            })
+            .chain(Some(Point::from_arr([f64::NAN, 0.0])))
+            .collect::<Vec<_>>();
+
+        let min_max = MinMaxScaler::fit(&points);
+        assert_eq!(min_max.min().as_slice(), &[0.0, 0.0]);
+        assert_eq!(min_max.max().as_slice(), &[10.9, 6000.0]);
+        let scaled = min_max.transform(points[150].clone());
+        assert!(scaled.as_slice().iter().all(|&x| (0.0..=1.0).contains(&x)));
+        let restored = min_max.inverse(scaled);
+        assert!((restored - &points[150]).norm2() < 1e-18);
+        assert!(min_max.transform(points[200].clone())[0].is_nan());
+
+        let standard = StandardScaler::fit(&points[..200]);
+        assert!((standard.mean()[0] - 5.45).abs() < 1e-9);
+        let scaled = points[..200]
+            .iter()
+            .map(|p| standard.transform(p.clone()))
+            .collect::<Vec<_>>();
+        for i in 0..2 {
+            let mean = scaled.iter().map(|p| p[i]).sum::<f64>() / 200.0;
+            let var = scaled.iter().map(|p| (p[i] - mean).powi(2)).sum::<f64>() / 200.0;
+            assert!(mean.abs() < 1e-9);
+            assert!((var - 1.0).abs() < 1e-9);
+        }
+
+        // once standardized, the two groups in the first dimension are two units apart, comparable to
+        // the spread of the second dimension, and no cluster mixes them
+        let config = BasicConfig::builder()
+            .capacity(4, 8)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let tree = BirchCFTree::<2>::from_iter_with_transform(
+            points[..200].iter().cloned(),
+            &standard,
+            config,
+        );
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        assert!(clusters.iter().all(|c| c.size > 0.0));
+        let centers = clusters
+            .iter()
+            .map(|c| standard.inverse(c.center.clone()))
+            .collect::<Vec<_>>();
+        assert!(centers.iter().all(|c| c[0] < 1.0 || c[0] > 10.0));
+    }
+}
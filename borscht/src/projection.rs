@@ -0,0 +1,182 @@
+/*!
+ * Random projections, for reducing the dimensionality of points before they are clustered.
+ *
+ * Clustering very high-dimensional points (e.g. embeddings with hundreds or thousands of
+ * dimensions) is expensive, and the tree only needs the distances between points. By the
+ * Johnson-Lindenstrauss lemma, multiplying points by a random `OUT x IN` matrix approximately
+ * preserves those distances with `OUT` far smaller than `IN`. A [RandomProjection] is a
+ * [Transform] from `Point<IN>` to `Point<OUT>`, generated reproducibly from a seed, e.g. for use
+ * with [CFTree::from_iter_with_transform](crate::cftree::CFTree::from_iter_with_transform).
+ */
+
+use alloc::vec::Vec;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    point::{Float, Point, Scalar},
+    preprocess::Transform,
+    rng::SplitMix64,
+    sparse::SparsePoint,
+};
+
+/// Distribution of the entries of a random projection matrix. Entries are scaled so that squared
+/// norms (and squared distances) are preserved in expectation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Distribution {
+    /// Entries drawn from a normal distribution.
+    Gaussian,
+    /// Entries of either sign with probability 1/6 each, and zero otherwise
+    /// ([Achlioptas](https://doi.org/10.1016/S0022-0000(03)00025-4)). Only a third of the entries
+    /// are non-zero, so projecting is about three times faster.
+    Achlioptas,
+}
+
+/// A random linear map from `IN` to `OUT` dimensions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct RandomProjection<const IN: usize, const OUT: usize, T = Scalar> {
+    distribution: Distribution,
+    seed: u64,
+    /// Rows of the projection matrix
+    rows: Vec<SparsePoint<IN, T>>,
+}
+
+impl<T: Float, const IN: usize, const OUT: usize> RandomProjection<IN, OUT, T> {
+    /// Generates a projection with entries drawn from `distribution`. The same seed always
+    /// generates the same projection.
+    pub fn new(distribution: Distribution, seed: u64) -> RandomProjection<IN, OUT, T> {
+        let mut rng = SplitMix64(seed);
+        let rows = (0..OUT)
+            .map(|_| {
+                SparsePoint::new((0..IN).map(|idx| {
+                    let value = match distribution {
+                        Distribution::Gaussian => rng.normal() / (OUT as Scalar).sqrt(),
+                        Distribution::Achlioptas => {
+                            let magnitude = (3.0 / OUT as Scalar).sqrt();
+                            match (rng.uniform() * 6.0) as u8 {
+                                0 => magnitude,
+                                1 => -magnitude,
+                                _ => 0.0,
+                            }
+                        }
+                    };
+                    (idx, T::from_scalar(value))
+                }))
+            })
+            .collect();
+        RandomProjection {
+            distribution,
+            seed,
+            rows,
+        }
+    }
+
+    pub fn distribution(&self) -> Distribution {
+        self.distribution
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Rows of the projection matrix.
+    pub fn rows(&self) -> &[SparsePoint<IN, T>] {
+        &self.rows
+    }
+}
+
+/// Projects points from `IN` down to `OUT` dimensions. The inverse maps a projected point back by
+/// the transpose of the projection, which only recovers the original point in expectation (over
+/// the random matrix); it is mainly useful for rough reconstructions of cluster centers.
+impl<T: Float, const IN: usize, const OUT: usize> Transform<Point<IN, T>, Point<OUT, T>>
+    for RandomProjection<IN, OUT, T>
+{
+    fn transform(&self, p: Point<IN, T>) -> Point<OUT, T> {
+        let mut out = Point::default();
+        for (j, row) in self.rows.iter().enumerate() {
+            out[j] = row
+                .entries()
+                .iter()
+                .fold(T::zero(), |acc, &(idx, value)| acc + value * p[idx]);
+        }
+        out
+    }
+
+    fn inverse(&self, q: Point<OUT, T>) -> Point<IN, T> {
+        let mut out = Point::default();
+        for (j, row) in self.rows.iter().enumerate() {
+            out += &(row * q[j]);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cftree::{BasicConfig, BirchCFTree};
+
+    const IN: usize = 256;
+    const OUT: usize = 64;
+
+    #[test]
+    fn projection() {
+        let mut rng = SplitMix64(7);
+        let mut point = || {
+            let mut p = Point::<IN>::default();
+            for x in p.as_mut_slice() {
+                *x = rng.normal();
+            }
+            p
+        };
+        let points = (0..20).map(|_| point()).collect::<Vec<_>>();
+
+        for distribution in [Distribution::Gaussian, Distribution::Achlioptas] {
+            let projection = RandomProjection::<IN, OUT>::new(distribution, 42);
+            assert_eq!(
+                projection.rows(),
+                RandomProjection::<IN, OUT>::new(distribution, 42).rows()
+            );
+            assert_ne!(
+                projection.rows(),
+                RandomProjection::<IN, OUT>::new(distribution, 43).rows()
+            );
+
+            // pairwise distances are roughly preserved
+            for pair in points.windows(2) {
+                let original = (&pair[0] - &pair[1]).norm2();
+                let projected = (projection.transform(pair[0].clone())
+                    - projection.transform(pair[1].clone()))
+                .norm2();
+                assert!((0.5..1.5).contains(&(projected / original)));
+            }
+        }
+
+        // two well-separated groups stay separated after projecting
+        let projection = RandomProjection::<IN, OUT>::new(Distribution::Achlioptas, 1);
+        let offset = Point::from_arr([10.0; IN]);
+        let grouped = points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| match i % 2 {
+                0 => p * 0.1,
+                _ => p * 0.1 + &offset,
+            })
+            .collect::<Vec<_>>();
+        let tree = BirchCFTree::<OUT>::from_iter_with_transform(
+            grouped,
+            &projection,
+            BasicConfig::builder()
+                .capacity(2, 4)
+                .threshold(10.0)
+                .build()
+                .unwrap(),
+        );
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.len(), 2);
+        assert!(clusters.iter().all(|c| c.size == 10.0));
+        let center = projection.inverse(clusters[1].center.clone());
+        assert!(center.as_slice().iter().filter(|&&x| x > 5.0).count() > IN / 2);
+    }
+}
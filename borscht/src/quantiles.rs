@@ -0,0 +1,273 @@
+/*!
+ * Approximate quantiles of the points absorbed by leaf entries.
+ *
+ * The center of a cluster is the mean of its points, which can be misleading for skewed data. A
+ * tree can keep a [QuantileSketch] in each leaf entry (see
+ * [TreeConfig::quantile_compression](crate::cftree::TreeConfig::quantile_compression)), from
+ * which approximate medians and other quantiles of each dimension are available. Sketches are
+ * [t-digests](https://arxiv.org/abs/1902.04023): each dimension keeps a sorted list of centroids
+ * (weighted means of adjacent values), whose weights are bounded so that centroids near the
+ * extremes stay small, which keeps tail quantiles accurate. Like samples, sketches of merged leaf
+ * entries are merged.
+ */
+
+use alloc::{vec, vec::Vec};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cfeature::CFeature,
+    cftree::CFTree,
+    point::{Float, Point, Scalar},
+    summary::collect_leaf_entries,
+};
+
+/// Weighted mean of adjacent values of a dimension.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Centroid {
+    mean: Scalar,
+    weight: Scalar,
+}
+
+/// Sketch of the distribution of each dimension of the points absorbed by a leaf entry, stored in
+/// [Scalar](crate::point::Scalar) precision.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuantileSketch<const DIMS: usize> {
+    /// Centroids of each dimension, sorted by mean (no dimensions if the sketch is empty)
+    centroids: Vec<Vec<Centroid>>,
+}
+
+impl<const DIMS: usize> QuantileSketch<DIMS> {
+    /// Sketch of the single point `p`. Missing (NaN) coordinates are left out.
+    pub(crate) fn with_point<T: Float>(p: &Point<DIMS, T>) -> QuantileSketch<DIMS> {
+        QuantileSketch {
+            centroids: p
+                .as_slice()
+                .iter()
+                .map(|x| match x.is_nan() {
+                    true => vec![],
+                    false => vec![Centroid {
+                        mean: x.to_scalar(),
+                        weight: 1.0,
+                    }],
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.centroids.is_empty()
+    }
+
+    /// Number of values sketched for dimension `dim`.
+    pub fn count(&self, dim: usize) -> Scalar {
+        self.centroids
+            .get(dim)
+            .map_or(0.0, |centroids| centroids.iter().map(|c| c.weight).sum())
+    }
+
+    /// Approximate `q`-quantile (for `q` from 0 to 1) of each dimension, interpolated between the
+    /// centroids of the dimension; NaN for dimensions without values. `None` if the sketch is
+    /// empty.
+    pub fn quantile(&self, q: Scalar) -> Option<Point<DIMS>> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(Point::from_fn(|d| quantile(&self.centroids[d], q)))
+    }
+
+    /// Approximate median of each dimension; see [QuantileSketch::quantile].
+    pub fn median(&self) -> Option<Point<DIMS>> {
+        self.quantile(0.5)
+    }
+
+    /// Number of centroids kept for each dimension.
+    pub fn centroids(&self) -> Vec<usize> {
+        self.centroids.iter().map(Vec::len).collect()
+    }
+
+    /// Bytes allocated on the heap by this sketch.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.centroids.capacity() * core::mem::size_of::<Vec<Centroid>>()
+            + self
+                .centroids
+                .iter()
+                .map(|centroids| centroids.capacity() * core::mem::size_of::<Centroid>())
+                .sum::<usize>()
+    }
+
+    /// Applies `scale` and `shift` to the sketched values of each dimension (e.g. to rescale them
+    /// along with their cluster feature).
+    pub(crate) fn rescale(mut self, scale: &Point<DIMS>, shift: &Point<DIMS>) -> Self {
+        for (d, centroids) in self.centroids.iter_mut().enumerate() {
+            for centroid in centroids.iter_mut() {
+                centroid.mean = centroid.mean * scale[d] + shift[d];
+            }
+            if scale[d] < 0.0 {
+                centroids.reverse();
+            }
+        }
+        self
+    }
+
+    /// Merges `other` into this sketch, compressing the centroids of each dimension with
+    /// `compression` (see [compress]).
+    pub(crate) fn merge(&mut self, other: QuantileSketch<DIMS>, compression: usize) {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            *self = other;
+            return;
+        }
+        for (centroids, other) in self.centroids.iter_mut().zip(other.centroids) {
+            let merged = merge_sorted(core::mem::take(centroids), other);
+            *centroids = compress(merged, compression);
+        }
+    }
+}
+
+/// Merges two lists of centroids sorted by mean.
+fn merge_sorted(left: Vec<Centroid>, right: Vec<Centroid>) -> Vec<Centroid> {
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+    loop {
+        let next = match (left.peek(), right.peek()) {
+            (Some(l), Some(r)) if l.mean <= r.mean => left.next(),
+            (Some(_), Some(_)) => right.next(),
+            (Some(_), None) => left.next(),
+            (None, _) => right.next(),
+        };
+        match next {
+            Some(centroid) => merged.push(centroid),
+            None => return merged,
+        }
+    }
+}
+
+/// Merges adjacent centroids of `centroids` as long as the weight of each stays within the bound
+/// of the t-digest for its quantile, `4 n q (1 - q) / compression`, which keeps a few times
+/// `compression` centroids at most. Lists of up to `compression` centroids are kept as they are.
+fn compress(centroids: Vec<Centroid>, compression: usize) -> Vec<Centroid> {
+    if centroids.len() <= compression {
+        return centroids;
+    }
+    let total = centroids.iter().map(|c| c.weight).sum::<Scalar>();
+    let mut compressed: Vec<Centroid> = Vec::with_capacity(compression);
+    // weight of the centroids before the last compressed one
+    let mut before = 0.0;
+    for centroid in centroids {
+        if let Some(last) = compressed.last_mut() {
+            let weight = last.weight + centroid.weight;
+            let q = (before + weight / 2.0) / total;
+            if weight <= 4.0 * total * q * (1.0 - q) / compression as Scalar {
+                last.mean += (centroid.mean - last.mean) * centroid.weight / weight;
+                last.weight = weight;
+                continue;
+            }
+            before += last.weight;
+        }
+        compressed.push(centroid);
+    }
+    compressed
+}
+
+/// Approximate `q`-quantile of the values summarized by `centroids`: each centroid stands for
+/// the value at the middle of its weight, and quantiles in between are interpolated.
+fn quantile(centroids: &[Centroid], q: Scalar) -> Scalar {
+    let total = centroids.iter().map(|c| c.weight).sum::<Scalar>();
+    let target = q.clamp(0.0, 1.0) * total;
+    let mut before = 0.0;
+    let mut previous: Option<(Scalar, Scalar)> = None;
+    for centroid in centroids {
+        let middle = before + centroid.weight / 2.0;
+        if target <= middle {
+            return match previous {
+                None => centroid.mean,
+                Some((previous_middle, previous_mean)) => {
+                    let t = (target - previous_middle) / (middle - previous_middle);
+                    previous_mean + (centroid.mean - previous_mean) * t
+                }
+            };
+        }
+        previous = Some((middle, centroid.mean));
+        before += centroid.weight;
+    }
+    previous.map_or(Scalar::NAN, |(_, mean)| mean)
+}
+
+impl<CF: CFeature<DIMS>, TC, const DIMS: usize> CFTree<CF, DIMS, TC> {
+    /// Quantile sketches of the points absorbed by each leaf cluster, indexed by cluster id (see
+    /// [ClusterSummary::id](crate::summary::ClusterSummary::id)). Empty unless the tree keeps
+    /// sketches (see
+    /// [TreeConfig::quantile_compression](crate::cftree::TreeConfig::quantile_compression)).
+    pub fn cluster_sketches(&self) -> Vec<&QuantileSketch<DIMS>> {
+        let mut entries = vec![];
+        collect_leaf_entries(self.root(), &mut entries);
+        entries.into_iter().map(|entry| &entry.quantiles).collect()
+    }
+
+    /// Approximate `q`-quantile of each dimension of each leaf cluster, indexed by cluster id, or
+    /// `None` for clusters without sketches; see [QuantileSketch::quantile].
+    pub fn cluster_quantiles(&self, q: Scalar) -> Vec<Option<Point<DIMS>>> {
+        self.cluster_sketches()
+            .into_iter()
+            .map(|sketch| sketch.quantile(q))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cftree::{BasicConfig, BirchCFTree};
+
+    #[test]
+    fn quantiles() {
+        // values merged one at a time, in a scrambled order
+        let mut sketch = QuantileSketch::<1>::default();
+        for i in 0..10000 {
+            let p = Point::from_arr([(i * 7919 % 10000) as Scalar]);
+            sketch.merge(QuantileSketch::with_point(&p), 50);
+        }
+        assert_eq!(sketch.count(0), 10000.0);
+        // a few times the compression, out of 10000 values
+        assert!(sketch.centroids()[0] < 300);
+        for (q, expected) in [(0.5, 5000.0), (0.1, 1000.0), (0.99, 9900.0)] {
+            assert!((sketch.quantile(q).unwrap()[0] - expected).abs() < 50.0);
+        }
+        // the tails are kept exactly
+        assert!(sketch.quantile(0.0).unwrap()[0] < 1.0);
+        assert!(sketch.quantile(1.0).unwrap()[0] > 9998.0);
+        assert_eq!(QuantileSketch::<1>::default().median(), None);
+
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(1e5)
+            .quantile_compression(20)
+            .build()
+            .unwrap();
+        // two skewed clusters, whose medians are far below their means
+        let points = (0..400).map(|i| {
+            let offset = (i % 2) as Scalar * 10000.0;
+            let skewed = ((i / 2) as Scalar / 20.0).powi(3) / 2.0;
+            Point::from_arr([offset + skewed, (i % 2) as Scalar])
+        });
+        let tree = BirchCFTree::<2>::from_iter(points, config);
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.len(), 2);
+        let medians = tree.cluster_quantiles(0.5);
+        for (cluster, median) in clusters.iter().zip(medians) {
+            let median = median.unwrap();
+            let offset = cluster.center[0].round() - cluster.center[0].round() % 10000.0;
+            // the median of (i / 20)^3 / 2 for i up to 200 is 62.5, about half the mean
+            assert!((median[0] - offset - 62.5).abs() < 5.0);
+            assert!(cluster.center[0] - offset > 120.0);
+            assert_eq!(median[1], cluster.center[1]);
+        }
+        assert!(tree
+            .cluster_sketches()
+            .iter()
+            .all(|sketch| sketch.count(0) == 200.0));
+    }
+}
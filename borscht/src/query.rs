@@ -0,0 +1,259 @@
+/*!
+ * Spatial queries over the leaf clusters of a tree.
+ *
+ * Queries descend the tree, skipping any subtree that provably cannot contain a matching leaf
+ * cluster. Returned [ClusterSummary] ids are consistent with those produced by [Node::clusters].
+ */
+
+use alloc::{vec, vec::Vec};
+
+use num_traits::Float as _;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{CFTree, Node},
+    point::Float,
+    summary::ClusterSummary,
+};
+
+impl<CF: CFeature<DIMS>, const DIMS: usize> Node<CF, DIMS> {
+    /// Number of leaf clusters in the tree rooted at this node.
+    pub fn leaf_count(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|entry| entry.child.as_ref().map_or(1, |child| child.leaf_count()))
+            .sum()
+    }
+
+    /// Collects summaries of leaf clusters which satisfy `accept`, only descending into entries
+    /// (and their child nodes) which satisfy `descend`. Returns the id following the last leaf of
+    /// this node.
+    fn collect_leaves<D, A>(
+        &self,
+        depth: usize,
+        mut next_id: usize,
+        descend: &D,
+        accept: &A,
+        out: &mut Vec<ClusterSummary<DIMS, CF::Scalar>>,
+    ) -> usize
+    where
+        D: Fn(&CF, &Node<CF, DIMS>) -> bool,
+        A: Fn(&CF) -> bool,
+    {
+        for entry in &self.entries {
+            match entry.child {
+                Some(ref child) if descend(&entry.feature, child) => {
+                    next_id = child.collect_leaves(depth + 1, next_id, descend, accept, out);
+                }
+                Some(ref child) => {
+                    next_id += child.leaf_count();
+                }
+                None => {
+                    if accept(&entry.feature) {
+                        out.push(ClusterSummary {
+                            id: next_id,
+                            center: entry.feature.center(),
+                            radius: entry.feature.radius(),
+                            diameter: entry.feature.diam(),
+                            size: entry.feature.size(),
+                            depth,
+                        });
+                    }
+                    next_id += 1;
+                }
+            }
+        }
+        next_id
+    }
+
+    /// Returns all leaf clusters whose boundary (the sphere of the cluster's radius around its
+    /// center) lies within distance `r` of `point`. This includes every cluster whose center lies
+    /// within `r` of `point`.
+    pub fn query_radius(
+        &self,
+        point: &FeaturePoint<CF, DIMS>,
+        r: CF::Scalar,
+    ) -> Vec<ClusterSummary<DIMS, CF::Scalar>> {
+        let mut out = vec![];
+        self.collect_leaves(
+            0,
+            0,
+            &|feature: &CF, child: &Node<CF, DIMS>| {
+                center_dist(feature, point) <= r + extent(feature, child)
+            },
+            &|feature: &CF| center_dist(feature, point) <= r + feature.radius(),
+            &mut out,
+        );
+        out
+    }
+
+    /// Returns all leaf clusters whose centers lie inside the axis-aligned box bounded by `min`
+    /// and `max` (inclusive).
+    pub fn query_box(
+        &self,
+        min: &FeaturePoint<CF, DIMS>,
+        max: &FeaturePoint<CF, DIMS>,
+    ) -> Vec<ClusterSummary<DIMS, CF::Scalar>> {
+        let mut out = vec![];
+        self.collect_leaves(
+            0,
+            0,
+            &|feature: &CF, child: &Node<CF, DIMS>| {
+                let center = feature.center();
+                let extent = extent(feature, child);
+                (0..DIMS).all(|d| center[d] + extent >= min[d] && center[d] - extent <= max[d])
+            },
+            &|feature: &CF| {
+                let center = feature.center();
+                (0..DIMS).all(|d| center[d] >= min[d] && center[d] <= max[d])
+            },
+            &mut out,
+        );
+        out
+    }
+}
+
+/// Distance from the center of a feature to a point.
+fn center_dist<CF: CFeature<DIMS>, const DIMS: usize>(
+    feature: &CF,
+    point: &FeaturePoint<CF, DIMS>,
+) -> CF::Scalar {
+    (&feature.center() - point).norm2().sqrt()
+}
+
+/// Upper bound on how far the center or boundary of any leaf cluster beneath a feature can lie
+/// from the feature's center, given the `child` node the feature summarizes.
+///
+/// A leaf cluster with weight `w`, center distance `d`, and radius `r_l` contributes
+/// `w * (d^2 + r_l^2)` to the feature's total squared deviation `n * r^2`, so
+/// `d + r_l <= sqrt(2 * n / w) * r`. Leaf weights can be arbitrarily small (e.g. once decayed, see
+/// [DecayFeature](crate::cfeature::decay::CFeature)), so the bound uses the smallest leaf weight
+/// beneath the child (see [Node::min_leaf_weight]), and is infinite if that isn't positive.
+pub(crate) fn extent<CF: CFeature<DIMS>, const DIMS: usize>(
+    feature: &CF,
+    child: &Node<CF, DIMS>,
+) -> CF::Scalar {
+    let min_weight = child.min_leaf_weight();
+    match min_weight > 0.0 {
+        true => {
+            let weights = feature.size() / CF::Scalar::from_scalar(min_weight);
+            (CF::Scalar::from_scalar(2.0) * weights).sqrt() * feature.radius()
+        }
+        false => CF::Scalar::infinity(),
+    }
+}
+
+impl<CF: CFeature<DIMS>, TC, const DIMS: usize> CFTree<CF, DIMS, TC> {
+    /// Returns all leaf clusters whose boundary lies within distance `r` of `point`. See
+    /// [Node::query_radius].
+    pub fn query_radius(
+        &self,
+        point: &FeaturePoint<CF, DIMS>,
+        r: CF::Scalar,
+    ) -> Vec<ClusterSummary<DIMS, CF::Scalar>> {
+        self.root().query_radius(point, r)
+    }
+
+    /// Returns all leaf clusters whose centers lie inside the axis-aligned box bounded by `min`
+    /// and `max`. See [Node::query_box].
+    pub fn query_box(
+        &self,
+        min: &FeaturePoint<CF, DIMS>,
+        max: &FeaturePoint<CF, DIMS>,
+    ) -> Vec<ClusterSummary<DIMS, CF::Scalar>> {
+        self.root().query_box(min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cftree::{BasicConfig, BetulaCFTree, BirchCFTree},
+        point::Point,
+    };
+
+    fn config() -> BasicConfig {
+        BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(0.5)
+            .build()
+            .unwrap()
+    }
+
+    fn grid() -> Vec<Point<2>> {
+        (0..100)
+            .map(|i| Point::from_arr([(i % 10) as f64, (i / 10) as f64]))
+            .collect()
+    }
+
+    #[test]
+    fn query_radius() {
+        let tree = BirchCFTree::from_iter(grid(), config());
+        let center = Point::from_arr([4.0, 4.0]);
+        let all = tree.clusters().collect::<Vec<_>>();
+        let expected = all
+            .iter()
+            .filter(|c| (&c.center - &center).norm2().sqrt() <= 1.5 + c.radius)
+            .cloned()
+            .collect::<Vec<_>>();
+        let found = tree.query_radius(&center, 1.5);
+        assert!(!found.is_empty());
+        assert_eq!(found, expected);
+
+        let tree = BetulaCFTree::from_iter(grid(), config());
+        assert_eq!(
+            tree.query_radius(&Point::from_arr([50.0, 50.0]), 1.0),
+            vec![]
+        );
+        assert_eq!(
+            tree.query_radius(&center, 100.0).len(),
+            tree.root().leaf_count()
+        );
+    }
+
+    #[test]
+    fn query_box() {
+        let tree = BirchCFTree::from_iter(grid(), config());
+        let min = Point::from_arr([2.0, 3.0]);
+        let max = Point::from_arr([5.0, 4.0]);
+        let found = tree.query_box(&min, &max);
+        let expected = tree
+            .clusters()
+            .filter(|c| (0..2).all(|d| c.center[d] >= min[d] && c.center[d] <= max[d]))
+            .collect::<Vec<_>>();
+        assert!(!found.is_empty());
+        assert_eq!(found, expected);
+
+        assert_eq!(
+            tree.query_box(
+                &Point::from_arr([20.0, 20.0]),
+                &Point::from_arr([30.0, 30.0])
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn decayed_weights() {
+        use crate::{cfeature::decay::CFeature as DecayFeature, cftree::CFTree};
+
+        // a heavy blob, and a lone point whose weight has all but decayed away
+        let mut tree = CFTree::<DecayFeature<2>, 2>::new(config());
+        for p in grid() {
+            tree.insert_feature(DecayFeature::weighted(&p, 1.0));
+        }
+        let far = Point::from_arr([100.0, 0.0]);
+        tree.insert_feature(DecayFeature::weighted(&far, 1e-4));
+        assert!(tree.root().height() > 2);
+        assert!(tree.root().min_leaf_weight() <= 1e-4);
+
+        let found = tree.query_radius(&far, 1.0);
+        assert_eq!(found.len(), 1);
+        assert!((found[0].size - 1e-4).abs() < 1e-12);
+        let found = tree.query_box(
+            &Point::from_arr([99.0, -1.0]),
+            &Point::from_arr([101.0, 1.0]),
+        );
+        assert_eq!(found.len(), 1);
+    }
+}
@@ -0,0 +1,179 @@
+/*!
+ * Bounded samples of the raw points absorbed by leaf entries.
+ *
+ * Cluster features only summarize the points they absorb, so the points themselves are gone once
+ * inserted. For visualization and post-hoc analysis, a tree can keep a [Reservoir] of up to `k`
+ * of the points absorbed by each leaf entry (see
+ * [TreeConfig::reservoir_size](crate::cftree::TreeConfig::reservoir_size)). Reservoirs use
+ * [reservoir sampling](https://en.wikipedia.org/wiki/Reservoir_sampling): every point absorbed
+ * by an entry is equally likely to be in its sample, however large the cluster grows, including
+ * when leaf entries holding samples are merged.
+ *
+ * Samples are drawn from a generator seeded by the ids of the inserted points, so the samples of a
+ * tree are as deterministic as its structure.
+ */
+
+use alloc::{vec, vec::Vec};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    point::{Float, Point},
+    rng::SplitMix64,
+};
+
+/// Uniform sample of up to a fixed number of the points absorbed by a leaf entry, stored in
+/// [Scalar](crate::point::Scalar) precision.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Reservoir<const DIMS: usize> {
+    samples: Vec<Point<DIMS>>,
+    /// Number of points sampled from
+    seen: u64,
+    /// State of the generator used for sampling
+    state: u64,
+}
+
+impl<const DIMS: usize> Reservoir<DIMS> {
+    /// Reservoir holding only the point `p`, with a generator seeded by `seed`.
+    pub(crate) fn with_sample<T: Float>(p: &Point<DIMS, T>, seed: u64) -> Reservoir<DIMS> {
+        Reservoir {
+            samples: vec![p.cast()],
+            seen: 1,
+            state: seed,
+        }
+    }
+
+    /// The sampled points, in no particular order.
+    pub fn samples(&self) -> &[Point<DIMS>] {
+        &self.samples
+    }
+
+    /// Number of points the sample was drawn from.
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+
+    /// Bytes allocated on the heap by this reservoir.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.samples.capacity() * core::mem::size_of::<Point<DIMS>>()
+    }
+
+    /// Applies `f` to each sampled point (e.g. to rescale them along with their cluster feature).
+    pub(crate) fn map_samples<F: FnMut(Point<DIMS>) -> Point<DIMS>>(mut self, f: F) -> Self {
+        self.samples = self.samples.into_iter().map(f).collect();
+        self
+    }
+
+    /// Merges `other` into this reservoir, keeping a uniform sample of at most `capacity` of the
+    /// points seen by either.
+    pub(crate) fn merge(&mut self, mut other: Reservoir<DIMS>, capacity: usize) {
+        if other.seen == 0 {
+            return;
+        }
+        if self.seen == 0 {
+            other.samples.truncate(capacity);
+            *self = other;
+            return;
+        }
+        let mut rng = SplitMix64(self.state ^ other.state.rotate_left(32));
+        let (mut remaining, mut other_remaining) = (self.seen, other.seen);
+        self.seen += other.seen;
+        let complete = |r: &Reservoir<DIMS>| r.samples.len() as u64 == r.seen;
+        if complete(self)
+            && complete(&other)
+            && self.samples.len() + other.samples.len() <= capacity
+        {
+            // both hold every point they have seen, and there is room for all of them
+            self.samples.append(&mut other.samples);
+        } else if other_remaining == 1 && self.samples.len() >= capacity {
+            // a single new point replaces a random sample with probability `capacity / seen`
+            let idx = rng.below(self.seen) as usize;
+            if idx < capacity {
+                self.samples[idx] = other.samples.swap_remove(0);
+            }
+            self.samples.truncate(capacity);
+        } else {
+            // draw without replacement from the union: each draw comes from either reservoir in
+            // proportion to the points it has seen that haven't been drawn yet
+            let mut samples = core::mem::take(&mut self.samples);
+            let mut merged = Vec::with_capacity(capacity);
+            while merged.len() < capacity && remaining + other_remaining > 0 {
+                let (source, count) = match rng.below(remaining + other_remaining) < remaining {
+                    true => (&mut samples, &mut remaining),
+                    false => (&mut other.samples, &mut other_remaining),
+                };
+                let idx = rng.below(source.len() as u64) as usize;
+                merged.push(source.swap_remove(idx));
+                *count -= 1;
+            }
+            self.samples = merged;
+        }
+        self.state = rng.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cftree::{BasicConfig, BirchCFTree};
+
+    #[test]
+    fn reservoir() {
+        // merging single points keeps a uniform sample of all of them
+        let mut counts = [0usize; 100];
+        for seed in 0..1000 {
+            let mut reservoir = Reservoir::default();
+            for i in 0..100 {
+                let p = Point::from_arr([i as f64]);
+                reservoir.merge(Reservoir::with_sample(&p, seed * 100 + i), 10);
+            }
+            assert_eq!(reservoir.seen(), 100);
+            assert_eq!(reservoir.samples().len(), 10);
+            for p in reservoir.samples() {
+                counts[p[0] as usize] += 1;
+            }
+        }
+        // each point is expected in 100 of the 1000 samples
+        assert!(counts.iter().all(|&count| (50..150).contains(&count)));
+
+        // as does merging larger reservoirs
+        let mut from_first = 0;
+        for seed in 0..1000 {
+            let mut first = Reservoir::default();
+            let mut second = Reservoir::default();
+            for i in 0..30 {
+                let p = Point::from_arr([i as f64]);
+                first.merge(Reservoir::with_sample(&p, 2 * seed * 30 + i), 10);
+            }
+            for i in 30..100 {
+                let p = Point::from_arr([i as f64]);
+                second.merge(Reservoir::with_sample(&p, (2 * seed + 1) * 30 + i), 10);
+            }
+            first.merge(second, 10);
+            assert_eq!(first.seen(), 100);
+            assert_eq!(first.samples().len(), 10);
+            from_first += first.samples().iter().filter(|p| p[0] < 30.0).count();
+        }
+        // 30% of the samples are expected from the first reservoir
+        assert!((2500..3500).contains(&from_first));
+
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .reservoir_size(5)
+            .build()
+            .unwrap();
+        let points = (0..200)
+            .map(|i| Point::from_arr([(i % 4) as f64 * 10.0, (i / 4) as f64 * 0.001]))
+            .collect::<Vec<_>>();
+        let tree = BirchCFTree::<2>::from_iter(points, config);
+        for (cluster, reservoir) in tree.clusters().zip(tree.cluster_samples()) {
+            assert_eq!(reservoir.seen() as f64, cluster.size);
+            assert_eq!(reservoir.samples().len(), 5);
+            assert!(reservoir
+                .samples()
+                .iter()
+                .all(|p| p[0] == cluster.center[0].round()));
+        }
+    }
+}
@@ -0,0 +1,35 @@
+/*!
+ * Random number generation for the randomized parts of the crate.
+ */
+
+use crate::point::Scalar;
+
+/// Small seedable generator ([SplitMix64](https://prng.di.unimi.it/splitmix64.c)), so that
+/// randomized structures are reproducible without depending on a random number crate.
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform sample from `[0, 1)`.
+    pub(crate) fn uniform(&mut self) -> Scalar {
+        (self.next_u64() >> 11) as Scalar / (1u64 << 53) as Scalar
+    }
+
+    /// Standard normal sample (Box-Muller transform).
+    pub(crate) fn normal(&mut self) -> Scalar {
+        let radius = (-2.0 * num_traits::Float::ln(1.0 - self.uniform())).sqrt();
+        radius * num_traits::Float::cos(2.0 * core::f64::consts::PI * self.uniform())
+    }
+
+    /// Uniform sample from `0..n` (`n` must be non-zero).
+    pub(crate) fn below(&mut self, n: u64) -> u64 {
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+}
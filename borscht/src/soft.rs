@@ -0,0 +1,148 @@
+/*!
+ * Soft (probabilistic) assignment of points to the leaf clusters of a tree.
+ *
+ * [CFTree::predict_proba] treats the nearest leaf clusters of a point as the components of a
+ * Gaussian mixture with diagonal covariance: each component is centered on the center of its
+ * cluster, has the per-dimension variance of its cluster (see [CFeature::variance]), and is
+ * weighted by the size of its cluster. The probabilities returned are the posterior probabilities
+ * of the point belonging to each component. Variances are most accurate for numerically stable
+ * features such as [BETULA's](crate::cfeature::betula::CFeature).
+ */
+
+use alloc::{vec, vec::Vec};
+
+use num_traits::Float as _;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{variance_padding, CFTree, TreeConfig},
+    point::Float,
+    summary::collect_leaves,
+};
+
+impl<CF: CFeature<DIMS>, TC: TreeConfig, const DIMS: usize> CFTree<CF, DIMS, TC> {
+    /// Probabilities of `p` belonging to each of the `k` leaf clusters nearest to it (under the
+    /// metric of the tree's configuration), as pairs of cluster id (see
+    /// [ClusterSummary::id](crate::summary::ClusterSummary::id)) and probability, in order of
+    /// decreasing probability. Probabilities sum to 1 over the returned clusters; returns an empty
+    /// list if the tree is empty or `k` is 0.
+    ///
+    /// As for [Metric::Mahalanobis](crate::cftree::Metric::Mahalanobis), the variance of every
+    /// dimension is padded by the spread allowed by the tree's threshold, so that small (or
+    /// single-point) clusters don't get arbitrarily sharp likelihoods. Missing coordinates of `p`
+    /// are handled as configured when finding the nearest clusters (see
+    /// [TreeConfig::missing_values]), and left out of the likelihoods (which are then marginal
+    /// over them).
+    pub fn predict_proba(&self, p: &FeaturePoint<CF, DIMS>, k: usize) -> Vec<(usize, CF::Scalar)> {
+        let mut leaves = vec![];
+        collect_leaves(self.root(), &mut leaves);
+        let metric = self.config().metric();
+        let mut nearest = leaves
+            .iter()
+            .enumerate()
+            .map(|(id, feature)| (id, metric.dist2(*feature, p, self.config())))
+            .collect::<Vec<_>>();
+        nearest.sort_by(|(_, left), (_, right)| left.to_scalar().total_cmp(&right.to_scalar()));
+        nearest.truncate(k);
+
+        let padding = variance_padding::<CF::Scalar, _, DIMS>(self.config());
+        let mut proba = nearest
+            .into_iter()
+            .map(|(id, _)| (id, log_likelihood(leaves[id], p, padding)))
+            .collect::<Vec<_>>();
+        // normalize in log space, so that far-away points don't underflow to all zeros
+        let max = proba
+            .iter()
+            .map(|&(_, log_likelihood)| log_likelihood)
+            .fold(CF::Scalar::neg_infinity(), CF::Scalar::max);
+        let mut total = CF::Scalar::from_scalar(0.0);
+        for (_, log_likelihood) in proba.iter_mut() {
+            *log_likelihood = (*log_likelihood - max).exp();
+            total += *log_likelihood;
+        }
+        for (_, likelihood) in proba.iter_mut() {
+            *likelihood /= total;
+        }
+        proba.sort_by(|(_, left), (_, right)| right.to_scalar().total_cmp(&left.to_scalar()));
+        proba
+    }
+}
+
+/// Logarithm of the size of `feature` times the likelihood of `p` under a diagonal Gaussian with
+/// the center and (padded) variance of `feature`, up to a constant shared by all features.
+fn log_likelihood<CF: CFeature<DIMS>, const DIMS: usize>(
+    feature: &CF,
+    p: &FeaturePoint<CF, DIMS>,
+    padding: CF::Scalar,
+) -> CF::Scalar {
+    let center = feature.center();
+    let variance = feature.variance();
+    let half = CF::Scalar::from_scalar(0.5);
+    (0..DIMS)
+        .filter(|&d| !p[d].is_nan())
+        .fold(feature.size().ln(), |acc, d| {
+            let variance = (variance[d] + padding).max(CF::Scalar::min_positive_value());
+            let diff = p[d] - center[d];
+            acc - half * (variance.ln() + diff * diff / variance)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cftree::{BasicConfig, BirchCFTree, MissingValues},
+        point::Point,
+    };
+
+    #[test]
+    fn predict_proba() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(20.0)
+            .missing_values(MissingValues::Skip)
+            .build()
+            .unwrap();
+        // a tight cluster around (0, 0), and a wide one around (40, 0)
+        let points = (0..200).map(|i| {
+            let angle = i as f64 * 0.7;
+            let (center, radius) = match i % 2 {
+                0 => (0.0, 0.1),
+                _ => (40.0, 1.0),
+            };
+            Point::from_arr([center + radius * angle.cos(), radius * angle.sin()])
+        });
+        let tree = BirchCFTree::<2>::from_iter(points, config.clone());
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.len(), 2);
+        let tight = clusters.iter().find(|c| c.center[0] < 20.0).unwrap().id;
+        let wide = clusters.iter().find(|c| c.center[0] > 20.0).unwrap().id;
+
+        let proba = tree.predict_proba(&Point::from_arr([0.05, 0.0]), 2);
+        assert_eq!(proba.len(), 2);
+        assert!((proba.iter().map(|&(_, p)| p).sum::<f64>() - 1.0).abs() < 1e-9);
+        assert_eq!(proba[0].0, tight);
+        assert!(proba[0].1 > 0.99);
+
+        // closer to the center of the tight cluster, but far outside its spread
+        let proba = tree.predict_proba(&Point::from_arr([19.9, 0.0]), 2);
+        assert_eq!(proba[0].0, wide);
+        assert!(proba[0].1 > 0.9);
+
+        // restricted to the nearest cluster, and marginal over missing coordinates
+        assert_eq!(
+            tree.predict_proba(&Point::from_arr([19.9, f64::NAN]), 1),
+            vec![(tight, 1.0)]
+        );
+        assert_eq!(
+            tree.predict_proba(&Point::from_arr([21.0, f64::NAN]), 2)[0].0,
+            wide
+        );
+        assert!(tree
+            .predict_proba(&Point::from_arr([0.0, 0.0]), 0)
+            .is_empty());
+        let empty = BirchCFTree::<2>::new(config);
+        assert!(empty
+            .predict_proba(&Point::from_arr([0.0, 0.0]), 2)
+            .is_empty());
+    }
+}
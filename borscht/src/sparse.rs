@@ -0,0 +1,340 @@
+/*!
+ * Sparse data point structure, for high-dimensional inputs which are mostly zero.
+ *
+ * A [SparsePoint] stores only its non-zero coordinates, and supports the arithmetic of [Point]
+ * which keeps implicit zeros at zero: addition, subtraction and multiplication with other sparse
+ * points, multiplication, division and remainder by a scalar, and negation. Operations which
+ * would make every coordinate non-zero (such as adding a scalar) require converting to a dense
+ * [Point] first. Sparse points can also be added to and subtracted from dense points, e.g. to
+ * accumulate a dense linear sum.
+ */
+
+use alloc::vec::Vec;
+use core::ops::{Add, AddAssign, Div, Mul, Neg, Rem, Sub, SubAssign};
+
+use num_traits::Zero;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::BorschtError,
+    point::{Float, Point, Scalar},
+};
+
+/// A point which stores its non-zero coordinates as `(index, value)` pairs, sorted by index.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct SparsePoint<const DIMS: usize, T = Scalar> {
+    entries: Vec<(usize, T)>,
+}
+
+impl<T: Float, const DIMS: usize> SparsePoint<DIMS, T> {
+    /// Creates a point from its non-zero coordinates, given as `(index, value)` pairs in any
+    /// order. Values of repeated indices are summed, and zero values are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any index is not less than `DIMS`; see [SparsePoint::try_new].
+    pub fn new<I: IntoIterator<Item = (usize, T)>>(entries: I) -> SparsePoint<DIMS, T> {
+        match SparsePoint::try_new(entries) {
+            Ok(point) => point,
+            Err(err) => panic!("invalid sparse point: {}", err),
+        }
+    }
+    /// Creates a point like [SparsePoint::new], failing if any index is not less than `DIMS`.
+    pub fn try_new<I: IntoIterator<Item = (usize, T)>>(
+        entries: I,
+    ) -> Result<SparsePoint<DIMS, T>, BorschtError> {
+        let mut entries = entries.into_iter().collect::<Vec<_>>();
+        if let Some(&(index, _)) = entries.iter().find(|&&(idx, _)| idx >= DIMS) {
+            return Err(BorschtError::IndexOutOfBounds { index, dims: DIMS });
+        }
+        entries.sort_by_key(|&(idx, _)| idx);
+        let mut merged: Vec<(usize, T)> = Vec::with_capacity(entries.len());
+        for (idx, value) in entries {
+            match merged.last_mut() {
+                Some(last) if last.0 == idx => last.1 += value,
+                _ => merged.push((idx, value)),
+            }
+        }
+        Ok(SparsePoint::from_sorted(merged))
+    }
+    /// Creates a point from `(index, value)` pairs already sorted by (unique) index, dropping
+    /// zero values.
+    fn from_sorted(mut entries: Vec<(usize, T)>) -> SparsePoint<DIMS, T> {
+        entries.retain(|(_, value)| !value.is_zero());
+        SparsePoint { entries }
+    }
+    /// Non-zero coordinates of this point, as `(index, value)` pairs sorted by index.
+    pub fn entries(&self) -> &[(usize, T)] {
+        &self.entries
+    }
+    /// Number of non-zero coordinates.
+    pub fn nnz(&self) -> usize {
+        self.entries.len()
+    }
+    /// Value of the coordinate `idx`.
+    pub fn get(&self, idx: usize) -> T {
+        self.entries
+            .binary_search_by_key(&idx, |&(i, _)| i)
+            .map(|pos| self.entries[pos].1)
+            .unwrap_or_else(|_| T::zero())
+    }
+    pub fn norm2(&self) -> T {
+        self.entries
+            .iter()
+            .fold(T::zero(), |acc, &(_, x)| acc + x * x)
+    }
+    /// Sum of the components of this point.
+    pub fn sum(&self) -> T {
+        self.entries.iter().fold(T::zero(), |acc, &(_, x)| acc + x)
+    }
+    /// Dot product of this point with `other`.
+    pub fn dot(&self, other: &SparsePoint<DIMS, T>) -> T {
+        (self * other).sum()
+    }
+    /// Converts this point into a dense [Point].
+    pub fn to_dense(&self) -> Point<DIMS, T> {
+        let mut dense = Point::zero();
+        for &(idx, value) in &self.entries {
+            dense[idx] = value;
+        }
+        dense
+    }
+    /// Applies `f` to every non-zero coordinate.
+    fn map_values<F: Fn(T) -> T>(&self, f: F) -> SparsePoint<DIMS, T> {
+        SparsePoint::from_sorted(
+            self.entries
+                .iter()
+                .map(|&(idx, value)| (idx, f(value)))
+                .collect(),
+        )
+    }
+}
+
+/// Combines the coordinates of two sparse points with `f`, visiting the union of their non-zero
+/// coordinates (or only the intersection, if `intersect` is set).
+fn merge<T: Float, F: Fn(T, T) -> T>(
+    left: &[(usize, T)],
+    right: &[(usize, T)],
+    intersect: bool,
+    f: F,
+) -> Vec<(usize, T)> {
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let (mut l, mut r) = (0, 0);
+    while l < left.len() || r < right.len() {
+        let lidx = left.get(l).map(|&(idx, _)| idx).unwrap_or(usize::MAX);
+        let ridx = right.get(r).map(|&(idx, _)| idx).unwrap_or(usize::MAX);
+        match lidx.cmp(&ridx) {
+            core::cmp::Ordering::Equal => {
+                merged.push((lidx, f(left[l].1, right[r].1)));
+                l += 1;
+                r += 1;
+            }
+            core::cmp::Ordering::Less => {
+                if !intersect {
+                    merged.push((lidx, f(left[l].1, T::zero())));
+                }
+                l += 1;
+            }
+            core::cmp::Ordering::Greater => {
+                if !intersect {
+                    merged.push((ridx, f(T::zero(), right[r].1)));
+                }
+                r += 1;
+            }
+        }
+    }
+    merged
+}
+
+impl<T: Float, const DIMS: usize> Zero for SparsePoint<DIMS, T> {
+    fn zero() -> SparsePoint<DIMS, T> {
+        SparsePoint {
+            entries: Vec::new(),
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<T: Float, const DIMS: usize> Default for SparsePoint<DIMS, T> {
+    fn default() -> SparsePoint<DIMS, T> {
+        Self::zero()
+    }
+}
+
+impl<T: Float, const DIMS: usize> From<&Point<DIMS, T>> for SparsePoint<DIMS, T> {
+    fn from(point: &Point<DIMS, T>) -> SparsePoint<DIMS, T> {
+        SparsePoint::from_sorted(point.as_slice().iter().copied().enumerate().collect())
+    }
+}
+
+impl<T: Float, const DIMS: usize> From<Point<DIMS, T>> for SparsePoint<DIMS, T> {
+    fn from(point: Point<DIMS, T>) -> SparsePoint<DIMS, T> {
+        SparsePoint::from(&point)
+    }
+}
+
+impl<T: Float, const DIMS: usize> From<SparsePoint<DIMS, T>> for Point<DIMS, T> {
+    fn from(point: SparsePoint<DIMS, T>) -> Point<DIMS, T> {
+        point.to_dense()
+    }
+}
+
+macro_rules! impl_sparse_op {
+    ($op_trait:ident $fname:ident $op:tt $intersect:expr) => {
+        impl<T: Float, const DIMS: usize> $op_trait<&SparsePoint<DIMS, T>>
+            for &SparsePoint<DIMS, T>
+        {
+            type Output = SparsePoint<DIMS, T>;
+
+            fn $fname(self, rhs: &SparsePoint<DIMS, T>) -> Self::Output {
+                SparsePoint::from_sorted(merge(&self.entries, &rhs.entries, $intersect, |l, r| {
+                    l $op r
+                }))
+            }
+        }
+        impl<T: Float, const DIMS: usize> $op_trait<SparsePoint<DIMS, T>>
+            for &SparsePoint<DIMS, T>
+        {
+            type Output = SparsePoint<DIMS, T>;
+
+            fn $fname(self, rhs: SparsePoint<DIMS, T>) -> Self::Output {
+                self $op &rhs
+            }
+        }
+        impl<T: Float, const DIMS: usize> $op_trait<&SparsePoint<DIMS, T>>
+            for SparsePoint<DIMS, T>
+        {
+            type Output = SparsePoint<DIMS, T>;
+
+            fn $fname(self, rhs: &SparsePoint<DIMS, T>) -> Self::Output {
+                &self $op rhs
+            }
+        }
+        impl<T: Float, const DIMS: usize> $op_trait<SparsePoint<DIMS, T>> for SparsePoint<DIMS, T> {
+            type Output = SparsePoint<DIMS, T>;
+
+            fn $fname(self, rhs: SparsePoint<DIMS, T>) -> Self::Output {
+                &self $op &rhs
+            }
+        }
+    };
+}
+
+impl_sparse_op!(Add add + false);
+impl_sparse_op!(Sub sub - false);
+impl_sparse_op!(Mul mul * true);
+
+macro_rules! impl_sparse_scalar_op {
+    ($op_trait:ident $fname:ident $op:tt) => {
+        impl<T: Float, const DIMS: usize> $op_trait<T> for &SparsePoint<DIMS, T> {
+            type Output = SparsePoint<DIMS, T>;
+
+            fn $fname(self, rhs: T) -> Self::Output {
+                self.map_values(|value| value $op rhs)
+            }
+        }
+        impl<T: Float, const DIMS: usize> $op_trait<T> for SparsePoint<DIMS, T> {
+            type Output = SparsePoint<DIMS, T>;
+
+            fn $fname(self, rhs: T) -> Self::Output {
+                &self $op rhs
+            }
+        }
+    };
+}
+
+impl_sparse_scalar_op!(Mul mul *);
+impl_sparse_scalar_op!(Div div /);
+impl_sparse_scalar_op!(Rem rem %);
+
+impl<T: Float, const DIMS: usize> Neg for &SparsePoint<DIMS, T> {
+    type Output = SparsePoint<DIMS, T>;
+    fn neg(self) -> Self::Output {
+        self.map_values(|value| -value)
+    }
+}
+impl<T: Float, const DIMS: usize> Neg for SparsePoint<DIMS, T> {
+    type Output = SparsePoint<DIMS, T>;
+    fn neg(self) -> Self::Output {
+        -&self
+    }
+}
+
+impl<T: Float, const DIMS: usize> AddAssign<&SparsePoint<DIMS, T>> for Point<DIMS, T> {
+    fn add_assign(&mut self, rhs: &SparsePoint<DIMS, T>) {
+        for &(idx, value) in &rhs.entries {
+            self[idx] += value;
+        }
+    }
+}
+
+impl<T: Float, const DIMS: usize> SubAssign<&SparsePoint<DIMS, T>> for Point<DIMS, T> {
+    fn sub_assign(&mut self, rhs: &SparsePoint<DIMS, T>) {
+        for &(idx, value) in &rhs.entries {
+            self[idx] -= value;
+        }
+    }
+}
+
+impl<T: Float, const DIMS: usize> Add<&SparsePoint<DIMS, T>> for Point<DIMS, T> {
+    type Output = Point<DIMS, T>;
+    fn add(mut self, rhs: &SparsePoint<DIMS, T>) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl<T: Float, const DIMS: usize> Sub<&SparsePoint<DIMS, T>> for Point<DIMS, T> {
+    type Output = Point<DIMS, T>;
+    fn sub(mut self, rhs: &SparsePoint<DIMS, T>) -> Self::Output {
+        self -= rhs;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ops() {
+        let left = SparsePoint::<1000>::new(vec![(500, 1.0), (3, 2.0), (500, 1.0)]);
+        let right = SparsePoint::<1000>::new(vec![(3, -2.0), (7, 4.0), (500, 0.5)]);
+        assert_eq!(left.entries(), &[(3, 2.0), (500, 2.0)]);
+        assert_eq!(left.get(500), 2.0);
+        assert_eq!(left.get(4), 0.0);
+
+        // cancelled coordinates don't linger as explicit zeros
+        assert_eq!((&left + &right).entries(), &[(7, 4.0), (500, 2.5)]);
+        assert_eq!(
+            (&left - &right).entries(),
+            &[(3, 4.0), (7, -4.0), (500, 1.5)]
+        );
+        assert_eq!((&left * &right).entries(), &[(3, -4.0), (500, 1.0)]);
+        assert_eq!(left.dot(&right), -3.0);
+        assert_eq!((&left * 2.0).entries(), &[(3, 4.0), (500, 4.0)]);
+        assert_eq!((&left / 2.0).entries(), &[(3, 1.0), (500, 1.0)]);
+        assert_eq!((&left % 2.0).nnz(), 0);
+        assert_eq!((-&left).entries(), &[(3, -2.0), (500, -2.0)]);
+        assert_eq!(left.norm2(), 8.0);
+
+        // agrees with dense arithmetic
+        let (dense_left, dense_right) = (left.to_dense(), right.to_dense());
+        assert_eq!((&left + &right).to_dense(), &dense_left + &dense_right);
+        assert_eq!((&left * &right).to_dense(), &dense_left * &dense_right);
+        assert_eq!(SparsePoint::from(&dense_left), left);
+        assert_eq!(Point::zero() + &left - &right, dense_left - dense_right);
+
+        assert_eq!(
+            SparsePoint::<1000>::try_new(vec![(3, 1.0), (1000, 1.0)]),
+            Err(BorschtError::IndexOutOfBounds {
+                index: 1000,
+                dims: 1000
+            })
+        );
+    }
+}
@@ -0,0 +1,443 @@
+/*!
+ * Node split policies.
+ *
+ * When a node overflows its capacity, its entries are partitioned into two new nodes. The
+ * [SplitPolicy] trait decides how that partition is made; the tree configuration selects which
+ * policy is used (see [TreeConfig::split_policy](crate::cftree::TreeConfig::split_policy)), e.g.
+ * one of the built-in policies through [BasicConfig::split](crate::cftree::BasicConfig::split).
+ *
+ * The built-in policies are deterministic, depending only on the entries being split (and, for
+ * [RandomSeeds], the configured seed). When several pairs of entries are equally far apart, the
+ * first pair in entry order is used as seeds, and entries equally close to both seeds are assigned
+ * to the second ('right') group.
+ */
+
+use alloc::{vec, vec::Vec};
+use core::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+use crate::point::Scalar;
+
+/// Summary of a node entry, as seen by a [SplitPolicy].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitEntry {
+    /// Center of the entry's cluster feature.
+    pub center: Vec<Scalar>,
+    /// Size (weight) of the entry's cluster feature.
+    pub size: Scalar,
+}
+
+impl SplitEntry {
+    fn dist2(&self, other: &SplitEntry) -> Scalar {
+        self.center
+            .iter()
+            .zip(&other.center)
+            .map(|(l, r)| (l - r) * (l - r))
+            .sum()
+    }
+}
+
+/// Strategy for partitioning the entries of an overflowing node into two groups. Implementations
+/// should be deterministic, so that trees built from the same input are identical.
+pub trait SplitPolicy: Debug {
+    /// Partitions `entries` into two groups, returning for each entry whether it belongs to the
+    /// first ('left') group. Both groups must be non-empty; `entries` always contains at least
+    /// two entries.
+    fn partition(&self, entries: &[SplitEntry]) -> Vec<bool>;
+}
+
+/// Assigns each entry to whichever of the two seed entries it is closest to.
+fn assign_to_seeds(entries: &[SplitEntry], lseed: usize, rseed: usize) -> Vec<bool> {
+    entries
+        .iter()
+        .enumerate()
+        .map(|(idx, entry)| {
+            idx == lseed
+                || (idx != rseed && entry.dist2(&entries[lseed]) < entry.dist2(&entries[rseed]))
+        })
+        .collect()
+}
+
+/// Pair of entries farthest apart from each other (O(n²) in the number of entries).
+fn farthest_pair(entries: &[SplitEntry]) -> (usize, usize) {
+    let mut farthest = (0, 1, Scalar::NEG_INFINITY);
+    for lidx in 0..entries.len() {
+        for ridx in lidx + 1..entries.len() {
+            let d2 = entries[lidx].dist2(&entries[ridx]);
+            if d2 > farthest.2 {
+                farthest = (lidx, ridx, d2);
+            }
+        }
+    }
+    (farthest.0, farthest.1)
+}
+
+/// Entry of `entries` (other than `skip`) farthest from `center`: the first, if several are equally
+/// far.
+fn farthest_from(entries: &[SplitEntry], center: &SplitEntry, skip: Option<usize>) -> usize {
+    let mut farthest = (0, Scalar::NEG_INFINITY);
+    for (idx, entry) in entries.iter().enumerate() {
+        let d2 = entry.dist2(center);
+        if Some(idx) != skip && d2 > farthest.1 {
+            farthest = (idx, d2);
+        }
+    }
+    farthest.0
+}
+
+/// Moves entries across a `partition` (as returned by [SplitPolicy::partition]) until both groups
+/// contain at least `min` entries (or as close to `min` as the number of entries allows). Each move
+/// takes the entry of the larger group closest to the center of the smaller group.
+pub fn rebalance(entries: &[SplitEntry], partition: &mut [bool], min: usize) {
+    let min = min.min(entries.len() / 2);
+    loop {
+        let nleft = partition.iter().filter(|&&left| left).count();
+        let small = match (nleft < min, entries.len() - nleft < min) {
+            (true, _) => true,
+            (_, true) => false,
+            _ => return,
+        };
+        // center of the smaller group
+        let dims = entries[0].center.len();
+        let members = entries
+            .iter()
+            .zip(partition.iter())
+            .filter(|&(_, &left)| left == small)
+            .map(|(entry, _)| entry)
+            .collect::<Vec<_>>();
+        if members.is_empty() {
+            partition[0] = small;
+            continue;
+        }
+        let center = SplitEntry {
+            center: (0..dims)
+                .map(|d| {
+                    members.iter().map(|entry| entry.center[d]).sum::<Scalar>()
+                        / members.len() as Scalar
+                })
+                .collect(),
+            size: 0.0,
+        };
+        let closest = entries
+            .iter()
+            .enumerate()
+            .filter(|&(idx, _)| partition[idx] != small)
+            .map(|(idx, entry)| (idx, entry.dist2(&center)))
+            .fold(
+                None,
+                |closest: Option<(usize, Scalar)>, (idx, d2)| match closest {
+                    Some((_, closest_d2)) if closest_d2 <= d2 => closest,
+                    _ => Some((idx, d2)),
+                },
+            );
+        match closest {
+            Some((idx, _)) => partition[idx] = small,
+            None => return,
+        }
+    }
+}
+
+/// Original BIRCH split: uses the farthest pair of entries as seeds, and assigns all other entries
+/// to the closest seed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct FarthestPair;
+
+impl SplitPolicy for FarthestPair {
+    fn partition(&self, entries: &[SplitEntry]) -> Vec<bool> {
+        let (lseed, rseed) = farthest_pair(entries);
+        assign_to_seeds(entries, lseed, rseed)
+    }
+}
+
+/// Linear-time approximation of [FarthestPair]: uses the entry farthest from the (size-weighted)
+/// centroid of the entries as one seed and the entry farthest from that seed as the other, then
+/// assigns all other entries to the closest seed. O(n) in the number of entries, rather than O(n²),
+/// which matters for nodes with large capacities; the seeds are usually, but not always, the
+/// farthest pair.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct LinearSeeds;
+
+impl SplitPolicy for LinearSeeds {
+    fn partition(&self, entries: &[SplitEntry]) -> Vec<bool> {
+        let dims = entries[0].center.len();
+        let total = entries.iter().map(|entry| entry.size).sum::<Scalar>();
+        let mut centroid = SplitEntry {
+            center: vec![0.0; dims],
+            size: total,
+        };
+        for entry in entries {
+            for (c, x) in centroid.center.iter_mut().zip(&entry.center) {
+                *c += match total > 0.0 {
+                    true => entry.size * x / total,
+                    false => x / entries.len() as Scalar,
+                };
+            }
+        }
+        let lseed = farthest_from(entries, &centroid, None);
+        let rseed = farthest_from(entries, &entries[lseed], Some(lseed));
+        let (lseed, rseed) = (lseed.min(rseed), lseed.max(rseed));
+        assign_to_seeds(entries, lseed, rseed)
+    }
+}
+
+/// Splits along the principal direction of the (size-weighted) entry centers, at the weighted
+/// mean. Tends to produce more compact groups than [FarthestPair] when entries are elongated
+/// along one direction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PcaSplit;
+
+impl PcaSplit {
+    const POWER_ITERATIONS: usize = 32;
+}
+
+impl SplitPolicy for PcaSplit {
+    fn partition(&self, entries: &[SplitEntry]) -> Vec<bool> {
+        let dims = entries[0].center.len();
+        let total = entries.iter().map(|entry| entry.size).sum::<Scalar>();
+        let (lseed, rseed) = farthest_pair(entries);
+        if total <= 0.0 {
+            // no weight to find a principal direction with (e.g. emptied entries); fall back to the
+            // original split
+            return assign_to_seeds(entries, lseed, rseed);
+        }
+        let mut mean = vec![0.0; dims];
+        for entry in entries {
+            for (m, c) in mean.iter_mut().zip(&entry.center) {
+                *m += entry.size * c / total;
+            }
+        }
+        let mut cov = vec![vec![0.0; dims]; dims];
+        for entry in entries {
+            for i in 0..dims {
+                for j in 0..dims {
+                    cov[i][j] +=
+                        entry.size * (entry.center[i] - mean[i]) * (entry.center[j] - mean[j]);
+                }
+            }
+        }
+        // power iteration for the principal eigenvector, starting from the direction between the
+        // farthest pair (which is usually close to the principal direction already)
+        let mut direction = entries[lseed]
+            .center
+            .iter()
+            .zip(&entries[rseed].center)
+            .map(|(l, r)| l - r)
+            .collect::<Vec<_>>();
+        for _ in 0..Self::POWER_ITERATIONS {
+            let next = cov
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .zip(&direction)
+                        .map(|(c, d)| c * d)
+                        .sum::<Scalar>()
+                })
+                .collect::<Vec<_>>();
+            let norm = next.iter().map(|x| x * x).sum::<Scalar>().sqrt();
+            if norm == 0.0 {
+                break;
+            }
+            direction = next.into_iter().map(|x| x / norm).collect();
+        }
+        let projections = entries
+            .iter()
+            .map(|entry| {
+                entry
+                    .center
+                    .iter()
+                    .zip(&mean)
+                    .zip(&direction)
+                    .map(|((c, m), d)| (c - m) * d)
+                    .sum::<Scalar>()
+            })
+            .collect::<Vec<_>>();
+        let mut partition = projections.iter().map(|&p| p < 0.0).collect::<Vec<_>>();
+        if partition.iter().all(|&left| left) || partition.iter().all(|&left| !left) {
+            // degenerate (e.g. all centers identical); fall back to the original split
+            partition = assign_to_seeds(entries, lseed, rseed);
+        }
+        partition
+    }
+}
+
+/// Uses two pseudo-randomly chosen entries as seeds, and assigns all other entries to the closest
+/// seed. The cheapest split, at the cost of split quality. Seed choice is deterministic given the
+/// configured seed and the entries being split.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RandomSeeds {
+    pub seed: u64,
+}
+
+impl RandomSeeds {
+    /// SplitMix64 step.
+    fn mix(mut z: u64) -> u64 {
+        z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl SplitPolicy for RandomSeeds {
+    fn partition(&self, entries: &[SplitEntry]) -> Vec<bool> {
+        let n = entries.len() as u64;
+        let state = entries
+            .iter()
+            .flat_map(|entry| entry.center.iter())
+            .fold(Self::mix(self.seed ^ n), |acc, x| {
+                Self::mix(acc ^ x.to_bits())
+            });
+        let lseed = (state % n) as usize;
+        // choose the second seed from the remaining entries
+        let rseed = ((lseed as u64 + 1 + Self::mix(state) % (n - 1)) % n) as usize;
+        assign_to_seeds(entries, lseed, rseed)
+    }
+}
+
+/// Uses the farthest pair of entries as seeds, but assigns entries so that both groups receive
+/// the same number of entries (±1): entries are assigned in order of how strongly they prefer one
+/// seed over the other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct BalancedSplit;
+
+impl SplitPolicy for BalancedSplit {
+    fn partition(&self, entries: &[SplitEntry]) -> Vec<bool> {
+        let (lseed, rseed) = farthest_pair(entries);
+        let mut preference = (0..entries.len())
+            .map(|idx| {
+                (
+                    idx,
+                    entries[idx].dist2(&entries[lseed]) - entries[idx].dist2(&entries[rseed]),
+                )
+            })
+            .collect::<Vec<_>>();
+        // most strongly left-preferring entries first (the sort is stable, so ties keep entry
+        // order)
+        preference.sort_by(|l, r| l.1.total_cmp(&r.1));
+        let nleft = entries.len().div_ceil(2);
+        let mut partition = vec![false; entries.len()];
+        for &(idx, _) in preference.iter().take(nleft) {
+            partition[idx] = true;
+        }
+        partition
+    }
+}
+
+/// Built-in split policies, as a single serializable type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Split {
+    #[default]
+    FarthestPair,
+    Pca,
+    Random(RandomSeeds),
+    Balanced,
+    Linear,
+}
+
+impl SplitPolicy for Split {
+    fn partition(&self, entries: &[SplitEntry]) -> Vec<bool> {
+        match self {
+            Split::FarthestPair => FarthestPair.partition(entries),
+            Split::Pca => PcaSplit.partition(entries),
+            Split::Random(random) => random.partition(entries),
+            Split::Balanced => BalancedSplit.partition(entries),
+            Split::Linear => LinearSeeds.partition(entries),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<SplitEntry> {
+        [
+            [0.0, 0.0],
+            [1.0, 0.5],
+            [0.5, 0.0],
+            [10.0, 0.0],
+            [9.0, 1.0],
+            [9.5, 0.5],
+        ]
+        .iter()
+        .map(|c| SplitEntry {
+            center: c.to_vec(),
+            size: 1.0,
+        })
+        .collect()
+    }
+
+    fn check_groups(partition: &[bool]) {
+        // entries 0-2 and 3-5 should end up in separate groups
+        assert!(partition[0] == partition[1] && partition[1] == partition[2]);
+        assert!(partition[3] == partition[4] && partition[4] == partition[5]);
+        assert_ne!(partition[0], partition[3]);
+    }
+
+    #[test]
+    fn policies() {
+        let entries = entries();
+        check_groups(&FarthestPair.partition(&entries));
+        check_groups(&PcaSplit.partition(&entries));
+        check_groups(&BalancedSplit.partition(&entries));
+        check_groups(&LinearSeeds.partition(&entries));
+        for seed in 0..10 {
+            let partition = RandomSeeds { seed }.partition(&entries);
+            assert!(partition.iter().any(|&l| l) && partition.iter().any(|&l| !l));
+        }
+    }
+
+    #[test]
+    fn pca_zero_weight() {
+        // without any weight, the split falls back to that of the farthest pair
+        let entries = entries()
+            .into_iter()
+            .map(|entry| SplitEntry { size: 0.0, ..entry })
+            .collect::<Vec<_>>();
+        let partition = PcaSplit.partition(&entries);
+        assert_eq!(partition, FarthestPair.partition(&entries));
+        check_groups(&partition);
+    }
+
+    #[test]
+    fn balanced() {
+        let mut entries = entries();
+        entries.truncate(4);
+        let partition = BalancedSplit.partition(&entries);
+        assert_eq!(partition.iter().filter(|&&l| l).count(), 2);
+        let partition = FarthestPair.partition(&entries);
+        assert_eq!(partition.iter().filter(|&&l| l).count(), 3);
+    }
+
+    #[test]
+    fn linear() {
+        // the seeds are the entries at either end, so the split matches that of the farthest pair
+        let entries = entries();
+        assert_eq!(
+            LinearSeeds.partition(&entries),
+            FarthestPair.partition(&entries)
+        );
+
+        // identical entries still produce two groups
+        let same = vec![entries[0].clone(); 4];
+        let partition = LinearSeeds.partition(&same);
+        assert!(partition.iter().any(|&l| l) && partition.iter().any(|&l| !l));
+    }
+
+    #[test]
+    fn rebalance_min() {
+        let entries = entries();
+        let mut partition = vec![true, true, true, true, true, false];
+        rebalance(&entries, &mut partition, 2);
+        // the entry closest to the lone right entry moves across
+        assert_eq!(partition, vec![true, true, true, false, true, false]);
+        rebalance(&entries, &mut partition, 3);
+        assert_eq!(partition, vec![true, true, true, false, false, false]);
+
+        // `min` larger than half the entries is clamped
+        let mut partition = vec![false, true, true, true, true, true];
+        rebalance(&entries, &mut partition, 5);
+        assert_eq!(partition.iter().filter(|&&l| l).count(), 3);
+    }
+}
@@ -0,0 +1,241 @@
+/*!
+ * Stability of the leaf clusters of a tree under resampling of its points.
+ *
+ * The leaf clusters BIRCH finds depend on the order the points are inserted in. A
+ * [StabilityAnalysis] measures how much: it builds a reference tree from the points in their
+ * original order, then a number of trees from resamples of the points (shuffled, or drawn with
+ * replacement), and matches the leaf clusters of every resampled tree to those of the reference
+ * tree (see [compare_within]). The stability of a reference cluster is the fraction of resampled
+ * trees with a cluster matched to it, so clusters which only exist for some insertion orders
+ * score low.
+ */
+
+use alloc::{vec, vec::Vec};
+use core::fmt::Debug;
+
+use num_traits::Zero;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{CFTree, TreeConfig},
+    compare::compare_within,
+    point::{Float, Scalar},
+    rng::SplitMix64,
+    summary::ClusterSummary,
+};
+
+/// How the points are resampled for each tree of a [StabilityAnalysis].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Resampling {
+    /// All the points, in a random order: measures sensitivity to the insertion order alone.
+    #[default]
+    Shuffle,
+    /// As many points as there are, drawn with replacement: also measures sensitivity to the
+    /// sample of points.
+    Bootstrap,
+}
+
+/// Stability of one leaf cluster of the reference tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterStability<const DIMS: usize, T = Scalar> {
+    pub cluster: ClusterSummary<DIMS, T>,
+    /// Fraction of resampled trees with a leaf cluster matched to this one.
+    pub stability: Scalar,
+    /// Average distance from the center of this cluster to the centers of the clusters matched
+    /// to it, or zero if none were.
+    pub mean_distance: T,
+}
+
+/// Results of a [StabilityAnalysis].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StabilityReport<const DIMS: usize, T = Scalar> {
+    /// Stability of each leaf cluster of the reference tree, indexed by cluster id.
+    pub clusters: Vec<ClusterStability<DIMS, T>>,
+    /// Number of leaf clusters of each resampled tree.
+    pub leaf_counts: Vec<usize>,
+}
+
+impl<const DIMS: usize, T> StabilityReport<DIMS, T> {
+    /// Average stability of the leaf clusters of the reference tree (1 if it has none).
+    pub fn mean_stability(&self) -> Scalar {
+        match self.clusters.len() {
+            0 => 1.0,
+            len => {
+                self.clusters
+                    .iter()
+                    .map(|cluster| cluster.stability)
+                    .sum::<Scalar>()
+                    / len as Scalar
+            }
+        }
+    }
+
+    /// Leaf clusters of the reference tree with a stability below `min_stability`.
+    pub fn unstable(
+        &self,
+        min_stability: Scalar,
+    ) -> impl Iterator<Item = &ClusterStability<DIMS, T>> {
+        self.clusters
+            .iter()
+            .filter(move |cluster| cluster.stability < min_stability)
+    }
+}
+
+/// Analysis of the stability of leaf clusters under resampling (see the
+/// [module documentation](self)).
+#[derive(Debug, Clone, PartialEq)]
+pub struct StabilityAnalysis {
+    runs: usize,
+    resampling: Resampling,
+    max_distance: Option<Scalar>,
+    seed: u64,
+}
+
+impl StabilityAnalysis {
+    /// Creates an analysis over `runs` resampled trees, shuffling the points for each by default.
+    pub fn new(runs: usize) -> StabilityAnalysis {
+        StabilityAnalysis {
+            runs,
+            resampling: Resampling::Shuffle,
+            max_distance: None,
+            seed: 0,
+        }
+    }
+
+    pub fn resampling(mut self, resampling: Resampling) -> Self {
+        self.resampling = resampling;
+        self
+    }
+
+    /// Only matches clusters whose centers are at most `max_distance` apart. Defaults to the
+    /// largest diameter the threshold of the tree allows for leaf clusters.
+    pub fn max_distance(mut self, max_distance: Scalar) -> Self {
+        self.max_distance = Some(max_distance);
+        self
+    }
+
+    /// Seeds the random resampling, so that analyses are reproducible.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Builds trees with configuration `config` from `points` and resamples of them, and reports
+    /// the stability of the leaf clusters of the tree built from `points` as they are.
+    pub fn analyze<CF, TC, const DIMS: usize>(
+        &self,
+        points: &[FeaturePoint<CF, DIMS>],
+        config: TC,
+    ) -> StabilityReport<DIMS, CF::Scalar>
+    where
+        CF: CFeature<DIMS> + Debug + Clone,
+        TC: TreeConfig + Clone,
+    {
+        let max_distance = self
+            .max_distance
+            .unwrap_or_else(|| num_traits::Float::sqrt(config.threshold()));
+        let reference = CFTree::<CF, DIMS, TC>::from_iter(points.iter().cloned(), config.clone());
+        let clusters = reference.clusters().collect::<Vec<_>>();
+        let mut matches = vec![0; clusters.len()];
+        let mut distances = vec![CF::Scalar::zero(); clusters.len()];
+        let mut leaf_counts = Vec::with_capacity(self.runs);
+        let mut rng = SplitMix64(self.seed);
+        for _ in 0..self.runs {
+            let resampled = self.resample(points, &mut rng);
+            let tree = CFTree::<CF, DIMS, TC>::from_iter(resampled, config.clone());
+            leaf_counts.push(tree.clusters().count());
+            let diff = compare_within(&reference, &tree, CF::Scalar::from_scalar(max_distance));
+            for moved in diff.moved {
+                matches[moved.before.id] += 1;
+                distances[moved.before.id] += moved.distance;
+            }
+        }
+
+        let clusters = clusters
+            .into_iter()
+            .zip(matches.into_iter().zip(distances))
+            .map(|(cluster, (matches, distance))| ClusterStability {
+                cluster,
+                stability: match self.runs {
+                    0 => 1.0,
+                    runs => matches as Scalar / runs as Scalar,
+                },
+                mean_distance: match matches {
+                    0 => CF::Scalar::zero(),
+                    matches => distance / CF::Scalar::from_scalar(matches as Scalar),
+                },
+            })
+            .collect();
+        StabilityReport {
+            clusters,
+            leaf_counts,
+        }
+    }
+
+    fn resample<P: Clone>(&self, points: &[P], rng: &mut SplitMix64) -> Vec<P> {
+        match self.resampling {
+            Resampling::Shuffle => shuffle(points, rng),
+            Resampling::Bootstrap => (0..points.len())
+                .map(|_| points[rng.below(points.len() as u64) as usize].clone())
+                .collect(),
+        }
+    }
+}
+
+/// Copy of `points` in a random order (Fisher-Yates).
+pub(crate) fn shuffle<P: Clone>(points: &[P], rng: &mut SplitMix64) -> Vec<P> {
+    let mut shuffled = points.to_vec();
+    for i in (1..shuffled.len()).rev() {
+        shuffled.swap(i, rng.below(i as u64 + 1) as usize);
+    }
+    shuffled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cfeature::birch::CFeature as BirchFeature, cftree::BasicConfig, point::Point};
+
+    #[test]
+    fn stability() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(4.0)
+            .build()
+            .unwrap();
+        // three tight blobs, which every insertion order finds
+        let blobs = (0..60)
+            .map(|i| {
+                let blob = (i % 3) as Scalar * 50.0;
+                Point::from_arr([blob + (i / 3 % 4) as Scalar * 0.1, (i / 12) as Scalar * 0.1])
+            })
+            .collect::<Vec<_>>();
+        let report = StabilityAnalysis::new(10)
+            .seed(7)
+            .analyze::<BirchFeature<2>, _, 2>(&blobs, config.clone());
+        assert_eq!(report.clusters.len(), 3);
+        assert_eq!(report.leaf_counts, vec![3; 10]);
+        assert_eq!(report.mean_stability(), 1.0);
+        assert!(report.clusters.iter().all(|c| c.mean_distance < 0.1));
+
+        // a chain of points about one threshold apart is cut differently for each order
+        let chain = (0..40)
+            .map(|i| Point::from_arr([i as Scalar * 1.2, 0.0]))
+            .collect::<Vec<_>>();
+        let analysis = StabilityAnalysis::new(10).seed(7).max_distance(0.5);
+        let report = analysis.analyze::<BirchFeature<2>, _, 2>(&chain, config.clone());
+        assert!(report.mean_stability() < 1.0);
+        assert!(report.unstable(1.0).count() > 0);
+        // resampling is reproducible
+        assert_eq!(
+            analysis.analyze::<BirchFeature<2>, _, 2>(&chain, config.clone()),
+            report
+        );
+
+        let report = StabilityAnalysis::new(10)
+            .resampling(Resampling::Bootstrap)
+            .analyze::<BirchFeature<2>, _, 2>(&blobs, config);
+        assert_eq!(report.clusters.len(), 3);
+        assert_eq!(report.mean_stability(), 1.0);
+    }
+}
@@ -0,0 +1,209 @@
+/*!
+ * Clustering of streams whose dimensions have unknown (and possibly shifting) scales.
+ *
+ * A [StandardizedCFTree] standardizes each inserted point with the running per-dimension mean and
+ * standard deviation of the stream so far (see [RunningStandardizer]). The features of the tree
+ * are expressed in terms of a fixed *basis* scaler; once the running estimates drift from the
+ * basis by more than a tolerance, the tree is rebuilt by [rescaling](Rescale) its leaf features
+ * to the current estimates and reinserting them into a new tree.
+ */
+
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{BasicConfig, CFTree, InsertOutcome, Node, NodeEntry, TreeConfig, TreeMetrics},
+    preprocess::{Rescale, RunningStandardizer, StandardScaler, Transform},
+    summary::ClusterSummary,
+};
+
+/// A cluster feature tree over standardized points of a stream.
+#[derive(Debug)]
+pub struct StandardizedCFTree<CF: CFeature<DIMS>, const DIMS: usize, TC = BasicConfig> {
+    tree: CFTree<CF, DIMS, TC>,
+    running: RunningStandardizer<DIMS, CF::Scalar>,
+    /// Scaler the features of the tree are expressed in
+    basis: StandardScaler<DIMS, CF::Scalar>,
+    tolerance: Option<CF::Scalar>,
+}
+
+impl<CF, TC, const DIMS: usize> StandardizedCFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Rescale<DIMS> + Debug + Clone,
+    TC: TreeConfig + Clone,
+{
+    /// Creates a new empty tree which is never rebuilt: each point is standardized with the
+    /// estimates at the time of its insertion, so features summarize points standardized in
+    /// slightly different ways while the estimates settle.
+    pub fn new(config: TC) -> StandardizedCFTree<CF, DIMS, TC> {
+        StandardizedCFTree {
+            tree: CFTree::new(config),
+            running: RunningStandardizer::new(),
+            basis: RunningStandardizer::new().scaler(),
+            tolerance: None,
+        }
+    }
+
+    /// Creates a new empty tree which is rebuilt whenever the running estimates
+    /// [drift](StandardScaler::drift) from those its features are expressed in by more than
+    /// `tolerance`.
+    pub fn with_rebuild_tolerance(
+        tolerance: CF::Scalar,
+        config: TC,
+    ) -> StandardizedCFTree<CF, DIMS, TC> {
+        StandardizedCFTree {
+            tolerance: Some(tolerance),
+            ..Self::new(config)
+        }
+    }
+
+    /// The underlying tree, over points standardized by the [basis](Self::basis) scaler.
+    pub fn tree(&self) -> &CFTree<CF, DIMS, TC> {
+        &self.tree
+    }
+
+    /// The scaler the features of the tree are expressed in.
+    pub fn basis(&self) -> &StandardScaler<DIMS, CF::Scalar> {
+        &self.basis
+    }
+
+    /// Running estimates of the mean and standard deviation of the stream.
+    pub fn standardizer(&self) -> &RunningStandardizer<DIMS, CF::Scalar> {
+        &self.running
+    }
+
+    /// Number of times the tree has been rebuilt (also counted in the tree's
+    /// [metrics](CFTree::metrics)).
+    pub fn rebuilds(&self) -> usize {
+        self.tree.metrics().rebuilds as usize
+    }
+
+    /// Updates the running estimates with `p` (rebuilding the tree if they drifted too far), then
+    /// inserts the standardized point.
+    pub fn insert(&mut self, p: FeaturePoint<CF, DIMS>) -> InsertOutcome {
+        self.running.update(&p);
+        let current = self.running.scaler();
+        match self.tolerance {
+            Some(tolerance) => {
+                if self.basis.drift(&current) > tolerance {
+                    self.rebuild(current);
+                }
+            }
+            None => self.basis = current,
+        }
+        self.tree.insert(self.basis.transform(p))
+    }
+
+    /// Rescales all leaf features from the current basis to `basis`, and reinserts them into a
+    /// new tree.
+    fn rebuild(&mut self, basis: StandardScaler<DIMS, CF::Scalar>) {
+        debug_event!(
+            drift = ?self.basis.drift(&basis),
+            rebuilds = self.tree.metrics().rebuilds,
+            "scales drifted, rebuilding tree"
+        );
+        let (scale, shift) = self.basis.rebase(&basis);
+        let mut leaves = Vec::new();
+        collect_leaves(self.tree.root(), &mut leaves);
+        let mut tree = CFTree::new(self.tree.config().clone());
+        let (sample_scale, sample_shift) = (scale.cast(), shift.cast());
+        for entry in leaves {
+            tree.insert_entry(NodeEntry {
+                feature: entry.feature.rescale(&scale, &shift),
+                child: None,
+                ids: entry.ids.clone(),
+                samples: entry
+                    .samples
+                    .clone()
+                    .map_samples(|p| p * &sample_scale + &sample_shift),
+                quantiles: entry
+                    .quantiles
+                    .clone()
+                    .rescale(&sample_scale, &sample_shift),
+                identity: entry.identity.clone(),
+            });
+        }
+        // the reinsertions aren't counted as insertions
+        *tree.metrics_mut() = TreeMetrics {
+            rebuilds: self.tree.metrics().rebuilds + 1,
+            ..self.tree.metrics().clone()
+        };
+        tree.trace_rebuild(&mut self.tree);
+        self.tree = tree;
+        self.basis = basis;
+    }
+
+    /// Summaries of the leaf clusters of the tree, with centers mapped back to the original scale
+    /// of the stream (radii and diameters remain in the standardized scale).
+    pub fn clusters(&self) -> Vec<ClusterSummary<DIMS, CF::Scalar>> {
+        self.tree
+            .clusters()
+            .map(|cluster| ClusterSummary {
+                center: self.basis.inverse(cluster.center.clone()),
+                ..cluster
+            })
+            .collect()
+    }
+}
+
+impl<CF, TC, const DIMS: usize> Extend<FeaturePoint<CF, DIMS>> for StandardizedCFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Rescale<DIMS> + Debug + Clone,
+    TC: TreeConfig + Clone,
+{
+    fn extend<T: IntoIterator<Item = FeaturePoint<CF, DIMS>>>(&mut self, iter: T) {
+        for p in iter {
+            self.insert(p);
+        }
+    }
+}
+
+fn collect_leaves<'a, CF, const DIMS: usize>(
+    node: &'a Node<CF, DIMS>,
+    leaves: &mut Vec<&'a NodeEntry<CF, DIMS>>,
+) {
+    for entry in &node.entries {
+        match entry.child {
+            Some(ref child) => collect_leaves(child, leaves),
+            None => leaves.push(entry),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cfeature::betula::CFeature as BetulaFeature, point::Point};
+
+    #[test]
+    fn standardized() {
+        // two blobs, whose second dimension is measured in much larger units
+        let points = (0..1000).map(|i| {
+            let jitter = ((i * 7919) % 100) as f64 / 100.0 - 0.5;
+            match i % 2 {
+                0 => Point::from_arr([jitter, 1000.0 * jitter]),
+                _ => Point::from_arr([10.0 + jitter, 20_000.0 + 1000.0 * jitter]),
+            }
+        });
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let mut tree =
+            StandardizedCFTree::<BetulaFeature<2>, 2>::with_rebuild_tolerance(0.1, config);
+        tree.extend(points);
+
+        assert!(tree.rebuilds() > 0 && tree.rebuilds() < 100);
+        assert!(tree.basis().drift(&tree.standardizer().scaler()) <= 0.1);
+        let clusters = tree.clusters();
+        assert!((clusters.iter().map(|c| c.size).sum::<f64>() - 1000.0).abs() < 1e-6);
+        for cluster in &clusters {
+            let near_first = cluster.center[0].abs() < 1.0 && cluster.center[1].abs() < 1000.0;
+            let near_second = (cluster.center[0] - 10.0).abs() < 1.0
+                && (cluster.center[1] - 20_000.0).abs() < 1000.0;
+            assert!(near_first || near_second, "{:?}", cluster.center);
+        }
+    }
+}
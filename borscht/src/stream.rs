@@ -0,0 +1,139 @@
+/*!
+ * Construction of trees from asynchronous [Stream]s of points, e.g. points received over a
+ * channel or a network connection in an async service.
+ *
+ * Insertion itself is synchronous (and fast), so a stream which always has points ready would
+ * otherwise never give other tasks a chance to run; insertion therefore yields back to the
+ * executor after every [YIELD_EVERY] points.
+ */
+
+use core::{
+    fmt::Debug,
+    future::{poll_fn, Future},
+    pin::{pin, Pin},
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{CFTree, TreeConfig},
+};
+
+/// Number of points inserted from a stream between yields to the executor.
+pub const YIELD_EVERY: usize = 1024;
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    TC: TreeConfig,
+{
+    /// Creates a tree from all points of `stream`.
+    pub async fn from_stream<S>(stream: S, config: TC) -> CFTree<CF, DIMS, TC>
+    where
+        S: Stream<Item = FeaturePoint<CF, DIMS>>,
+    {
+        let mut tree = CFTree::new(config);
+        tree.insert_stream(stream).await;
+        tree
+    }
+
+    /// Inserts all points of `stream` into this tree, returning the number of inserted points.
+    pub async fn insert_stream<S>(&mut self, stream: S) -> usize
+    where
+        S: Stream<Item = FeaturePoint<CF, DIMS>>,
+    {
+        let mut stream = pin!(stream);
+        let mut inserted = 0;
+        while let Some(p) = poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+            self.insert(p);
+            inserted += 1;
+            if inserted % YIELD_EVERY == 0 {
+                YieldNow { yielded: false }.await;
+            }
+        }
+        inserted
+    }
+}
+
+/// Future which is pending (but immediately woken) the first time it is polled.
+struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cftree::{BasicConfig, BirchCFTree},
+        point::Point,
+    };
+
+    /// Stream over the items of an iterator, which counts how often it is polled.
+    struct IterStream<I> {
+        iter: I,
+        polls: usize,
+    }
+
+    impl<I: Iterator + Unpin> Stream for IterStream<I> {
+        type Item = I::Item;
+
+        fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<I::Item>> {
+            self.polls += 1;
+            Poll::Ready(self.iter.next())
+        }
+    }
+
+    #[test]
+    fn stream() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let points = || (0..3000).map(|i| Point::from_arr([(i % 30) as f64 * 10.0, 0.0]));
+
+        let stream = IterStream {
+            iter: points(),
+            polls: 0,
+        };
+        let tree =
+            futures_executor::block_on(BirchCFTree::<2>::from_stream(stream, config.clone()));
+        let expected = BirchCFTree::<2>::from_iter(points(), config);
+        assert_eq!(
+            format!("{:?}", tree.root()),
+            format!("{:?}", expected.root())
+        );
+
+        // insertion yields to the executor between batches of points
+        let mut tree = expected;
+        let mut stream = IterStream {
+            iter: points(),
+            polls: 0,
+        };
+        {
+            let mut insertion = pin!(tree.insert_stream(&mut stream));
+            let waker = futures_executor::block_on(poll_fn(|cx| Poll::Ready(cx.waker().clone())));
+            let mut cx = Context::from_waker(&waker);
+            assert_eq!(insertion.as_mut().poll(&mut cx), Poll::Pending);
+            assert_eq!(insertion.as_mut().poll(&mut cx), Poll::Pending);
+            assert_eq!(insertion.as_mut().poll(&mut cx), Poll::Ready(3000));
+        }
+        assert_eq!(stream.polls, 3001);
+        assert_eq!(tree.metrics().inserted, 6000);
+    }
+}
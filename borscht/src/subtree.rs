@@ -0,0 +1,165 @@
+/*!
+ * Extraction of subtrees as independent trees.
+ *
+ * A subtree is addressed by a path of entry indices from the root: `[2, 0]` is the child node of
+ * the first entry of the child node of the third entry of the root. [CFTree::subtree_at] copies
+ * the subtree at a path into a tree of its own, while [CFTree::extract_cluster] splits it off,
+ * removing it from the original tree. Either way, the new tree has its own configuration, e.g.
+ * a finer threshold to re-cluster a region of a large heterogeneous dataset: the entries of the
+ * subtree are kept as they are, and the new configuration applies to points inserted from then
+ * on. Nodes may temporarily hold more entries than the capacity of the new configuration allows,
+ * until they next split.
+ */
+
+use alloc::{sync::Arc, vec};
+use core::fmt::Debug;
+
+use crate::{
+    cfeature::CFeature,
+    cftree::{unshare, CFTree, Node, NodeEntry, TreeConfig},
+};
+
+impl<CF, const DIMS: usize> Node<CF, DIMS> {
+    /// The child node reached by following `path` (indices of non-leaf entries) from this node,
+    /// or `None` if the path leads out of the tree. An empty path leads to this node.
+    pub fn subtree_at(&self, path: &[usize]) -> Option<&Node<CF, DIMS>> {
+        path.iter()
+            .try_fold(self, |node, &idx| node.entries.get(idx)?.child.as_deref())
+    }
+}
+
+impl<CF: CFeature<DIMS> + Debug + Clone, const DIMS: usize> Node<CF, DIMS> {
+    /// Removes the entry at `path` (indices of entries, the last of which may be a leaf entry)
+    /// from the tree rooted at this node, along with any nodes left without entries, and
+    /// recomputes the features of its remaining ancestors.
+    fn remove_entry(&mut self, path: &[usize]) -> Option<NodeEntry<CF, DIMS>> {
+        let (&idx, rest) = path.split_first()?;
+        if idx >= self.entries.len() {
+            return None;
+        }
+        let removed = match rest.is_empty() {
+            true => self.entries.remove(idx),
+            false => {
+                let child = Arc::make_mut(self.entries[idx].child.as_mut()?);
+                let removed = child.remove_entry(rest)?;
+                match child.entries.is_empty() {
+                    true => {
+                        self.entries.remove(idx);
+                    }
+                    false => self.entries[idx].feature = child.compute_feature(),
+                }
+                removed
+            }
+        };
+        self.refresh();
+        Some(removed)
+    }
+}
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+    TC: TreeConfig,
+{
+    /// Copies the subtree of this tree at `path` (see [Node::subtree_at]) into an independent tree
+    /// with configuration `config`. Returns `None` if the path leads out of the tree.
+    pub fn subtree_at<TC2: TreeConfig>(
+        &self,
+        path: &[usize],
+        config: TC2,
+    ) -> Option<CFTree<CF, DIMS, TC2>> {
+        let root = self.root().subtree_at(path)?.clone();
+        Some(CFTree::from_root(root, config))
+    }
+
+    /// Removes the entry of this tree at `path` (indices of entries from the root), and returns
+    /// it as an independent tree with configuration `config`: the subtree under the entry, or a
+    /// tree holding just the entry if it's a leaf entry. The features of the ancestors of the
+    /// entry are recomputed, and nodes left without entries are removed. Returns `None`, leaving
+    /// this tree unchanged, if the path is empty or leads out of the tree.
+    pub fn extract_cluster<TC2: TreeConfig>(
+        &mut self,
+        path: &[usize],
+        config: TC2,
+    ) -> Option<CFTree<CF, DIMS, TC2>> {
+        // check the path first, so that nothing is removed if it's invalid
+        let (&last, parent) = path.split_last()?;
+        self.root().subtree_at(parent)?.entries.get(last)?;
+        let entry = self.root_mut().remove_entry(path)?;
+        let root = match entry.child {
+            Some(child) => unshare(child),
+            None => Node::with_entries(vec![entry]),
+        };
+        Some(CFTree::from_root(root, config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cfeature::CFeature,
+        cftree::{BasicConfig, BirchCFTree},
+        point::{Point, Scalar},
+    };
+
+    #[test]
+    fn extract_cluster() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(1.0)
+            .build()
+            .unwrap();
+        let points = (0..100)
+            .map(|i| Point::from_arr([(i % 10) as Scalar * 10.0, (i / 10) as Scalar * 10.0]))
+            .collect::<Vec<_>>();
+        let mut tree = BirchCFTree::<2>::from_iter(points, config.clone());
+        assert!(tree.root().height() > 2);
+
+        let finer = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.1)
+            .build()
+            .unwrap();
+        let copy = tree.subtree_at(&[0], finer.clone()).unwrap();
+        assert!(tree.subtree_at(&[0, 99], finer.clone()).is_none());
+        assert_eq!(copy.config().threshold, 0.1);
+        let leaves = copy.root().leaf_count();
+        let total = tree.root().leaf_count();
+        assert_eq!(copy.root().height(), tree.root().height() - 1);
+
+        // splitting off the same subtree removes it from the tree
+        let mut region = tree.extract_cluster(&[0], finer).unwrap();
+        assert_eq!(region.root().leaf_count(), leaves);
+        assert_eq!(tree.root().leaf_count(), total - leaves);
+        let weights =
+            |tree: &BirchCFTree<2>| tree.clusters().map(|cluster| cluster.size).sum::<Scalar>();
+        assert_eq!(weights(&tree) + weights(&region), 100.0);
+        assert_eq!(
+            tree.root()
+                .entries
+                .iter()
+                .map(|entry| entry.feature.size())
+                .sum::<Scalar>(),
+            weights(&tree)
+        );
+
+        // the extracted tree takes further insertions with its own threshold
+        let p = region.clusters().next().unwrap().center;
+        region.insert(p + &Point::from_arr([0.5, 0.0]));
+        assert_eq!(region.root().leaf_count(), leaves + 1);
+
+        // a leaf entry becomes a tree of its own
+        let mut path = vec![];
+        let mut node = tree.root();
+        while let Some(ref child) = node.entries[0].child {
+            path.push(0);
+            node = child;
+        }
+        path.push(0);
+        let leaf = tree.extract_cluster(&path, config.clone()).unwrap();
+        assert_eq!(leaf.root().leaf_count(), 1);
+        assert!(tree.extract_cluster(&[], config.clone()).is_none());
+        assert!(tree.extract_cluster(&[99], config).is_none());
+        assert_eq!(weights(&tree) + weights(&region) + weights(&leaf), 101.0);
+    }
+}
@@ -0,0 +1,307 @@
+/*!
+ * Flat summaries of the leaf clusters of a tree. Lets downstream code (metrics, plotting,
+ * exporting) work with the clusters found by the tree without knowing its internal layout.
+ */
+
+use alloc::{vec, vec::Vec};
+
+use num_traits::Float as _;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{variance_padding, CFTree, Node, NodeEntry, TreeConfig},
+    point::{Float, Point, Scalar},
+    reservoir::Reservoir,
+};
+
+/// Summary statistics of a single leaf cluster.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterSummary<const DIMS: usize, T = Scalar> {
+    /// Index of this cluster among the leaf clusters of the tree, in depth-first order.
+    pub id: usize,
+    pub center: Point<DIMS, T>,
+    pub radius: T,
+    pub diameter: T,
+    pub size: T,
+    /// Depth of the leaf node containing this cluster (the root node has depth 0).
+    pub depth: usize,
+}
+
+/// How [CFTree::top_clusters] ranks leaf clusters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// Largest first, by size.
+    #[default]
+    Weight,
+    /// Densest first, by size per volume of the ball of the cluster's radius. The squared radius
+    /// is padded by the spread allowed by the threshold (as with [Metric::Mahalanobis]), so that
+    /// small clusters aren't arbitrarily dense.
+    ///
+    /// [Metric::Mahalanobis]: crate::cftree::Metric::Mahalanobis
+    Density,
+}
+
+impl<CF: CFeature<DIMS>, const DIMS: usize> NodeEntry<CF, DIMS> {
+    fn summarize(&self, id: usize, depth: usize) -> ClusterSummary<DIMS, CF::Scalar> {
+        ClusterSummary {
+            id,
+            center: self.feature.center(),
+            radius: self.feature.radius(),
+            diameter: self.feature.diam(),
+            size: self.feature.size(),
+            depth,
+        }
+    }
+}
+
+/// Iterator over the leaf clusters of a tree. Created by [Node::clusters] or [CFTree::clusters].
+pub struct Clusters<'a, CF, const DIMS: usize> {
+    stack: Vec<(core::slice::Iter<'a, NodeEntry<CF, DIMS>>, usize)>,
+    next_id: usize,
+}
+
+impl<'a, CF: CFeature<DIMS>, const DIMS: usize> Iterator for Clusters<'a, CF, DIMS> {
+    type Item = ClusterSummary<DIMS, CF::Scalar>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (entries, depth) = self.stack.last_mut()?;
+            let depth = *depth;
+            match entries.next() {
+                Some(NodeEntry {
+                    child: Some(child), ..
+                }) => {
+                    self.stack.push((child.entries.iter(), depth + 1));
+                }
+                Some(entry) => {
+                    let id = self.next_id;
+                    self.next_id += 1;
+                    return Some(entry.summarize(id, depth));
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+impl<CF: CFeature<DIMS>, const DIMS: usize> Node<CF, DIMS> {
+    /// Returns an iterator over summaries of the leaf clusters of the tree rooted at this node.
+    pub fn clusters(&self) -> Clusters<'_, CF, DIMS> {
+        Clusters {
+            stack: vec![(self.entries.iter(), 0)],
+            next_id: 0,
+        }
+    }
+}
+
+impl<CF: CFeature<DIMS>, TC, const DIMS: usize> CFTree<CF, DIMS, TC> {
+    /// Returns an iterator over summaries of the leaf clusters of this tree.
+    pub fn clusters(&self) -> Clusters<'_, CF, DIMS> {
+        self.root().clusters()
+    }
+}
+
+impl<CF: CFeature<DIMS>, TC: TreeConfig, const DIMS: usize> CFTree<CF, DIMS, TC> {
+    /// Labels each point with the id of the leaf cluster closest to it (ties are resolved in
+    /// favor of the lower id), or `None` if the tree is empty. Distances are measured with the
+    /// metric of the tree's configuration (see [TreeConfig::metric]).
+    pub fn labels<'a, I>(&self, points: I) -> Vec<Option<usize>>
+    where
+        I: IntoIterator<Item = &'a FeaturePoint<CF, DIMS>>,
+        FeaturePoint<CF, DIMS>: 'a,
+    {
+        let mut leaves = vec![];
+        collect_leaves(self.root(), &mut leaves);
+        let metric = self.config().metric();
+        points
+            .into_iter()
+            .map(|p| {
+                leaves
+                    .iter()
+                    .map(|feature| metric.dist2(*feature, p, self.config()))
+                    .enumerate()
+                    .fold(None, |closest, (id, d2)| match closest {
+                        Some((_, closest_d2)) if closest_d2 <= d2 => closest,
+                        _ => Some((id, d2)),
+                    })
+                    .map(|(id, _)| id)
+            })
+            .collect()
+    }
+
+    /// Maps each point to its distances from the leaf clusters of this tree (indexed by
+    /// [ClusterSummary::id]), measured with the metric of the tree's configuration, e.g. to use
+    /// the tree as a feature extractor for downstream models. Every point maps to as many
+    /// distances as the tree has leaf clusters (none if the tree is empty).
+    pub fn transform<'a, I>(&self, points: I) -> Vec<Vec<CF::Scalar>>
+    where
+        I: IntoIterator<Item = &'a FeaturePoint<CF, DIMS>>,
+        FeaturePoint<CF, DIMS>: 'a,
+    {
+        let mut leaves = vec![];
+        collect_leaves(self.root(), &mut leaves);
+        let metric = self.config().metric();
+        points
+            .into_iter()
+            .map(|p| {
+                leaves
+                    .iter()
+                    .map(|feature| metric.dist2(*feature, p, self.config()).sqrt())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Summaries of the (at most) `k` leaf clusters of this tree ranked first by `by`, e.g. the
+    /// main segments of the data. Ties are ranked in order of cluster id.
+    pub fn top_clusters(&self, k: usize, by: SortKey) -> Vec<ClusterSummary<DIMS, CF::Scalar>> {
+        let padding = variance_padding::<Scalar, _, DIMS>(self.config()) * DIMS as Scalar;
+        let mut ranked = self
+            .clusters()
+            .map(|cluster| {
+                let size = cluster.size.to_scalar();
+                let key = match by {
+                    SortKey::Weight => size,
+                    SortKey::Density => {
+                        let radius2 = (cluster.radius * cluster.radius).to_scalar() + padding;
+                        size / radius2.sqrt().powi(DIMS as i32)
+                    }
+                };
+                (key, cluster)
+            })
+            .collect::<Vec<_>>();
+        // stable, so ties stay in order of cluster id
+        ranked.sort_by(|(left, _), (right, _)| right.total_cmp(left));
+        ranked
+            .into_iter()
+            .take(k)
+            .map(|(_, cluster)| cluster)
+            .collect()
+    }
+}
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC> {
+    /// Ids of the points absorbed by each leaf cluster, indexed by cluster id (see
+    /// [ClusterSummary::id]). Empty unless the tree tracks ids (see [TreeConfig::track_ids]).
+    pub fn cluster_ids(&self) -> Vec<&[u64]> {
+        let mut entries = vec![];
+        collect_leaf_entries(self.root(), &mut entries);
+        entries
+            .into_iter()
+            .map(|entry| entry.ids.as_slice())
+            .collect()
+    }
+
+    /// Samples of the points absorbed by each leaf cluster, indexed by cluster id. Empty unless
+    /// the tree keeps samples (see [TreeConfig::reservoir_size]).
+    pub fn cluster_samples(&self) -> Vec<&Reservoir<DIMS>> {
+        let mut entries = vec![];
+        collect_leaf_entries(self.root(), &mut entries);
+        entries.into_iter().map(|entry| &entry.samples).collect()
+    }
+}
+
+/// Collects the leaf entries of the tree rooted at `node`, in order of cluster id.
+pub(crate) fn collect_leaf_entries<'a, CF, const DIMS: usize>(
+    node: &'a Node<CF, DIMS>,
+    entries: &mut Vec<&'a NodeEntry<CF, DIMS>>,
+) {
+    for entry in &node.entries {
+        match entry.child {
+            Some(ref child) => collect_leaf_entries(child, entries),
+            None => entries.push(entry),
+        }
+    }
+}
+
+/// Collects the leaf cluster features of the tree rooted at `node`, in order of cluster id.
+pub(crate) fn collect_leaves<'a, CF, const DIMS: usize>(
+    node: &'a Node<CF, DIMS>,
+    leaves: &mut Vec<&'a CF>,
+) {
+    for entry in &node.entries {
+        match entry.child {
+            Some(ref child) => collect_leaves(child, leaves),
+            None => leaves.push(&entry.feature),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cftree::{BasicConfig, BirchCFTree};
+
+    #[test]
+    fn clusters() {
+        let points = (0..12)
+            .map(|i| Point::from_arr([(i % 4) as f64 * 10.0, (i / 4) as f64 * 10.0]))
+            .collect::<Vec<_>>();
+        let tree = BirchCFTree::from_iter(
+            points.clone(),
+            BasicConfig::builder()
+                .capacity(1, 3)
+                .threshold(0.5)
+                .build()
+                .unwrap(),
+        );
+        let clusters = tree.clusters().collect::<Vec<_>>();
+
+        assert_eq!(clusters.len(), 12);
+        assert!(clusters.iter().enumerate().all(|(i, c)| c.id == i));
+        assert!(clusters
+            .iter()
+            .all(|c| c.size == 1.0 && c.radius == 0.0 && c.diameter == 0.0));
+        assert!(points
+            .iter()
+            .all(|p| clusters.iter().any(|c| &c.center == p)));
+        let height = tree.root().height();
+        assert!(clusters.iter().all(|c| c.depth == height - 1));
+
+        let labels = tree.labels(&points);
+        assert!(points
+            .iter()
+            .zip(labels)
+            .all(|(p, label)| &clusters[label.unwrap()].center == p));
+
+        let distances = tree.transform(&points);
+        assert_eq!(distances.len(), points.len());
+        for (p, distances) in points.iter().zip(distances) {
+            assert_eq!(distances.len(), clusters.len());
+            for (cluster, distance) in clusters.iter().zip(distances) {
+                assert_eq!(distance, (&cluster.center - p).norm2().sqrt());
+            }
+        }
+    }
+
+    #[test]
+    fn top_clusters() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(2.0)
+            .build()
+            .unwrap();
+        // a large spread-out cluster, a smaller tight one, and scattered single points
+        let points = (0..30)
+            .map(|i| Point::from_arr([(i % 2) as f64 * 1.1, (i / 2 % 2) as f64 * 1.1]))
+            .chain((0..20).map(|i| Point::from_arr([50.0 + (i % 2) as f64 * 0.1, 0.0])))
+            .chain((0..5).map(|i| Point::from_arr([100.0 + i as f64 * 10.0, 100.0])))
+            .collect::<Vec<_>>();
+        let tree = BirchCFTree::<2>::from_iter(points, config);
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.len(), 7);
+
+        let largest = tree.top_clusters(2, SortKey::Weight);
+        assert_eq!(largest.len(), 2);
+        assert_eq!((largest[0].size, largest[1].size), (30.0, 20.0));
+        assert_eq!(largest[0], clusters[largest[0].id]);
+        let densest = tree.top_clusters(1, SortKey::Density);
+        assert_eq!(densest[0].size, 20.0);
+        // ties keep the order of cluster ids
+        let all = tree.top_clusters(10, SortKey::Weight);
+        assert_eq!(all.len(), 7);
+        assert!(all[2..].windows(2).all(|w| w[0].id < w[1].id));
+    }
+}
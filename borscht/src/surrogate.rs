@@ -0,0 +1,119 @@
+/*!
+ * Generation of surrogate data sets from the leaf clusters of a tree.
+ *
+ * [sample_from_tree] treats the leaf clusters of a tree as a Gaussian mixture (as in
+ * [crate::soft], but without padding): each point is drawn from a leaf cluster chosen with
+ * probability proportional to its size, from a Gaussian with the center and per-dimension variance
+ * of that cluster. Comparing a surrogate data set with the original data (e.g. by plotting both)
+ * is a quick check of how well the tree summarizes it.
+ */
+
+use alloc::vec::Vec;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::CFTree,
+    point::{Float, Point, Scalar},
+    rng::SplitMix64,
+    summary::collect_leaves,
+};
+
+/// Draws `n` points from the leaf clusters of `tree` (see the [module documentation](self)). The
+/// same seed always generates the same points from the same tree. Returns no points if the tree
+/// is empty.
+pub fn sample_from_tree<CF, TC, const DIMS: usize>(
+    tree: &CFTree<CF, DIMS, TC>,
+    n: usize,
+    seed: u64,
+) -> Vec<FeaturePoint<CF, DIMS>>
+where
+    CF: CFeature<DIMS>,
+{
+    let mut leaves = Vec::new();
+    collect_leaves(tree.root(), &mut leaves);
+    let components = leaves
+        .into_iter()
+        .map(|feature| {
+            let center = feature.center().cast::<Scalar>();
+            let mut std_dev = feature.variance().cast::<Scalar>();
+            for d in 0..DIMS {
+                std_dev[d] = std_dev[d].max(0.0).sqrt();
+            }
+            (center, std_dev, feature.size().to_scalar())
+        })
+        .collect::<Vec<_>>();
+    // cumulative sizes, to choose clusters in proportion to their size
+    let mut cumulative = Vec::with_capacity(components.len());
+    let mut total = 0.0;
+    for (_, _, size) in &components {
+        total += size;
+        cumulative.push(total);
+    }
+    if components.is_empty() || total <= 0.0 {
+        return Vec::new();
+    }
+
+    let mut rng = SplitMix64(seed);
+    (0..n)
+        .map(|_| {
+            let target = rng.uniform() * total;
+            let idx = cumulative
+                .partition_point(|&size| size <= target)
+                .min(components.len() - 1);
+            let (center, std_dev, _) = &components[idx];
+            let mut p = Point::<DIMS>::default();
+            for d in 0..DIMS {
+                p[d] = center[d] + std_dev[d] * rng.normal();
+            }
+            p.cast()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cftree::{BasicConfig, BirchCFTree};
+
+    #[test]
+    fn sample_from_tree() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(200.0)
+            .build()
+            .unwrap();
+        // a cluster of 300 points spread along x around (0, 0), and one of 100 points around
+        // (50, 50) spread along y
+        let points = (0..400).map(|i| {
+            let offset = ((i / 4) % 11) as f64 - 5.0;
+            match i % 4 {
+                3 => Point::from_arr([50.0, 50.0 + offset * 0.2]),
+                _ => Point::from_arr([offset, 0.0]),
+            }
+        });
+        let tree = BirchCFTree::<2>::from_iter(points, config.clone());
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.len(), 2);
+
+        let samples = super::sample_from_tree(&tree, 4000, 7);
+        assert_eq!(samples.len(), 4000);
+        assert_eq!(samples, super::sample_from_tree(&tree, 4000, 7));
+        let (near, far): (Vec<_>, Vec<_>) = samples.iter().partition(|p| p[0] < 25.0);
+        assert!((2800..3200).contains(&near.len()));
+        let moments = |points: &[&Point<2>], d: usize| {
+            let mean = points.iter().map(|p| p[d]).sum::<f64>() / points.len() as f64;
+            let variance =
+                points.iter().map(|p| (p[d] - mean).powi(2)).sum::<f64>() / points.len() as f64;
+            (mean, variance)
+        };
+        // the variances of the clusters are 10 and 0.4 along their spread dimension
+        let (mean, variance) = moments(&near, 0);
+        assert!(mean.abs() < 0.3 && (variance - 10.0).abs() < 1.0);
+        assert_eq!(moments(&near, 1), (0.0, 0.0));
+        let (mean, variance) = moments(&far, 1);
+        assert!((mean - 50.0).abs() < 0.1 && (variance - 0.4).abs() < 0.05);
+
+        let empty = BirchCFTree::<2>::new(config);
+        assert!(super::sample_from_tree(&empty, 10, 7).is_empty());
+    }
+}
@@ -0,0 +1,231 @@
+/*!
+ * Recording of the structural changes of a tree as it grows, e.g. to replay its construction step
+ * by step when debugging, or to animate it.
+ *
+ * A [TraceRecorder] attached to a tree (see [CFTree::with_recorder]) logs a [TraceEvent] for every
+ * insertion (whether it was absorbed or started a new leaf entry), for every node split it caused
+ * (with the entries that ended up on each side), and for every rebuild of the tree with a larger
+ * threshold (by a [Birch](crate::birch::Birch) estimator bounding its leaf clusters). Events only
+ * summarize the entries involved (see [TracedEntry]), and serialize with serde, so logs can be
+ * saved and replayed elsewhere. Batch insertions (see [CFTree::insert_batch]) record the insertion
+ * of each point of the batch in order, followed by all the splits of the batch.
+ */
+
+use alloc::{vec, vec::Vec};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cfeature::CFeature,
+    cftree::{CFTree, Node, TreeConfig},
+    point::{Float, Point, Scalar},
+    summary::collect_leaves,
+};
+
+/// Summary of an entry (or inserted feature) involved in a [TraceEvent].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TracedEntry<const DIMS: usize> {
+    pub center: Point<DIMS>,
+    /// Total size (weight) of the points summarized by the entry.
+    pub size: Scalar,
+}
+
+impl<const DIMS: usize> TracedEntry<DIMS> {
+    pub(crate) fn of<CF: CFeature<DIMS>>(feature: &CF) -> TracedEntry<DIMS> {
+        TracedEntry {
+            center: feature.center().cast(),
+            size: feature.size().to_scalar(),
+        }
+    }
+}
+
+/// A structural change of a tree.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TraceEvent<const DIMS: usize> {
+    /// The `index`-th insertion into the tree (counting from zero) was absorbed into an existing
+    /// leaf entry.
+    Absorbed {
+        index: u64,
+        entry: TracedEntry<DIMS>,
+    },
+    /// The `index`-th insertion into the tree started a new leaf entry. Any splits it caused
+    /// follow, from the leaves up.
+    NewEntry {
+        index: u64,
+        entry: TracedEntry<DIMS>,
+    },
+    /// The node at depth `depth` (the root has depth 0) overflowed and split in two: before the
+    /// split, it held the entries of both halves. A split of the root grows the tree by a level.
+    Split {
+        depth: usize,
+        left: Vec<TracedEntry<DIMS>>,
+        right: Vec<TracedEntry<DIMS>>,
+    },
+    /// The tree was rebuilt from scratch with threshold `threshold`, and now has leaf entries
+    /// `leaves`.
+    Rebuild {
+        threshold: Scalar,
+        leaves: Vec<TracedEntry<DIMS>>,
+    },
+}
+
+impl<const DIMS: usize> TraceEvent<DIMS> {
+    pub(crate) fn split<CF: CFeature<DIMS>>(
+        depth: usize,
+        left: &Node<CF, DIMS>,
+        right: &Node<CF, DIMS>,
+    ) -> TraceEvent<DIMS> {
+        let entries = |node: &Node<CF, DIMS>| {
+            node.entries
+                .iter()
+                .map(|entry| TracedEntry::of(&entry.feature))
+                .collect()
+        };
+        TraceEvent::Split {
+            depth,
+            left: entries(left),
+            right: entries(right),
+        }
+    }
+}
+
+/// Log of the [TraceEvent]s of a tree, in the order they happened.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TraceRecorder<const DIMS: usize> {
+    events: Vec<TraceEvent<DIMS>>,
+}
+
+impl<const DIMS: usize> TraceRecorder<DIMS> {
+    pub fn new() -> TraceRecorder<DIMS> {
+        TraceRecorder { events: vec![] }
+    }
+
+    pub fn events(&self) -> &[TraceEvent<DIMS>] {
+        &self.events
+    }
+
+    pub fn into_events(self) -> Vec<TraceEvent<DIMS>> {
+        self.events
+    }
+
+    /// Discards the events recorded so far (e.g. once they've been saved).
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub(crate) fn push(&mut self, event: TraceEvent<DIMS>) {
+        self.events.push(event);
+    }
+}
+
+impl<CF, TC, const DIMS: usize> CFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + core::fmt::Debug + Clone,
+    TC: TreeConfig,
+{
+    /// Moves the recorder of `previous` (the tree this one was rebuilt from) to this tree, and
+    /// records the rebuild.
+    pub(crate) fn trace_rebuild(&mut self, previous: &mut CFTree<CF, DIMS, TC>) {
+        if let Some(mut recorder) = previous.take_recorder() {
+            let mut leaves = vec![];
+            collect_leaves(self.root(), &mut leaves);
+            recorder.push(TraceEvent::Rebuild {
+                threshold: self.config().threshold(),
+                leaves: leaves.into_iter().map(TracedEntry::of).collect(),
+            });
+            *self.recorder_mut() = Some(recorder);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        birch::Birch,
+        cfeature::birch::CFeature as BirchFeature,
+        cftree::{BasicConfig, BirchCFTree},
+    };
+
+    #[test]
+    fn trace() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let mut tree = BirchCFTree::<2>::new(config.clone()).with_recorder(TraceRecorder::new());
+        for i in 0..200 {
+            tree.insert(Point::from_arr([(i % 17) as Scalar, (i % 13) as Scalar]));
+        }
+        let metrics = tree.metrics().clone();
+        let events = tree.take_recorder().unwrap().into_events();
+
+        let (mut absorbed, mut new_entries, mut splits) = (0, 0, 0);
+        let mut index = 0;
+        for event in &events {
+            match event {
+                TraceEvent::Absorbed { index: i, entry }
+                | TraceEvent::NewEntry { index: i, entry } => {
+                    assert_eq!(*i, index);
+                    assert_eq!(entry.size, 1.0);
+                    index += 1;
+                    match event {
+                        TraceEvent::Absorbed { .. } => absorbed += 1,
+                        _ => new_entries += 1,
+                    }
+                }
+                TraceEvent::Split { left, right, .. } => {
+                    splits += 1;
+                    // a full node splits into halves of at least the minimum capacity
+                    assert_eq!(
+                        (left.len().min(right.len()), left.len() + right.len()),
+                        (2, 4)
+                    );
+                }
+                TraceEvent::Rebuild { .. } => unreachable!("no rebuilds"),
+            }
+        }
+        assert_eq!(index, 200);
+        assert_eq!(
+            (absorbed, new_entries, splits),
+            (
+                metrics.absorbed,
+                metrics.new_entries,
+                metrics.total_splits()
+            )
+        );
+        assert!(splits > 0);
+        assert!(tree.recorder().is_none());
+
+        // rebuilds are recorded, with the reinserted leaf entries
+        let mut birch = Birch::<BirchFeature<2>, 2>::new(config)
+            .max_leaves(10)
+            .with_recorder(TraceRecorder::new());
+        birch.fit((0..200).map(|i| Point::from_arr([(i % 17) as Scalar, (i % 13) as Scalar])));
+        let recorder = birch.take_recorder().unwrap();
+        let rebuilds = recorder
+            .events()
+            .iter()
+            .filter_map(|event| match event {
+                TraceEvent::Rebuild { threshold, leaves } => Some((threshold, leaves)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rebuilds.len() as u64, birch.tree().metrics().rebuilds);
+        assert!(!rebuilds.is_empty());
+        for (&threshold, leaves) in rebuilds {
+            assert!(threshold > 0.5);
+            assert!(leaves.iter().map(|leaf| leaf.size).sum::<Scalar>() <= 200.0);
+        }
+
+        #[cfg(feature = "json")]
+        {
+            let json = serde_json::to_string(&recorder).unwrap();
+            assert_eq!(
+                serde_json::from_str::<TraceRecorder<2>>(&json).unwrap(),
+                recorder
+            );
+        }
+    }
+}
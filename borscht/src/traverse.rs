@@ -0,0 +1,297 @@
+/*!
+ * Iterators over the nodes and entries of a tree, without recursion.
+ *
+ * [Node::iter_nodes_bfs] visits nodes level by level, [Node::iter_nodes_dfs] visits them depth
+ * first (each node before its children), and [Node::iter_entries_with_depth] visits every entry
+ * depth first, along with the depth of the node holding it. Depth-first orders follow the order
+ * of entries within nodes, so leaf entries are visited in the order of the ids of their clusters
+ * (see [ClusterSummary::id](crate::summary::ClusterSummary::id)).
+ *
+ * For analyses which need more context, a [TreeVisitor] is called back for every node and entry
+ * (see [Node::accept]), with their depth and parent, and controls the traversal: it can skip the
+ * subtree under a node or entry, or stop altogether.
+ */
+
+use alloc::{collections::VecDeque, vec, vec::Vec};
+
+use crate::cftree::{Node, NodeEntry};
+
+/// Iterator over the nodes of a tree, level by level. Created by [Node::iter_nodes_bfs].
+pub struct NodesBfs<'a, CF, const DIMS: usize> {
+    queue: VecDeque<&'a Node<CF, DIMS>>,
+}
+
+impl<'a, CF, const DIMS: usize> Iterator for NodesBfs<'a, CF, DIMS> {
+    type Item = &'a Node<CF, DIMS>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.queue.pop_front()?;
+        self.queue.extend(
+            node.entries
+                .iter()
+                .filter_map(|entry| entry.child.as_deref()),
+        );
+        Some(node)
+    }
+}
+
+/// Iterator over the nodes of a tree, depth first. Created by [Node::iter_nodes_dfs].
+pub struct NodesDfs<'a, CF, const DIMS: usize> {
+    stack: Vec<&'a Node<CF, DIMS>>,
+}
+
+impl<'a, CF, const DIMS: usize> Iterator for NodesDfs<'a, CF, DIMS> {
+    type Item = &'a Node<CF, DIMS>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        // pushed in reverse, so that the child of the first entry is visited first
+        self.stack.extend(
+            node.entries
+                .iter()
+                .rev()
+                .filter_map(|entry| entry.child.as_deref()),
+        );
+        Some(node)
+    }
+}
+
+/// Iterator over the entries of a tree, depth first, with the depth of the node holding each
+/// entry (the root node has depth 0). Created by [Node::iter_entries_with_depth].
+pub struct EntriesWithDepth<'a, CF, const DIMS: usize> {
+    stack: Vec<(core::slice::Iter<'a, NodeEntry<CF, DIMS>>, usize)>,
+}
+
+impl<'a, CF, const DIMS: usize> Iterator for EntriesWithDepth<'a, CF, DIMS> {
+    type Item = (usize, &'a NodeEntry<CF, DIMS>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (entries, depth) = self.stack.last_mut()?;
+            let depth = *depth;
+            match entries.next() {
+                Some(entry) => {
+                    if let Some(ref child) = entry.child {
+                        self.stack.push((child.entries.iter(), depth + 1));
+                    }
+                    return Some((depth, entry));
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+impl<CF, const DIMS: usize> Node<CF, DIMS> {
+    /// Returns an iterator over the nodes of the tree rooted at this node (including this one),
+    /// level by level.
+    pub fn iter_nodes_bfs(&self) -> NodesBfs<'_, CF, DIMS> {
+        NodesBfs {
+            queue: VecDeque::from(vec![self]),
+        }
+    }
+
+    /// Returns an iterator over the nodes of the tree rooted at this node (including this one),
+    /// depth first.
+    pub fn iter_nodes_dfs(&self) -> NodesDfs<'_, CF, DIMS> {
+        NodesDfs { stack: vec![self] }
+    }
+
+    /// Returns an iterator over the entries of the tree rooted at this node, depth first, with
+    /// the depth of the node holding each entry relative to this node.
+    pub fn iter_entries_with_depth(&self) -> EntriesWithDepth<'_, CF, DIMS> {
+        EntriesWithDepth {
+            stack: vec![(self.entries.iter(), 0)],
+        }
+    }
+
+    /// Number of nodes in the tree rooted at this node (including this one).
+    pub fn node_count(&self) -> usize {
+        self.iter_nodes_dfs().count()
+    }
+
+    /// Walks the tree rooted at this node depth first, calling `visitor` back for each node, then
+    /// for each of its entries (each followed by the subtree under it), as long as the visitor
+    /// lets the traversal continue (see [Visit]). Returns `false` if the visitor stopped the
+    /// traversal early.
+    pub fn accept<V: TreeVisitor<CF, DIMS> + ?Sized>(&self, visitor: &mut V) -> bool {
+        let mut stack = vec![];
+        match visitor.visit_node(self, 0, None) {
+            Visit::Continue => stack.push((self, self.entries.iter(), 0)),
+            Visit::SkipChildren => return true,
+            Visit::Stop => return false,
+        }
+        while let Some((node, entries, depth)) = stack.last_mut() {
+            let (node, depth) = (*node, *depth);
+            let entry = match entries.next() {
+                Some(entry) => entry,
+                None => {
+                    stack.pop();
+                    continue;
+                }
+            };
+            match visitor.visit_entry(entry, depth, node) {
+                Visit::Continue => {}
+                Visit::SkipChildren => continue,
+                Visit::Stop => return false,
+            }
+            if let Some(ref child) = entry.child {
+                match visitor.visit_node(child, depth + 1, Some(entry)) {
+                    Visit::Continue => stack.push((child, child.entries.iter(), depth + 1)),
+                    Visit::SkipChildren => {}
+                    Visit::Stop => return false,
+                }
+            }
+        }
+        true
+    }
+}
+
+/// How a traversal by [Node::accept] continues after a visit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visit {
+    Continue,
+    /// Continue, but skip the entries of the visited node, or the subtree under the visited
+    /// entry.
+    SkipChildren,
+    /// End the traversal.
+    Stop,
+}
+
+/// Callbacks for the nodes and entries of a tree, in the depth-first order of [Node::accept].
+/// Depths are relative to the node the traversal starts from, which has depth 0. Both methods
+/// continue the traversal by default.
+pub trait TreeVisitor<CF, const DIMS: usize> {
+    /// Visits `node`, at depth `depth`, under the entry `parent` (or `None` for the node the
+    /// traversal starts from).
+    fn visit_node(
+        &mut self,
+        _node: &Node<CF, DIMS>,
+        _depth: usize,
+        _parent: Option<&NodeEntry<CF, DIMS>>,
+    ) -> Visit {
+        Visit::Continue
+    }
+
+    /// Visits `entry`, of the node `parent` at depth `depth`.
+    fn visit_entry(
+        &mut self,
+        _entry: &NodeEntry<CF, DIMS>,
+        _depth: usize,
+        _parent: &Node<CF, DIMS>,
+    ) -> Visit {
+        Visit::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cfeature::{birch::CFeature as BirchFeature, CFeature},
+        cftree::{BasicConfig, BirchCFTree},
+        point::{Point, Scalar},
+    };
+
+    /// Counts entries per level, stopping at the first leaf entry of more than `stop_size`
+    /// points, and skipping the subtrees under entries of fewer than `skip_size` points.
+    struct Histogram {
+        entries: Vec<usize>,
+        skip_size: Scalar,
+        stop_size: Scalar,
+    }
+
+    impl TreeVisitor<BirchFeature<2>, 2> for Histogram {
+        fn visit_entry(
+            &mut self,
+            entry: &NodeEntry<BirchFeature<2>, 2>,
+            depth: usize,
+            _parent: &Node<BirchFeature<2>, 2>,
+        ) -> Visit {
+            if self.entries.len() <= depth {
+                self.entries.resize(depth + 1, 0);
+            }
+            self.entries[depth] += 1;
+            match entry.feature.size() {
+                size if entry.child.is_none() && size > self.stop_size => Visit::Stop,
+                size if size < self.skip_size => Visit::SkipChildren,
+                _ => Visit::Continue,
+            }
+        }
+    }
+
+    #[test]
+    fn traversal() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.5)
+            .build()
+            .unwrap();
+        let points =
+            (0..200).map(|i| Point::from_arr([(i * 7 % 31) as Scalar, (i % 13) as Scalar]));
+        let tree = BirchCFTree::<2>::from_iter(points, config);
+        let root = tree.root();
+        assert!(root.height() > 3);
+
+        // both orders visit every node once, starting from the root
+        let bfs = root.iter_nodes_bfs().collect::<Vec<_>>();
+        let dfs = root.iter_nodes_dfs().collect::<Vec<_>>();
+        assert_eq!(bfs.len(), dfs.len());
+        assert_eq!(root.node_count(), dfs.len());
+        assert!(core::ptr::eq(bfs[0], root) && core::ptr::eq(dfs[0], root));
+        // level by level: heights never increase
+        assert!(bfs.windows(2).all(|w| w[0].height() >= w[1].height()));
+        // depth first: the first leaf node is reached by following first entries
+        let mut first_leaf = root;
+        while let Some(ref child) = first_leaf.entries[0].child {
+            first_leaf = child;
+        }
+        assert!(core::ptr::eq(dfs[root.height() - 1], first_leaf));
+
+        // leaf entries come in the order of cluster ids, at the depth of their clusters
+        let leaves = root
+            .iter_entries_with_depth()
+            .filter(|(_, entry)| entry.child.is_none())
+            .collect::<Vec<_>>();
+        let clusters = tree.clusters().collect::<Vec<_>>();
+        assert_eq!(leaves.len(), clusters.len());
+        for ((depth, entry), cluster) in leaves.into_iter().zip(clusters) {
+            assert_eq!(depth, cluster.depth);
+            assert_eq!(entry.feature.center(), cluster.center);
+        }
+        let entries = root.iter_entries_with_depth().count();
+        let nodes = dfs.iter().map(|node| node.entries.len()).sum::<usize>();
+        assert_eq!(entries, nodes);
+
+        // a visitor sees the same entries at the same depths
+        let mut histogram = Histogram {
+            entries: vec![],
+            skip_size: 0.0,
+            stop_size: Scalar::INFINITY,
+        };
+        assert!(root.accept(&mut histogram));
+        let mut expected = vec![0; root.height()];
+        for (depth, _) in root.iter_entries_with_depth() {
+            expected[depth] += 1;
+        }
+        assert_eq!(histogram.entries, expected);
+        // skipping every subtree only visits the root entries
+        let mut histogram = Histogram {
+            entries: vec![],
+            skip_size: Scalar::INFINITY,
+            stop_size: Scalar::INFINITY,
+        };
+        assert!(root.accept(&mut histogram));
+        assert_eq!(histogram.entries, vec![root.entries.len()]);
+        // stopping at the first leaf entry
+        let mut histogram = Histogram {
+            entries: vec![],
+            skip_size: 0.0,
+            stop_size: 0.0,
+        };
+        assert!(!root.accept(&mut histogram));
+        assert_eq!(histogram.entries, vec![1; root.height()]);
+    }
+}
@@ -0,0 +1,414 @@
+/*!
+ * Hyperparameter search over the threshold and capacity of a tree.
+ *
+ * [tune] builds a tree over the same points for every configuration of a [Search] (a grid, or
+ * random draws), scores each tree with an internal clustering metric (one needing no ground
+ * truth, see [Score]), and returns a [TuneReport] ranking the configurations from best to worst.
+ *
+ * The internal metrics are also available on their own: [davies_bouldin] and
+ * [calinski_harabasz] are computed from the leaf cluster features alone, while [silhouette]
+ * needs the points themselves. All three treat the leaf clusters of the tree as the clusters.
+ */
+
+use alloc::vec::Vec;
+use core::{fmt::Debug, ops::RangeInclusive};
+
+use num_traits::Float;
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{BasicConfig, BasicConfigBuilder, CFTree, Capacity, TreeConfig, TreeMetrics},
+    error::BorschtError,
+    point::{Float as _, Point, Scalar},
+    rng::SplitMix64,
+    summary::collect_leaves,
+};
+
+/// Configurations to try, each combining a threshold and a capacity.
+#[derive(Debug, Clone)]
+pub enum Search {
+    /// Every combination of the given thresholds and capacities.
+    Grid {
+        thresholds: Vec<Scalar>,
+        capacities: Vec<Capacity>,
+    },
+    /// `trials` combinations of a threshold drawn from `thresholds` (log-uniformly if its lower
+    /// bound is positive, so that every order of magnitude is equally likely, and uniformly
+    /// otherwise) and a capacity drawn from `capacities`, generated from `seed`.
+    Random {
+        thresholds: RangeInclusive<Scalar>,
+        capacities: Vec<Capacity>,
+        trials: usize,
+        seed: u64,
+    },
+}
+
+impl Search {
+    /// The (threshold, capacity) combinations to try.
+    fn candidates(&self) -> Vec<(Scalar, Capacity)> {
+        match self {
+            Search::Grid {
+                thresholds,
+                capacities,
+            } => thresholds
+                .iter()
+                .flat_map(|&threshold| {
+                    capacities
+                        .iter()
+                        .map(move |capacity| (threshold, capacity.clone()))
+                })
+                .collect(),
+            Search::Random {
+                thresholds,
+                capacities,
+                trials,
+                seed,
+            } => {
+                if capacities.is_empty() {
+                    return Vec::new();
+                }
+                let mut rng = SplitMix64(*seed);
+                let (low, high) = (*thresholds.start(), *thresholds.end());
+                (0..*trials)
+                    .map(|_| {
+                        let threshold = match low > 0.0 {
+                            true => {
+                                let (low, high) = (Float::ln(low), Float::ln(high));
+                                Float::exp(low + rng.uniform() * (high - low))
+                            }
+                            false => low + rng.uniform() * (high - low),
+                        };
+                        let capacity = &capacities[rng.below(capacities.len() as u64) as usize];
+                        (threshold, capacity.clone())
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Internal clustering metric used to rank configurations.
+///
+/// [Score::DaviesBouldin] and [Score::CalinskiHarabasz] reward compact clusters, and so tend to
+/// favor small thresholds which split natural clusters into many leaf clusters; they are best
+/// suited to comparing configurations with similar numbers of leaf clusters (e.g. when tuning
+/// capacities). [Score::Silhouette] (the default) also penalizes splitting natural clusters, so
+/// suits tuning the threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Score {
+    /// [silhouette] of up to `sample_size` of the points (evenly spread through them). Higher is
+    /// better.
+    Silhouette { sample_size: usize },
+    /// [davies_bouldin]. Lower is better.
+    DaviesBouldin,
+    /// [calinski_harabasz]. Higher is better.
+    CalinskiHarabasz,
+}
+
+impl Default for Score {
+    fn default() -> Score {
+        Score::Silhouette { sample_size: 1000 }
+    }
+}
+
+impl Score {
+    /// Whether higher scores are better.
+    pub fn higher_is_better(&self) -> bool {
+        !matches!(self, Score::DaviesBouldin)
+    }
+
+    fn evaluate<CF, const DIMS: usize>(
+        &self,
+        tree: &CFTree<CF, DIMS, BasicConfig>,
+        points: &[FeaturePoint<CF, DIMS>],
+    ) -> Option<Scalar>
+    where
+        CF: CFeature<DIMS>,
+    {
+        match *self {
+            Score::Silhouette { sample_size } => {
+                let step = points.len().div_ceil(sample_size.max(1)).max(1);
+                let sample = points.iter().step_by(step).cloned().collect::<Vec<_>>();
+                silhouette(tree, &sample)
+            }
+            Score::DaviesBouldin => davies_bouldin(tree),
+            Score::CalinskiHarabasz => calinski_harabasz(tree),
+        }
+    }
+}
+
+/// Outcome of building a tree with one configuration.
+#[derive(Debug, Clone)]
+pub struct Trial {
+    pub config: BasicConfig,
+    /// Score of the tree, or `None` if the metric is undefined for it (e.g. it has a single leaf
+    /// cluster).
+    pub score: Option<Scalar>,
+    /// Number of leaf clusters of the tree.
+    pub clusters: usize,
+    pub metrics: TreeMetrics,
+}
+
+/// Trials of a search, ranked from best to worst. Created by [tune].
+#[derive(Debug, Clone)]
+pub struct TuneReport {
+    /// Trials ranked from best to worst score; trials without a score come last, in the order they
+    /// were tried.
+    pub trials: Vec<Trial>,
+    pub score: Score,
+}
+
+impl TuneReport {
+    /// The best trial, if any configuration was tried.
+    pub fn best(&self) -> Option<&Trial> {
+        self.trials.first()
+    }
+}
+
+/// Builds a tree over `points` for every configuration of `search` and ranks them by `score` (see
+/// the [module documentation](self)). The thresholds and capacities of `search` override those of
+/// `base`, which provides every other setting.
+///
+/// Returns an error if any configuration is invalid (e.g. a capacity with `min` greater than half
+/// of `max`).
+pub fn tune<CF, const DIMS: usize>(
+    points: &[FeaturePoint<CF, DIMS>],
+    base: &BasicConfigBuilder,
+    search: &Search,
+    score: Score,
+) -> Result<TuneReport, BorschtError>
+where
+    CF: CFeature<DIMS> + Debug + Clone,
+{
+    let mut trials = Vec::new();
+    for (threshold, capacity) in search.candidates() {
+        let config = base
+            .clone()
+            .capacity(capacity.min, capacity.max)
+            .threshold(threshold)
+            .build()?;
+        debug_event!(threshold, ?capacity, "tuning trial");
+        let tree = CFTree::<CF, DIMS, _>::from_iter(points.iter().cloned(), config);
+        trials.push(Trial {
+            score: score.evaluate(&tree, points),
+            clusters: tree.clusters().count(),
+            metrics: tree.metrics().clone(),
+            config: tree.config().clone(),
+        });
+    }
+    // stable sort: unscored trials keep their order at the end
+    trials.sort_by(|left, right| match (left.score, right.score) {
+        (Some(left), Some(right)) => match score.higher_is_better() {
+            true => right.total_cmp(&left),
+            false => left.total_cmp(&right),
+        },
+        (left, right) => right.is_some().cmp(&left.is_some()),
+    });
+    Ok(TuneReport { trials, score })
+}
+
+/// Center, size and mean squared distance from the center of each leaf cluster of `tree`.
+fn leaf_stats<CF, TC, const DIMS: usize>(
+    tree: &CFTree<CF, DIMS, TC>,
+) -> Vec<(Point<DIMS>, Scalar, Scalar)>
+where
+    CF: CFeature<DIMS>,
+{
+    let mut leaves = Vec::new();
+    collect_leaves(tree.root(), &mut leaves);
+    leaves
+        .into_iter()
+        .map(|feature| {
+            (
+                feature.center().cast(),
+                feature.size().to_scalar(),
+                feature.radius2().to_scalar(),
+            )
+        })
+        .collect()
+}
+
+/// [Davies–Bouldin index](https://en.wikipedia.org/wiki/Davies%E2%80%93Bouldin_index) of the leaf
+/// clusters of `tree`: the mean, over clusters, of the largest ratio between the summed spreads of
+/// the cluster and another and the distance between their centers. The spread of a cluster is its
+/// radius (the root-mean-square, rather than the mean, distance of its points from its center).
+/// Lower is better. Returns `None` if the tree has fewer than two leaf clusters.
+pub fn davies_bouldin<CF, TC, const DIMS: usize>(tree: &CFTree<CF, DIMS, TC>) -> Option<Scalar>
+where
+    CF: CFeature<DIMS>,
+{
+    let stats = leaf_stats(tree);
+    if stats.len() < 2 {
+        return None;
+    }
+    let total = stats
+        .iter()
+        .enumerate()
+        .map(|(i, (center, _, radius2))| {
+            stats
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, (other, _, other_radius2))| {
+                    (radius2.sqrt() + other_radius2.sqrt()) / (center - other).norm2().sqrt()
+                })
+                .fold(0.0, Scalar::max)
+        })
+        .sum::<Scalar>();
+    Some(total / stats.len() as Scalar)
+}
+
+/// [Calinski–Harabasz index](https://en.wikipedia.org/wiki/Calinski%E2%80%93Harabasz_index) of
+/// the leaf clusters of `tree`: the ratio of the dispersion between clusters to that within
+/// clusters, each normalized by its degrees of freedom. Higher is better. Returns `None` if the
+/// tree has fewer than two leaf clusters, or no more points than leaf clusters.
+pub fn calinski_harabasz<CF, TC, const DIMS: usize>(tree: &CFTree<CF, DIMS, TC>) -> Option<Scalar>
+where
+    CF: CFeature<DIMS>,
+{
+    let stats = leaf_stats(tree);
+    let k = stats.len() as Scalar;
+    let n = stats.iter().map(|&(_, size, _)| size).sum::<Scalar>();
+    if stats.len() < 2 || n <= k {
+        return None;
+    }
+    let mean = stats
+        .iter()
+        .fold(Point::<DIMS>::default(), |acc, (center, size, _)| {
+            acc + center * *size
+        })
+        / n;
+    let between = stats
+        .iter()
+        .map(|(center, size, _)| size * (center - &mean).norm2())
+        .sum::<Scalar>();
+    let within = stats
+        .iter()
+        .map(|(_, size, radius2)| size * radius2)
+        .sum::<Scalar>();
+    Some((between / (k - 1.0)) / (within / (n - k)))
+}
+
+/// Mean [silhouette](https://en.wikipedia.org/wiki/Silhouette_(clustering)) of `points`, each
+/// belonging to the leaf cluster of `tree` closest to it (see [CFTree::labels]): how much closer
+/// (by Euclidean distance) each point is on average to the other points of its cluster than to
+/// those of the nearest other cluster, from -1 (worst) to 1 (best). Points alone in their cluster
+/// score 0. Takes time quadratic in the number of points. Returns `None` if the points belong to
+/// fewer than two clusters.
+pub fn silhouette<CF, TC, const DIMS: usize>(
+    tree: &CFTree<CF, DIMS, TC>,
+    points: &[FeaturePoint<CF, DIMS>],
+) -> Option<Scalar>
+where
+    CF: CFeature<DIMS>,
+    TC: TreeConfig,
+{
+    let labels = tree
+        .labels(points)
+        .into_iter()
+        .collect::<Option<Vec<_>>>()?;
+    let clusters = labels.iter().max()? + 1;
+    let mut sizes = alloc::vec![0usize; clusters];
+    for &label in &labels {
+        sizes[label] += 1;
+    }
+    if sizes.iter().filter(|&&size| size > 0).count() < 2 {
+        return None;
+    }
+    let points = points
+        .iter()
+        .map(|p| p.cast::<Scalar>())
+        .collect::<Vec<_>>();
+    let total = points
+        .iter()
+        .zip(&labels)
+        .map(|(p, &label)| {
+            if sizes[label] == 1 {
+                return 0.0;
+            }
+            let mut sums = alloc::vec![0.0; clusters];
+            for (q, &other) in points.iter().zip(&labels) {
+                sums[other] += (p - q).norm2().sqrt();
+            }
+            let within = sums[label] / (sizes[label] - 1) as Scalar;
+            let nearest = (0..clusters)
+                .filter(|&other| other != label && sizes[other] > 0)
+                .map(|other| sums[other] / sizes[other] as Scalar)
+                .fold(Scalar::INFINITY, Scalar::min);
+            match within.max(nearest) {
+                max if max > 0.0 => (nearest - within) / max,
+                _ => 0.0,
+            }
+        })
+        .sum::<Scalar>();
+    Some(total / points.len() as Scalar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cfeature::birch::CFeature as BirchCF, cftree::ConfigError};
+
+    #[test]
+    fn tune() {
+        // three blobs of 50 points each
+        let points = (0..150)
+            .map(|i| {
+                let blob = (i % 3) as f64 * 10.0;
+                let angle = i as f64 * 0.7;
+                Point::from_arr([blob + 0.3 * angle.cos(), 0.3 * angle.sin()])
+            })
+            .collect::<Vec<_>>();
+        let base = BasicConfig::builder().capacity(2, 4);
+        let search = Search::Grid {
+            thresholds: vec![0.01, 1.0, 1000.0],
+            capacities: vec![Capacity { min: 2, max: 4 }, Capacity { min: 5, max: 10 }],
+        };
+
+        let report =
+            super::tune::<BirchCF<2>, 2>(&points, &base, &search, Score::default()).unwrap();
+        assert_eq!(report.trials.len(), 6);
+        let best = report.best().unwrap();
+        assert_eq!(best.config.threshold, 1.0);
+        assert_eq!(best.clusters, 3);
+        assert!(best.score.unwrap() > 0.9);
+        assert_eq!(best.metrics.inserted, 150);
+        // a single cluster has no silhouette
+        assert!(report.trials[4..].iter().all(|trial| trial.score.is_none()));
+        assert!(report.trials[4..]
+            .iter()
+            .all(|trial| trial.config.threshold == 1000.0));
+
+        let report =
+            super::tune::<BirchCF<2>, 2>(&points, &base, &search, Score::DaviesBouldin).unwrap();
+        let scores = report
+            .trials
+            .iter()
+            .filter_map(|trial| trial.score)
+            .collect::<Vec<_>>();
+        assert!(scores.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let search = Search::Random {
+            thresholds: 0.5..=2.0,
+            capacities: vec![Capacity { min: 2, max: 4 }],
+            trials: 5,
+            seed: 3,
+        };
+        let report =
+            super::tune::<BirchCF<2>, 2>(&points, &base, &search, Score::CalinskiHarabasz).unwrap();
+        assert_eq!(report.trials.len(), 5);
+        assert!(report
+            .trials
+            .iter()
+            .all(|trial| (0.5..=2.0).contains(&trial.config.threshold) && trial.clusters == 3));
+
+        let invalid = Search::Grid {
+            thresholds: vec![1.0],
+            capacities: vec![Capacity { min: 3, max: 4 }],
+        };
+        assert_eq!(
+            super::tune::<BirchCF<2>, 2>(&points, &base, &invalid, Score::default()).unwrap_err(),
+            BorschtError::Config(ConfigError::InvalidCapacity { min: 3, max: 4 })
+        );
+    }
+}
@@ -0,0 +1,137 @@
+/*!
+ * [wasm-bindgen](https://rustwasm.github.io/wasm-bindgen/) bindings, for running the clustering
+ * client-side in a browser. Requires the `wasm` feature; build for `wasm32-unknown-unknown`
+ * with default features disabled (so no file I/O is compiled in).
+ *
+ * Points are passed across the JavaScript boundary as flat, row-major `Float64Array`s, and the
+ * dimensionality of the tree is fixed when it is created.
+ */
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    cftree::BasicConfig,
+    dynamic::{
+        cfeature::CFeature,
+        cftree::{BetulaTree, Node},
+        point::DynPoint,
+    },
+};
+
+/// A (Betula) cluster feature tree over points of a fixed runtime dimensionality.
+#[wasm_bindgen]
+pub struct WasmTree {
+    root: Option<BetulaTree>,
+    dims: usize,
+    config: BasicConfig,
+}
+
+#[wasm_bindgen]
+impl WasmTree {
+    /// Creates an empty tree over points with `dims` dimensions. Nodes hold between `min` and
+    /// `max` entries, and leaf clusters absorb points while their squared diameter stays below
+    /// `threshold`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(dims: usize, min: usize, max: usize, threshold: f64) -> Result<WasmTree, JsError> {
+        if dims == 0 {
+            return Err(JsError::new("dims must be positive"));
+        }
+        let config = BasicConfig::builder()
+            .capacity(min, max)
+            .threshold(threshold)
+            .build()?;
+        Ok(WasmTree {
+            root: Some(Node::new(&config)),
+            dims,
+            config,
+        })
+    }
+
+    /// Creates a tree over points with `dims` dimensions from the flat, row-major `data`.
+    pub fn build(
+        dims: usize,
+        data: &[f64],
+        min: usize,
+        max: usize,
+        threshold: f64,
+    ) -> Result<WasmTree, JsError> {
+        let mut tree = WasmTree::new(dims, min, max, threshold)?;
+        tree.insert_many(data)?;
+        Ok(tree)
+    }
+
+    pub fn dims(&self) -> usize {
+        self.dims
+    }
+
+    /// Inserts a single point.
+    pub fn insert(&mut self, point: &[f64]) -> Result<(), JsError> {
+        if point.len() != self.dims {
+            return Err(JsError::new("point length must match tree dimensionality"));
+        }
+        self.insert_point(DynPoint::from(point));
+        Ok(())
+    }
+
+    /// Inserts every point of the flat, row-major `data`.
+    pub fn insert_many(&mut self, data: &[f64]) -> Result<(), JsError> {
+        if !data.len().is_multiple_of(self.dims) {
+            return Err(JsError::new(
+                "data length must be a multiple of tree dimensionality",
+            ));
+        }
+        for point in data.chunks_exact(self.dims) {
+            self.insert_point(DynPoint::from(point));
+        }
+        Ok(())
+    }
+
+    /// Number of leaf clusters in the tree.
+    #[wasm_bindgen(js_name = clusterCount)]
+    pub fn cluster_count(&self) -> usize {
+        let mut count = 0;
+        for_each_leaf(self.root(), &mut |_| count += 1);
+        count
+    }
+
+    /// Summaries of the leaf clusters of the tree, as a JSON array of
+    /// `{center, radius, diameter, size}` objects.
+    #[wasm_bindgen(js_name = clusterSummaries)]
+    pub fn cluster_summaries(&self) -> String {
+        let mut summaries = vec![];
+        for_each_leaf(self.root(), &mut |feature| {
+            summaries.push(serde_json::json!({
+                "center": feature.center().as_slice(),
+                "radius": feature.radius(),
+                "diameter": feature.diam(),
+                "size": feature.size(),
+            }))
+        });
+        serde_json::Value::Array(summaries).to_string()
+    }
+}
+
+impl WasmTree {
+    fn root(&self) -> &BetulaTree {
+        self.root
+            .as_ref()
+            .expect("root is only taken during insertion")
+    }
+
+    fn insert_point(&mut self, point: DynPoint) {
+        let root = self
+            .root
+            .take()
+            .expect("root is only taken during insertion");
+        self.root = Some(root.insert_root(point, &self.config));
+    }
+}
+
+fn for_each_leaf<CF, F: FnMut(&CF)>(node: &Node<CF>, f: &mut F) {
+    for entry in &node.entries {
+        match entry.child {
+            Some(ref child) => for_each_leaf(child, f),
+            None => f(&entry.feature),
+        }
+    }
+}
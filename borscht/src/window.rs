@@ -0,0 +1,263 @@
+/*!
+ * Sliding-window clustering, where the tree only reflects the most recent points of a stream.
+ *
+ * A [WindowedCFTree] keeps a queue of the last `window` inserted points alongside the tree. Once
+ * the window is full, each insertion evicts the oldest point by subtracting it from the leaf
+ * cluster which absorbed it (and from the features of that cluster's ancestors), even if another
+ * leaf cluster has since become closer to it. Leaf clusters which become empty are removed, along
+ * with any nodes left without entries.
+ *
+ * To find the leaf cluster holding each point, the leaf clusters of a windowed tree always get
+ * stable ids (see [TreeConfig::stable_cluster_ids]), whatever its configuration. The tree keeps
+ * the path to each of them, and only walks all of its leaf clusters again when the one it is
+ * looking for has been created, or moved by a split or removal, since the last walk.
+ */
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{fmt::Debug, ops::Sub};
+
+use crate::{
+    cfeature::{CFeature, FeaturePoint},
+    cftree::{BasicConfig, CFTree, InsertOutcome, Node, NodeEntry, TreeConfig},
+    point::Float as _,
+};
+
+/// A cluster feature tree over the last `window` points inserted into it.
+#[derive(Debug)]
+pub struct WindowedCFTree<CF: CFeature<DIMS>, const DIMS: usize, TC = BasicConfig> {
+    tree: CFTree<CF, DIMS, TC>,
+    window: usize,
+    /// Points currently in the window, oldest first, along with the stable id of the leaf cluster
+    /// which absorbed each of them.
+    recent: VecDeque<(FeaturePoint<CF, DIMS>, u64)>,
+    /// Indices of the entries leading from the root to the leaf cluster with each stable id (or
+    /// which has absorbed the leaf cluster with that id), as of the last time they were indexed.
+    paths: BTreeMap<u64, Vec<usize>>,
+}
+
+impl<CF, TC, const DIMS: usize> WindowedCFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone + for<'a> Sub<&'a FeaturePoint<CF, DIMS>, Output = CF>,
+    TC: TreeConfig,
+{
+    /// Creates a new empty tree over a window of the last `window` (at least one) points.
+    pub fn new(window: usize, config: TC) -> WindowedCFTree<CF, DIMS, TC> {
+        WindowedCFTree {
+            tree: CFTree::new(config),
+            window: window.max(1),
+            recent: VecDeque::with_capacity(window.max(1)),
+            paths: BTreeMap::new(),
+        }
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Number of points currently in the window.
+    pub fn len(&self) -> usize {
+        self.recent.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recent.is_empty()
+    }
+
+    /// The tree summarizing the points currently in the window.
+    pub fn tree(&self) -> &CFTree<CF, DIMS, TC> {
+        &self.tree
+    }
+
+    /// Inserts a point, evicting the oldest point in the window if the window is full.
+    pub fn insert(&mut self, p: FeaturePoint<CF, DIMS>) -> InsertOutcome {
+        if self.recent.len() == self.window {
+            self.evict_oldest();
+        }
+        let p = self.tree.root().complete(p, self.tree.config());
+        let entry =
+            NodeEntry::with_point(p.clone(), self.tree.metrics().inserted, self.tree.config());
+        let (outcome, holder) = self.tree.insert_held_entry(entry);
+        self.recent.push_back((p, holder));
+        outcome
+    }
+
+    /// Removes the oldest point in the window from the tree, returning it.
+    pub fn evict_oldest(&mut self) -> Option<FeaturePoint<CF, DIMS>> {
+        let (p, holder) = self.recent.pop_front()?;
+        let indexed = self
+            .paths
+            .get(&holder)
+            .is_some_and(|path| holds(self.tree.root(), path, holder));
+        if !indexed {
+            self.paths.clear();
+            index_paths(self.tree.root(), &mut vec![], &mut self.paths);
+        }
+        if let Some(path) = self.paths.get(&holder) {
+            remove_point(self.tree.root_mut(), path, &p);
+        }
+        Some(p)
+    }
+}
+
+impl<CF, TC, const DIMS: usize> Extend<FeaturePoint<CF, DIMS>> for WindowedCFTree<CF, DIMS, TC>
+where
+    CF: CFeature<DIMS> + Debug + Clone + for<'a> Sub<&'a FeaturePoint<CF, DIMS>, Output = CF>,
+    TC: TreeConfig,
+{
+    fn extend<T: IntoIterator<Item = FeaturePoint<CF, DIMS>>>(&mut self, iter: T) {
+        for p in iter {
+            self.insert(p);
+        }
+    }
+}
+
+/// Whether `path` leads from `node` to the leaf entry with the stable id `holder` (or which has
+/// absorbed the leaf entry with that id).
+fn holds<CF, const DIMS: usize>(node: &Node<CF, DIMS>, path: &[usize], holder: u64) -> bool {
+    let Some((&idx, rest)) = path.split_first() else {
+        return false;
+    };
+    match node.entries.get(idx) {
+        Some(NodeEntry {
+            child: Some(ref child),
+            ..
+        }) => holds(child, rest, holder),
+        Some(NodeEntry {
+            child: None,
+            identity: Some(ref identity),
+            ..
+        }) => rest.is_empty() && (identity.id == holder || identity.merged.contains(&holder)),
+        _ => false,
+    }
+}
+
+/// Adds the paths to the leaf entries of the subtree rooted at `node` to `paths`, by their stable
+/// ids (and those of the leaf entries they have absorbed), with `path` leading to `node`.
+fn index_paths<CF, const DIMS: usize>(
+    node: &Node<CF, DIMS>,
+    path: &mut Vec<usize>,
+    paths: &mut BTreeMap<u64, Vec<usize>>,
+) {
+    for (idx, entry) in node.entries.iter().enumerate() {
+        path.push(idx);
+        match (&entry.child, &entry.identity) {
+            (Some(child), _) => index_paths(child, path, paths),
+            (None, Some(identity)) => {
+                for &id in core::iter::once(&identity.id).chain(&identity.merged) {
+                    paths.insert(id, path.clone());
+                }
+            }
+            (None, None) => {}
+        }
+        path.pop();
+    }
+}
+
+/// Subtracts `p` from the leaf entry at the end of `path` in the subtree rooted at `node` (and
+/// from the features of its ancestors).
+fn remove_point<CF, const DIMS: usize>(
+    node: &mut Node<CF, DIMS>,
+    path: &[usize],
+    p: &FeaturePoint<CF, DIMS>,
+) where
+    CF: CFeature<DIMS> + Debug + Clone + for<'a> Sub<&'a FeaturePoint<CF, DIMS>, Output = CF>,
+{
+    let idx = path[0];
+    let entry = &mut node.entries[idx];
+    match entry.child {
+        Some(ref mut child) => {
+            let child = Arc::make_mut(child);
+            remove_point(child, &path[1..], p);
+            if child.entries.is_empty() {
+                node.entries.remove(idx);
+            } else {
+                entry.feature = child.compute_feature();
+            }
+        }
+        None => {
+            entry.feature = entry.feature.clone() - p;
+            if entry.feature.size() < CF::Scalar::from_scalar(0.5) {
+                node.entries.remove(idx);
+            }
+        }
+    }
+    node.refresh();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cfeature::{betula::CFeature as BetulaFeature, birch::CFeature as BirchFeature},
+        point::Point,
+    };
+
+    fn config() -> BasicConfig {
+        BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(1.0)
+            .build()
+            .unwrap()
+    }
+
+    fn points() -> impl Iterator<Item = Point<2>> {
+        let old = (0..100).map(|i| Point::from_arr([(i % 10) as f64 * 0.05, 0.0]));
+        let new = (0..100).map(|i| Point::from_arr([20.0, (i % 10) as f64 * 0.05]));
+        old.chain(new)
+    }
+
+    #[test]
+    fn window() {
+        let mut tree = WindowedCFTree::<BirchFeature<2>, 2>::new(100, config());
+        tree.extend(points());
+        assert_eq!(tree.len(), 100);
+        let clusters = tree.tree().clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.iter().map(|c| c.size).sum::<f64>(), 100.0);
+        assert!(clusters.iter().all(|c| c.center[0] > 19.0));
+
+        let mut tree = WindowedCFTree::<BetulaFeature<2>, 2>::new(150, config());
+        tree.extend(points());
+        let clusters = tree.tree().clusters().collect::<Vec<_>>();
+        assert!((clusters.iter().map(|c| c.size).sum::<f64>() - 150.0).abs() < 1e-9);
+        let old = clusters
+            .iter()
+            .filter(|c| c.center[0] < 10.0)
+            .map(|c| c.size)
+            .sum::<f64>();
+        assert!((old - 50.0).abs() < 1e-9);
+
+        while tree.evict_oldest().is_some() {}
+        assert!(tree.is_empty());
+        assert_eq!(tree.tree().clusters().count(), 0);
+    }
+
+    #[test]
+    fn evict_from_absorbing_leaf() {
+        let config = BasicConfig::builder()
+            .capacity(2, 4)
+            .threshold(0.09)
+            .build()
+            .unwrap();
+        let mut tree = WindowedCFTree::<BirchFeature<2>, 2>::new(3, config);
+        tree.insert(Point::from_arr([0.0, 0.0]));
+        tree.insert(Point::from_arr([0.3, 0.0]));
+        // too far from the center of the first cluster to join it, but closer to the first point
+        tree.insert(Point::from_arr([-0.14, 0.0]));
+        assert_eq!(tree.tree().clusters().count(), 2);
+
+        // the first point is evicted from the cluster which absorbed it, not the closest one
+        tree.insert(Point::from_arr([10.0, 10.0]));
+        let clusters = tree.tree().clusters().collect::<Vec<_>>();
+        assert_eq!(clusters.len(), 3);
+        assert!(clusters.iter().all(|c| (c.size - 1.0).abs() < 1e-9));
+        let mut xs = clusters.iter().map(|c| c.center[0]).collect::<Vec<_>>();
+        xs.sort_by(f64::total_cmp);
+        assert!((xs[0] + 0.14).abs() < 1e-9);
+        assert!((xs[1] - 0.3).abs() < 1e-9);
+    }
+}
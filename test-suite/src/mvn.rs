@@ -1,9 +1,10 @@
 use borscht::{
     cfeature::birch::CFeature as BirchFeature,
-    cftree::{BasicConfig, BirchTree, Capacity, Node},
+    cftree::{BasicConfig, BirchTree, Node},
+    error::BorschtError,
     point::Point,
 };
-use borscht_visualizer::{draw_to_file, VisualizerError};
+use borscht_visualizer::{draw_to_file, VisualizerOptions};
 use datagen::distribution::MultivariateNormal;
 
 use rand::{distributions::Distribution, SeedableRng};
@@ -11,7 +12,7 @@ use rand_xoshiro::Xoshiro256PlusPlus;
 
 pub type TreeNode = Node<BirchFeature<3>, 3>;
 
-pub fn generate(seed: u64, count: usize) -> TreeNode {
+pub fn generate(seed: u64, count: usize) -> Result<TreeNode, BorschtError> {
     let means = [128u8, 52, 255];
     let stds = [5.0f64, 4.0f64, 3.0f64];
     let cov = [
@@ -25,17 +26,21 @@ pub fn generate(seed: u64, count: usize) -> TreeNode {
         let arr_u64 = dist.sample(&mut rng);
         Point::from_arr([arr_u64[0] as f64, arr_u64[1] as f64, arr_u64[2] as f64])
     });
-    BirchTree::from_iter(
+    Ok(BirchTree::from_iter(
         generator.take(count),
-        &BasicConfig {
-            capacity: Capacity { min: 1, max: 3 },
-            threshold: 0.5,
-        },
-    )
+        &BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(0.5)
+            .build()?,
+    ))
 }
 
-pub fn visualize(target_filename: &str, seed: u64, count: usize) -> Result<(), VisualizerError> {
-    let tree = generate(seed, count);
-    draw_to_file(target_filename, &tree)?;
+pub fn visualize(
+    target_filename: &str,
+    seed: u64,
+    count: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tree = generate(seed, count)?;
+    draw_to_file(target_filename, &tree, &VisualizerOptions::default())?;
     Ok(())
 }
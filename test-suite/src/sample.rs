@@ -1,30 +1,31 @@
 use borscht::{
     cfeature::birch::CFeature as BirchFeature,
-    cftree::{BasicConfig, BirchTree, Capacity, Node},
+    cftree::{BasicConfig, BirchTree, Node},
+    error::BorschtError,
     point::Point,
 };
-use borscht_visualizer::{draw_to_file, VisualizerError};
+use borscht_visualizer::{draw_to_file, VisualizerOptions};
 
 pub type TreeNode = Node<BirchFeature<3>, 3>;
 
-pub fn generate(_seed: u64) -> TreeNode {
+pub fn generate(_seed: u64) -> Result<TreeNode, BorschtError> {
     let mut points = vec![
         Point::from_arr([1.0, 2.0, 3.0]),
         Point::from_arr([2.0, 2.0, 3.0]),
         Point::from_arr([1.0, 3.0, 3.0]),
         Point::from_arr([1.0, 2.0, 4.0]),
     ];
-    BirchTree::from_iter(
+    Ok(BirchTree::from_iter(
         points.drain(..),
-        &BasicConfig {
-            capacity: Capacity { min: 1, max: 3 },
-            threshold: 0.5,
-        },
-    )
+        &BasicConfig::builder()
+            .capacity(1, 3)
+            .threshold(0.5)
+            .build()?,
+    ))
 }
 
-pub fn visualize(target_filename: &str) -> Result<(), VisualizerError> {
-    let tree = generate(0);
-    draw_to_file(target_filename, &tree)?;
+pub fn visualize(target_filename: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let tree = generate(0)?;
+    draw_to_file(target_filename, &tree, &VisualizerOptions::default())?;
     Ok(())
 }